@@ -1,3 +1,4 @@
+use secrets::{SecretError, Secrets};
 use serde::{Deserialize, Serialize};
 
 use std::{env, fs, path::Path};
@@ -5,10 +6,37 @@ use std::{env, fs, path::Path};
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Config {
     pub sender: String,
+    /// Overridden by the `SMTP_PASSWORD` secret, so it can be left out of the file
+    #[serde(default)]
     pub smtp_pass: String,
     pub smtp_relay: String,
+    /// Overridden by the `SMTP_USERNAME` secret
+    #[serde(default)]
     pub smtp_username: String,
     pub port: i32,
+    /// Emails accepted but not yet sent, above which new ones are rejected with 429.
+    /// Unbounded if omitted
+    #[serde(default)]
+    pub max_queue_depth: Option<usize>,
+    /// `Retry-After` sent along with 429, in seconds
+    #[serde(default = "default_retry_after_secs")]
+    pub retry_after_secs: u64,
+}
+
+const fn default_retry_after_secs() -> u64 {
+    5
+}
+
+/// Replaces the SMTP credentials of the file with the `SMTP_USERNAME` and `SMTP_PASSWORD`
+/// secrets, read from `<NAME>_FILE`, Vault (`<NAME>_VAULT`) or the variables themselves
+pub async fn load_secrets(config: &mut Config, secrets: &Secrets) -> Result<(), SecretError> {
+    if let Some(username) = secrets.get("SMTP_USERNAME").await? {
+        config.smtp_username = username.into_inner();
+    }
+    if let Some(password) = secrets.get("SMTP_PASSWORD").await? {
+        config.smtp_pass = password.into_inner();
+    }
+    Ok(())
 }
 
 pub fn load_config() -> Result<Config, Box<dyn std::error::Error>> {
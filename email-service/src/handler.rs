@@ -1,7 +1,7 @@
 use axum::{
     Json,
     extract::State,
-    http::StatusCode,
+    http::{StatusCode, header},
     response::{IntoResponse, Response},
 };
 use axum_macros::debug_handler;
@@ -25,6 +25,12 @@ pub async fn send_email(
                 EmailServiceError::AddressFormat(_) => {
                     (StatusCode::BAD_REQUEST, Json("Invalid address format")).into_response()
                 }
+                EmailServiceError::QueueFull { retry_after } => (
+                    StatusCode::TOO_MANY_REQUESTS,
+                    [(header::RETRY_AFTER, retry_after.as_secs().to_string())],
+                    Json("Send queue is full, try again later"),
+                )
+                    .into_response(),
                 _ => (
                     StatusCode::INTERNAL_SERVER_ERROR,
                     Json("Failed to send email"),
@@ -39,3 +45,12 @@ pub async fn send_email(
 pub async fn health_check() -> Response {
     (StatusCode::OK, "Hello from email service!").into_response()
 }
+
+#[debug_handler]
+pub async fn metrics(State(service): State<Arc<EmailService>>) -> Response {
+    (
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+        service.render_metrics(),
+    )
+        .into_response()
+}
@@ -17,7 +17,16 @@ async fn main() {
     tracing_subscriber::fmt().init();
 
     // Load config
-    let cfg = config::load_config().expect("failed to locate or load config file");
+    let mut cfg = config::load_config().expect("failed to locate or load config file");
+    let secrets = secrets::Secrets::from_env()
+        .await
+        .expect("failed to connect to Vault");
+    config::load_secrets(&mut cfg, &secrets)
+        .await
+        .expect("failed to read SMTP credentials");
+    if let Some(vault) = secrets.vault() {
+        tokio::spawn(vault.clone().run_renewal());
+    }
     tracing::info!("Successfully loaded email service config");
 
     // Setup service
@@ -28,6 +37,7 @@ async fn main() {
     let router = Router::new()
         .route("/email", post(handler::send_email))
         .route("/", get(handler::health_check))
+        .route("/metrics", get(handler::metrics))
         .with_state(service_ptr)
         .layer(TraceLayer::new_for_http());
 
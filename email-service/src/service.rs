@@ -6,11 +6,53 @@ use crate::{
 use lettre::transport::smtp::authentication::Credentials;
 use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
 
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::time::Duration;
+
 pub struct EmailService {
     sender: String,
     smtp_pass: String,
     smtp_relay: String,
     smtp_username: String,
+    queue: SendQueue,
+}
+
+/// Tracks emails accepted but not yet sent
+struct SendQueue {
+    depth: AtomicUsize,
+    max_depth: Option<usize>,
+    rejected: AtomicU64,
+    retry_after: Duration,
+}
+
+/// Place in the send queue, released when dropped
+struct QueueSlot<'a>(&'a SendQueue);
+
+impl SendQueue {
+    fn enter(&self) -> Option<QueueSlot<'_>> {
+        let entered = self
+            .depth
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |depth| {
+                match self.max_depth {
+                    Some(max) if depth >= max => None,
+                    _ => Some(depth + 1),
+                }
+            });
+
+        match entered {
+            Ok(_) => Some(QueueSlot(self)),
+            Err(_) => {
+                self.rejected.fetch_add(1, Ordering::Relaxed);
+                None
+            }
+        }
+    }
+}
+
+impl Drop for QueueSlot<'_> {
+    fn drop(&mut self) {
+        self.0.depth.fetch_sub(1, Ordering::AcqRel);
+    }
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -26,6 +68,9 @@ pub enum EmailServiceError {
 
     #[error("Failed to connect to SMTP relay: {0}")]
     SmtpRelay(lettre::transport::smtp::Error),
+
+    #[error("Send queue is full, retry after {retry_after:?}")]
+    QueueFull { retry_after: Duration },
 }
 
 impl EmailService {
@@ -35,13 +80,52 @@ impl EmailService {
             smtp_pass: config.smtp_pass,
             smtp_relay: config.smtp_relay,
             smtp_username: config.smtp_username,
+            queue: SendQueue {
+                depth: AtomicUsize::new(0),
+                max_depth: config.max_queue_depth,
+                rejected: AtomicU64::new(0),
+                retry_after: Duration::from_secs(config.retry_after_secs),
+            },
         }
     }
 
+    /// Queue depth and rejection counters in the Prometheus text exposition format
+    pub fn render_metrics(&self) -> String {
+        let mut out = String::new();
+        out.push_str("# HELP email_queue_depth Emails accepted but not yet sent\n");
+        out.push_str("# TYPE email_queue_depth gauge\n");
+        out.push_str(&format!(
+            "email_queue_depth {}\n",
+            self.queue.depth.load(Ordering::Relaxed)
+        ));
+        if let Some(max_depth) = self.queue.max_depth {
+            out.push_str(
+                "# HELP email_queue_max_depth Queue depth above which emails are rejected\n",
+            );
+            out.push_str("# TYPE email_queue_max_depth gauge\n");
+            out.push_str(&format!("email_queue_max_depth {}\n", max_depth));
+        }
+        out.push_str(
+            "# HELP email_queue_rejected_total Emails rejected because the queue was full\n",
+        );
+        out.push_str("# TYPE email_queue_rejected_total counter\n");
+        out.push_str(&format!(
+            "email_queue_rejected_total {}\n",
+            self.queue.rejected.load(Ordering::Relaxed)
+        ));
+        out
+    }
+
     pub async fn send_email(
         &self,
         request: SendEmailRequest,
     ) -> Result<SendEmailResponse, EmailServiceError> {
+        let Some(_slot) = self.queue.enter() else {
+            return Err(EmailServiceError::QueueFull {
+                retry_after: self.queue.retry_after,
+            });
+        };
+
         let email = Message::builder()
             .from(self.sender.clone().parse()?)
             .to(request.to.clone().parse()?)
@@ -1,34 +1,99 @@
-use tonic::Request;
+mod profile;
 
-// Include the generated proto code
-pub mod notes {
-    tonic::include_proto!("notes");
-}
+use profile::{OutputFormat, Profile};
+use serde::Serialize;
+use tonic::metadata::MetadataValue;
+use tonic::service::Interceptor;
+use tonic::transport::{Certificate, Channel, ClientTlsConfig, Identity};
+use tonic::{Request, Status};
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
-use notes::{
-    CreateNoteRequest, DeleteNoteRequest, GetAllNotesRequest, GetNoteRequest, UpdateNoteRequest,
-    note_service_client::NoteServiceClient,
+use notes_proto::google::protobuf::Timestamp;
+use notes_proto::notes::v1::{
+    BatchCreateNotesRequest, BatchDeleteNotesRequest, CreateNoteRequest, DeleteNoteRequest,
+    GetAllNotesRequest, GetNoteRequest, ListNotesRequest, UpdateNoteRequest, WatchNotesRequest,
+    batch_create_note_result, note_service_client::NoteServiceClient,
 };
 
-use serde_json::to_string_pretty;
+/// Attaches the profile's token to every request
+#[derive(Clone)]
+struct BearerToken(Option<MetadataValue<tonic::metadata::Ascii>>);
+
+impl Interceptor for BearerToken {
+    fn call(&mut self, mut request: Request<()>) -> Result<Request<()>, Status> {
+        if let Some(token) = &self.0 {
+            request
+                .metadata_mut()
+                .insert("authorization", token.clone());
+        }
+        Ok(request)
+    }
+}
+
+async fn connect(profile: &Profile, addr: String) -> Result<Channel, Box<dyn std::error::Error>> {
+    let mut endpoint = Channel::from_shared(addr)?;
+
+    if let Some(tls) = &profile.tls {
+        let mut config = ClientTlsConfig::new().with_native_roots();
+        if let Some(ca_cert) = &tls.ca_cert {
+            config = config.ca_certificate(Certificate::from_pem(std::fs::read(ca_cert)?));
+        }
+        if let (Some(cert), Some(key)) = (&tls.client_cert, &tls.client_key) {
+            config = config.identity(Identity::from_pem(
+                std::fs::read(cert)?,
+                std::fs::read(key)?,
+            ));
+        }
+        if let Some(domain) = &tls.domain {
+            config = config.domain_name(domain);
+        }
+        endpoint = endpoint.tls_config(config)?;
+    }
+
+    Ok(endpoint.connect().await?)
+}
+
+fn render<T: Serialize>(value: &T, format: OutputFormat) -> serde_json::Result<String> {
+    match format {
+        OutputFormat::Pretty => serde_json::to_string_pretty(value),
+        OutputFormat::Json => serde_json::to_string(value),
+    }
+}
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    // Connect to the gRPC server
-    let addr =
-        std::env::var("GRPC_SERVER_ADDR").unwrap_or_else(|_| "http://127.0.0.1:50051".to_string());
+    let profile = profile::load()?;
+    let output = profile.output;
+
+    let token = profile
+        .token
+        .as_ref()
+        .map(|token| format!("Bearer {}", token).parse())
+        .transpose()?;
 
-    let mut client = NoteServiceClient::connect(addr.clone()).await?;
+    // Connect to the gRPC server
+    let addr = profile.address();
+    let channel = connect(&profile, addr.clone()).await?;
+    let mut client = NoteServiceClient::with_interceptor(channel, BearerToken(token));
     println!("Connected to gRPC server at address {}\n", addr);
 
+    // Watch changes made below, the events are printed at the end
+    let mut events = client
+        .watch_notes(Request::new(WatchNotesRequest {}))
+        .await?
+        .into_inner();
+
     // Create note
     println!("1. Creating a note...");
     let create_request = CreateNoteRequest {
         content: "Test string gRPC".to_string(),
+        expires_at: None,
+        remind_at: None,
     };
     let create_response = client.create_note(Request::new(create_request)).await?;
     let created_note = create_response.into_inner();
-    println!("Created note: {}\n", to_string_pretty(&created_note)?);
+    println!("Created note: {}\n", render(&created_note, output)?);
     let note_id = created_note.id;
 
     // Get one note
@@ -36,31 +101,135 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let get_request = GetNoteRequest { id: note_id };
     let get_response = client.get_note(Request::new(get_request)).await?;
     let note = get_response.into_inner();
-    println!("Note: {}\n", to_string_pretty(&note)?);
+    println!("Note: {}\n", render(&note, output)?);
 
     // Update note
     println!("3. Updating the note...");
     let update_request = UpdateNoteRequest {
         id: note_id,
         content: "Test string gRPC 2".to_string(),
+        expires_at: None,
+        remind_at: None,
+        clear_expires_at: false,
+        clear_remind_at: false,
     };
     let update_response = client.update_note(Request::new(update_request)).await?;
     let updated_note = update_response.into_inner();
-    println!("Updated note: {}\n", to_string_pretty(&updated_note)?);
+    println!("Updated note: {}\n", render(&updated_note, output)?);
 
-    // Get all notes
-    println!("4. Getting all notes...");
-    let get_all_request = GetAllNotesRequest {};
+    // Get the first page of notes
+    println!("4. Getting the first page of notes...");
+    let get_all_request = GetAllNotesRequest {
+        page_size: 10,
+        page_token: String::new(),
+    };
     let get_all_response = client.get_all_notes(Request::new(get_all_request)).await?;
-    let all_notes = get_all_response.into_inner();
-    println!("Notes: {}\n", to_string_pretty(&all_notes)?);
+    let notes_page = get_all_response.into_inner();
+    println!("Notes: {}\n", render(&notes_page, output)?);
+
+    // Stream notes
+    println!("5. Streaming all notes...");
+    let mut stream = client
+        .list_notes(Request::new(ListNotesRequest {}))
+        .await?
+        .into_inner();
+    let mut streamed = 0;
+    while let Some(note) = stream.message().await? {
+        println!("Streamed note: {}", render(&note, output)?);
+        streamed += 1;
+    }
+    println!("Streamed {} notes\n", streamed);
 
     // Delete note
-    println!("5. Deleting the note...");
+    println!("6. Deleting the note...");
     let delete_request = DeleteNoteRequest { id: note_id };
     let delete_response = client.delete_note(Request::new(delete_request)).await?;
     let delete_result = delete_response.into_inner();
     println!("Delete result: {}\n", delete_result.success);
 
+    // Batch create notes
+    println!("7. Creating notes in a batch...");
+    let batch_request = BatchCreateNotesRequest {
+        requests: ["Batch note gRPC 1", "Batch note gRPC 2"]
+            .into_iter()
+            .map(|content| CreateNoteRequest {
+                content: content.to_string(),
+                expires_at: None,
+                remind_at: None,
+            })
+            .collect(),
+    };
+    let batch_response = client
+        .batch_create_notes(Request::new(batch_request))
+        .await?;
+    let batch_created = batch_response.into_inner();
+    println!(
+        "Batch create results: {}\n",
+        render(&batch_created, output)?
+    );
+    let batch_ids: Vec<i64> = batch_created
+        .results
+        .into_iter()
+        .filter_map(|result| match result.result {
+            Some(batch_create_note_result::Result::Note(note)) => Some(note.id),
+            _ => None,
+        })
+        .collect();
+
+    // Batch delete notes, the already deleted note is reported as not found
+    println!("8. Deleting notes in a batch...");
+    let batch_request = BatchDeleteNotesRequest {
+        ids: batch_ids.into_iter().chain([note_id]).collect(),
+    };
+    let batch_response = client
+        .batch_delete_notes(Request::new(batch_request))
+        .await?;
+    let batch_deleted = batch_response.into_inner();
+    println!(
+        "Batch delete results: {}\n",
+        render(&batch_deleted, output)?
+    );
+
+    // Import notes, they expire in a minute. The one with an invalid time is counted as failed
+    println!("9. Importing notes...");
+    let expires_at = Timestamp {
+        seconds: i64::try_from(SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs())? + 60,
+        nanos: 0,
+    };
+    let invalid = Timestamp {
+        seconds: 0,
+        nanos: -1,
+    };
+    let imported: Vec<CreateNoteRequest> = [
+        ("Imported note gRPC 1", expires_at),
+        ("Imported note gRPC 2", expires_at),
+        ("Imported note gRPC 3", expires_at),
+        ("Imported note gRPC with an invalid time", invalid),
+    ]
+    .into_iter()
+    .map(|(content, expires_at)| CreateNoteRequest {
+        content: content.to_string(),
+        expires_at: Some(expires_at),
+        remind_at: None,
+    })
+    .collect();
+    let import_response = client
+        .import_notes(Request::new(tokio_stream::iter(imported)))
+        .await?;
+    let summary = import_response.into_inner();
+    println!("Import summary: {}\n", render(&summary, output)?);
+
+    // Watch changes
+    println!("10. Changes seen while running...");
+    // Created, updated and deleted, then two created and deleted in batches
+    // and three imported, other clients may add more
+    for _ in 0..10 {
+        match tokio::time::timeout(Duration::from_secs(1), events.message()).await {
+            Ok(Ok(Some(event))) => println!("Event: {}", render(&event, output)?),
+            Ok(Err(status)) => return Err(status.into()),
+            Ok(Ok(None)) | Err(_) => break,
+        }
+    }
+
     Ok(())
 }
@@ -0,0 +1,106 @@
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::{env, fs};
+
+/// Address used when neither the profile nor `GRPC_SERVER_ADDR` sets one
+const DEFAULT_ADDR: &str = "http://127.0.0.1:50051";
+/// Name of the config file in the home directory
+const CONFIG_FILE_NAME: &str = ".notes-client.toml";
+
+/// How responses are printed
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputFormat {
+    #[default]
+    Pretty,
+    Json,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct TlsSettings {
+    /// CA used to verify the server certificate, system roots if omitted
+    pub ca_cert: Option<PathBuf>,
+    /// Client certificate and key, for servers requiring mutual TLS
+    pub client_cert: Option<PathBuf>,
+    pub client_key: Option<PathBuf>,
+    /// Name to verify the server certificate against, the address host if omitted
+    pub domain: Option<String>,
+}
+
+/// Connection details of a single target environment
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct Profile {
+    pub address: Option<String>,
+    pub tls: Option<TlsSettings>,
+    /// Sent as `authorization: Bearer <token>` with every request
+    pub token: Option<String>,
+    #[serde(default)]
+    pub output: OutputFormat,
+}
+
+impl Profile {
+    pub fn address(&self) -> String {
+        self.address
+            .clone()
+            .or_else(|| env::var("GRPC_SERVER_ADDR").ok())
+            .unwrap_or_else(|| DEFAULT_ADDR.to_string())
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct ConfigFile {
+    /// Profile used when `--profile` is not given
+    default_profile: Option<String>,
+    #[serde(default)]
+    profiles: HashMap<String, Profile>,
+}
+
+/// `NOTES_CLIENT_CONFIG`, or `~/.notes-client.toml`
+fn config_path() -> Option<PathBuf> {
+    env::var_os("NOTES_CLIENT_CONFIG")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(CONFIG_FILE_NAME)))
+}
+
+/// Value of the `--profile <name>` (or `--profile=<name>`) flag
+fn profile_flag() -> Result<Option<String>, Box<dyn std::error::Error>> {
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--profile" {
+            return Ok(Some(
+                args.next().ok_or("--profile requires a profile name")?,
+            ));
+        }
+        if let Some(name) = arg.strip_prefix("--profile=") {
+            return Ok(Some(name.to_string()));
+        }
+    }
+    Ok(None)
+}
+
+/// Resolves the profile to use: the one named by `--profile`, otherwise the config's
+/// `default_profile`. Without a config file, or a profile to select, connection
+/// details come from the environment as before
+pub fn load() -> Result<Profile, Box<dyn std::error::Error>> {
+    let requested = profile_flag()?;
+
+    let config = match config_path() {
+        Some(path) if path.exists() => {
+            let contents = fs::read_to_string(&path)?;
+            toml::from_str(&contents)
+                .map_err(|e| format!("Failed to parse {}: {}", path.display(), e))?
+        }
+        _ => ConfigFile::default(),
+    };
+
+    let Some(name) = requested.or(config.default_profile) else {
+        return Ok(Profile::default());
+    };
+
+    config
+        .profiles
+        .get(&name)
+        .cloned()
+        .ok_or_else(|| format!("Profile '{}' not found in the config file", name).into())
+}
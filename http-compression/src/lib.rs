@@ -0,0 +1,14 @@
+use tower_http::compression::{
+    CompressionLayer, DefaultPredicate, Predicate, predicate::NotForContentType,
+};
+
+/// gzip or brotli, as negotiated by `Accept-Encoding`. Responses that are already encoded
+/// are passed as is, as are tiny bodies, images, gRPC, event streams and compressed archives
+pub fn layer() -> CompressionLayer<impl Predicate> {
+    let predicate = DefaultPredicate::new()
+        .and(NotForContentType::const_new("application/gzip"))
+        .and(NotForContentType::const_new("application/zip"))
+        .and(NotForContentType::const_new("application/zstd"));
+
+    CompressionLayer::new().compress_when(predicate)
+}
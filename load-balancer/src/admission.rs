@@ -0,0 +1,100 @@
+use crate::protocol::Protocol;
+use axum::http::StatusCode;
+use axum::response::Response;
+use std::fmt::Write;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Why the balancer answered a request itself instead of forwarding it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RejectionReason {
+    NoAliveServers,
+    BodyTooLarge,
+}
+
+impl RejectionReason {
+    const ALL: [RejectionReason; 2] = [Self::NoAliveServers, Self::BodyTooLarge];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::NoAliveServers => "no_alive_servers",
+            Self::BodyTooLarge => "body_too_large",
+        }
+    }
+
+    fn status(self) -> StatusCode {
+        match self {
+            Self::NoAliveServers => StatusCode::SERVICE_UNAVAILABLE,
+            Self::BodyTooLarge => StatusCode::PAYLOAD_TOO_LARGE,
+        }
+    }
+
+    fn message(self) -> &'static str {
+        match self {
+            Self::NoAliveServers => "Service unavailable (no alive servers)",
+            Self::BodyTooLarge => "Request body too large",
+        }
+    }
+}
+
+/// Error of forwarding a request: either the balancer rejected it itself,
+/// or the upstreams failed with the given status
+#[derive(Debug, Clone, Copy)]
+pub enum ForwardError {
+    Rejected(RejectionReason),
+    Failed(StatusCode),
+}
+
+impl From<StatusCode> for ForwardError {
+    fn from(status: StatusCode) -> Self {
+        Self::Failed(status)
+    }
+}
+
+/// Counts rejected requests per reason, exposed on `/metrics`
+#[derive(Debug, Default)]
+pub struct Admission {
+    rejections: [AtomicU64; RejectionReason::ALL.len()],
+}
+
+impl Admission {
+    /// Records the rejection and builds the response sent to the client
+    pub fn reject(
+        &self,
+        reason: RejectionReason,
+        protocol: Protocol,
+        client: SocketAddr,
+        route: &str,
+    ) -> Response {
+        self.rejections[reason as usize].fetch_add(1, Ordering::Relaxed);
+
+        let status = reason.status();
+        tracing::warn!(
+            reason = reason.as_str(),
+            status = status.as_u16(),
+            %client,
+            route,
+            "Rejected request"
+        );
+
+        protocol.error_response(status, reason.message())
+    }
+
+    /// Counters in the Prometheus text exposition format
+    pub fn render_metrics(&self) -> String {
+        let mut out = String::from(
+            "# HELP lb_rejected_requests_total Requests rejected by the balancer itself, by reason\n\
+             # TYPE lb_rejected_requests_total counter\n",
+        );
+        for reason in RejectionReason::ALL {
+            let count = self.rejections[reason as usize].load(Ordering::Relaxed);
+            let _ = writeln!(
+                out,
+                "lb_rejected_requests_total{{reason=\"{}\"}} {}",
+                reason.as_str(),
+                count
+            );
+        }
+        out
+    }
+}
@@ -1,25 +1,61 @@
+use crate::admission::{Admission, ForwardError, RejectionReason};
 use crate::config::Config;
 use crate::instance::Instance;
+use crate::state::{self, BalancerState};
 use crate::strategy::{self, InstanceSnapshot};
+use axum::body::{Body, Bytes};
 use axum::extract::Request;
-use axum::http::StatusCode;
+use axum::http::{HeaderName, StatusCode, header};
 use axum::response::Response;
+use http_body_util::LengthLimitError;
+use pki::ClientTls;
+use std::error::Error;
 use std::sync::Arc;
 use std::sync::atomic::Ordering;
 use std::time::Duration;
 use tokio::sync::{Mutex, RwLock};
 
+/// gRPC status the call is retried on another server for: UNAVAILABLE, the instance
+/// didn't take the call
+const GRPC_UNAVAILABLE: &str = "14";
+/// gRPC status that counts against the instance but isn't retried: DEADLINE_EXCEEDED,
+/// the instance may have made the change before running out of time
+const GRPC_DEADLINE_EXCEEDED: &str = "4";
+
+/// gRPC errors arrive as HTTP 200 with a `grpc-status` header (trailers-only response)
+fn grpc_status(response: &Response) -> Option<&str> {
+    response
+        .headers()
+        .get("grpc-status")
+        .and_then(|v| v.to_str().ok())
+}
+
+/// Headers describing the upstream connection rather than the response. The body is
+/// buffered and re-sent, so e.g. a copied `transfer-encoding: chunked` would be wrong
+const HOP_BY_HOP_HEADERS: [HeaderName; 5] = [
+    header::CONNECTION,
+    header::TRANSFER_ENCODING,
+    header::TE,
+    header::TRAILER,
+    header::UPGRADE,
+];
+
 #[derive(Clone)]
 pub struct LoadBalancer {
     instances: Arc<RwLock<Vec<Instance>>>,
     health_check_interval: Duration,
     con_timeout: Duration,
     max_retries: Option<u32>,
+    outlier_consecutive_failures: Option<u32>,
     strategy: Arc<Mutex<Box<dyn strategy::BalancingStrategy>>>,
+    state_file: Option<String>,
+    max_body_size: Option<usize>,
+    tls: ClientTls,
+    admission: Arc<Admission>,
 }
 
 impl LoadBalancer {
-    pub fn new(instances: Arc<RwLock<Vec<Instance>>>, cfg: &Config) -> Self {
+    pub fn new(instances: Arc<RwLock<Vec<Instance>>>, cfg: &Config, tls: ClientTls) -> Self {
         let strategy: Box<dyn strategy::BalancingStrategy> = match cfg.strategy.as_str() {
             "round_robin" => Box::new(strategy::RoundRobin::new()),
             "least_connections" => Box::new(strategy::LeastConnections::new()),
@@ -30,18 +66,70 @@ impl LoadBalancer {
             health_check_interval: cfg.health_check_interval,
             con_timeout: cfg.connection_timeout,
             max_retries: cfg.max_retries,
+            outlier_consecutive_failures: cfg.outlier_consecutive_failures,
             strategy: Arc::new(Mutex::new(strategy)),
+            state_file: cfg.state_file.clone(),
+            max_body_size: cfg.max_body_size,
+            tls,
+            admission: Arc::new(Admission::default()),
+        }
+    }
+
+    pub fn admission(&self) -> &Admission {
+        &self.admission
+    }
+
+    /// Buffers the request body, rejecting it if it exceeds `max_body_size`
+    async fn read_body(&self, body: Body) -> Result<Bytes, ForwardError> {
+        axum::body::to_bytes(body, self.max_body_size.unwrap_or(usize::MAX))
+            .await
+            .map_err(|e| {
+                if e.source().is_some_and(|s| s.is::<LengthLimitError>()) {
+                    ForwardError::Rejected(RejectionReason::BodyTooLarge)
+                } else {
+                    ForwardError::Failed(StatusCode::BAD_REQUEST)
+                }
+            })
+    }
+
+    /// Restores instance state saved by a previous run, if persistence is enabled
+    pub async fn restore_state(&self) {
+        let Some(path) = &self.state_file else {
+            return;
+        };
+        let Some(saved) = state::load(path) else {
+            return;
+        };
+
+        let mut instances = self.instances.write().await;
+        for instance in instances.iter_mut() {
+            if let Some(instance_state) = saved.find(&instance.get_rest_url()) {
+                instance.restore(instance_state);
+            }
         }
+        tracing::info!("Restored balancer state from {}", path);
     }
 
     pub async fn health_check_all(&self) {
         let mut interval = tokio::time::interval(self.health_check_interval);
+        let mut last_saved: Option<BalancerState> = None;
         loop {
             interval.tick().await;
             let mut instances = self.instances.write().await;
             for instance in instances.iter_mut() {
                 instance.health_check().await;
             }
+            let current = BalancerState {
+                instances: instances.iter().map(Instance::state).collect(),
+            };
+            drop(instances);
+
+            if let Some(path) = &self.state_file
+                && last_saved.as_ref() != Some(&current)
+            {
+                state::save(path, &current).await;
+                last_saved = Some(current);
+            }
         }
     }
 
@@ -52,6 +140,28 @@ impl LoadBalancer {
         (alive_count, total_count)
     }
 
+    /// Counts the failed gRPC calls of the instance in a row. After
+    /// `outlier_consecutive_failures` of them it is ejected until its next successful
+    /// health check
+    async fn record_grpc_call(&self, instance_idx: usize, failed: bool) {
+        let Some(limit) = self.outlier_consecutive_failures else {
+            return;
+        };
+
+        let instances = self.instances.read().await;
+        let failed_calls = &instances[instance_idx].failed_calls;
+        if !failed {
+            failed_calls.store(0, Ordering::Relaxed);
+            return;
+        }
+        let failures = failed_calls.fetch_add(1, Ordering::Relaxed) + 1;
+        drop(instances);
+
+        if failures >= limit {
+            self.instances.write().await[instance_idx].eject(failures);
+        }
+    }
+
     async fn try_forward_to_instance(
         &self,
         instance_idx: usize,
@@ -67,9 +177,9 @@ impl LoadBalancer {
             .fetch_add(1, Ordering::Relaxed);
         drop(instances);
 
-        let client = reqwest::Client::builder()
-            .timeout(self.con_timeout)
-            .danger_accept_invalid_certs(true)
+        let client = self
+            .tls
+            .apply(reqwest::Client::builder().timeout(self.con_timeout))
             .build()
             .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
@@ -100,7 +210,10 @@ impl LoadBalancer {
                     );
                 }
 
-                let headers = response.headers().clone();
+                let mut headers = response.headers().clone();
+                for name in &HOP_BY_HOP_HEADERS {
+                    headers.remove(name);
+                }
                 let body_bytes = response
                     .bytes()
                     .await
@@ -119,11 +232,9 @@ impl LoadBalancer {
         }
     }
 
-    pub async fn forward_request(&self, request: Request) -> Result<Response, StatusCode> {
+    pub async fn forward_request(&self, request: Request) -> Result<Response, ForwardError> {
         let (parts, body) = request.into_parts();
-        let body_bytes = axum::body::to_bytes(body, usize::MAX)
-            .await
-            .map_err(|_| StatusCode::BAD_REQUEST)?;
+        let body_bytes = self.read_body(body).await?;
         let method = parts.method.clone();
         let path_and_query = parts.uri.path_and_query().map(|s| s.as_str()).unwrap_or("");
         let headers = parts.headers;
@@ -149,7 +260,7 @@ impl LoadBalancer {
         drop(instances);
 
         if alive_snapshots.is_empty() {
-            return Err(StatusCode::SERVICE_UNAVAILABLE);
+            return Err(ForwardError::Rejected(RejectionReason::NoAliveServers));
         }
 
         let max_retries = self
@@ -212,14 +323,14 @@ impl LoadBalancer {
                         );
                         alive_snapshots.remove(selected_idx_in_snapshot);
                     } else {
-                        return Err(e);
+                        return Err(e.into());
                     }
                 }
-                Err(e) => return Err(e),
+                Err(e) => return Err(e.into()),
             }
         }
 
-        Err(StatusCode::SERVICE_UNAVAILABLE)
+        Err(ForwardError::Rejected(RejectionReason::NoAliveServers))
     }
 
     async fn try_forward_grpc_to_instance(
@@ -237,10 +348,13 @@ impl LoadBalancer {
             .fetch_add(1, Ordering::Relaxed);
         drop(instances);
 
-        let client = reqwest::Client::builder()
-            .http2_prior_knowledge()
-            .timeout(self.con_timeout)
-            .danger_accept_invalid_certs(true)
+        let client = self
+            .tls
+            .apply(
+                reqwest::Client::builder()
+                    .http2_prior_knowledge()
+                    .timeout(self.con_timeout),
+            )
             .build()
             .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
@@ -293,11 +407,9 @@ impl LoadBalancer {
     pub async fn forward_grpc_request(
         &self,
         request: axum::extract::Request,
-    ) -> Result<axum::response::Response, StatusCode> {
+    ) -> Result<axum::response::Response, ForwardError> {
         let (parts, body) = request.into_parts();
-        let body_bytes = axum::body::to_bytes(body, usize::MAX)
-            .await
-            .map_err(|_| StatusCode::BAD_REQUEST)?;
+        let body_bytes = self.read_body(body).await?;
         let method = parts.method.clone();
         let path_and_query = parts.uri.path_and_query().map(|s| s.as_str()).unwrap_or("");
         let headers = parts.headers;
@@ -323,7 +435,7 @@ impl LoadBalancer {
         drop(instances);
 
         if alive_snapshots.is_empty() {
-            return Err(StatusCode::SERVICE_UNAVAILABLE);
+            return Err(ForwardError::Rejected(RejectionReason::NoAliveServers));
         }
 
         let max_retries = self
@@ -376,8 +488,25 @@ impl LoadBalancer {
                 )
                 .await
             {
-                Ok(response) => return Ok(response),
+                Ok(response) => {
+                    let grpc_status = grpc_status(&response);
+                    let failed = grpc_status
+                        .is_some_and(|s| s == GRPC_UNAVAILABLE || s == GRPC_DEADLINE_EXCEEDED);
+                    self.record_grpc_call(actual_idx, failed).await;
+
+                    if grpc_status == Some(GRPC_UNAVAILABLE) && attempt < max_retries {
+                        tracing::warn!(
+                            "gRPC request to {} failed with grpc-status {}, trying next server",
+                            grpc_url,
+                            GRPC_UNAVAILABLE
+                        );
+                        alive_snapshots.remove(selected_idx_in_snapshot);
+                        continue;
+                    }
+                    return Ok(response);
+                }
                 Err(e) if e.is_server_error() => {
+                    self.record_grpc_call(actual_idx, true).await;
                     if attempt < max_retries {
                         tracing::warn!(
                             "gRPC request to {} failed: {:?}, trying next server",
@@ -386,15 +515,15 @@ impl LoadBalancer {
                         );
                         alive_snapshots.remove(selected_idx_in_snapshot);
                     } else {
-                        return Err(e);
+                        return Err(e.into());
                     }
                 }
                 Err(e) => {
-                    return Err(e);
+                    return Err(e.into());
                 }
             }
         }
 
-        Err(StatusCode::SERVICE_UNAVAILABLE)
+        Err(ForwardError::Rejected(RejectionReason::NoAliveServers))
     }
 }
@@ -2,6 +2,10 @@ use std::time::Duration;
 
 use serde::Deserialize;
 
+fn default_health_check_path() -> String {
+    "/readyz".to_string()
+}
+
 #[derive(Debug, Deserialize, Clone)]
 pub struct InstanceConfig {
     pub base_url: String,
@@ -19,8 +23,16 @@ pub struct Config {
     pub health_check_interval: Duration,
     #[serde(with = "humantime_serde")]
     pub health_check_time_limit: Duration,
+    #[serde(default = "default_health_check_path")]
+    pub health_check_path: String, // Must answer 2xx while the server can take requests
     #[serde(with = "humantime_serde")]
     pub connection_timeout: Duration,
     #[serde(default)]
     pub max_retries: Option<u32>, // None means try all alive servers
+    #[serde(default)]
+    pub outlier_consecutive_failures: Option<u32>, // None disables ejecting servers
+    #[serde(default)]
+    pub state_file: Option<String>, // None disables state persistence
+    #[serde(default)]
+    pub max_body_size: Option<usize>, // In bytes, None means unlimited
 }
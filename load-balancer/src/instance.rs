@@ -1,6 +1,8 @@
 use crate::config::Config;
+use crate::state::InstanceState;
+use pki::ClientTls;
 use reqwest::Client;
-use std::sync::atomic::AtomicU32;
+use std::sync::atomic::{AtomicU32, Ordering};
 use std::time::{Duration, Instant};
 
 #[derive(Debug)]
@@ -10,21 +12,32 @@ pub struct Instance {
     grpc_port: u16,
     con_timeout: Duration,
     health_check_time_limit: Duration,
+    health_check_path: String,
+    tls: ClientTls,
 
     pub con_count: AtomicU32,
+    /// Failed gRPC calls in a row, see `LoadBalancer::record_grpc_call`
+    pub failed_calls: AtomicU32,
     is_alive: bool,
     last_healthy: Option<Instant>,
 }
 
 impl Instance {
-    pub fn new(instance_config: &crate::config::InstanceConfig, cfg: &Config) -> Self {
+    pub fn new(
+        instance_config: &crate::config::InstanceConfig,
+        cfg: &Config,
+        tls: &ClientTls,
+    ) -> Self {
         Self {
             base_url: instance_config.base_url.clone(),
             rest_port: instance_config.rest_port,
             grpc_port: instance_config.grpc_port,
             con_timeout: cfg.connection_timeout,
             health_check_time_limit: cfg.health_check_time_limit,
+            health_check_path: cfg.health_check_path.clone(),
+            tls: tls.clone(),
             con_count: AtomicU32::default(),
+            failed_calls: AtomicU32::default(),
             is_alive: true,
             last_healthy: None,
         }
@@ -50,14 +63,14 @@ impl Instance {
     }
 
     pub async fn health_check(&mut self) {
-        let client = Client::builder()
-            .timeout(self.con_timeout)
-            .danger_accept_invalid_certs(true)
+        let client = self
+            .tls
+            .apply(Client::builder().timeout(self.con_timeout))
             .build()
             .expect("failed to initialize a client");
 
         let rest_url = self.get_rest_url();
-        let health_url = format!("{}/", rest_url);
+        let health_url = format!("{}{}", rest_url, self.health_check_path);
         match client.get(&health_url).send().await {
             Ok(response) => {
                 if !response.status().is_success() {
@@ -68,13 +81,47 @@ impl Instance {
                     tracing::info!("Restored connection to server {}", rest_url);
                 }
                 self.is_alive = true;
+                self.failed_calls.store(0, Ordering::Relaxed);
                 self.last_healthy = Some(Instant::now())
             }
             Err(_) => self._handle_health_check_error(),
         }
     }
 
+    /// Takes the instance out of rotation after `failures` failed calls in a row, until
+    /// its next successful health check
+    pub fn eject(&mut self, failures: u32) {
+        if self.is_alive {
+            tracing::warn!(
+                "Ejecting server {} after {} failed gRPC calls in a row",
+                self.get_rest_url(),
+                failures
+            );
+        }
+        self.is_alive = false;
+        self.failed_calls.store(0, Ordering::Relaxed);
+    }
+
     pub fn is_alive(&self) -> bool {
         self.is_alive
     }
+
+    pub fn state(&self) -> InstanceState {
+        InstanceState {
+            url: self.get_rest_url(),
+            is_alive: self.is_alive,
+        }
+    }
+
+    /// Applies state saved by a previous run. An instance restored as dead stays out
+    /// of rotation until its first successful health check
+    pub fn restore(&mut self, state: &InstanceState) {
+        if !state.is_alive {
+            tracing::info!(
+                "Server {} was dead before restart, waiting for a successful health check",
+                self.get_rest_url()
+            );
+        }
+        self.is_alive = state.is_alive;
+    }
 }
@@ -1,19 +1,24 @@
+mod admission;
 mod balancer;
 mod config;
 mod instance;
+mod protocol;
+mod state;
 mod strategy;
 
+use admission::ForwardError;
 use axum::{
     Router,
-    extract::{Request, State},
+    extract::{ConnectInfo, Request, State},
     response::{IntoResponse, Response},
-    routing::any,
+    routing::{any, get},
 };
 use axum_macros::debug_handler;
 use axum_server::tls_rustls::RustlsConfig;
 use balancer::LoadBalancer;
 use config::Config;
 use instance::Instance;
+use protocol::Protocol;
 use std::fs;
 use std::net::SocketAddr;
 use std::sync::Arc;
@@ -21,21 +26,53 @@ use tokio::{net::TcpListener, sync::RwLock};
 use tower_http::trace::TraceLayer;
 
 #[debug_handler]
-async fn proxy_handler(State(balancer): State<LoadBalancer>, request: Request) -> Response {
+async fn proxy_handler(
+    State(balancer): State<LoadBalancer>,
+    ConnectInfo(client): ConnectInfo<SocketAddr>,
+    request: Request,
+) -> Response {
+    let route = request.uri().path().to_string();
+    let protocol = Protocol::detect(&request);
     match balancer.forward_request(request).await {
         Ok(response) => response,
-        Err(status) => (status, "Service unavailable (no alive servers)").into_response(),
+        Err(ForwardError::Rejected(reason)) => balancer
+            .admission()
+            .reject(reason, protocol, client, &route),
+        Err(ForwardError::Failed(status)) => {
+            protocol.error_response(status, "Service unavailable (no alive servers)")
+        }
     }
 }
 
 #[debug_handler]
-async fn grpc_proxy_handler(State(balancer): State<LoadBalancer>, request: Request) -> Response {
+async fn grpc_proxy_handler(
+    State(balancer): State<LoadBalancer>,
+    ConnectInfo(client): ConnectInfo<SocketAddr>,
+    request: Request,
+) -> Response {
+    let route = request.uri().path().to_string();
     match balancer.forward_grpc_request(request).await {
         Ok(response) => response,
-        Err(status) => (status, "Service unavailable (no alive servers)").into_response(),
+        Err(ForwardError::Rejected(reason)) => {
+            balancer
+                .admission()
+                .reject(reason, Protocol::Http, client, &route)
+        }
+        Err(ForwardError::Failed(status)) => {
+            (status, "Service unavailable (no alive servers)").into_response()
+        }
     }
 }
 
+#[debug_handler]
+async fn metrics(State(balancer): State<LoadBalancer>) -> Response {
+    (
+        [("Content-Type", "text/plain; version=0.0.4")],
+        balancer.admission().render_metrics(),
+    )
+        .into_response()
+}
+
 fn load_config(path: &str) -> Result<Config, Box<dyn std::error::Error>> {
     let contents = fs::read_to_string(path)?;
     let config: Config = serde_yaml::from_str(&contents)?;
@@ -49,15 +86,21 @@ async fn main() {
     let cfg = load_config("config.yaml").expect("failed to locate or load config file");
     tracing::info!("Successfully loaded balancer config");
 
+    let upstream_tls = pki::ClientTls::from_env().expect("failed to load upstream TLS settings");
+    if upstream_tls.is_mutual() {
+        tracing::info!("Presenting a client certificate to upstreams (mutual TLS)");
+    }
+
     let mut instances_vec: Vec<Instance> = Vec::new();
 
     tracing::info!("Configured upstreams: {:?}", cfg.instances);
 
     for instance_config in cfg.instances.iter() {
-        instances_vec.push(Instance::new(instance_config, &cfg));
+        instances_vec.push(Instance::new(instance_config, &cfg, &upstream_tls));
     }
 
-    let balancer = LoadBalancer::new(Arc::new(RwLock::new(instances_vec)), &cfg);
+    let balancer = LoadBalancer::new(Arc::new(RwLock::new(instances_vec)), &cfg, upstream_tls);
+    balancer.restore_state().await;
 
     {
         let balancer = balancer.clone();
@@ -68,8 +111,10 @@ async fn main() {
 
     let router = Router::new()
         .route("/", any(root))
+        .route("/metrics", get(metrics))
         .route("/{*path}", any(proxy_handler))
         .with_state(balancer.clone())
+        .layer(http_compression::layer())
         .layer(TraceLayer::new_for_http());
 
     let grpc_router = Router::new()
@@ -98,9 +143,9 @@ async fn main() {
             cert_path,
             key_path
         );
-        let tls_config = RustlsConfig::from_pem_file(&cert_path, &key_path)
-            .await
-            .expect("Failed to load TLS certificates");
+        let tls_config =
+            pki::server_config_from_env(&cert_path, &key_path).expect("Failed to load TLS config");
+        let tls_config = RustlsConfig::from_config(Arc::new(tls_config));
 
         tracing::info!("HTTPS Load balancer listening on {}", rest_addr);
         tracing::info!("HTTPS gRPC Load balancer listening on {}", grpc_addr);
@@ -108,14 +153,14 @@ async fn main() {
         // Run both HTTPS servers concurrently
         tokio::select! {
             result = axum_server::bind_rustls(rest_addr, tls_config.clone())
-                .serve(router.into_make_service()) => {
+                .serve(router.into_make_service_with_connect_info::<SocketAddr>()) => {
                 if let Err(e) = result {
                     tracing::error!("HTTPS server error: {e}");
                     panic!("failed to start HTTPS server: {e}");
                 }
             }
             result = axum_server::bind_rustls(grpc_addr, tls_config)
-                .serve(grpc_router.into_make_service()) => {
+                .serve(grpc_router.into_make_service_with_connect_info::<SocketAddr>()) => {
                 if let Err(e) = result {
                     tracing::error!("HTTPS gRPC server error: {e}");
                     panic!("failed to start HTTPS gRPC server: {e}");
@@ -139,13 +184,13 @@ async fn main() {
 
         // Run both HTTP servers concurrently
         tokio::select! {
-            result = axum::serve(listener, router) => {
+            result = axum::serve(listener, router.into_make_service_with_connect_info::<SocketAddr>()) => {
                 if let Err(e) = result {
                     tracing::error!("HTTP server error: {e}");
                     panic!("failed to start HTTP server: {e}");
                 }
             }
-            result = axum::serve(grpc_listener, grpc_router) => {
+            result = axum::serve(grpc_listener, grpc_router.into_make_service_with_connect_info::<SocketAddr>()) => {
                 if let Err(e) = result {
                     tracing::error!("gRPC server error: {e}");
                     panic!("failed to start gRPC server: {e}");
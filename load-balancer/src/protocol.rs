@@ -0,0 +1,63 @@
+use axum::extract::Request;
+use axum::http::{StatusCode, header};
+use axum::response::{IntoResponse, Response};
+use soap_envelope::{Fault, FaultCode, SoapVersion};
+
+/// Path prefix of the notes server SOAP endpoint
+const SOAP_PATH: &str = "/soap";
+
+/// Wire protocol of a proxied request, used to answer errors in a form its client can parse
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Protocol {
+    Http,
+    /// SOAP 1.1, `text/xml` envelopes
+    Soap11,
+    /// SOAP 1.2, `application/soap+xml` envelopes
+    Soap12,
+}
+
+impl Protocol {
+    /// SOAP if the request targets the SOAP endpoint or carries a SOAP content type or action.
+    /// The SOAP version follows the content type, as the body is not inspected
+    pub fn detect(request: &Request) -> Self {
+        let path = request.uri().path();
+        let content_type = request
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .unwrap_or_default();
+
+        if content_type.starts_with("application/soap+xml") {
+            Self::Soap12
+        } else if path == SOAP_PATH
+            || path.starts_with("/soap/")
+            || request.headers().contains_key("SOAPAction")
+        {
+            Self::Soap11
+        } else {
+            Self::Http
+        }
+    }
+
+    /// Error response generated by the balancer itself, SOAP faults are built the same way
+    /// as the ones of the notes server
+    pub fn error_response(self, status: StatusCode, message: &str) -> Response {
+        let version = match self {
+            Self::Http => return (status, message.to_string()).into_response(),
+            Self::Soap11 => SoapVersion::Soap11,
+            Self::Soap12 => SoapVersion::Soap12,
+        };
+        let code = if status.is_client_error() {
+            FaultCode::Client
+        } else {
+            FaultCode::Server
+        };
+
+        (
+            status,
+            [(header::CONTENT_TYPE, version.content_type())],
+            Fault::new(code, message).to_envelope(version),
+        )
+            .into_response()
+    }
+}
@@ -0,0 +1,67 @@
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// Learned state of a single upstream, keyed by its REST url
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct InstanceState {
+    pub url: String,
+    pub is_alive: bool,
+}
+
+/// Everything the balancer learned at runtime that should survive a restart
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct BalancerState {
+    pub instances: Vec<InstanceState>,
+}
+
+impl BalancerState {
+    pub fn find(&self, url: &str) -> Option<&InstanceState> {
+        self.instances.iter().find(|i| i.url == url)
+    }
+}
+
+/// Loads a previously saved state. A missing or unreadable file is not an error:
+/// the balancer simply starts from scratch
+pub fn load(path: &str) -> Option<BalancerState> {
+    if !Path::new(path).exists() {
+        tracing::info!("No saved balancer state at {}, starting fresh", path);
+        return None;
+    }
+
+    let contents = match std::fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(e) => {
+            tracing::warn!("Failed to read balancer state from {}: {}", path, e);
+            return None;
+        }
+    };
+
+    match serde_yaml::from_str(&contents) {
+        Ok(state) => Some(state),
+        Err(e) => {
+            tracing::warn!("Failed to parse balancer state from {}: {}", path, e);
+            None
+        }
+    }
+}
+
+/// Writes the state to a temporary file first and renames it over the old one,
+/// so a crash mid-write never leaves a truncated state file behind
+pub async fn save(path: &str, state: &BalancerState) {
+    let contents = match serde_yaml::to_string(state) {
+        Ok(contents) => contents,
+        Err(e) => {
+            tracing::warn!("Failed to serialize balancer state: {}", e);
+            return;
+        }
+    };
+
+    let tmp_path = format!("{}.tmp", path);
+    if let Err(e) = tokio::fs::write(&tmp_path, contents).await {
+        tracing::warn!("Failed to write balancer state to {}: {}", tmp_path, e);
+        return;
+    }
+    if let Err(e) = tokio::fs::rename(&tmp_path, path).await {
+        tracing::warn!("Failed to move balancer state into {}: {}", path, e);
+    }
+}
@@ -0,0 +1,13 @@
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    // Tell Cargo that if the given file changes, to rerun this build script.
+    println!("cargo:rerun-if-changed=../proto/notes/v1/notes.proto");
+
+    tonic_build::configure()
+        .build_server(true)
+        .build_client(true)
+        // Generate well-known types locally so they get the serde derives as well
+        .compile_well_known_types(true)
+        .type_attribute(".", "#[derive(serde::Serialize, serde::Deserialize)]")
+        .compile_protos(&["../proto/notes/v1/notes.proto"], &["../proto"])?;
+    Ok(())
+}
@@ -0,0 +1,13 @@
+// The generated code refers to the well-known types as `super::super::google::protobuf`,
+// so the modules mirror the proto packages
+pub mod google {
+    pub mod protobuf {
+        tonic::include_proto!("google.protobuf");
+    }
+}
+
+pub mod notes {
+    pub mod v1 {
+        tonic::include_proto!("notes.v1");
+    }
+}
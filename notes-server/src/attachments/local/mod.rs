@@ -0,0 +1,96 @@
+use async_trait::async_trait;
+use futures_util::{StreamExt, TryStreamExt};
+use tokio::{fs, io::AsyncWriteExt};
+use tokio_util::io::ReaderStream;
+
+use std::{
+    io,
+    path::{Path, PathBuf},
+};
+
+use super::{AttachmentStore, ByteStream, StoreError, StoredObject};
+
+/// Suffix of files still being uploaded, renamed once complete
+const PARTIAL_SUFFIX: &str = ".partial";
+
+/// Attachments as files in a local directory
+pub struct LocalAttachmentStore {
+    dir: PathBuf,
+}
+
+impl LocalAttachmentStore {
+    /// Attachments in `dir`, which is created if missing
+    pub fn new(dir: impl AsRef<Path>) -> io::Result<Self> {
+        std::fs::create_dir_all(&dir)?;
+        Ok(Self {
+            dir: dir.as_ref().to_path_buf(),
+        })
+    }
+
+    async fn write(path: &Path, mut data: ByteStream) -> io::Result<u64> {
+        let mut file = fs::File::create(path).await?;
+        let mut size = 0;
+        while let Some(chunk) = data.try_next().await? {
+            file.write_all(&chunk).await?;
+            size += chunk.len() as u64;
+        }
+        file.sync_all().await?;
+        Ok(size)
+    }
+}
+
+#[async_trait]
+impl AttachmentStore for LocalAttachmentStore {
+    fn name(&self) -> &'static str {
+        "local"
+    }
+
+    async fn put(&self, key: &str, data: ByteStream) -> Result<u64, StoreError> {
+        let partial = self.dir.join(format!("{key}{PARTIAL_SUFFIX}"));
+        match Self::write(&partial, data).await {
+            Ok(size) => {
+                fs::rename(&partial, self.dir.join(key)).await?;
+                Ok(size)
+            }
+            Err(e) => {
+                if let Err(remove) = fs::remove_file(&partial).await {
+                    tracing::warn!("Failed to remove partial attachment {key}: {remove}");
+                }
+                Err(e.into())
+            }
+        }
+    }
+
+    async fn get(&self, key: &str) -> Result<Option<ByteStream>, StoreError> {
+        match fs::File::open(self.dir.join(key)).await {
+            Ok(file) => Ok(Some(ReaderStream::new(file).boxed())),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), StoreError> {
+        match fs::remove_file(self.dir.join(key)).await {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+
+    async fn list(&self) -> Result<Vec<StoredObject>, StoreError> {
+        let mut objects = Vec::new();
+        let mut entries = fs::read_dir(&self.dir).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let metadata = entry.metadata().await?;
+            if !metadata.is_file() {
+                continue;
+            }
+            if let Ok(key) = entry.file_name().into_string() {
+                objects.push(StoredObject {
+                    key,
+                    last_modified: metadata.modified()?.into(),
+                });
+            }
+        }
+        Ok(objects)
+    }
+}
@@ -0,0 +1,94 @@
+pub mod local;
+pub mod s3;
+
+use async_trait::async_trait;
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
+use bytes::Bytes;
+use chrono::{DateTime, Utc};
+use futures_util::{Stream, StreamExt, TryStreamExt, stream::BoxStream};
+use rand::{RngCore, rng};
+
+use std::{io, sync::Arc};
+
+/// Bytes of an attachment as they are uploaded or downloaded, never held in memory whole
+pub type ByteStream = BoxStream<'static, io::Result<Bytes>>;
+
+/// Errors of the storage backend itself
+#[derive(Debug, thiserror::Error)]
+pub enum StoreError {
+    #[error("{0}")]
+    Io(#[from] io::Error),
+
+    #[error("{0}")]
+    ObjectStore(#[from] object_store::Error),
+}
+
+/// An attachment's bytes as the store keeps them
+pub struct StoredObject {
+    pub key: String,
+    pub last_modified: DateTime<Utc>,
+}
+
+/// Where the bytes of note attachments are kept. Attachments are only referenced by
+/// their key, which the database row of the attachment holds
+#[async_trait]
+pub trait AttachmentStore: Send + Sync {
+    /// Name of the backend in logs
+    fn name(&self) -> &'static str;
+
+    /// Stores `data` under `key` as it arrives, returning its size in bytes. If
+    /// `data` fails, nothing is left under `key`
+    async fn put(&self, key: &str, data: ByteStream) -> Result<u64, StoreError>;
+
+    /// The bytes stored under `key`, `None` if there are none
+    async fn get(&self, key: &str) -> Result<Option<ByteStream>, StoreError>;
+
+    /// Removes the bytes stored under `key`, if any
+    async fn delete(&self, key: &str) -> Result<(), StoreError>;
+
+    /// Everything in the store, partial uploads included, to find the orphaned objects
+    async fn list(&self) -> Result<Vec<StoredObject>, StoreError>;
+}
+
+/// The attachment store along with the limits of the attachments kept in it
+#[derive(Clone)]
+pub struct Attachments {
+    pub store: Arc<dyn AttachmentStore>,
+    /// Largest attachment in bytes
+    pub max_bytes: u64,
+}
+
+/// Random key of a new attachment, never derived from the request
+pub fn new_key() -> String {
+    let mut bytes = [0u8; 16];
+    rng().fill_bytes(&mut bytes);
+    URL_SAFE_NO_PAD.encode(bytes)
+}
+
+/// Passes `data` on, failing with `io::ErrorKind::FileTooLarge` once more than
+/// `max_bytes` have passed
+pub fn limit(data: ByteStream, max_bytes: u64) -> ByteStream {
+    let mut seen = 0;
+    data.and_then(move |chunk| {
+        seen += chunk.len() as u64;
+        let result = if seen > max_bytes {
+            Err(io::Error::new(
+                io::ErrorKind::FileTooLarge,
+                format!("attachment is larger than {max_bytes} bytes"),
+            ))
+        } else {
+            Ok(chunk)
+        };
+        std::future::ready(result)
+    })
+    .boxed()
+}
+
+/// Stream of `data`'s chunks with its errors turned into `io::Error`s
+pub fn byte_stream<S, E>(data: S) -> ByteStream
+where
+    S: Stream<Item = Result<Bytes, E>> + Send + 'static,
+    E: Into<Box<dyn std::error::Error + Send + Sync>> + 'static,
+{
+    data.map_err(io::Error::other).boxed()
+}
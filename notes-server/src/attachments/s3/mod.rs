@@ -0,0 +1,100 @@
+use async_trait::async_trait;
+use futures_util::TryStreamExt;
+use object_store::{ObjectStore, WriteMultipart, aws::AmazonS3Builder, path::Path as ObjectPath};
+
+use super::{AttachmentStore, ByteStream, StoreError, StoredObject, byte_stream};
+
+/// Uploaded bytes collected before they are handed to the upload as one part
+const UPLOAD_CHUNK_SIZE: usize = 5 * 1024 * 1024;
+/// Number of parts uploaded at the same time
+const MAX_CONCURRENT_PARTS: usize = 4;
+
+/// Attachments as objects in an S3-compatible bucket
+pub struct S3AttachmentStore {
+    store: Box<dyn ObjectStore>,
+    prefix: ObjectPath,
+}
+
+impl S3AttachmentStore {
+    /// Attachments under `prefix` in `bucket`, credentials, region and endpoint are
+    /// taken from the standard `AWS_*` variables
+    pub fn new(bucket: &str, prefix: &str) -> Result<Self, StoreError> {
+        let store = AmazonS3Builder::from_env()
+            .with_bucket_name(bucket)
+            .build()?;
+
+        Ok(Self {
+            store: Box::new(store),
+            prefix: ObjectPath::from(prefix),
+        })
+    }
+
+    fn location(&self, key: &str) -> ObjectPath {
+        self.prefix.child(key)
+    }
+}
+
+/// Uploads `data` in parts, returning its size
+async fn write(upload: &mut WriteMultipart, mut data: ByteStream) -> Result<u64, StoreError> {
+    let mut size = 0;
+    while let Some(chunk) = data.try_next().await? {
+        upload.wait_for_capacity(MAX_CONCURRENT_PARTS).await?;
+        size += chunk.len() as u64;
+        upload.write(&chunk);
+    }
+    Ok(size)
+}
+
+#[async_trait]
+impl AttachmentStore for S3AttachmentStore {
+    fn name(&self) -> &'static str {
+        "s3"
+    }
+
+    async fn put(&self, key: &str, data: ByteStream) -> Result<u64, StoreError> {
+        let location = self.location(key);
+        let upload = self.store.put_multipart(&location).await?;
+        let mut upload = WriteMultipart::new_with_chunk_size(upload, UPLOAD_CHUNK_SIZE);
+        match write(&mut upload, data).await {
+            Ok(size) => {
+                upload.finish().await?;
+                Ok(size)
+            }
+            Err(e) => {
+                if let Err(abort) = upload.abort().await {
+                    tracing::warn!("Failed to discard partial attachment {location}: {abort}");
+                }
+                Err(e)
+            }
+        }
+    }
+
+    async fn get(&self, key: &str) -> Result<Option<ByteStream>, StoreError> {
+        match self.store.get(&self.location(key)).await {
+            Ok(result) => Ok(Some(byte_stream(result.into_stream()))),
+            Err(object_store::Error::NotFound { .. }) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), StoreError> {
+        match self.store.delete(&self.location(key)).await {
+            Ok(()) | Err(object_store::Error::NotFound { .. }) => Ok(()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn list(&self) -> Result<Vec<StoredObject>, StoreError> {
+        Ok(self
+            .store
+            .list(Some(&self.prefix))
+            .try_filter_map(|meta| async move {
+                Ok(meta.location.filename().map(|key| StoredObject {
+                    key: key.to_string(),
+                    last_modified: meta.last_modified,
+                }))
+            })
+            .try_collect()
+            .await?)
+    }
+}
@@ -0,0 +1,181 @@
+use chrono::Utc;
+use flate2::{Compression, write::GzEncoder};
+use futures_util::{Stream, TryStreamExt, future};
+use object_store::{
+    ObjectStore, WriteMultipart, aws::AmazonS3Builder, local::LocalFileSystem, path::Path,
+};
+
+use std::{io::Write, sync::Arc};
+
+use serde::Serialize;
+
+use crate::{dto::NoteResponse, repository::RepositoryError, tenant::Tenant};
+
+/// Compressed bytes collected before they are handed to the upload as one part
+const UPLOAD_CHUNK_SIZE: usize = 5 * 1024 * 1024;
+/// Number of parts uploaded at the same time
+const MAX_CONCURRENT_PARTS: usize = 4;
+/// Snapshot names are `notes-<UTC time>.ndjson.gz`, so they sort by time
+const SNAPSHOT_PREFIX: &str = "notes-";
+const SNAPSHOT_SUFFIX: &str = ".ndjson.gz";
+
+/// Errors of taking a snapshot of the notes
+#[derive(Debug, thiserror::Error)]
+pub enum BackupError {
+    #[error("backups are not configured")]
+    Disabled,
+
+    #[error("failed to load notes: {0}")]
+    Database(#[from] RepositoryError),
+
+    #[error("failed to store the snapshot: {0}")]
+    Storage(#[from] object_store::Error),
+
+    #[error("failed to compress the snapshot: {0}")]
+    Compression(#[from] std::io::Error),
+}
+
+/// A line of a snapshot, the note along with its tenant
+#[derive(Serialize)]
+struct Record<'a> {
+    tenant: &'a str,
+    #[serde(flatten)]
+    note: &'a NoteResponse,
+}
+
+/// A snapshot written to the backup store
+pub struct Snapshot {
+    pub name: String,
+    pub notes: u64,
+    /// Compressed size in bytes
+    pub size: u64,
+}
+
+/// Local directory or S3-compatible bucket keeping the newest `retain` snapshots of the notes
+pub struct BackupStore {
+    store: Arc<dyn ObjectStore>,
+    prefix: Path,
+    retain: usize,
+}
+
+impl BackupStore {
+    /// Snapshots in `dir`, which is created if missing
+    pub fn local(dir: &str, prefix: &str, retain: usize) -> Result<Self, BackupError> {
+        std::fs::create_dir_all(dir)?;
+        let store = LocalFileSystem::new_with_prefix(dir)?;
+
+        Ok(Self {
+            store: Arc::new(store),
+            prefix: Path::from(prefix),
+            retain,
+        })
+    }
+
+    /// Snapshots in `bucket`, credentials, region and endpoint are taken from
+    /// the standard `AWS_*` variables
+    pub fn s3(bucket: &str, prefix: &str, retain: usize) -> Result<Self, BackupError> {
+        let store = AmazonS3Builder::from_env()
+            .with_bucket_name(bucket)
+            .build()?;
+
+        Ok(Self {
+            store: Arc::new(store),
+            prefix: Path::from(prefix),
+            retain,
+        })
+    }
+
+    /// Writes the notes as gzip-compressed NDJSON, one note with its tenant per line, uploaded in parts
+    /// so the snapshot is never held in memory. Afterwards only the newest `retain`
+    /// snapshots are kept. If writing fails, the partial snapshot is discarded
+    pub async fn write_snapshot<S>(&self, notes: S) -> Result<Snapshot, BackupError>
+    where
+        S: Stream<Item = Result<(Tenant, NoteResponse), RepositoryError>> + Send,
+    {
+        let name = format!(
+            "{SNAPSHOT_PREFIX}{}{SNAPSHOT_SUFFIX}",
+            Utc::now().format("%Y%m%dT%H%M%S%.3fZ")
+        );
+        let location = self.prefix.child(name.as_str());
+
+        let upload = self.store.put_multipart(&location).await?;
+        let mut upload = WriteMultipart::new_with_chunk_size(upload, UPLOAD_CHUNK_SIZE);
+        let (notes, size) = match write_notes(&mut upload, notes).await {
+            Ok(written) => written,
+            Err(e) => {
+                if let Err(abort) = upload.abort().await {
+                    tracing::warn!("Failed to discard partial snapshot {location}: {abort}");
+                }
+                return Err(e);
+            }
+        };
+        upload.finish().await?;
+
+        // The snapshot is already stored, old ones are removed on the next run
+        if let Err(e) = self.prune().await {
+            tracing::error!("Failed to remove old snapshots: {e}");
+        }
+
+        Ok(Snapshot {
+            name: location.to_string(),
+            notes,
+            size,
+        })
+    }
+
+    /// Removes all but the newest `retain` snapshots
+    async fn prune(&self) -> Result<(), object_store::Error> {
+        let mut snapshots: Vec<Path> = self
+            .store
+            .list(Some(&self.prefix))
+            .map_ok(|meta| meta.location)
+            .try_filter(|location| {
+                future::ready(location.filename().is_some_and(|name| {
+                    name.starts_with(SNAPSHOT_PREFIX) && name.ends_with(SNAPSHOT_SUFFIX)
+                }))
+            })
+            .try_collect()
+            .await?;
+        snapshots.sort();
+
+        let excess = snapshots.len().saturating_sub(self.retain);
+        for location in &snapshots[..excess] {
+            self.store.delete(location).await?;
+            tracing::info!("Removed old snapshot {location}");
+        }
+        Ok(())
+    }
+}
+
+/// Compresses the notes into `upload`, returning how many were written and the compressed size
+async fn write_notes<S>(upload: &mut WriteMultipart, notes: S) -> Result<(u64, u64), BackupError>
+where
+    S: Stream<Item = Result<(Tenant, NoteResponse), RepositoryError>> + Send,
+{
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    let mut count = 0;
+    let mut size = 0;
+
+    let mut notes = std::pin::pin!(notes);
+    while let Some((tenant, note)) = notes.try_next().await? {
+        let record = Record {
+            tenant: tenant.as_str(),
+            note: &note,
+        };
+        serde_json::to_writer(&mut encoder, &record).map_err(std::io::Error::from)?;
+        encoder.write_all(b"\n")?;
+        count += 1;
+
+        if encoder.get_ref().len() >= UPLOAD_CHUNK_SIZE {
+            upload.wait_for_capacity(MAX_CONCURRENT_PARTS).await?;
+            let chunk = std::mem::take(encoder.get_mut());
+            size += chunk.len() as u64;
+            upload.write(&chunk);
+        }
+    }
+
+    let rest = encoder.finish()?;
+    size += rest.len() as u64;
+    upload.write(&rest);
+    Ok((count, size))
+}
@@ -0,0 +1,141 @@
+use moka::sync::Cache;
+
+use std::{
+    fmt::Write,
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+
+use super::is_expired;
+use crate::{
+    dto::{NoteResponse, NotesPage},
+    models::NoteOrder,
+    tenant::Tenant,
+};
+
+/// An unfiltered first page of a listing, the only pages cached
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PageKey {
+    /// Listed by offset
+    Offset {
+        limit: i64,
+        order: NoteOrder,
+        include_archived: bool,
+    },
+    /// Listed after an ID
+    After { limit: i64, include_archived: bool },
+}
+
+/// Lookup counters of one of the caches
+#[derive(Default)]
+struct Counters {
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl Counters {
+    fn count<T>(&self, value: Option<T>) -> Option<T> {
+        let counter = if value.is_some() {
+            &self.hits
+        } else {
+            &self.misses
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+        value
+    }
+}
+
+/// In-process cache of single notes and of the first listing pages of each tenant,
+/// in front of the database (and the Redis cache, when there is one).
+///
+/// Writes through this instance drop the affected entries right away, changes made
+/// through other instances as their notifications arrive, see `run_change_feed`.
+/// Entries written concurrently by readers, or affected by changes that aren't
+/// notified (reordering on another instance), may be stale until the TTL runs out
+pub struct LocalCache {
+    notes: Cache<(Tenant, i64), NoteResponse>,
+    pages: Cache<(Tenant, PageKey), NotesPage>,
+    note_counters: Counters,
+    page_counters: Counters,
+}
+
+impl LocalCache {
+    /// Up to `capacity` notes and `capacity` pages, each kept for at most `ttl`
+    pub fn new(capacity: u64, ttl: Duration) -> Self {
+        Self {
+            notes: Cache::builder()
+                .max_capacity(capacity)
+                .time_to_live(ttl)
+                .build(),
+            pages: Cache::builder()
+                .max_capacity(capacity)
+                .time_to_live(ttl)
+                .support_invalidation_closures()
+                .build(),
+            note_counters: Counters::default(),
+            page_counters: Counters::default(),
+        }
+    }
+
+    /// The note if cached and not expired since
+    pub fn note(&self, id: i64) -> Option<NoteResponse> {
+        let note = self
+            .notes
+            .get(&(Tenant::current(), id))
+            .filter(|note| !is_expired(note));
+        self.note_counters.count(note)
+    }
+
+    pub fn put_note(&self, note: &NoteResponse) {
+        self.notes
+            .insert((Tenant::current(), note.id), note.clone());
+    }
+
+    /// The page if cached and none of its notes expired since
+    pub fn page(&self, key: PageKey) -> Option<NotesPage> {
+        let page = self
+            .pages
+            .get(&(Tenant::current(), key))
+            .filter(|page| !page.items.iter().any(is_expired));
+        self.page_counters.count(page)
+    }
+
+    pub fn put_page(&self, key: PageKey, page: &NotesPage) {
+        self.pages.insert((Tenant::current(), key), page.clone());
+    }
+
+    /// Drops the notes and every page of the tenant
+    pub fn invalidate(&self, tenant: &Tenant, ids: &[i64]) {
+        for &id in ids {
+            self.notes.invalidate(&(tenant.clone(), id));
+        }
+
+        let tenant = tenant.clone();
+        if let Err(e) = self
+            .pages
+            .invalidate_entries_if(move |(page_tenant, _), _| *page_tenant == tenant)
+        {
+            // Only possible without invalidation closures support, drop them all instead
+            tracing::error!("Failed to invalidate cached pages: {e}");
+            self.pages.invalidate_all();
+        }
+    }
+
+    /// Lookup counters in the Prometheus text format
+    pub fn render(&self) -> String {
+        let mut out = String::from(
+            "# HELP notes_local_cache_requests_total In-process cache lookups, by cache and result\n\
+             # TYPE notes_local_cache_requests_total counter\n",
+        );
+        for (cache, counters) in [("note", &self.note_counters), ("page", &self.page_counters)] {
+            for (result, counter) in [("hit", &counters.hits), ("miss", &counters.misses)] {
+                let _ = writeln!(
+                    out,
+                    "notes_local_cache_requests_total{{cache=\"{cache}\",result=\"{result}\"}} {}",
+                    counter.load(Ordering::Relaxed)
+                );
+            }
+        }
+        out
+    }
+}
@@ -0,0 +1,158 @@
+mod local;
+
+pub use local::{LocalCache, PageKey};
+
+use chrono::Utc;
+use redis::{AsyncCommands, RedisError, aio::ConnectionManager};
+use serde::{Serialize, de::DeserializeOwned};
+
+use std::{
+    fmt::Write,
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+
+use crate::{dto::NoteResponse, tenant::Tenant};
+
+/// Keys are per tenant, so tenants never see each other's entries
+fn note_key(tenant: &Tenant, id: i64) -> String {
+    format!("notes:{tenant}:note:{id}")
+}
+
+/// Key of the cached list of all notes of the tenant
+fn all_notes_key(tenant: &Tenant) -> String {
+    format!("notes:{tenant}:all")
+}
+
+fn is_expired(note: &NoteResponse) -> bool {
+    note.expires_at
+        .is_some_and(|expires_at| expires_at <= Utc::now())
+}
+
+/// Redis cache of single notes and of the list of all notes of the current tenant,
+/// shared by all replicas.
+///
+/// Writers drop the affected entries right after changing the database, entries
+/// written concurrently by readers may still be stale until the TTL runs out.
+/// Redis errors are logged and treated as misses, the database stays the source of truth
+pub struct NoteCache {
+    connection: ConnectionManager,
+    ttl: Duration,
+    hits: AtomicU64,
+    misses: AtomicU64,
+    errors: AtomicU64,
+}
+
+impl NoteCache {
+    pub async fn connect(url: &str, ttl: Duration) -> Result<Self, RedisError> {
+        let client = redis::Client::open(url)?;
+        let connection = ConnectionManager::new(client).await?;
+
+        Ok(Self {
+            connection,
+            ttl,
+            hits: AtomicU64::default(),
+            misses: AtomicU64::default(),
+            errors: AtomicU64::default(),
+        })
+    }
+
+    /// The note if cached and not expired since
+    pub async fn note(&self, id: i64) -> Option<NoteResponse> {
+        self.get::<NoteResponse>(&note_key(&Tenant::current(), id))
+            .await
+            .filter(|note| !is_expired(note))
+    }
+
+    pub async fn put_note(&self, note: &NoteResponse) {
+        self.put(&note_key(&Tenant::current(), note.id), note).await;
+    }
+
+    /// All notes if cached, without those expired since
+    pub async fn all_notes(&self) -> Option<Vec<NoteResponse>> {
+        let mut notes = self
+            .get::<Vec<NoteResponse>>(&all_notes_key(&Tenant::current()))
+            .await?;
+        notes.retain(|note| !is_expired(note));
+        Some(notes)
+    }
+
+    pub async fn put_all_notes(&self, notes: &[NoteResponse]) {
+        self.put(&all_notes_key(&Tenant::current()), notes).await;
+    }
+
+    /// Drops the notes and the list of all notes
+    pub async fn invalidate(&self, ids: &[i64]) {
+        let tenant = Tenant::current();
+        let keys: Vec<String> = ids
+            .iter()
+            .map(|&id| note_key(&tenant, id))
+            .chain([all_notes_key(&tenant)])
+            .collect();
+
+        let mut connection = self.connection.clone();
+        if let Err(e) = connection.del::<_, ()>(keys).await {
+            self.errors.fetch_add(1, Ordering::Relaxed);
+            tracing::error!("Failed to invalidate {} cached notes: {e}", ids.len());
+        }
+    }
+
+    async fn get<T: DeserializeOwned>(&self, key: &str) -> Option<T> {
+        let mut connection = self.connection.clone();
+        let cached = match connection.get::<_, Option<Vec<u8>>>(key).await {
+            Ok(cached) => cached,
+            Err(e) => {
+                self.errors.fetch_add(1, Ordering::Relaxed);
+                tracing::error!("Failed to read {key} from the cache: {e}");
+                None
+            }
+        };
+        let value = cached.and_then(|bytes| serde_json::from_slice(&bytes).ok());
+
+        let counter = if value.is_some() {
+            &self.hits
+        } else {
+            &self.misses
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+        value
+    }
+
+    async fn put<T: Serialize + Sync + ?Sized>(&self, key: &str, value: &T) {
+        let Ok(bytes) = serde_json::to_vec(value) else {
+            return;
+        };
+
+        let mut connection = self.connection.clone();
+        if let Err(e) = connection
+            .set_ex::<_, _, ()>(key, bytes, self.ttl.as_secs().max(1))
+            .await
+        {
+            self.errors.fetch_add(1, Ordering::Relaxed);
+            tracing::error!("Failed to write {key} to the cache: {e}");
+        }
+    }
+
+    /// Lookup counters in the Prometheus text format
+    pub fn render(&self) -> String {
+        let mut out = String::from(
+            "# HELP notes_cache_requests_total Note cache lookups, by result\n\
+             # TYPE notes_cache_requests_total counter\n",
+        );
+        for (result, counter) in [("hit", &self.hits), ("miss", &self.misses)] {
+            let _ = writeln!(
+                out,
+                "notes_cache_requests_total{{result=\"{result}\"}} {}",
+                counter.load(Ordering::Relaxed)
+            );
+        }
+        let _ = writeln!(
+            out,
+            "# HELP notes_cache_errors_total Failed Redis commands\n\
+             # TYPE notes_cache_errors_total counter\n\
+             notes_cache_errors_total {}",
+            self.errors.load(Ordering::Relaxed)
+        );
+        out
+    }
+}
@@ -0,0 +1,103 @@
+use std::process::ExitCode;
+
+use tokio_postgres::Config;
+
+use crate::{models::Migration, repository::Repository};
+
+const USAGE: &str = "Usage: notes-server migrate --status | --up | --down <n>\n\n\
+    --status    lists applied and pending migrations\n\
+    --up        applies the pending migrations\n\
+    --down <n>  reverts the <n> most recently applied migrations\n\n\
+    Without arguments, notes-server applies pending migrations (unless\n\
+    AUTO_MIGRATE=false) and starts serving";
+
+enum MigrateCommand {
+    Status,
+    Up,
+    Down(usize),
+}
+
+impl MigrateCommand {
+    fn parse(args: &[String]) -> Option<Self> {
+        match args {
+            [flag] if flag == "--status" => Some(Self::Status),
+            [flag] if flag == "--up" => Some(Self::Up),
+            [flag, count] if flag == "--down" => {
+                count.parse().ok().filter(|&n| n > 0).map(Self::Down)
+            }
+            _ => None,
+        }
+    }
+}
+
+fn describe(migration: &Migration) -> String {
+    let name = format!("V{}__{}", migration.version, migration.name);
+    match migration.applied_at {
+        Some(applied_at) => format!("{name} (applied at {applied_at})"),
+        None => name,
+    }
+}
+
+/// Runs a command line call, returns `None` for no arguments, meaning the servers
+/// should be started
+pub async fn run(args: &[String], database_config: &Config) -> Option<ExitCode> {
+    let (command, args) = args.split_first()?;
+    if command != "migrate" {
+        eprintln!("{USAGE}");
+        return Some(ExitCode::FAILURE);
+    }
+    let Some(command) = MigrateCommand::parse(args) else {
+        eprintln!("{USAGE}");
+        return Some(ExitCode::FAILURE);
+    };
+
+    Some(migrate(command, database_config.clone()).await)
+}
+
+async fn migrate(command: MigrateCommand, database_config: Config) -> ExitCode {
+    let mut repo = match Repository::new(database_config, Some(1), None).await {
+        Ok(repo) => repo,
+        Err(e) => {
+            eprintln!("Failed to connect to the database: {e}");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let result = match command {
+        MigrateCommand::Status => repo.migration_status().await.map(|(applied, pending)| {
+            println!("Applied migrations:");
+            for m in &applied {
+                println!("  {}", describe(m));
+            }
+            println!("Pending migrations:");
+            for m in &pending {
+                println!("  {}", describe(m));
+            }
+        }),
+        MigrateCommand::Up => repo.migrate().await.map(|()| {
+            let applied = repo.startup_migrations();
+            if applied.is_empty() {
+                println!("No pending migrations");
+            }
+            for m in applied {
+                println!("Applied {}", describe(m));
+            }
+        }),
+        MigrateCommand::Down(count) => repo.revert_migrations(count).await.map(|reverted| {
+            if reverted.is_empty() {
+                println!("No applied migrations");
+            }
+            for m in &reverted {
+                println!("Reverted {}", describe(m));
+            }
+        }),
+    };
+
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("Migration command failed: {e}");
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -1,24 +1,132 @@
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use utoipa::ToSchema;
 
+use std::collections::HashMap;
+
+use crate::{
+    models::{
+        Attachment, DigestSubscription, DuplicateCluster, Metadata, Migration, Note, NoteTemplate,
+        SoapAuditEntry, User, Webhook, WebhookDelivery,
+    },
+    service::NoteOperation,
+};
+
 #[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub struct NoteResponse {
     /// Note ID
     pub id: i64,
     /// Note content
     pub content: String,
+    /// Creation timestamp
+    pub created_at: DateTime<Utc>,
+    /// Last modification timestamp
+    pub updated_at: DateTime<Utc>,
+    /// Time after which the note is deleted, if any
+    pub expires_at: Option<DateTime<Utc>>,
+    /// Time at which a reminder with the note is emailed, if any
+    pub remind_at: Option<DateTime<Utc>>,
+    /// Client-defined fields
+    #[serde(default)]
+    #[schema(value_type = Object)]
+    pub metadata: Metadata,
+    /// Place of the note in the user-defined order, only the relative order is meaningful
+    #[serde(default)]
+    pub position: i64,
+    /// Incremented by every change of the note, its ``ETag``
+    #[serde(default)]
+    pub version: i64,
+    /// ID of an existing note with the same content, only given when the note is created
+    /// and duplicates are hinted at
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub duplicate_of: Option<i64>,
+}
+
+impl From<Note> for NoteResponse {
+    fn from(note: Note) -> Self {
+        Self {
+            id: note.id,
+            content: note.content,
+            created_at: note.created_at,
+            updated_at: note.updated_at,
+            expires_at: note.expires_at,
+            remind_at: note.remind_at,
+            metadata: note.metadata,
+            position: note.position,
+            version: note.version,
+            duplicate_of: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct DuplicateClusterResponse {
+    /// Hash of the content shared by the notes, with case and whitespace normalized
+    pub content_hash: String,
+    /// IDs of the notes, oldest first
+    pub note_ids: Vec<i64>,
+}
+
+impl From<DuplicateCluster> for DuplicateClusterResponse {
+    fn from(cluster: DuplicateCluster) -> Self {
+        Self {
+            content_hash: cluster.content_hash,
+            note_ids: cluster.note_ids,
+        }
+    }
+}
+
+/// A single page of notes
+#[derive(Debug, Clone)]
+pub struct NotesPage {
+    pub items: Vec<NoteResponse>,
+    /// Number of notes across all pages
+    pub total: i64,
+    /// ID to list the next page after, for pages listed after an ID.
+    /// `None` on the last page and for pages listed by offset
+    pub next_after: Option<i64>,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct NoteListResponse {
+    /// Notes on this page
+    pub items: Vec<NoteResponse>,
+    /// Number of notes across all pages
+    pub total: i64,
+    /// URL of the next page, absent on the last one
+    pub next: Option<String>,
+    /// URL of the previous page, absent on the first one
+    pub prev: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub struct CreateNoteRequest {
     /// Note content
     pub content: String,
+    /// Time after which the note is deleted, the note never expires if omitted
+    #[serde(default)]
+    pub expires_at: Option<DateTime<Utc>>,
+    /// Time at which a reminder with the note is emailed, no reminder if omitted
+    #[serde(default)]
+    pub remind_at: Option<DateTime<Utc>>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub struct UpdateNoteRequest {
     /// Note content
     pub content: String,
+    /// New expiration time, the current one is kept if omitted
+    #[serde(default)]
+    pub expires_at: Option<DateTime<Utc>>,
+    /// New reminder time, the current one is kept if omitted
+    #[serde(default)]
+    pub remind_at: Option<DateTime<Utc>>,
+    /// Removes the expiration time, so the note is kept. Takes precedence over `expires_at`
+    #[serde(default)]
+    pub clear_expires_at: bool,
+    /// Cancels the reminder. Takes precedence over `remind_at`
+    #[serde(default)]
+    pub clear_remind_at: bool,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
@@ -26,3 +134,417 @@ pub struct ShareNotesRequest {
     /// Email address to send notes to
     pub email: String,
 }
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct TemplateRequest {
+    /// Template name
+    pub name: String,
+    /// Content of notes created from the template, may contain `{{placeholders}}`
+    pub content: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct TemplateResponse {
+    /// Template ID
+    pub id: i64,
+    /// Template name
+    pub name: String,
+    /// Content of notes created from the template, may contain `{{placeholders}}`
+    pub content: String,
+    /// Creation timestamp
+    pub created_at: DateTime<Utc>,
+    /// Last modification timestamp
+    pub updated_at: DateTime<Utc>,
+}
+
+impl From<NoteTemplate> for TemplateResponse {
+    fn from(template: NoteTemplate) -> Self {
+        Self {
+            id: template.id,
+            name: template.name,
+            content: template.content,
+            created_at: template.created_at,
+            updated_at: template.updated_at,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct WebhookRequest {
+    /// URL the note changes are sent to, `http` or `https`
+    pub url: String,
+    /// Operations sent to the URL, all of them when empty
+    #[serde(default)]
+    pub events: Vec<NoteOperation>,
+    /// Whether changes are sent to the URL
+    #[serde(default = "default_active")]
+    pub active: bool,
+}
+
+const fn default_active() -> bool {
+    true
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct WebhookResponse {
+    /// Webhook ID
+    pub id: i64,
+    /// URL the note changes are sent to
+    pub url: String,
+    /// Operations sent to the URL, all of them when empty
+    pub events: Vec<String>,
+    /// Whether changes are sent to the URL
+    pub active: bool,
+    /// Key of the `X-Notes-Signature` of the requests, only returned on creation
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub secret: Option<String>,
+    /// Failed requests in a row
+    pub consecutive_failures: i32,
+    /// Nothing is sent to the URL until then, after too many failed requests
+    pub circuit_open_until: Option<DateTime<Utc>>,
+    /// Creation timestamp
+    pub created_at: DateTime<Utc>,
+    /// Last modification timestamp
+    pub updated_at: DateTime<Utc>,
+}
+
+impl From<Webhook> for WebhookResponse {
+    fn from(webhook: Webhook) -> Self {
+        Self {
+            id: webhook.id,
+            url: webhook.url,
+            events: webhook.events,
+            active: webhook.active,
+            secret: None,
+            consecutive_failures: webhook.consecutive_failures,
+            circuit_open_until: webhook.circuit_open_until,
+            created_at: webhook.created_at,
+            updated_at: webhook.updated_at,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct WebhookDeliveryResponse {
+    /// Delivery ID, sent in `X-Notes-Delivery`
+    pub id: i64,
+    /// Sequence number of the change
+    pub event_id: i64,
+    /// Operation of the change
+    pub operation: String,
+    /// Body of the requests
+    #[schema(value_type = Object)]
+    pub payload: serde_json::Value,
+    /// `pending`, `delivered` or `failed` (out of attempts)
+    pub status: String,
+    /// Requests made so far
+    pub attempts: i32,
+    /// When the next request is made, while pending
+    pub next_attempt_at: Option<DateTime<Utc>>,
+    /// Status of the last response, absent if there was none
+    pub last_status_code: Option<i32>,
+    /// Why the last request failed
+    pub last_error: Option<String>,
+    /// When the change was queued for the webhook
+    pub created_at: DateTime<Utc>,
+    /// When the delivery succeeded or failed for good
+    pub completed_at: Option<DateTime<Utc>>,
+}
+
+impl From<WebhookDelivery> for WebhookDeliveryResponse {
+    fn from(delivery: WebhookDelivery) -> Self {
+        let pending = delivery.status == "pending";
+        Self {
+            id: delivery.id,
+            event_id: delivery.event_id,
+            operation: delivery.operation,
+            payload: delivery.payload,
+            status: delivery.status,
+            attempts: delivery.attempts,
+            next_attempt_at: pending.then_some(delivery.next_attempt_at),
+            last_status_code: delivery.last_status_code,
+            last_error: delivery.last_error,
+            created_at: delivery.created_at,
+            completed_at: delivery.completed_at,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, ToSchema)]
+pub struct CreateFromTemplateRequest {
+    /// Placeholder values, these take precedence over the built-in `date`, `time` and `datetime`
+    #[serde(default)]
+    pub values: HashMap<String, String>,
+    /// Time after which the note is deleted, the note never expires if omitted
+    #[serde(default)]
+    pub expires_at: Option<DateTime<Utc>>,
+    /// Time at which a reminder with the note is emailed, no reminder if omitted
+    #[serde(default)]
+    pub remind_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct ReorderNotesRequest {
+    /// IDs of the notes in their new order, the notes swap the positions they held
+    pub ids: Vec<i64>,
+}
+
+/// Fields to set on the note's metadata, `null` values remove the field
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+#[serde(transparent)]
+#[schema(value_type = Object)]
+pub struct MetadataPatch(pub Metadata);
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, ToSchema)]
+pub struct CreateShareLinkRequest {
+    /// Time after which the link stops working, in a week if omitted
+    #[serde(default)]
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct ShareLinkResponse {
+    /// Secret token identifying the link, it can't be retrieved again
+    pub token: String,
+    /// Path of the read-only view of the note
+    pub url: String,
+    /// ID of the shared note
+    pub note_id: i64,
+    /// Time after which the link stops working
+    pub expires_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct MigrationResponse {
+    /// Migration version
+    pub version: i64,
+    /// Migration name
+    pub name: String,
+    /// Time the migration was applied, absent for pending migrations
+    pub applied_at: Option<DateTime<Utc>>,
+}
+
+impl From<Migration> for MigrationResponse {
+    fn from(migration: Migration) -> Self {
+        Self {
+            version: migration.version,
+            name: migration.name,
+            applied_at: migration.applied_at,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct MigrationStatusResponse {
+    /// Migrations recorded in the database
+    pub applied: Vec<MigrationResponse>,
+    /// Migrations embedded in this replica but not applied to the database
+    pub pending: Vec<MigrationResponse>,
+    /// Migrations this replica found pending and applied when it started
+    pub applied_at_startup: Vec<MigrationResponse>,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct GenerateNotesResponse {
+    /// Number of notes created
+    pub created: u64,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct BackupResponse {
+    /// Location of the snapshot in the backup store
+    pub name: String,
+    /// Number of notes in the snapshot
+    pub notes: u64,
+    /// Compressed size of the snapshot in bytes
+    pub size: u64,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct RetentionReport {
+    /// Whether the background job only reports what the rules apply to
+    pub dry_run: bool,
+    /// The rules, in the order they are applied
+    pub rules: Vec<RetentionRuleReport>,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct RetentionRuleReport {
+    /// `archive` or `delete`
+    pub action: String,
+    /// The rule applies to notes not modified for this many days
+    pub after_days: i64,
+    /// Number of notes of all tenants the rule applies to now
+    pub notes: i64,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct SoapAuditResponse {
+    pub id: i64,
+    /// Operations requested in the envelope, empty if it was rejected before dispatch
+    pub operations: Vec<String>,
+    /// Address of the peer that sent the request
+    pub caller: String,
+    /// `X-Forwarded-For` of the request, as received
+    pub forwarded_for: Option<String>,
+    /// Envelope version of the response, `1.1` or `1.2`
+    pub soap_version: String,
+    /// Code of the returned fault, absent if the request succeeded
+    pub fault_code: Option<String>,
+    /// Time taken to answer the request
+    pub latency_ms: i64,
+    /// Request envelope, only captured with `SOAP_AUDIT_CAPTURE_ENVELOPES=true`
+    pub envelope: Option<String>,
+    pub occurred_at: DateTime<Utc>,
+}
+
+impl From<SoapAuditEntry> for SoapAuditResponse {
+    fn from(entry: SoapAuditEntry) -> Self {
+        Self {
+            id: entry.id,
+            operations: entry.operations,
+            caller: entry.caller,
+            forwarded_for: entry.forwarded_for,
+            soap_version: entry.soap_version,
+            fault_code: entry.fault_code,
+            latency_ms: entry.latency_ms,
+            envelope: entry.envelope,
+            occurred_at: entry.occurred_at,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct UserResponse {
+    pub id: i64,
+    /// Issuer of the user's tokens
+    pub issuer: String,
+    /// `sub` claim of the user's tokens
+    pub subject: String,
+    /// Tenant the user was added to
+    pub tenant_id: String,
+    pub email: Option<String>,
+    pub name: Option<String>,
+    /// When the user's first token was seen
+    pub created_at: DateTime<Utc>,
+    pub last_seen_at: DateTime<Utc>,
+}
+
+impl From<User> for UserResponse {
+    fn from(user: User) -> Self {
+        Self {
+            id: user.id,
+            issuer: user.issuer,
+            subject: user.subject,
+            tenant_id: user.tenant_id,
+            email: user.email,
+            name: user.name,
+            created_at: user.created_at,
+            last_seen_at: user.last_seen_at,
+        }
+    }
+}
+
+/// Requests counted against a quota in its current window
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct QuotaWindow {
+    /// Requests allowed in the window
+    pub limit: u64,
+    pub used: u64,
+    pub remaining: u64,
+    /// End of the window, when the count starts over
+    pub resets_at: DateTime<Utc>,
+}
+
+/// Requests made by the caller against their quotas
+#[derive(Debug, Clone, Default, Serialize, Deserialize, ToSchema)]
+pub struct UsageResponse {
+    /// API key fingerprint (`key:...`) or user (`user:...`) the requests are counted for
+    pub principal: String,
+    /// Tenant of the caller
+    pub tenant: String,
+    /// Usage in the current UTC minute, absent without a per-minute quota
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub minute: Option<QuotaWindow>,
+    /// Usage in the current UTC day, absent without a per-day quota
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub day: Option<QuotaWindow>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct AttachmentResponse {
+    /// Attachment ID
+    pub id: i64,
+    /// ID of the note the file is attached to
+    pub note_id: i64,
+    /// Name of the file as uploaded
+    pub filename: String,
+    /// Media type of the file, sent back on download
+    pub content_type: String,
+    /// Size in bytes
+    pub size: i64,
+    /// Upload timestamp
+    pub created_at: DateTime<Utc>,
+}
+
+impl From<Attachment> for AttachmentResponse {
+    fn from(attachment: Attachment) -> Self {
+        Self {
+            id: attachment.id,
+            note_id: attachment.note_id,
+            filename: attachment.filename,
+            content_type: attachment.content_type,
+            size: attachment.size,
+            created_at: attachment.created_at,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct DigestRequest {
+    /// Address the digests are sent to
+    pub email: String,
+    /// Cron expression of when the digest is sent, in UTC, e.g. `0 8 * * Mon-Fri`
+    pub schedule: String,
+    /// Whether digests are sent
+    #[serde(default = "default_active")]
+    pub active: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct DigestResponse {
+    /// Subscription ID
+    pub id: i64,
+    /// Address the digests are sent to
+    pub email: String,
+    /// Cron expression of when the digest is sent, in UTC
+    pub schedule: String,
+    /// Whether digests are sent
+    pub active: bool,
+    /// Up to when the notes were included in the previous digest
+    pub last_run_at: Option<DateTime<Utc>>,
+    /// When the next digest is sent, while active
+    pub next_run_at: Option<DateTime<Utc>>,
+    /// Why the last digest couldn't be sent
+    pub last_error: Option<String>,
+    /// Creation timestamp
+    pub created_at: DateTime<Utc>,
+    /// Last modification timestamp
+    pub updated_at: DateTime<Utc>,
+}
+
+impl From<DigestSubscription> for DigestResponse {
+    fn from(digest: DigestSubscription) -> Self {
+        Self {
+            id: digest.id,
+            email: digest.email,
+            schedule: digest.schedule,
+            active: digest.active,
+            last_run_at: digest.last_run_at,
+            next_run_at: digest.active.then_some(digest.next_run_at),
+            last_error: digest.last_error,
+            created_at: digest.created_at,
+            updated_at: digest.updated_at,
+        }
+    }
+}
@@ -0,0 +1,77 @@
+use async_trait::async_trait;
+use pki::ClientTls;
+use reqwest::{StatusCode, header};
+use serde::Serialize;
+
+use std::time::Duration;
+
+/// Errors returned while handing an email over to the email service
+#[derive(Debug, thiserror::Error)]
+pub enum EmailError {
+    #[error("email service returned {0}")]
+    Rejected(reqwest::StatusCode),
+
+    /// The email service's send queue is full, callers should back off
+    #[error("email service is overloaded")]
+    Throttled { retry_after: Option<Duration> },
+
+    #[error("failed to reach email service: {0}")]
+    Transport(#[from] reqwest::Error),
+}
+
+/// Outgoing email, in the shape accepted by the email service
+#[derive(Debug, Clone, Serialize)]
+pub struct Email {
+    pub to: String,
+    pub subject: String,
+    pub body: String,
+}
+
+/// Anything able to deliver an email on behalf of the notes service
+#[async_trait]
+pub trait EmailClient: Send + Sync {
+    async fn send(&self, email: Email) -> Result<(), EmailError>;
+}
+
+/// `EmailClient` backed by the email service REST API (`POST /email`)
+pub struct HttpEmailClient {
+    base_url: String,
+    client: reqwest::Client,
+}
+
+impl HttpEmailClient {
+    pub fn new(base_url: String, tls: &ClientTls) -> Self {
+        let client = tls
+            .apply(reqwest::Client::builder())
+            .build()
+            .unwrap_or_else(|_| reqwest::Client::new());
+
+        Self { base_url, client }
+    }
+}
+
+#[async_trait]
+impl EmailClient for HttpEmailClient {
+    async fn send(&self, email: Email) -> Result<(), EmailError> {
+        let response = self
+            .client
+            .post(format!("{}/email", self.base_url))
+            .json(&email)
+            .send()
+            .await?;
+
+        match response.status() {
+            status if status.is_success() => Ok(()),
+            StatusCode::TOO_MANY_REQUESTS => {
+                let retry_after = response
+                    .headers()
+                    .get(header::RETRY_AFTER)
+                    .and_then(|v| v.to_str().ok())
+                    .and_then(|v| v.parse().ok())
+                    .map(Duration::from_secs);
+                Err(EmailError::Throttled { retry_after })
+            }
+            status => Err(EmailError::Rejected(status)),
+        }
+    }
+}
@@ -0,0 +1,260 @@
+use axum::http::{HeaderMap, Request, Response};
+use futures_util::future::BoxFuture;
+use http_body::{Body, Frame, SizeHint};
+use tonic::Code;
+use tower::{Layer, Service};
+
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    pin::Pin,
+    sync::{Arc, Mutex, PoisonError},
+    task::{Context, Poll, ready},
+    time::Instant,
+};
+
+/// Upper bounds of the latency histogram buckets, in seconds
+const LATENCY_BUCKETS: [f64; 11] = [
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+/// Label used for calls of methods the server doesn't implement, so arbitrary
+/// paths sent by clients don't end up as label values
+const UNKNOWN_METHOD: &str = "unknown";
+
+#[derive(Debug, Default)]
+struct MethodStats {
+    /// Completed calls by status code
+    handled: BTreeMap<i32, u64>,
+    /// Calls per latency bucket, not cumulative
+    buckets: [u64; LATENCY_BUCKETS.len()],
+    latency_sum: f64,
+    count: u64,
+}
+
+/// Calls of the gRPC server per method, with their status codes and latencies,
+/// exposed on `/metrics`. Latencies of streaming calls span until the stream ends
+#[derive(Debug, Default)]
+pub struct GrpcMetrics {
+    methods: Mutex<BTreeMap<String, MethodStats>>,
+}
+
+impl GrpcMetrics {
+    fn record(&self, method: &str, code: Code, seconds: f64) {
+        let method = if code == Code::Unimplemented {
+            UNKNOWN_METHOD
+        } else {
+            method
+        };
+
+        let mut methods = self.methods.lock().unwrap_or_else(PoisonError::into_inner);
+        let stats = methods.entry(method.to_string()).or_default();
+        *stats.handled.entry(code.into()).or_default() += 1;
+        if let Some(bucket) = LATENCY_BUCKETS.iter().position(|&le| seconds <= le) {
+            stats.buckets[bucket] += 1;
+        }
+        stats.latency_sum += seconds;
+        stats.count += 1;
+        drop(methods);
+    }
+
+    /// Counters and histograms in the Prometheus text exposition format
+    pub fn render(&self) -> String {
+        let methods = self.methods.lock().unwrap_or_else(PoisonError::into_inner);
+
+        let mut out = String::from(
+            "# HELP grpc_server_handled_total Completed gRPC calls, by method and status code\n\
+             # TYPE grpc_server_handled_total counter\n",
+        );
+        for (method, stats) in methods.iter() {
+            for (&code, count) in &stats.handled {
+                let _ = writeln!(
+                    out,
+                    "grpc_server_handled_total{{{},grpc_code=\"{:?}\"}} {count}",
+                    method_labels(method),
+                    Code::from(code),
+                );
+            }
+        }
+
+        out.push_str(
+            "# HELP grpc_server_handling_seconds Time from receiving a gRPC call to its status\n\
+             # TYPE grpc_server_handling_seconds histogram\n",
+        );
+        for (method, stats) in methods.iter() {
+            let labels = method_labels(method);
+            let mut cumulative = 0;
+            for (le, count) in LATENCY_BUCKETS.iter().zip(stats.buckets) {
+                cumulative += count;
+                let _ = writeln!(
+                    out,
+                    "grpc_server_handling_seconds_bucket{{{labels},le=\"{le}\"}} {cumulative}"
+                );
+            }
+            let _ = writeln!(
+                out,
+                "grpc_server_handling_seconds_bucket{{{labels},le=\"+Inf\"}} {}\n\
+                 grpc_server_handling_seconds_sum{{{labels}}} {}\n\
+                 grpc_server_handling_seconds_count{{{labels}}} {}",
+                stats.count, stats.latency_sum, stats.count,
+            );
+        }
+        drop(methods);
+
+        out
+    }
+}
+
+/// `grpc_service` and `grpc_method` labels from a request path like `/notes.v1.NoteService/GetNote`
+fn method_labels(path: &str) -> String {
+    let (service, method) = path
+        .trim_start_matches('/')
+        .split_once('/')
+        .unwrap_or((path, path));
+    format!("grpc_service=\"{service}\",grpc_method=\"{method}\"")
+}
+
+/// Status code of a call from the `grpc-status` of its headers or trailers
+fn status_code(headers: &HeaderMap) -> Option<Code> {
+    headers
+        .get("grpc-status")
+        .map(|status| Code::from_bytes(status.as_bytes()))
+}
+
+/// Records every call of the wrapped gRPC services in `GrpcMetrics` and logs it once completed
+#[derive(Debug, Clone)]
+pub struct MetricsLayer(Arc<GrpcMetrics>);
+
+impl MetricsLayer {
+    pub const fn new(metrics: Arc<GrpcMetrics>) -> Self {
+        Self(metrics)
+    }
+
+    pub fn metrics(&self) -> Arc<GrpcMetrics> {
+        self.0.clone()
+    }
+}
+
+impl<S> Layer<S> for MetricsLayer {
+    type Service = Metered<S>;
+
+    fn layer(&self, inner: S) -> Metered<S> {
+        Metered {
+            inner,
+            metrics: self.0.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Metered<S> {
+    inner: S,
+    metrics: Arc<GrpcMetrics>,
+}
+
+impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for Metered<S>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+    S::Future: Send + 'static,
+{
+    type Response = Response<MeteredBody<ResBody>>;
+    type Error = S::Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request<ReqBody>) -> Self::Future {
+        let mut call = Call {
+            metrics: self.metrics.clone(),
+            method: request.uri().path().to_string(),
+            started: Instant::now(),
+            code: None,
+        };
+        let response = self.inner.call(request);
+
+        Box::pin(async move {
+            let response = response.await?;
+            // Set right away on trailers-only responses, otherwise read from the trailers
+            call.code = status_code(response.headers());
+
+            Ok(response.map(|inner| MeteredBody {
+                inner,
+                call: Some(call),
+            }))
+        })
+    }
+}
+
+/// A call whose outcome is not recorded yet
+struct Call {
+    metrics: Arc<GrpcMetrics>,
+    method: String,
+    started: Instant,
+    code: Option<Code>,
+}
+
+impl Call {
+    fn finish(self) {
+        // Without a status the client went away before the call completed
+        let code = self.code.unwrap_or(Code::Cancelled);
+        let elapsed = self.started.elapsed();
+        self.metrics
+            .record(&self.method, code, elapsed.as_secs_f64());
+
+        tracing::info!(
+            grpc.method = self.method,
+            grpc.code = ?code,
+            latency_ms = elapsed.as_millis(),
+            "gRPC call completed"
+        );
+    }
+}
+
+/// Response body recording its call once the trailers are sent or the body is dropped
+pub struct MeteredBody<B> {
+    inner: B,
+    call: Option<Call>,
+}
+
+impl<B: Body + Unpin> Body for MeteredBody<B> {
+    type Data = B::Data;
+    type Error = B::Error;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let frame = ready!(Pin::new(&mut self.inner).poll_frame(cx));
+
+        let trailers = match &frame {
+            Some(Ok(frame)) => frame.trailers_ref(),
+            Some(Err(_)) | None => None,
+        };
+        if let Some(trailers) = trailers
+            && let Some(mut call) = self.call.take()
+        {
+            call.code = status_code(trailers).or(call.code);
+            call.finish();
+        }
+
+        Poll::Ready(frame)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+impl<B> Drop for MeteredBody<B> {
+    fn drop(&mut self) {
+        if let Some(call) = self.call.take() {
+            call.finish();
+        }
+    }
+}
@@ -1,135 +1,597 @@
-use std::sync::Arc;
+pub mod metrics;
+pub mod tenant;
+pub mod web;
 
-use tonic::{Request, Response, Status};
+use std::{
+    collections::HashSet,
+    future::Future,
+    pin::Pin,
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::Duration,
+};
 
-use crate::service::NoteService;
+use chrono::{DateTime, Utc};
+use futures_util::{Stream, StreamExt, TryStreamExt, future, stream, stream::TryChunksError};
+use tokio::time::{self, Instant};
+use tonic::{Request, Response, Status, Streaming, metadata::MetadataValue};
 
-// Include the generated proto code
-pub mod notes {
-    tonic::include_proto!("notes");
-}
+use notes_proto::google::protobuf::Timestamp;
+
+use crate::{
+    dto,
+    email::EmailError,
+    i18n::{Catalog, Localizer, MessageKey},
+    models::MetadataFilter,
+    operations::{self, ImportError, Operation, OperationError},
+    service::{self, NoteService},
+};
+
+pub use notes_proto::notes::v1 as notes;
 
 use notes::{
+    BatchCreateNoteResult, BatchCreateNotesRequest, BatchCreateNotesResponse,
+    BatchDeleteNoteResult, BatchDeleteNotesRequest, BatchDeleteNotesResponse, BatchItemError,
     CreateNoteRequest, DeleteNoteRequest, DeleteNoteResponse, GetAllNotesRequest,
-    GetAllNotesResponse, GetNoteRequest, NoteResponse, UpdateNoteRequest,
+    GetAllNotesResponse, GetNoteRequest, ImportSummary, ListNotesRequest, NoteEvent, NoteOperation,
+    NoteResponse, ShareNotesRequest, ShareNotesResponse, UpdateNoteRequest, WatchNotesRequest,
+    batch_create_note_result,
     note_service_server::{NoteService as NoteServiceTrait, NoteServiceServer},
 };
 
+/// Page size used when `page_size` is not set
+const DEFAULT_PAGE_SIZE: u32 = 50;
+/// Largest page a client may request
+const MAX_PAGE_SIZE: u32 = 500;
+/// Imported notes sent to the database with a single COPY
+const IMPORT_BATCH_SIZE: usize = 5000;
+/// Retry delay suggested when the overloaded email service gives none
+const DEFAULT_RETRY_AFTER: Duration = Duration::from_secs(5);
+/// Taken off the client's timeout, so the call fails with `DEADLINE_EXCEEDED` slightly before
+/// tonic's own `grpc-timeout` handling drops it with a bare `CANCELLED`
+const DEADLINE_MARGIN: Duration = Duration::from_millis(10);
+
+pub const fn to_timestamp(time: DateTime<Utc>) -> Timestamp {
+    Timestamp {
+        seconds: time.timestamp(),
+        #[allow(clippy::cast_possible_wrap)]
+        nanos: time.timestamp_subsec_nanos() as i32,
+    }
+}
+
+fn from_timestamp(timestamp: Timestamp) -> Option<DateTime<Utc>> {
+    u32::try_from(timestamp.nanos)
+        .ok()
+        .and_then(|nanos| DateTime::from_timestamp(timestamp.seconds, nanos))
+}
+
+/// Converts an optional protobuf timestamp, `invalid` describes the field when it's out of range
+fn optional_time(
+    timestamp: Option<Timestamp>,
+    invalid: MessageKey,
+) -> Result<Option<DateTime<Utc>>, OperationError> {
+    timestamp
+        .map(|ts| from_timestamp(ts).ok_or(OperationError::InvalidArgument(invalid)))
+        .transpose()
+}
+
+fn to_status(err: &OperationError, l10n: &Localizer) -> Status {
+    let message = l10n.get(err.message_key());
+
+    match err {
+        OperationError::NotFound => Status::not_found(message),
+        OperationError::PreconditionFailed => Status::failed_precondition(message),
+        OperationError::InvalidArgument(_) => Status::invalid_argument(message),
+        OperationError::Duplicate(id) => {
+            let mut status = Status::already_exists(message);
+            status.metadata_mut().insert("duplicate-of", (*id).into());
+            status
+        }
+        OperationError::Database { source, .. } if source.is_transient() => {
+            Status::unavailable(message)
+        }
+        OperationError::Database { .. } => Status::internal(message),
+        OperationError::Email(e) => email_unavailable(message, e),
+    }
+}
+
+/// `UNAVAILABLE` carrying the email service failure in the `email-error` metadata, along with
+/// the standard `grpc-retry-pushback-ms` when the email service asks to back off
+fn email_unavailable(message: String, err: &EmailError) -> Status {
+    let mut status = Status::unavailable(message);
+    let metadata = status.metadata_mut();
+
+    if let Ok(details) = MetadataValue::try_from(err.to_string()) {
+        metadata.insert("email-error", details);
+    }
+    if let EmailError::Throttled { retry_after } = err {
+        let retry_after = retry_after.unwrap_or(DEFAULT_RETRY_AFTER).as_millis();
+        metadata.insert(
+            "grpc-retry-pushback-ms",
+            u64::try_from(retry_after).unwrap_or(u64::MAX).into(),
+        );
+    }
+    status
+}
+
+/// Error of a single batch item, with the code and message the call would have failed with
+fn to_item_error(err: &OperationError, l10n: &Localizer) -> BatchItemError {
+    let status = to_status(err, l10n);
+
+    BatchItemError {
+        code: status.code().into(),
+        message: status.message().to_string(),
+    }
+}
+
+/// Parses a `grpc-timeout` value: up to 8 digits followed by the unit
+fn parse_timeout(value: &str) -> Option<Duration> {
+    let (digits, unit) = value.split_at_checked(value.len().checked_sub(1)?)?;
+    if digits.is_empty() || digits.len() > 8 || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let amount: u64 = digits.parse().ok()?;
+
+    match unit {
+        "H" => Some(Duration::from_secs(amount * 60 * 60)),
+        "M" => Some(Duration::from_secs(amount * 60)),
+        "S" => Some(Duration::from_secs(amount)),
+        "m" => Some(Duration::from_millis(amount)),
+        "u" => Some(Duration::from_micros(amount)),
+        "n" => Some(Duration::from_nanos(amount)),
+        _ => None,
+    }
+}
+
+/// Language and deadline of a single call
+struct CallContext {
+    l10n: Localizer,
+    deadline: Option<Instant>,
+}
+
+impl CallContext {
+    fn status(&self, err: &OperationError) -> Status {
+        to_status(err, &self.l10n)
+    }
+
+    fn deadline_exceeded(&self) -> Status {
+        Status::deadline_exceeded(self.l10n.get(MessageKey::DeadlineExceeded))
+    }
+
+    /// Runs the handler until the deadline. A timed out handler is dropped, so it stops
+    /// waiting for the database, though a query already sent still runs to completion
+    #[allow(clippy::result_large_err)]
+    async fn within_deadline<T>(
+        &self,
+        handler: impl Future<Output = Result<T, Status>>,
+    ) -> Result<T, Status> {
+        match self.deadline {
+            Some(deadline) => time::timeout_at(deadline, handler)
+                .await
+                .map_err(|_| self.deadline_exceeded())?,
+            None => handler.await,
+        }
+    }
+
+    /// Runs the operation until the deadline
+    #[allow(clippy::result_large_err)]
+    async fn run<T>(
+        &self,
+        operation: impl Future<Output = Result<T, OperationError>>,
+    ) -> Result<T, Status> {
+        self.within_deadline(async { operation.await.map_err(|e| self.status(&e)) })
+            .await
+    }
+
+    /// Streams the items until the deadline, then ends the stream with `DEADLINE_EXCEEDED`.
+    /// tonic's own timeout only covers the call up to the response headers
+    fn bound<T: Send + 'static>(
+        self,
+        items: impl Stream<Item = Result<T, OperationError>> + Send + 'static,
+    ) -> Pin<Box<dyn Stream<Item = Result<T, Status>> + Send>> {
+        let expired = self
+            .deadline
+            .map(|deadline| (deadline, self.deadline_exceeded()));
+        #[allow(clippy::result_large_err)]
+        let items = items.map(move |item| item.map_err(|e| self.status(&e)));
+
+        let Some((deadline, expired)) = expired else {
+            return Box::pin(items);
+        };
+        let tail =
+            stream::once(async move { (Instant::now() >= deadline).then_some(Err(expired)) })
+                .filter_map(future::ready);
+
+        Box::pin(items.take_until(time::sleep_until(deadline)).chain(tail))
+    }
+}
+
+fn create_request(req: CreateNoteRequest) -> Result<dto::CreateNoteRequest, OperationError> {
+    Ok(dto::CreateNoteRequest {
+        content: req.content,
+        expires_at: optional_time(req.expires_at, MessageKey::InvalidExpiration)?,
+        remind_at: optional_time(req.remind_at, MessageKey::InvalidReminder)?,
+    })
+}
+
+/// Converts a note of a batch or an import along with normalizing its content, so a
+/// rejected note fails on its own instead of failing all of them in the service
+fn batch_item(
+    req: CreateNoteRequest,
+    service: &NoteService,
+) -> Result<dto::CreateNoteRequest, OperationError> {
+    let mut req = create_request(req)?;
+    req.content = service.normalize_content(req.content)?;
+    Ok(req)
+}
+
+impl From<dto::NoteResponse> for NoteResponse {
+    fn from(note: dto::NoteResponse) -> Self {
+        Self {
+            id: note.id,
+            content: note.content,
+            expires_at: note.expires_at.map(to_timestamp),
+            remind_at: note.remind_at.map(to_timestamp),
+            created_at: Some(to_timestamp(note.created_at)),
+            updated_at: Some(to_timestamp(note.updated_at)),
+        }
+    }
+}
+
+impl From<service::NoteOperation> for NoteOperation {
+    fn from(operation: service::NoteOperation) -> Self {
+        match operation {
+            service::NoteOperation::Created => Self::Created,
+            service::NoteOperation::Updated => Self::Updated,
+            service::NoteOperation::Deleted => Self::Deleted,
+        }
+    }
+}
+
+impl From<service::NoteEvent> for NoteEvent {
+    fn from(event: service::NoteEvent) -> Self {
+        Self {
+            id: event.id,
+            operation: NoteOperation::from(event.operation).into(),
+            timestamp: Some(to_timestamp(event.timestamp)),
+        }
+    }
+}
+
 // gRPC service implementation
 pub struct GrpcNoteService {
     service: Arc<NoteService>,
+    catalog: Arc<Catalog>,
 }
 
 impl GrpcNoteService {
-    pub const fn new(service: Arc<NoteService>) -> Self {
-        Self { service }
+    pub const fn new(service: Arc<NoteService>, catalog: Arc<Catalog>) -> Self {
+        Self { service, catalog }
+    }
+
+    /// Context from the request's `accept-language` and `grpc-timeout` metadata
+    fn context<T>(&self, request: &Request<T>) -> CallContext {
+        let metadata = request.metadata();
+        let accept_language = metadata
+            .get("accept-language")
+            .and_then(|v| v.to_str().ok());
+        let deadline = metadata
+            .get("grpc-timeout")
+            .and_then(|v| v.to_str().ok())
+            .and_then(parse_timeout)
+            .map(|timeout| Instant::now() + timeout.saturating_sub(DEADLINE_MARGIN));
+
+        CallContext {
+            l10n: Localizer::new(self.catalog.clone(), accept_language),
+            deadline,
+        }
+    }
+
+    /// A page of notes ordered by ID, the page token is the ID of the last note of the
+    /// previous page
+    async fn notes_page(
+        &self,
+        req: GetAllNotesRequest,
+    ) -> Result<GetAllNotesResponse, OperationError> {
+        let limit = u32::try_from(req.page_size)
+            .ok()
+            .filter(|&size| size > 0)
+            .unwrap_or(DEFAULT_PAGE_SIZE)
+            .min(MAX_PAGE_SIZE);
+        let after_id = match req.page_token.trim() {
+            "" => 0,
+            token => token
+                .parse::<i64>()
+                .map_err(|_| OperationError::InvalidArgument(MessageKey::InvalidPageToken))?,
+        };
+
+        let page = operations::ListNotesAfter {
+            after_id,
+            limit: limit.into(),
+            filter: MetadataFilter::default(),
+            include_archived: false,
+        }
+        .execute(&self.service)
+        .await?;
+
+        Ok(GetAllNotesResponse {
+            notes: page.items.into_iter().map(Into::into).collect(),
+            next_page_token: page.next_after.map(|id| id.to_string()).unwrap_or_default(),
+        })
     }
 }
 
 #[tonic::async_trait]
 impl NoteServiceTrait for GrpcNoteService {
+    type ListNotesStream = Pin<Box<dyn Stream<Item = Result<NoteResponse, Status>> + Send>>;
+    type WatchNotesStream = Pin<Box<dyn Stream<Item = Result<NoteEvent, Status>> + Send>>;
+
     async fn create_note(
         &self,
         request: Request<CreateNoteRequest>,
     ) -> Result<Response<NoteResponse>, Status> {
-        let req = request.into_inner();
-        let dto_req = crate::dto::CreateNoteRequest {
-            content: req.content,
-        };
+        let call = self.context(&request);
+        let req = create_request(request.into_inner()).map_err(|e| call.status(&e))?;
 
-        match self.service.create_note(dto_req).await {
-            Ok(note) => Ok(Response::new(NoteResponse {
-                id: note.id,
-                content: note.content,
-            })),
-            Err(e) => {
-                tracing::error!("Failed to create note: {e}");
-                Err(Status::internal("Failed to create note"))
-            }
-        }
+        call.run(operations::CreateNote(req).execute(&self.service))
+            .await
+            .map(|note| Response::new(note.into()))
     }
 
     async fn get_note(
         &self,
         request: Request<GetNoteRequest>,
     ) -> Result<Response<NoteResponse>, Status> {
+        let call = self.context(&request);
         let req = request.into_inner();
 
-        match self.service.get_one_note(req.id).await {
-            Ok(Some(note)) => Ok(Response::new(NoteResponse {
-                id: note.id,
-                content: note.content,
-            })),
-            Ok(None) => Err(Status::not_found("Note not found")),
-            Err(e) => {
-                tracing::error!("Failed to get note: {e}");
-                Err(Status::internal("Failed to get note"))
-            }
-        }
+        call.run(operations::GetNote { id: req.id }.execute(&self.service))
+            .await
+            .map(|note| Response::new(note.into()))
     }
 
     async fn get_all_notes(
         &self,
-        _request: Request<GetAllNotesRequest>,
+        request: Request<GetAllNotesRequest>,
     ) -> Result<Response<GetAllNotesResponse>, Status> {
-        match self.service.get_all_notes().await {
-            Ok(notes) => {
-                let grpc_notes: Vec<NoteResponse> = notes
-                    .into_iter()
-                    .map(|note| NoteResponse {
-                        id: note.id,
-                        content: note.content,
-                    })
-                    .collect();
+        let call = self.context(&request);
+        let req = request.into_inner();
 
-                Ok(Response::new(GetAllNotesResponse { notes: grpc_notes }))
-            }
-            Err(e) => {
-                tracing::error!("Failed to get all notes: {e}");
-                Err(Status::internal("Failed to get all notes"))
+        call.run(async {
+            if req.page_size == 0 && req.page_token.is_empty() {
+                operations::GetAllNotes
+                    .execute(&self.service)
+                    .await
+                    .map(|notes| GetAllNotesResponse {
+                        notes: notes.into_iter().map(Into::into).collect(),
+                        next_page_token: String::new(),
+                    })
+            } else {
+                self.notes_page(req).await
             }
-        }
+        })
+        .await
+        .map(Response::new)
+    }
+
+    async fn list_notes(
+        &self,
+        request: Request<ListNotesRequest>,
+    ) -> Result<Response<Self::ListNotesStream>, Status> {
+        let call = self.context(&request);
+        let notes = operations::StreamNotes::stream(&self.service).map(|note| note.map(Into::into));
+
+        Ok(Response::new(call.bound(notes)))
+    }
+
+    async fn watch_notes(
+        &self,
+        request: Request<WatchNotesRequest>,
+    ) -> Result<Response<Self::WatchNotesStream>, Status> {
+        let call = self.context(&request);
+        let events = self
+            .service
+            .subscribe_events()
+            .map(|event| Ok(event.into()));
+
+        Ok(Response::new(call.bound(events)))
     }
 
     async fn update_note(
         &self,
         request: Request<UpdateNoteRequest>,
     ) -> Result<Response<NoteResponse>, Status> {
+        let call = self.context(&request);
         let req = request.into_inner();
-        let dto_req = crate::dto::UpdateNoteRequest {
-            content: req.content,
+
+        let update = operations::UpdateNote {
+            id: req.id,
+            request: dto::UpdateNoteRequest {
+                content: req.content,
+                expires_at: optional_time(req.expires_at, MessageKey::InvalidExpiration)
+                    .map_err(|e| call.status(&e))?,
+                remind_at: optional_time(req.remind_at, MessageKey::InvalidReminder)
+                    .map_err(|e| call.status(&e))?,
+                clear_expires_at: req.clear_expires_at,
+                clear_remind_at: req.clear_remind_at,
+            },
+            expected_versions: None,
         };
 
-        match self.service.update_note(req.id, dto_req).await {
-            Ok(Some(note)) => Ok(Response::new(NoteResponse {
-                id: note.id,
-                content: note.content,
-            })),
-            Ok(None) => Err(Status::not_found("Note not found")),
-            Err(e) => {
-                tracing::error!("Failed to update note: {e}");
-                Err(Status::internal("Failed to update note"))
-            }
-        }
+        call.run(update.execute(&self.service))
+            .await
+            .map(|note| Response::new(note.into()))
     }
 
     async fn delete_note(
         &self,
         request: Request<DeleteNoteRequest>,
     ) -> Result<Response<DeleteNoteResponse>, Status> {
+        let call = self.context(&request);
         let req = request.into_inner();
 
-        match self.service.delete_note(req.id).await {
-            Ok(true) => Ok(Response::new(DeleteNoteResponse { success: true })),
-            Ok(false) => Err(Status::not_found("Note not found")),
-            Err(e) => {
-                tracing::error!("Failed to delete note: {e}");
-                Err(Status::internal("Failed to delete note"))
-            }
+        let delete = operations::DeleteNote {
+            id: req.id,
+            expected_versions: None,
+        };
+
+        call.run(delete.execute(&self.service))
+            .await
+            .map(|()| Response::new(DeleteNoteResponse { success: true }))
+    }
+
+    async fn batch_create_notes(
+        &self,
+        request: Request<BatchCreateNotesRequest>,
+    ) -> Result<Response<BatchCreateNotesResponse>, Status> {
+        let call = self.context(&request);
+
+        // Invalid requests get their error, the valid ones are created with a single statement
+        let requests = request.into_inner().requests;
+        let mut valid = Vec::with_capacity(requests.len());
+        let mut outcomes = Vec::with_capacity(requests.len());
+        for req in requests {
+            outcomes.push(batch_item(req, &self.service).map(|req| valid.push(req)));
         }
+
+        let mut notes = call
+            .run(operations::BatchCreateNotes(valid).execute(&self.service))
+            .await?
+            .into_iter();
+
+        let results = outcomes
+            .into_iter()
+            .map(|outcome| BatchCreateNoteResult {
+                result: match outcome {
+                    Ok(()) => notes
+                        .next()
+                        .map(|note| batch_create_note_result::Result::Note(note.into())),
+                    Err(e) => Some(batch_create_note_result::Result::Error(to_item_error(
+                        &e, &call.l10n,
+                    ))),
+                },
+            })
+            .collect();
+
+        Ok(Response::new(BatchCreateNotesResponse { results }))
+    }
+
+    async fn batch_delete_notes(
+        &self,
+        request: Request<BatchDeleteNotesRequest>,
+    ) -> Result<Response<BatchDeleteNotesResponse>, Status> {
+        let call = self.context(&request);
+        let ids = request.into_inner().ids;
+
+        let deleted: HashSet<i64> = call
+            .run(operations::BatchDeleteNotes { ids: ids.clone() }.execute(&self.service))
+            .await?
+            .into_iter()
+            .collect();
+
+        let results = ids
+            .into_iter()
+            .map(|id| BatchDeleteNoteResult {
+                id,
+                error: (!deleted.contains(&id))
+                    .then(|| to_item_error(&OperationError::NotFound, &call.l10n)),
+            })
+            .collect();
+
+        Ok(Response::new(BatchDeleteNotesResponse { results }))
+    }
+
+    async fn import_notes(
+        &self,
+        request: Request<Streaming<CreateNoteRequest>>,
+    ) -> Result<Response<ImportSummary>, Status> {
+        let call = self.context(&request);
+        let failed = Arc::new(AtomicU64::new(0));
+
+        let invalid = Arc::clone(&failed);
+        let service = Arc::clone(&self.service);
+        let batches = request
+            .into_inner()
+            .try_filter_map(move |req| {
+                let req = batch_item(req, &service).ok();
+                if req.is_none() {
+                    invalid.fetch_add(1, Ordering::Relaxed);
+                }
+                future::ready(Ok(req))
+            })
+            .try_chunks(IMPORT_BATCH_SIZE)
+            .map_err(|TryChunksError(_, status)| status);
+
+        let import = async {
+            let created = operations::ImportNotes(batches)
+                .execute(&self.service)
+                .await
+                .map_err(|e| match e {
+                    ImportError::Source(status) => status,
+                    ImportError::Operation(e) => call.status(&e),
+                })?;
+
+            Ok(ImportSummary {
+                created,
+                failed: failed.load(Ordering::Relaxed),
+            })
+        };
+
+        call.within_deadline(import).await.map(Response::new)
     }
+
+    async fn share_notes(
+        &self,
+        request: Request<ShareNotesRequest>,
+    ) -> Result<Response<ShareNotesResponse>, Status> {
+        let call = self.context(&request);
+        let req = request.into_inner();
+
+        let sent = call
+            .run(async {
+                match req.note_id {
+                    Some(id) => operations::ShareNote {
+                        id,
+                        email: req.email,
+                    }
+                    .execute(&self.service)
+                    .await
+                    .map(|()| MessageKey::NoteSent),
+                    None => operations::ShareNotes { email: req.email }
+                        .execute(&self.service)
+                        .await
+                        .map(|()| MessageKey::NotesSent),
+                }
+            })
+            .await?;
+
+        Ok(Response::new(ShareNotesResponse {
+            message: call.l10n.get(sent),
+        }))
+    }
+}
+
+/// Largest messages the gRPC service decodes and encodes, in bytes. tonic's defaults
+/// (4 MiB decoded, unlimited encoded) apply to the ones not set
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MessageLimits {
+    pub max_decoding_size: Option<usize>,
+    pub max_encoding_size: Option<usize>,
 }
 
-pub fn create_grpc_server(service: Arc<NoteService>) -> NoteServiceServer<GrpcNoteService> {
-    NoteServiceServer::new(GrpcNoteService::new(service))
+pub fn create_grpc_server(
+    service: Arc<NoteService>,
+    catalog: Arc<Catalog>,
+    limits: MessageLimits,
+) -> NoteServiceServer<GrpcNoteService> {
+    let mut server = NoteServiceServer::new(GrpcNoteService::new(service, catalog));
+    if let Some(size) = limits.max_decoding_size {
+        server = server.max_decoding_message_size(size);
+    }
+    if let Some(size) = limits.max_encoding_size {
+        server = server.max_encoding_message_size(size);
+    }
+    server
 }
@@ -0,0 +1,236 @@
+use axum::http::{Request, Response};
+use bytes::Bytes;
+use futures_util::future::{self, BoxFuture};
+use http_body::Body;
+use tonic::{Status, body::BoxBody};
+use tower::{BoxError, Layer, Service};
+
+use std::{
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use crate::{
+    i18n::{Catalog, Localizer, MessageKey},
+    middleware::Quotas,
+    mtls::{AllowedClients, ClientIdentity},
+    oidc::{self, OidcAuthenticator},
+    signing::{ContentVerified, RequestSigning, SignedBy},
+    tenant::{Principal, TenantKeys},
+};
+
+/// Runs every call of the wrapped gRPC services as the tenant of its credentials: the
+/// user of its OIDC bearer token in `authorization`, or its `x-api-key` metadata.
+/// Answers `UNAUTHENTICATED` when they're missing or invalid, and `RESOURCE_EXHAUSTED`
+/// once the caller used up a quota. Without OIDC and keys every call belongs to the
+/// default tenant, its caller is the service of its signing key or client certificate if
+/// any. Calls from clients whose certificate isn't allowed are answered `PERMISSION_DENIED`
+/// first, then unsigned ones `UNAUTHENTICATED` when requests must be signed
+#[derive(Clone)]
+pub struct TenantLayer {
+    keys: Option<Arc<TenantKeys>>,
+    oidc: Option<Arc<OidcAuthenticator>>,
+    quotas: Option<Arc<Quotas>>,
+    allowed_clients: Option<Arc<AllowedClients>>,
+    signing: Option<Arc<RequestSigning>>,
+    catalog: Arc<Catalog>,
+}
+
+impl TenantLayer {
+    pub const fn new(
+        keys: Option<Arc<TenantKeys>>,
+        oidc: Option<Arc<OidcAuthenticator>>,
+        quotas: Option<Arc<Quotas>>,
+        allowed_clients: Option<Arc<AllowedClients>>,
+        signing: Option<Arc<RequestSigning>>,
+        catalog: Arc<Catalog>,
+    ) -> Self {
+        Self {
+            keys,
+            oidc,
+            quotas,
+            allowed_clients,
+            signing,
+            catalog,
+        }
+    }
+
+    /// The request with its body checked against the signed hash, the response to answer
+    /// with when its signature isn't accepted. gRPC-Web calls were already checked by the
+    /// HTTP listener
+    #[allow(clippy::result_large_err)]
+    fn verify_signature<B>(
+        &self,
+        request: Request<B>,
+    ) -> Result<Request<BoxBody>, Response<BoxBody>>
+    where
+        B: Body<Data = Bytes> + Unpin + Send + 'static,
+        B::Error: Into<BoxError>,
+    {
+        let Some(signing) = &self.signing else {
+            return Ok(request.map(tonic::body::boxed));
+        };
+        if request.extensions().get::<SignedBy>().is_some() {
+            return Ok(request.map(tonic::body::boxed));
+        }
+
+        match signing.verify(request.method(), request.uri(), request.headers()) {
+            Ok((signed_by, content_sha256)) => {
+                let (mut parts, body) = request.into_parts();
+                parts.extensions.insert(signed_by);
+                let body = tonic::body::boxed(ContentVerified::new(body, content_sha256));
+                Ok(Request::from_parts(parts, body))
+            }
+            Err(e) => {
+                tracing::warn!(
+                    method = request.uri().path(),
+                    "Request signature rejected: {e}"
+                );
+                let l10n = self.localizer(&request);
+                let message = format!("{}: {e}", l10n.get(MessageKey::InvalidSignature));
+                Err(Status::unauthenticated(message).into_http())
+            }
+        }
+    }
+
+    /// Counts the call against the caller's quotas, the response to answer with
+    /// when one is used up
+    fn admit(&self, principal: &Principal, l10n: &Localizer) -> Option<Response<BoxBody>> {
+        let quotas = self.quotas.as_ref()?;
+        if quotas.try_acquire(principal) {
+            return None;
+        }
+
+        tracing::warn!(%principal, "Quota exceeded");
+        let status = Status::resource_exhausted(l10n.get(MessageKey::QuotaExceeded));
+        Some(status.into_http())
+    }
+
+    fn localizer<B>(&self, request: &Request<B>) -> Localizer {
+        let accept_language = request
+            .headers()
+            .get("accept-language")
+            .and_then(|v| v.to_str().ok());
+        Localizer::new(self.catalog.clone(), accept_language)
+    }
+}
+
+impl<S> Layer<S> for TenantLayer {
+    type Service = TenantScoped<S>;
+
+    fn layer(&self, inner: S) -> TenantScoped<S> {
+        TenantScoped {
+            inner,
+            layer: self.clone(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct TenantScoped<S> {
+    inner: S,
+    layer: TenantLayer,
+}
+
+impl<S, ReqBody> Service<Request<ReqBody>> for TenantScoped<S>
+where
+    S: Service<Request<BoxBody>, Response = Response<BoxBody>> + Clone + Send + 'static,
+    S::Future: Send + 'static,
+    S::Error: Send + 'static,
+    ReqBody: Body<Data = Bytes> + Unpin + Send + 'static,
+    ReqBody::Error: Into<BoxError>,
+{
+    type Response = Response<BoxBody>;
+    type Error = S::Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request<ReqBody>) -> Self::Future {
+        let client = ClientIdentity::of_request(&request);
+        if let Some(allowed) = &self.layer.allowed_clients
+            && !allowed.allows(client.as_ref())
+        {
+            let client = client.map_or_else(|| "none".to_string(), |client| client.to_string());
+            tracing::warn!(%client, "Client certificate not allowed");
+            let l10n = self.layer.localizer(&request);
+            let status = Status::permission_denied(l10n.get(MessageKey::ClientNotAllowed));
+            return Box::pin(future::ok(status.into_http()));
+        }
+        let request = match self.layer.verify_signature(request) {
+            Ok(request) => request,
+            Err(rejection) => return Box::pin(future::ok(rejection)),
+        };
+
+        if let Some(oidc) = self.layer.oidc.clone()
+            && let Some(token) = oidc::bearer_token(request.headers()).map(str::to_owned)
+        {
+            // The token is checked asynchronously, so the service made ready by
+            // `poll_ready` is taken for the call and a clone left in its place
+            let clone = self.inner.clone();
+            let mut inner = std::mem::replace(&mut self.inner, clone);
+            let l10n = self.layer.localizer(&request);
+            let layer = self.layer.clone();
+            return Box::pin(async move {
+                match oidc.authenticate(&token).await {
+                    Ok((tenant, principal)) => {
+                        if let Some(rejection) = layer.admit(&principal, &l10n) {
+                            return Ok(rejection);
+                        }
+                        let response = tenant.clone().sync_scope(|| inner.call(request));
+                        tenant.scope(response).await
+                    }
+                    Err(e) if e.is_rejection() => Ok(Status::unauthenticated(format!(
+                        "{}: {e}",
+                        l10n.get(MessageKey::InvalidToken)
+                    ))
+                    .into_http()),
+                    Err(e) => {
+                        tracing::error!("Failed to authenticate the call: {e}");
+                        Ok(Status::unavailable(l10n.get(MessageKey::AuthUnavailable)).into_http())
+                    }
+                }
+            });
+        }
+
+        let keys = self.layer.keys.as_ref();
+        if keys.is_none() && self.layer.oidc.is_none() {
+            let signed_by = request
+                .extensions()
+                .get::<SignedBy>()
+                .map(SignedBy::principal);
+            if let Some(principal) = signed_by.or_else(|| client.map(|client| client.principal()))
+                && let Some(rejection) = self
+                    .layer
+                    .admit(&principal, &self.layer.localizer(&request))
+            {
+                return Box::pin(future::ok(rejection));
+            }
+            return Box::pin(self.inner.call(request));
+        }
+
+        let Some((tenant, principal)) = keys.and_then(|keys| keys.resolve(request.headers()))
+        else {
+            let l10n = self.layer.localizer(&request);
+            let key = if self.layer.oidc.is_some() {
+                MessageKey::InvalidToken
+            } else {
+                MessageKey::InvalidApiKey
+            };
+            let status = Status::unauthenticated(l10n.get(key));
+            return Box::pin(future::ok(status.into_http()));
+        };
+
+        if let Some(rejection) = self
+            .layer
+            .admit(&principal, &self.layer.localizer(&request))
+        {
+            return Box::pin(future::ok(rejection));
+        }
+
+        let response = tenant.clone().sync_scope(|| self.inner.call(request));
+        Box::pin(tenant.scope(response))
+    }
+}
@@ -0,0 +1,120 @@
+use axum::{
+    body::{Body, Bytes},
+    extract::{Request, State},
+    http::{HeaderMap, HeaderValue, StatusCode, header},
+    response::{IntoResponse, Response},
+};
+use base64::{Engine, engine::general_purpose::STANDARD};
+use futures_util::StreamExt;
+use http_body::Frame;
+use http_body_util::{BodyExt, BodyStream, Collected, StreamBody};
+use tower::ServiceExt;
+
+use super::{
+    GrpcNoteService, metrics::Metered, notes::note_service_server::NoteServiceServer,
+    tenant::TenantScoped,
+};
+
+/// Flag of the length-prefixed message carrying the trailers
+const TRAILERS_FLAG: u8 = 0x80;
+
+/// How gRPC-Web messages are put in HTTP bodies
+#[derive(Debug, Clone, Copy)]
+enum Encoding {
+    /// `application/grpc-web`, messages as is
+    Binary,
+    /// `application/grpc-web-text`, messages in base64, for clients that can't read binary bodies
+    Text,
+}
+
+impl Encoding {
+    fn from_headers(headers: &HeaderMap) -> Option<Self> {
+        let content_type = headers.get(header::CONTENT_TYPE)?.to_str().ok()?;
+        let mime = content_type.split(';').next()?.trim();
+
+        match mime {
+            "application/grpc-web" | "application/grpc-web+proto" => Some(Self::Binary),
+            "application/grpc-web-text" | "application/grpc-web-text+proto" => Some(Self::Text),
+            _ => None,
+        }
+    }
+
+    fn encode(self, data: Bytes) -> Bytes {
+        match self {
+            Self::Binary => data,
+            Self::Text => STANDARD.encode(data).into(),
+        }
+    }
+}
+
+/// gRPC-Web trailers message: `name:value` lines prefixed like any other message
+fn trailers_message(trailers: &HeaderMap) -> Bytes {
+    let mut block = Vec::new();
+    for (name, value) in trailers {
+        block.extend_from_slice(name.as_str().as_bytes());
+        block.push(b':');
+        block.extend_from_slice(value.as_bytes());
+        block.extend_from_slice(b"\r\n");
+    }
+
+    let mut message = Vec::with_capacity(block.len() + 5);
+    message.push(TRAILERS_FLAG);
+    message.extend_from_slice(&u32::try_from(block.len()).unwrap_or(u32::MAX).to_be_bytes());
+    message.extend_from_slice(&block);
+    message.into()
+}
+
+/// Serves gRPC-Web requests from browsers by translating them to gRPC for the notes
+/// service and translating its responses back, trailers included. Responses are
+/// streamed, so server-streaming calls work as well
+pub async fn handle_request(
+    State(server): State<Metered<TenantScoped<NoteServiceServer<GrpcNoteService>>>>,
+    request: Request,
+) -> Response {
+    let Some(encoding) = Encoding::from_headers(request.headers()) else {
+        return StatusCode::UNSUPPORTED_MEDIA_TYPE.into_response();
+    };
+    let response_type = request.headers()[header::CONTENT_TYPE].clone();
+
+    let (mut parts, body) = request.into_parts();
+    parts.headers.insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static("application/grpc"),
+    );
+    let body = match encoding {
+        Encoding::Binary => body,
+        Encoding::Text => {
+            let Ok(text) = body.collect().await.map(Collected::to_bytes) else {
+                return StatusCode::BAD_REQUEST.into_response();
+            };
+            // Whitespace may separate the chunks of a streamed body
+            let text: Vec<u8> = text
+                .into_iter()
+                .filter(|b| !b.is_ascii_whitespace())
+                .collect();
+            let Ok(messages) = STANDARD.decode(text) else {
+                return StatusCode::BAD_REQUEST.into_response();
+            };
+            parts.headers.remove(header::CONTENT_LENGTH);
+            Body::from(messages)
+        }
+    };
+
+    let Ok(response) = server.oneshot(Request::from_parts(parts, body)).await;
+    let (mut parts, body) = response.into_parts();
+    parts.headers.insert(header::CONTENT_TYPE, response_type);
+    parts.headers.remove(header::CONTENT_LENGTH);
+
+    #[allow(clippy::result_large_err)]
+    let frames = BodyStream::new(body).map(move |frame| {
+        frame.map(|frame| match frame.into_data() {
+            Ok(data) => Frame::data(encoding.encode(data)),
+            Err(frame) => {
+                let trailers = frame.into_trailers().unwrap_or_default();
+                Frame::data(encoding.encode(trailers_message(&trailers)))
+            }
+        })
+    });
+
+    Response::from_parts(parts, Body::new(StreamBody::new(frames)))
+}
@@ -0,0 +1,252 @@
+use std::sync::Arc;
+
+use axum::{
+    Json,
+    body::Bytes,
+    extract::State,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+use serde_json::Value;
+
+use crate::{
+    dto,
+    i18n::{Localizer, MessageKey},
+    operations::{self, Operation, OperationError},
+    service::NoteService,
+};
+
+const JSONRPC_VERSION: &str = "2.0";
+
+/// Error codes reserved by the JSON-RPC 2.0 specification
+mod code {
+    pub const PARSE_ERROR: i64 = -32700;
+    pub const INVALID_REQUEST: i64 = -32600;
+    pub const METHOD_NOT_FOUND: i64 = -32601;
+    pub const INVALID_PARAMS: i64 = -32602;
+    pub const INTERNAL_ERROR: i64 = -32603;
+
+    // Implementation-defined server errors
+    pub const NOTE_NOT_FOUND: i64 = -32001;
+    pub const NOTE_MODIFIED: i64 = -32002;
+    pub const EMAIL_FAILED: i64 = -32003;
+    pub const IDENTICAL_NOTE_EXISTS: i64 = -32004;
+}
+
+#[derive(Debug, Deserialize)]
+struct RpcRequest {
+    jsonrpc: String,
+    method: String,
+    #[serde(default)]
+    params: Value,
+}
+
+#[derive(Debug, Serialize)]
+struct RpcError {
+    code: i64,
+    message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    data: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct RpcResponse {
+    jsonrpc: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    result: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<RpcError>,
+    id: Value,
+}
+
+impl RpcResponse {
+    const fn success(id: Value, result: Value) -> Self {
+        Self {
+            jsonrpc: JSONRPC_VERSION,
+            result: Some(result),
+            error: None,
+            id,
+        }
+    }
+
+    const fn failure(id: Value, error: RpcError) -> Self {
+        Self {
+            jsonrpc: JSONRPC_VERSION,
+            result: None,
+            error: Some(error),
+            id,
+        }
+    }
+}
+
+/// Why a single call failed
+enum CallError {
+    InvalidRequest,
+    MethodNotFound,
+    InvalidParams(serde_json::Error),
+    Operation(OperationError),
+}
+
+impl CallError {
+    fn into_rpc_error(self, l10n: &Localizer) -> RpcError {
+        let (code, key, data) = match self {
+            Self::InvalidRequest => (code::INVALID_REQUEST, MessageKey::RpcInvalidRequest, None),
+            Self::MethodNotFound => (code::METHOD_NOT_FOUND, MessageKey::RpcMethodNotFound, None),
+            Self::InvalidParams(e) => (
+                code::INVALID_PARAMS,
+                MessageKey::RpcInvalidParams,
+                Some(e.to_string()),
+            ),
+            Self::Operation(e) => {
+                let code = match &e {
+                    OperationError::NotFound => code::NOTE_NOT_FOUND,
+                    OperationError::PreconditionFailed => code::NOTE_MODIFIED,
+                    OperationError::InvalidArgument(_) => code::INVALID_PARAMS,
+                    OperationError::Duplicate(_) => code::IDENTICAL_NOTE_EXISTS,
+                    OperationError::Database { .. } => code::INTERNAL_ERROR,
+                    OperationError::Email(_) => code::EMAIL_FAILED,
+                };
+                // The existing note's ID, so clients can open it instead
+                let data = match &e {
+                    OperationError::Duplicate(id) => Some(id.to_string()),
+                    _ => None,
+                };
+                (code, e.message_key(), data)
+            }
+        };
+
+        RpcError {
+            code,
+            message: l10n.get(key),
+            data,
+        }
+    }
+}
+
+/// Params of the methods addressing a single note
+#[derive(Debug, Deserialize)]
+struct NoteIdParams {
+    id: i64,
+}
+
+#[derive(Debug, Deserialize)]
+struct UpdateNoteParams {
+    id: i64,
+    #[serde(flatten)]
+    request: dto::UpdateNoteRequest,
+}
+
+fn parse_params<T: DeserializeOwned>(params: Value) -> Result<T, CallError> {
+    serde_json::from_value(params).map_err(CallError::InvalidParams)
+}
+
+fn to_result<T: Serialize>(output: Result<T, OperationError>) -> Result<Value, CallError> {
+    let output = output.map_err(CallError::Operation)?;
+    // Plain data structures always serialize
+    Ok(serde_json::to_value(output).unwrap_or_default())
+}
+
+async fn call(service: &NoteService, method: &str, params: Value) -> Result<Value, CallError> {
+    match method {
+        "notes.create" => {
+            let request: dto::CreateNoteRequest = parse_params(params)?;
+            to_result(operations::CreateNote(request).execute(service).await)
+        }
+        "notes.get" => {
+            let NoteIdParams { id } = parse_params(params)?;
+            to_result(operations::GetNote { id }.execute(service).await)
+        }
+        "notes.list" => to_result(operations::GetAllNotes.execute(service).await),
+        "notes.update" => {
+            let UpdateNoteParams { id, request } = parse_params(params)?;
+            let op = operations::UpdateNote {
+                id,
+                request,
+                expected_versions: None,
+            };
+            to_result(op.execute(service).await)
+        }
+        "notes.delete" => {
+            let NoteIdParams { id } = parse_params(params)?;
+            let op = operations::DeleteNote {
+                id,
+                expected_versions: None,
+            };
+            to_result(op.execute(service).await.map(|()| true))
+        }
+        _ => Err(CallError::MethodNotFound),
+    }
+}
+
+/// Handles a single request object, returns `None` for notifications (requests without an id)
+async fn handle_one(service: &NoteService, l10n: &Localizer, value: Value) -> Option<RpcResponse> {
+    let id = match value.get("id") {
+        None => None,
+        Some(id @ (Value::Null | Value::Number(_) | Value::String(_))) => Some(id.clone()),
+        Some(_) => {
+            let error = CallError::InvalidRequest.into_rpc_error(l10n);
+            return Some(RpcResponse::failure(Value::Null, error));
+        }
+    };
+
+    let request = match serde_json::from_value::<RpcRequest>(value) {
+        Ok(request) if request.jsonrpc == JSONRPC_VERSION => request,
+        // Invalid requests are answered even without an id
+        _ => {
+            let error = CallError::InvalidRequest.into_rpc_error(l10n);
+            return Some(RpcResponse::failure(id.unwrap_or_default(), error));
+        }
+    };
+
+    let result = call(service, &request.method, request.params).await;
+
+    // Notifications are executed, but never answered
+    let id = id?;
+    Some(match result {
+        Ok(result) => RpcResponse::success(id, result),
+        Err(e) => RpcResponse::failure(id, e.into_rpc_error(l10n)),
+    })
+}
+
+/// JSON-RPC 2.0 endpoint, accepts a single request or a batch
+pub async fn handle_request(
+    State(service): State<Arc<NoteService>>,
+    l10n: Localizer,
+    body: Bytes,
+) -> Response {
+    let value: Value = match serde_json::from_slice(&body) {
+        Ok(value) => value,
+        Err(e) => {
+            let error = RpcError {
+                code: code::PARSE_ERROR,
+                message: l10n.get(MessageKey::InvalidJson),
+                data: Some(e.to_string()),
+            };
+            return Json(RpcResponse::failure(Value::Null, error)).into_response();
+        }
+    };
+
+    match value {
+        Value::Array(requests) if requests.is_empty() => {
+            let error = CallError::InvalidRequest.into_rpc_error(&l10n);
+            Json(RpcResponse::failure(Value::Null, error)).into_response()
+        }
+        Value::Array(requests) => {
+            let mut responses = Vec::with_capacity(requests.len());
+            for request in requests {
+                responses.extend(handle_one(&service, &l10n, request).await);
+            }
+
+            if responses.is_empty() {
+                StatusCode::NO_CONTENT.into_response()
+            } else {
+                Json(responses).into_response()
+            }
+        }
+        request => handle_one(&service, &l10n, request).await.map_or_else(
+            || StatusCode::NO_CONTENT.into_response(),
+            |response| Json(response).into_response(),
+        ),
+    }
+}
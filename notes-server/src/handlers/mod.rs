@@ -1,3 +1,4 @@
 pub mod grpc;
+pub mod jsonrpc;
 pub mod rest;
 pub mod soap;
@@ -0,0 +1,92 @@
+use axum::{
+    Json,
+    body::Body,
+    extract::{FromRequestParts, Request},
+    http::header,
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use std::fmt::Display;
+
+use crate::i18n::{Localizer, MessageKey};
+
+/// Largest plain-text rejection body turned into an `ErrorResponse`
+const MAX_REJECTION_SIZE: usize = 16 * 1024;
+
+/// Body of every REST error response
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct ErrorResponse {
+    /// Stable machine-readable error code, e.g. `note_not_found`
+    pub code: String,
+    /// Human-readable message in the language negotiated with `Accept-Language`
+    pub message: String,
+    /// More information about the error, not localized
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub details: Option<String>,
+    /// ID of the existing note with the same content, for `identical_note_exists`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub duplicate_of: Option<i64>,
+}
+
+impl ErrorResponse {
+    pub fn new(key: MessageKey, l10n: &Localizer) -> Self {
+        Self {
+            code: key.key().to_string(),
+            message: l10n.get(key),
+            details: None,
+            duplicate_of: None,
+        }
+    }
+
+    #[must_use]
+    pub fn with_details(mut self, details: impl Display) -> Self {
+        self.details = Some(details.to_string());
+        self
+    }
+
+    #[must_use]
+    pub const fn with_duplicate_of(mut self, id: i64) -> Self {
+        self.duplicate_of = Some(id);
+        self
+    }
+}
+
+impl IntoResponse for ErrorResponse {
+    fn into_response(self) -> Response {
+        Json(self).into_response()
+    }
+}
+
+fn is_plain_text(response: &Response) -> bool {
+    response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.starts_with("text/plain"))
+}
+
+/// Turns the plain-text client errors axum's extractors reject requests with
+/// (malformed path or query parameters and such) into an `ErrorResponse`
+pub async fn structured_rejections(request: Request, next: Next) -> Response {
+    let (mut parts, body) = request.into_parts();
+    let Ok(l10n) = Localizer::from_request_parts(&mut parts, &()).await;
+    let response = next.run(Request::from_parts(parts, body)).await;
+
+    if !response.status().is_client_error() || !is_plain_text(&response) {
+        return response;
+    }
+
+    let (mut parts, body) = response.into_parts();
+    let Ok(bytes) = axum::body::to_bytes(body, MAX_REJECTION_SIZE).await else {
+        return Response::from_parts(parts, Body::empty());
+    };
+    let error = ErrorResponse::new(MessageKey::InvalidRequest, &l10n)
+        .with_details(String::from_utf8_lossy(&bytes));
+
+    parts.headers.remove(header::CONTENT_TYPE);
+    parts.headers.remove(header::CONTENT_LENGTH);
+    (parts, error).into_response()
+}
@@ -0,0 +1,104 @@
+use axum::{
+    body::Bytes,
+    extract::{FromRequest, FromRequestParts, Request},
+    http::{StatusCode, header},
+    response::{IntoResponse, Response},
+};
+use serde::de::DeserializeOwned;
+
+use super::ErrorResponse;
+use crate::i18n::{Localizer, MessageKey};
+
+/// How request bodies with fields unknown to the DTO are treated
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum JsonParsing {
+    /// Unknown fields are ignored (logged as a warning)
+    #[default]
+    Lenient,
+    /// Unknown fields are rejected with `422 UNPROCESSABLE_ENTITY`
+    Strict,
+}
+
+/// JSON body extractor honoring the `JsonParsing` mode installed as a request extension
+/// (lenient when absent)
+pub struct JsonBody<T>(pub T);
+
+fn is_json_content_type(req: &Request) -> bool {
+    req.headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.split(';').next())
+        .is_some_and(|mime| {
+            let mime = mime.trim().to_lowercase();
+            mime == "application/json" || mime.ends_with("+json")
+        })
+}
+
+impl<T, S> FromRequest<S> for JsonBody<T>
+where
+    T: DeserializeOwned,
+    S: Send + Sync,
+{
+    type Rejection = Response;
+
+    async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection> {
+        let mode = req
+            .extensions()
+            .get::<JsonParsing>()
+            .copied()
+            .unwrap_or_default();
+
+        let (mut parts, body) = req.into_parts();
+        let Ok(l10n) = Localizer::from_request_parts(&mut parts, state).await;
+        let req = Request::from_parts(parts, body);
+
+        if !is_json_content_type(&req) {
+            return Err((
+                StatusCode::UNSUPPORTED_MEDIA_TYPE,
+                ErrorResponse::new(MessageKey::ExpectedJson, &l10n),
+            )
+                .into_response());
+        }
+
+        let bytes = Bytes::from_request(req, state)
+            .await
+            .map_err(IntoResponse::into_response)?;
+
+        let mut unknown_fields = Vec::new();
+        let mut deserializer = serde_json::Deserializer::from_slice(&bytes);
+        let value = serde_ignored::deserialize(&mut deserializer, |path| {
+            unknown_fields.push(path.to_string());
+        })
+        .and_then(|value| deserializer.end().map(|()| value))
+        .map_err(|e| {
+            let status = if e.is_data() {
+                StatusCode::UNPROCESSABLE_ENTITY
+            } else {
+                StatusCode::BAD_REQUEST
+            };
+            (
+                status,
+                ErrorResponse::new(MessageKey::InvalidJson, &l10n).with_details(e),
+            )
+                .into_response()
+        })?;
+
+        if !unknown_fields.is_empty() {
+            let fields = unknown_fields.join(", ");
+            match mode {
+                JsonParsing::Strict => {
+                    return Err((
+                        StatusCode::UNPROCESSABLE_ENTITY,
+                        ErrorResponse::new(MessageKey::UnknownFields, &l10n).with_details(fields),
+                    )
+                        .into_response());
+                }
+                JsonParsing::Lenient => {
+                    tracing::warn!("Ignoring unknown fields in request body: {fields}");
+                }
+            }
+        }
+
+        Ok(Self(value))
+    }
+}
@@ -1,17 +1,52 @@
+mod error;
+mod json;
+mod negotiation;
+
+pub use error::{ErrorResponse, structured_rejections};
+pub use json::{JsonBody, JsonParsing};
+pub use negotiation::negotiate_format;
+
 use axum::{
     Json,
-    extract::{Path, State},
-    http::StatusCode,
-    response::{IntoResponse, Response},
+    body::Body,
+    extract::{Extension, Path, Query, State},
+    http::{HeaderMap, StatusCode, header},
+    response::{
+        IntoResponse, Response,
+        sse::{Event, KeepAlive, Sse},
+    },
 };
 use axum_macros::debug_handler;
-use utoipa::OpenApi;
+use chrono::{DateTime, Utc};
+use futures_util::StreamExt;
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, OpenApi, ToSchema};
 
-use std::sync::Arc;
+use std::{sync::Arc, time::Duration};
 
 use crate::{
-    dto::{CreateNoteRequest, NoteResponse, ShareNotesRequest, UpdateNoteRequest},
-    service::NoteService,
+    attachments::byte_stream,
+    backup::BackupError,
+    dto::{
+        AttachmentResponse, BackupResponse, CreateFromTemplateRequest, CreateNoteRequest,
+        CreateShareLinkRequest, DigestRequest, DigestResponse, DuplicateClusterResponse,
+        GenerateNotesResponse, MetadataPatch, MigrationResponse, MigrationStatusResponse,
+        NoteListResponse, NoteResponse, QuotaWindow, ReorderNotesRequest, RetentionReport,
+        RetentionRuleReport, ShareLinkResponse, ShareNotesRequest, SoapAuditResponse,
+        TemplateRequest, TemplateResponse, UpdateNoteRequest, UsageResponse, UserResponse,
+        WebhookDeliveryResponse, WebhookRequest, WebhookResponse,
+    },
+    email::EmailError,
+    i18n::{Localizer, MessageKey},
+    middleware::Quotas,
+    models::{MetadataFilter, NoteOrder},
+    operations::{self, Operation, OperationError},
+    repository::RepositoryError,
+    service::{
+        AttachmentError, DigestError, ExportFormat, FixtureSpec, InvalidDigest, NoteEvent,
+        NoteOperation, NoteService, RetentionPolicy, WebhookError, WriteError, parse_rules,
+    },
+    tenant::{Principal, Tenant},
 };
 
 #[derive(OpenApi)]
@@ -19,74 +54,316 @@ use crate::{
     paths(
         create_note,
         update_note,
+        patch_metadata,
         delete_note,
+        undo_delete,
+        duplicate_note,
+        duplicate_clusters,
+        reorder_notes,
         get_one_note,
         get_all_notes,
-        share_notes
+        export_notes,
+        note_events,
+        recent_activity,
+        search_notes,
+        share_notes,
+        share_note,
+        create_share_link,
+        get_shared_note,
+        create_template,
+        list_templates,
+        get_template,
+        update_template,
+        delete_template,
+        create_note_from_template,
+        create_webhook,
+        list_webhooks,
+        get_webhook,
+        update_webhook,
+        delete_webhook,
+        webhook_deliveries,
+        create_digest,
+        list_digests,
+        get_digest,
+        update_digest,
+        delete_digest,
+        upload_attachment,
+        list_attachments,
+        download_attachment,
+        delete_attachment,
+        migration_status,
+        generate_notes,
+        backup_notes,
+        soap_audit,
+        list_users,
+        retention_report,
+        my_usage
     ),
     components(schemas(
+        ErrorResponse,
         NoteResponse,
+        NoteListResponse,
+        DuplicateClusterResponse,
+        ExportFormat,
+        NoteEvent,
+        NoteOperation,
         CreateNoteRequest,
         UpdateNoteRequest,
-        ShareNotesRequest
+        MetadataPatch,
+        ReorderNotesRequest,
+        SortBy,
+        ShareNotesRequest,
+        CreateShareLinkRequest,
+        ShareLinkResponse,
+        TemplateRequest,
+        TemplateResponse,
+        CreateFromTemplateRequest,
+        WebhookRequest,
+        WebhookResponse,
+        WebhookDeliveryResponse,
+        DigestRequest,
+        DigestResponse,
+        AttachmentResponse,
+        MigrationResponse,
+        MigrationStatusResponse,
+        GenerateNotesResponse,
+        BackupResponse,
+        SoapAuditResponse,
+        UserResponse,
+        UsageResponse,
+        QuotaWindow,
+        RetentionReport,
+        RetentionRuleReport
     )),
     tags(
-        (name = "notes", description = "Notes management API")
+        (name = "notes", description = "Notes management API"),
+        (name = "templates", description = "Reusable note templates"),
+        (name = "webhooks", description = "Callback URLs notified of note changes"),
+        (name = "digests", description = "Scheduled emails of the changed notes"),
+        (name = "attachments", description = "Files attached to notes"),
+        (name = "admin", description = "Operational endpoints"),
+        (name = "me", description = "The authenticated caller")
     )
 )]
 pub struct ApiDoc;
 
+/// Strong ``ETag`` of the note, its `version`
+fn etag(note: &NoteResponse) -> String {
+    format!("\"{}\"", note.version)
+}
+
+/// Parses an ``If-Match`` header into the list of acceptable `version` values.
+/// Returns `None` when the header is absent or is `*` (any existing version matches).
+/// Tags not issued by this server are dropped, so they can never match.
+fn parse_if_match(headers: &HeaderMap) -> Option<Vec<i64>> {
+    let value = headers.get(header::IF_MATCH)?.to_str().unwrap_or_default();
+
+    if value.trim() == "*" {
+        return None;
+    }
+
+    Some(
+        value
+            .split(',')
+            .filter_map(|tag| tag.trim().trim_matches('"').parse().ok())
+            .collect(),
+    )
+}
+
 #[utoipa::path(
     post,
     path = "/notes",
     request_body = CreateNoteRequest,
     responses(
         (status = 201, description = "Note created successfully", body = NoteResponse),
-        (status = 500, description = "Internal server error")
+        (status = 400, description = "Content is empty or too large", body = ErrorResponse),
+        (status = 409, description = "A note with the same content exists and duplicates are rejected", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
     ),
     tag = "notes"
 )]
 #[debug_handler]
 pub async fn create_note(
     State(service): State<Arc<NoteService>>,
-    Json(payload): Json<CreateNoteRequest>,
+    l10n: Localizer,
+    JsonBody(payload): JsonBody<CreateNoteRequest>,
 ) -> Response {
-    match service.create_note(payload).await {
+    match operations::CreateNote(payload).execute(&service).await {
         Ok(note) => (StatusCode::CREATED, Json(note)).into_response(),
+        Err(e) => error_response(&e, &l10n),
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/notes/{id}/duplicate",
+    params(
+        ("id" = i64, Path, description = "ID of the note to copy")
+    ),
+    responses(
+        (status = 201, description = "Copy created with the note's content and expiration time", body = NoteResponse,
+            headers(("ETag" = String, description = "Version of the new note"))),
+        (status = 404, description = "Note not found", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    tag = "notes"
+)]
+#[debug_handler]
+pub async fn duplicate_note(
+    State(service): State<Arc<NoteService>>,
+    l10n: Localizer,
+    Path(id): Path<i64>,
+) -> Response {
+    match (operations::DuplicateNote { id }).execute(&service).await {
+        Ok(note) => (
+            StatusCode::CREATED,
+            [(header::ETAG, etag(&note))],
+            Json(note),
+        )
+            .into_response(),
+        Err(e) => error_response(&e, &l10n),
+    }
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct DuplicatesParams {
+    /// Maximum number of groups, 50 by default, at most 500
+    #[serde(default = "default_page_size")]
+    pub limit: u32,
+}
+
+#[utoipa::path(
+    get,
+    path = "/notes/duplicates",
+    params(DuplicatesParams),
+    responses(
+        (status = 200, description = "Groups of notes with the same content, ignoring case and whitespace, largest first", body = Vec<DuplicateClusterResponse>),
+        (status = 400, description = "Invalid parameters", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    tag = "notes"
+)]
+#[debug_handler]
+pub async fn duplicate_clusters(
+    State(service): State<Arc<NoteService>>,
+    l10n: Localizer,
+    Query(params): Query<DuplicatesParams>,
+) -> Response {
+    let limit = params.limit.clamp(1, MAX_PAGE_SIZE);
+
+    match service.duplicate_clusters(limit.into()).await {
+        Ok(clusters) => (StatusCode::OK, Json(clusters)).into_response(),
         Err(e) => {
-            tracing::error!("failed to create note entry: {}", e);
-            (StatusCode::INTERNAL_SERVER_ERROR, "Failed to create note").into_response()
+            tracing::error!("{}: {e}", MessageKey::DuplicateClustersFailed.english());
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                ErrorResponse::new(MessageKey::DuplicateClustersFailed, &l10n),
+            )
+                .into_response()
         }
     }
 }
 
+#[utoipa::path(
+    post,
+    path = "/notes/reorder",
+    request_body = ReorderNotesRequest,
+    responses(
+        (status = 204, description = "Notes reordered, list them with `sort_by=position`"),
+        (status = 400, description = "Empty list or duplicate IDs", body = ErrorResponse),
+        (status = 404, description = "Some of the notes were not found", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    tag = "notes"
+)]
+#[debug_handler]
+pub async fn reorder_notes(
+    State(service): State<Arc<NoteService>>,
+    l10n: Localizer,
+    JsonBody(request): JsonBody<ReorderNotesRequest>,
+) -> Response {
+    match (operations::ReorderNotes { ids: request.ids })
+        .execute(&service)
+        .await
+    {
+        Ok(()) => StatusCode::NO_CONTENT.into_response(),
+        Err(e) => error_response(&e, &l10n),
+    }
+}
+
 #[utoipa::path(
     put,
     path = "/notes/{id}",
     params(
-        ("id" = i64, Path, description = "Note ID")
+        ("id" = i64, Path, description = "Note ID"),
+        ("If-Match" = Option<String>, Header, description = "Only update if the note's ETag matches")
     ),
     request_body = UpdateNoteRequest,
     responses(
-        (status = 200, description = "Note updated successfully", body = NoteResponse),
-        (status = 404, description = "Note not found"),
-        (status = 500, description = "Internal server error")
+        (status = 200, description = "Note updated successfully", body = NoteResponse,
+            headers(("ETag" = String, description = "New version of the note"))),
+        (status = 400, description = "Content is empty or too large", body = ErrorResponse),
+        (status = 404, description = "Note not found", body = ErrorResponse),
+        (status = 412, description = "Note was modified since the given ETag", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
     ),
     tag = "notes"
 )]
 #[debug_handler]
 pub async fn update_note(
     State(service): State<Arc<NoteService>>,
+    l10n: Localizer,
     Path(id): Path<i64>,
-    Json(payload): Json<UpdateNoteRequest>,
+    headers: HeaderMap,
+    JsonBody(payload): JsonBody<UpdateNoteRequest>,
 ) -> Response {
-    match service.update_note(id, payload).await {
-        Ok(Some(note)) => (StatusCode::OK, Json(note)).into_response(),
-        Ok(None) => (StatusCode::NOT_FOUND, "Note not found").into_response(),
-        Err(e) => {
-            tracing::error!("failed to update note entry: {}", e);
-            (StatusCode::INTERNAL_SERVER_ERROR, "Failed to update note").into_response()
-        }
+    let operation = operations::UpdateNote {
+        id,
+        request: payload,
+        expected_versions: parse_if_match(&headers),
+    };
+
+    match operation.execute(&service).await {
+        Ok(note) => (StatusCode::OK, [(header::ETAG, etag(&note))], Json(note)).into_response(),
+        Err(e) => error_response(&e, &l10n),
+    }
+}
+
+#[utoipa::path(
+    patch,
+    path = "/notes/{id}/metadata",
+    params(
+        ("id" = i64, Path, description = "Note ID"),
+        ("If-Match" = Option<String>, Header, description = "Only update if the note's ETag matches")
+    ),
+    request_body = MetadataPatch,
+    responses(
+        (status = 200, description = "Metadata updated", body = NoteResponse,
+            headers(("ETag" = String, description = "New version of the note"))),
+        (status = 404, description = "Note not found", body = ErrorResponse),
+        (status = 412, description = "Note was modified since the given ETag", body = ErrorResponse),
+        (status = 422, description = "Body is not a JSON object", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    tag = "notes"
+)]
+#[debug_handler]
+pub async fn patch_metadata(
+    State(service): State<Arc<NoteService>>,
+    l10n: Localizer,
+    Path(id): Path<i64>,
+    headers: HeaderMap,
+    JsonBody(MetadataPatch(patch)): JsonBody<MetadataPatch>,
+) -> Response {
+    let operation = operations::PatchMetadata {
+        id,
+        patch,
+        expected_versions: parse_if_match(&headers),
+    };
+
+    match operation.execute(&service).await {
+        Ok(note) => (StatusCode::OK, [(header::ETAG, etag(&note))], Json(note)).into_response(),
+        Err(e) => error_response(&e, &l10n),
     }
 }
 
@@ -94,23 +371,61 @@ pub async fn update_note(
     delete,
     path = "/notes/{id}",
     params(
-        ("id" = i64, Path, description = "Note ID")
+        ("id" = i64, Path, description = "Note ID"),
+        ("If-Match" = Option<String>, Header, description = "Only delete if the note's ETag matches")
     ),
     responses(
         (status = 204, description = "Note deleted successfully"),
-        (status = 404, description = "Note not found"),
-        (status = 500, description = "Internal server error")
+        (status = 404, description = "Note not found", body = ErrorResponse),
+        (status = 412, description = "Note was modified since the given ETag", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    tag = "notes"
+)]
+#[debug_handler]
+pub async fn delete_note(
+    State(service): State<Arc<NoteService>>,
+    l10n: Localizer,
+    Path(id): Path<i64>,
+    headers: HeaderMap,
+) -> Response {
+    let operation = operations::DeleteNote {
+        id,
+        expected_versions: parse_if_match(&headers),
+    };
+
+    match operation.execute(&service).await {
+        Ok(()) => (StatusCode::NO_CONTENT).into_response(),
+        Err(e) => error_response(&e, &l10n),
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/notes/undo",
+    responses(
+        (status = 200, description = "Notes removed by the most recent delete, restored", body = [NoteResponse]),
+        (status = 404, description = "No delete within the undo window", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
     ),
     tag = "notes"
 )]
 #[debug_handler]
-pub async fn delete_note(State(service): State<Arc<NoteService>>, Path(id): Path<i64>) -> Response {
-    match service.delete_note(id).await {
-        Ok(true) => (StatusCode::NO_CONTENT).into_response(),
-        Ok(false) => (StatusCode::NOT_FOUND, "Note not found").into_response(),
+pub async fn undo_delete(State(service): State<Arc<NoteService>>, l10n: Localizer) -> Response {
+    match service.undo_last_delete().await {
+        Ok(notes) if notes.is_empty() => (
+            StatusCode::NOT_FOUND,
+            ErrorResponse::new(MessageKey::NothingToUndo, &l10n),
+        )
+            .into_response(),
+        Ok(notes) => (StatusCode::OK, Json(notes)).into_response(),
         Err(e) => {
-            tracing::error!("failed to delete note entry: {}", e);
-            (StatusCode::INTERNAL_SERVER_ERROR, "Failed to delete note").into_response()
+            tracing::error!("{}: {e}", MessageKey::UndoFailed.english());
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                ErrorResponse::new(MessageKey::UndoFailed, &l10n),
+            )
+                .into_response()
         }
     }
 }
@@ -122,43 +437,358 @@ pub async fn delete_note(State(service): State<Arc<NoteService>>, Path(id): Path
         ("id" = i64, Path, description = "Note ID")
     ),
     responses(
-        (status = 200, description = "Note found", body = NoteResponse),
-        (status = 404, description = "Note not found"),
-        (status = 500, description = "Internal server error")
+        (status = 200, description = "Note found", body = NoteResponse,
+            headers(("ETag" = String, description = "Current version of the note"))),
+        (status = 404, description = "Note not found", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
     ),
     tag = "notes"
 )]
 #[debug_handler]
 pub async fn get_one_note(
     State(service): State<Arc<NoteService>>,
+    l10n: Localizer,
     Path(id): Path<i64>,
 ) -> Response {
-    match service.get_one_note(id).await {
-        Ok(Some(note)) => (StatusCode::OK, Json(note)).into_response(),
-        Ok(None) => (StatusCode::NOT_FOUND, "Note not found").into_response(),
-        Err(e) => {
-            tracing::error!("failed to get note entry: {}", e);
-            (StatusCode::INTERNAL_SERVER_ERROR, "Failed to get note").into_response()
+    match (operations::GetNote { id }).execute(&service).await {
+        Ok(note) => (StatusCode::OK, [(header::ETAG, etag(&note))], Json(note)).into_response(),
+        Err(e) => error_response(&e, &l10n),
+    }
+}
+
+/// Page size used when `limit` is omitted
+const DEFAULT_PAGE_SIZE: u32 = 50;
+/// Largest page a client may request
+const MAX_PAGE_SIZE: u32 = 500;
+
+const fn default_page_size() -> u32 {
+    DEFAULT_PAGE_SIZE
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, IntoParams)]
+pub struct PageParams {
+    /// Maximum number of notes on the page, 50 by default, at most 500
+    #[serde(default = "default_page_size")]
+    pub limit: u32,
+    /// Number of notes to skip
+    #[serde(default)]
+    pub offset: u32,
+    /// List the notes with ID greater than this instead of skipping `offset`, the `next`
+    /// link continues after the last note. Deep pages stay fast, but there is no `prev` link.
+    /// Only for notes sorted by ID
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub after: Option<i64>,
+    /// Only notes whose metadata contains this JSON object, e.g. `{"project":"x"}`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<String>,
+    /// Only notes having all of these comma-separated metadata fields
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub has_metadata: Option<String>,
+    /// Order of the notes, by ID by default
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sort_by: Option<SortBy>,
+    /// Also list notes moved to the archive for not being modified for a long time
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub include_archived: bool,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum SortBy {
+    Id,
+    /// User-defined order, see `POST /notes/reorder`
+    Position,
+}
+
+impl From<SortBy> for NoteOrder {
+    fn from(sort_by: SortBy) -> Self {
+        match sort_by {
+            SortBy::Id => Self::Id,
+            SortBy::Position => Self::Position,
+        }
+    }
+}
+
+impl PageParams {
+    /// `None` if `metadata` is not a JSON object
+    fn metadata_filter(&self) -> Option<MetadataFilter> {
+        let contains = match &self.metadata {
+            Some(metadata) => Some(
+                serde_json::from_str::<serde_json::Value>(metadata)
+                    .ok()
+                    .filter(serde_json::Value::is_object)?,
+            ),
+            None => None,
+        };
+        let has_keys = self.has_metadata.as_ref().map(|keys| {
+            keys.split(',')
+                .map(str::trim)
+                .filter(|key| !key.is_empty())
+                .map(ToString::to_string)
+                .collect()
+        });
+
+        Some(MetadataFilter { contains, has_keys })
+    }
+
+    /// URL of the page at `offset`, with the same size and filters
+    fn page_url(&self, offset: u32) -> String {
+        Self {
+            offset,
+            ..self.clone()
+        }
+        .url()
+    }
+
+    /// URL of the page after the note `id`, with the same size and filters
+    fn after_url(&self, id: i64) -> String {
+        Self {
+            after: Some(id),
+            offset: 0,
+            ..self.clone()
         }
+        .url()
+    }
+
+    fn url(&self) -> String {
+        format!(
+            "/notes?{}",
+            serde_urlencoded::to_string(self).unwrap_or_default()
+        )
     }
 }
 
 #[utoipa::path(
     get,
     path = "/notes",
+    params(PageParams),
     responses(
-        (status = 200, description = "List of all notes", body = Vec<NoteResponse>),
-        (status = 500, description = "Internal server error")
+        (status = 200, description = "A page of notes ordered by ID or by `sort_by`", body = NoteListResponse,
+            headers(("Link" = String, description = "RFC 5988 links to the next and previous pages"))),
+        (status = 400, description = "Invalid page or metadata filter parameters", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
     ),
     tag = "notes"
 )]
 #[debug_handler]
-pub async fn get_all_notes(State(service): State<Arc<NoteService>>) -> Response {
-    match service.get_all_notes().await {
-        Ok(note) => (StatusCode::OK, Json(note)).into_response(),
+pub async fn get_all_notes(
+    State(service): State<Arc<NoteService>>,
+    l10n: Localizer,
+    Query(params): Query<PageParams>,
+) -> Response {
+    let params = PageParams {
+        limit: params.limit.clamp(1, MAX_PAGE_SIZE),
+        ..params
+    };
+    let (limit, offset) = (params.limit, params.offset);
+    let Some(filter) = params.metadata_filter() else {
+        return (
+            StatusCode::BAD_REQUEST,
+            ErrorResponse::new(MessageKey::InvalidMetadataFilter, &l10n),
+        )
+            .into_response();
+    };
+
+    let order = params.sort_by.map(NoteOrder::from).unwrap_or_default();
+    let page = match (params.after, order) {
+        (Some(_), NoteOrder::Position) => {
+            return (
+                StatusCode::BAD_REQUEST,
+                ErrorResponse::new(MessageKey::CursorRequiresIdOrder, &l10n),
+            )
+                .into_response();
+        }
+        (Some(after_id), NoteOrder::Id) => {
+            operations::ListNotesAfter {
+                after_id,
+                limit: limit.into(),
+                filter,
+                include_archived: params.include_archived,
+            }
+            .execute(&service)
+            .await
+        }
+        (None, order) => {
+            operations::ListNotes {
+                limit: limit.into(),
+                offset: offset.into(),
+                filter,
+                order,
+                include_archived: params.include_archived,
+            }
+            .execute(&service)
+            .await
+        }
+    };
+    let page = match page {
+        Ok(page) => page,
+        Err(e) => return error_response(&e, &l10n),
+    };
+
+    let (next, prev) = if params.after.is_some() {
+        (page.next_after.map(|id| params.after_url(id)), None)
+    } else {
+        (
+            (i64::from(offset) + i64::from(limit) < page.total)
+                .then(|| params.page_url(offset + limit)),
+            (offset > 0).then(|| params.page_url(offset.saturating_sub(limit))),
+        )
+    };
+
+    let links = [(&next, "next"), (&prev, "prev")]
+        .into_iter()
+        .filter_map(|(url, rel)| url.as_ref().map(|url| format!("<{url}>; rel=\"{rel}\"")))
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    let body = NoteListResponse {
+        items: page.items,
+        total: page.total,
+        next,
+        prev,
+    };
+
+    if links.is_empty() {
+        (StatusCode::OK, Json(body)).into_response()
+    } else {
+        (StatusCode::OK, [(header::LINK, links)], Json(body)).into_response()
+    }
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct ExportParams {
+    /// Export file format, `json` by default
+    #[param(inline)]
+    #[serde(default)]
+    pub format: ExportFormat,
+}
+
+#[utoipa::path(
+    get,
+    path = "/notes/export",
+    params(ExportParams),
+    responses(
+        (status = 200, description = "All notes as a downloadable file (json, csv or markdown)"),
+        (status = 400, description = "Unsupported format", body = ErrorResponse)
+    ),
+    tag = "notes"
+)]
+#[debug_handler]
+pub async fn export_notes(
+    State(service): State<Arc<NoteService>>,
+    Query(params): Query<ExportParams>,
+) -> Response {
+    let format = params.format;
+    let disposition = format!("attachment; filename=\"notes.{}\"", format.file_extension());
+
+    (
+        StatusCode::OK,
+        [
+            (header::CONTENT_TYPE, format.content_type().to_string()),
+            (header::CONTENT_DISPOSITION, disposition),
+        ],
+        Body::from_stream(service.export_notes(format)),
+    )
+        .into_response()
+}
+
+#[utoipa::path(
+    get,
+    path = "/notes/events",
+    responses(
+        (status = 200, description = "Server-Sent Events stream of note changes, \
+            one `created`, `updated` or `deleted` event per change", body = NoteEvent,
+            content_type = "text/event-stream")
+    ),
+    tag = "notes"
+)]
+#[debug_handler]
+pub async fn note_events(State(service): State<Arc<NoteService>>) -> Response {
+    let events = service.subscribe_events().map(|event| {
+        Event::default()
+            .event(event.operation.as_str())
+            .json_data(event)
+    });
+
+    Sse::new(events)
+        .keep_alive(KeepAlive::default())
+        .into_response()
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct SearchParams {
+    /// Words to look for. Supports quoted phrases, `or` and `-word` to exclude a word
+    pub q: String,
+    /// Maximum number of notes, 50 by default, at most 500
+    #[serde(default = "default_page_size")]
+    pub limit: u32,
+}
+
+#[utoipa::path(
+    get,
+    path = "/notes/search",
+    params(SearchParams),
+    responses(
+        (status = 200, description = "Notes matching the query, best matches first. Encrypted notes are not searched", body = Vec<NoteResponse>),
+        (status = 400, description = "Empty query or invalid parameters", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    tag = "notes"
+)]
+#[debug_handler]
+pub async fn search_notes(
+    State(service): State<Arc<NoteService>>,
+    l10n: Localizer,
+    Query(params): Query<SearchParams>,
+) -> Response {
+    let search = operations::SearchNotes {
+        query: params.q,
+        limit: params.limit.clamp(1, MAX_PAGE_SIZE).into(),
+    };
+
+    match search.execute(&service).await {
+        Ok(notes) => (StatusCode::OK, Json(notes)).into_response(),
+        Err(e) => error_response(&e, &l10n),
+    }
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct ActivityParams {
+    /// Only changes made after this time
+    pub since: Option<DateTime<Utc>>,
+    /// Maximum number of changes, 50 by default, at most 500
+    #[serde(default = "default_page_size")]
+    pub limit: u32,
+}
+
+#[utoipa::path(
+    get,
+    path = "/activity",
+    params(ActivityParams),
+    responses(
+        (status = 200, description = "Most recent changes of notes, newest first. Changes are kept for 30 days", body = Vec<NoteEvent>),
+        (status = 400, description = "Invalid parameters", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    tag = "notes"
+)]
+#[debug_handler]
+pub async fn recent_activity(
+    State(service): State<Arc<NoteService>>,
+    l10n: Localizer,
+    Query(params): Query<ActivityParams>,
+) -> Response {
+    let limit = params.limit.clamp(1, MAX_PAGE_SIZE);
+
+    match service.recent_activity(params.since, limit.into()).await {
+        Ok(activity) => (StatusCode::OK, Json(activity)).into_response(),
         Err(e) => {
-            tracing::error!("failed to get note entries: {}", e);
-            (StatusCode::INTERNAL_SERVER_ERROR, "Failed to get all notes").into_response()
+            tracing::error!("{}: {e}", MessageKey::ActivityFailed.english());
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                ErrorResponse::new(MessageKey::ActivityFailed, &l10n),
+            )
+                .into_response()
         }
     }
 }
@@ -169,86 +799,1215 @@ pub async fn get_all_notes(State(service): State<Arc<NoteService>>) -> Response
     request_body = ShareNotesRequest,
     responses(
         (status = 200, description = "Notes sent successfully"),
-        (status = 400, description = "Bad request"),
-        (status = 500, description = "Internal server error")
+        (status = 400, description = "Bad request", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse),
+        (status = 502, description = "Email service error", body = ErrorResponse),
+        (status = 503, description = "Email service is overloaded, retry after `Retry-After` seconds", body = ErrorResponse)
     ),
     tag = "notes"
 )]
 #[debug_handler]
 pub async fn share_notes(
     State(service): State<Arc<NoteService>>,
-    Json(payload): Json<ShareNotesRequest>,
+    l10n: Localizer,
+    JsonBody(payload): JsonBody<ShareNotesRequest>,
 ) -> Response {
-    use chrono::Local;
-    use std::env;
+    let operation = operations::ShareNotes {
+        email: payload.email,
+    };
 
-    // Get email service URL
-    let email_service_url =
-        env::var("EMAIL_SERVICE_URL").unwrap_or_else(|_| "http://localhost:8001".to_string());
+    match operation.execute(&service).await {
+        Ok(()) => (StatusCode::OK, l10n.get(MessageKey::NotesSent)).into_response(),
+        Err(e) => error_response(&e, &l10n),
+    }
+}
 
-    // Get all notes
-    let notes = match service.get_all_notes_with_timestamps().await {
-        Ok(notes) => notes,
-        Err(e) => {
-            tracing::error!("failed to get notes: {}", e);
-            return (StatusCode::INTERNAL_SERVER_ERROR, "Failed to get notes").into_response();
-        }
+#[utoipa::path(
+    post,
+    path = "/notes/{id}/share",
+    params(
+        ("id" = i64, Path, description = "Note ID")
+    ),
+    request_body = ShareNotesRequest,
+    responses(
+        (status = 200, description = "Note sent successfully"),
+        (status = 404, description = "Note not found", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse),
+        (status = 502, description = "Email service error", body = ErrorResponse),
+        (status = 503, description = "Email service is overloaded, retry after `Retry-After` seconds", body = ErrorResponse)
+    ),
+    tag = "notes"
+)]
+#[debug_handler]
+pub async fn share_note(
+    State(service): State<Arc<NoteService>>,
+    l10n: Localizer,
+    Path(id): Path<i64>,
+    JsonBody(payload): JsonBody<ShareNotesRequest>,
+) -> Response {
+    let operation = operations::ShareNote {
+        id,
+        email: payload.email,
     };
 
-    // Format notes
-    let body = if notes.is_empty() {
-        "No notes available.".to_string()
-    } else {
-        notes
-            .into_iter()
-            .map(|note| {
-                let time_str = note
-                    .created_at
-                    .with_timezone(&Local)
-                    .format("%Y-%m-%d %H:%M:%S");
-                format!("{}: {}", time_str, note.content)
-            })
-            .collect::<Vec<_>>()
-            .join("\n")
-    };
-
-    // Call email service
-    let email_request = serde_json::json!({
-        "to": payload.email,
-        "subject": "Notes",
-        "body": body
-    });
+    match operation.execute(&service).await {
+        Ok(()) => (StatusCode::OK, l10n.get(MessageKey::NoteSent)).into_response(),
+        Err(e) => error_response(&e, &l10n),
+    }
+}
 
-    let client = reqwest::Client::builder()
-        .danger_accept_invalid_certs(true)
-        .build()
-        .unwrap_or_else(|_| reqwest::Client::new());
-    match client
-        .post(format!("{email_service_url}/email"))
-        .json(&email_request)
-        .send()
+#[utoipa::path(
+    post,
+    path = "/notes/{id}/share-link",
+    params(
+        ("id" = i64, Path, description = "Note ID")
+    ),
+    request_body = CreateShareLinkRequest,
+    responses(
+        (status = 201, description = "Link created, the token is only returned once", body = ShareLinkResponse),
+        (status = 400, description = "Expiration time is not in the future", body = ErrorResponse),
+        (status = 404, description = "Note not found", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    tag = "notes"
+)]
+#[debug_handler]
+pub async fn create_share_link(
+    State(service): State<Arc<NoteService>>,
+    l10n: Localizer,
+    Path(note_id): Path<i64>,
+    JsonBody(request): JsonBody<CreateShareLinkRequest>,
+) -> Response {
+    match (operations::CreateShareLink { note_id, request })
+        .execute(&service)
         .await
     {
-        Ok(response) => {
-            if response.status().is_success() {
-                (StatusCode::OK, "Notes sent successfully").into_response()
-            } else {
-                let status_text = response.status().to_string();
-                tracing::error!("Email service returned error: {}", status_text);
-                (
-                    StatusCode::BAD_GATEWAY,
-                    format!("Email service error: {status_text}"),
-                )
-                    .into_response()
-            }
-        }
-        Err(e) => {
-            tracing::error!("Failed to call email service: {}", e);
-            (
-                StatusCode::BAD_GATEWAY,
-                format!("Failed to send email: {e}"),
-            )
-                .into_response()
-        }
+        Ok(link) => (StatusCode::CREATED, Json(link)).into_response(),
+        Err(e) => error_response(&e, &l10n),
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/shared/{token}",
+    params(
+        ("token" = String, Path, description = "Share link token")
+    ),
+    responses(
+        (status = 200, description = "Read-only view of the shared note", body = NoteResponse),
+        (status = 404, description = "Unknown or expired link, or the note is gone", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    tag = "notes"
+)]
+#[debug_handler]
+pub async fn get_shared_note(
+    State(service): State<Arc<NoteService>>,
+    l10n: Localizer,
+    Path(token): Path<String>,
+) -> Response {
+    match (operations::GetSharedNote { token })
+        .execute(&service)
+        .await
+    {
+        Ok(note) => (StatusCode::OK, Json(note)).into_response(),
+        Err(e) => error_response(&e, &l10n),
+    }
+}
+
+fn template_error(e: &RepositoryError, l10n: &Localizer) -> Response {
+    tracing::error!("{}: {e}", MessageKey::TemplateFailed.english());
+    (
+        StatusCode::INTERNAL_SERVER_ERROR,
+        ErrorResponse::new(MessageKey::TemplateFailed, l10n),
+    )
+        .into_response()
+}
+
+fn template_not_found(l10n: &Localizer) -> Response {
+    (
+        StatusCode::NOT_FOUND,
+        ErrorResponse::new(MessageKey::TemplateNotFound, l10n),
+    )
+        .into_response()
+}
+
+#[utoipa::path(
+    post,
+    path = "/templates",
+    request_body = TemplateRequest,
+    responses(
+        (status = 201, description = "Template created successfully", body = TemplateResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    tag = "templates"
+)]
+#[debug_handler]
+pub async fn create_template(
+    State(service): State<Arc<NoteService>>,
+    l10n: Localizer,
+    JsonBody(request): JsonBody<TemplateRequest>,
+) -> Response {
+    match service.create_template(request).await {
+        Ok(template) => (StatusCode::CREATED, Json(template)).into_response(),
+        Err(e) => template_error(&e, &l10n),
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/templates",
+    responses(
+        (status = 200, description = "All templates", body = Vec<TemplateResponse>),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    tag = "templates"
+)]
+#[debug_handler]
+pub async fn list_templates(State(service): State<Arc<NoteService>>, l10n: Localizer) -> Response {
+    match service.list_templates().await {
+        Ok(templates) => (StatusCode::OK, Json(templates)).into_response(),
+        Err(e) => template_error(&e, &l10n),
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/templates/{id}",
+    params(
+        ("id" = i64, Path, description = "Template ID")
+    ),
+    responses(
+        (status = 200, description = "Template found", body = TemplateResponse),
+        (status = 404, description = "Template not found", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    tag = "templates"
+)]
+#[debug_handler]
+pub async fn get_template(
+    State(service): State<Arc<NoteService>>,
+    l10n: Localizer,
+    Path(id): Path<i64>,
+) -> Response {
+    match service.get_template(id).await {
+        Ok(Some(template)) => (StatusCode::OK, Json(template)).into_response(),
+        Ok(None) => template_not_found(&l10n),
+        Err(e) => template_error(&e, &l10n),
+    }
+}
+
+#[utoipa::path(
+    put,
+    path = "/templates/{id}",
+    params(
+        ("id" = i64, Path, description = "Template ID")
+    ),
+    request_body = TemplateRequest,
+    responses(
+        (status = 200, description = "Template updated successfully", body = TemplateResponse),
+        (status = 404, description = "Template not found", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    tag = "templates"
+)]
+#[debug_handler]
+pub async fn update_template(
+    State(service): State<Arc<NoteService>>,
+    l10n: Localizer,
+    Path(id): Path<i64>,
+    JsonBody(request): JsonBody<TemplateRequest>,
+) -> Response {
+    match service.update_template(id, request).await {
+        Ok(Some(template)) => (StatusCode::OK, Json(template)).into_response(),
+        Ok(None) => template_not_found(&l10n),
+        Err(e) => template_error(&e, &l10n),
+    }
+}
+
+#[utoipa::path(
+    delete,
+    path = "/templates/{id}",
+    params(
+        ("id" = i64, Path, description = "Template ID")
+    ),
+    responses(
+        (status = 204, description = "Template deleted successfully"),
+        (status = 404, description = "Template not found", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    tag = "templates"
+)]
+#[debug_handler]
+pub async fn delete_template(
+    State(service): State<Arc<NoteService>>,
+    l10n: Localizer,
+    Path(id): Path<i64>,
+) -> Response {
+    match service.delete_template(id).await {
+        Ok(true) => StatusCode::NO_CONTENT.into_response(),
+        Ok(false) => template_not_found(&l10n),
+        Err(e) => template_error(&e, &l10n),
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/notes/from-template/{template_id}",
+    params(
+        ("template_id" = i64, Path, description = "ID of the template to instantiate")
+    ),
+    request_body = CreateFromTemplateRequest,
+    responses(
+        (status = 201, description = "Note created with the template's placeholders substituted", body = NoteResponse,
+            headers(("ETag" = String, description = "Version of the new note"))),
+        (status = 400, description = "Content with the placeholders substituted is empty or too large", body = ErrorResponse),
+        (status = 404, description = "Template not found", body = ErrorResponse),
+        (status = 409, description = "A note with the same content exists and duplicates are rejected", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    tag = "templates"
+)]
+#[debug_handler]
+pub async fn create_note_from_template(
+    State(service): State<Arc<NoteService>>,
+    l10n: Localizer,
+    Path(template_id): Path<i64>,
+    JsonBody(request): JsonBody<CreateFromTemplateRequest>,
+) -> Response {
+    match service
+        .create_note_from_template(template_id, request)
+        .await
+    {
+        Ok(Some(note)) => (
+            StatusCode::CREATED,
+            [(header::ETAG, etag(&note))],
+            Json(note),
+        )
+            .into_response(),
+        Ok(None) => template_not_found(&l10n),
+        Err(WriteError::InvalidContent(e)) => error_response(&e.into(), &l10n),
+        Err(WriteError::Duplicate(id)) => error_response(&OperationError::Duplicate(id), &l10n),
+        Err(WriteError::Database(e)) => template_error(&e, &l10n),
+    }
+}
+
+fn webhook_error(e: &WebhookError, l10n: &Localizer) -> Response {
+    match e {
+        WebhookError::Invalid(_) => (
+            StatusCode::BAD_REQUEST,
+            ErrorResponse::new(MessageKey::InvalidWebhookUrl, l10n),
+        )
+            .into_response(),
+        WebhookError::Database(e) => webhook_repository_error(e, l10n),
+    }
+}
+
+fn webhook_repository_error(e: &RepositoryError, l10n: &Localizer) -> Response {
+    tracing::error!("{}: {e}", MessageKey::WebhookFailed.english());
+    (
+        StatusCode::INTERNAL_SERVER_ERROR,
+        ErrorResponse::new(MessageKey::WebhookFailed, l10n),
+    )
+        .into_response()
+}
+
+fn webhook_not_found(l10n: &Localizer) -> Response {
+    (
+        StatusCode::NOT_FOUND,
+        ErrorResponse::new(MessageKey::WebhookNotFound, l10n),
+    )
+        .into_response()
+}
+
+#[utoipa::path(
+    post,
+    path = "/webhooks",
+    request_body = WebhookRequest,
+    responses(
+        (status = 201, description = "Webhook created, the response carries its signing secret", body = WebhookResponse),
+        (status = 400, description = "URL isn't an absolute http or https URL", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    tag = "webhooks"
+)]
+#[debug_handler]
+pub async fn create_webhook(
+    State(service): State<Arc<NoteService>>,
+    l10n: Localizer,
+    JsonBody(request): JsonBody<WebhookRequest>,
+) -> Response {
+    match service.create_webhook(request).await {
+        Ok(webhook) => (StatusCode::CREATED, Json(webhook)).into_response(),
+        Err(e) => webhook_error(&e, &l10n),
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/webhooks",
+    responses(
+        (status = 200, description = "All webhooks", body = Vec<WebhookResponse>),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    tag = "webhooks"
+)]
+#[debug_handler]
+pub async fn list_webhooks(State(service): State<Arc<NoteService>>, l10n: Localizer) -> Response {
+    match service.list_webhooks().await {
+        Ok(webhooks) => (StatusCode::OK, Json(webhooks)).into_response(),
+        Err(e) => webhook_repository_error(&e, &l10n),
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/webhooks/{id}",
+    params(
+        ("id" = i64, Path, description = "Webhook ID")
+    ),
+    responses(
+        (status = 200, description = "Webhook found", body = WebhookResponse),
+        (status = 404, description = "Webhook not found", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    tag = "webhooks"
+)]
+#[debug_handler]
+pub async fn get_webhook(
+    State(service): State<Arc<NoteService>>,
+    l10n: Localizer,
+    Path(id): Path<i64>,
+) -> Response {
+    match service.get_webhook(id).await {
+        Ok(Some(webhook)) => (StatusCode::OK, Json(webhook)).into_response(),
+        Ok(None) => webhook_not_found(&l10n),
+        Err(e) => webhook_repository_error(&e, &l10n),
+    }
+}
+
+#[utoipa::path(
+    put,
+    path = "/webhooks/{id}",
+    params(
+        ("id" = i64, Path, description = "Webhook ID")
+    ),
+    request_body = WebhookRequest,
+    responses(
+        (status = 200, description = "Webhook updated, its circuit is closed again", body = WebhookResponse),
+        (status = 400, description = "URL isn't an absolute http or https URL", body = ErrorResponse),
+        (status = 404, description = "Webhook not found", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    tag = "webhooks"
+)]
+#[debug_handler]
+pub async fn update_webhook(
+    State(service): State<Arc<NoteService>>,
+    l10n: Localizer,
+    Path(id): Path<i64>,
+    JsonBody(request): JsonBody<WebhookRequest>,
+) -> Response {
+    match service.update_webhook(id, request).await {
+        Ok(Some(webhook)) => (StatusCode::OK, Json(webhook)).into_response(),
+        Ok(None) => webhook_not_found(&l10n),
+        Err(e) => webhook_error(&e, &l10n),
+    }
+}
+
+#[utoipa::path(
+    delete,
+    path = "/webhooks/{id}",
+    params(
+        ("id" = i64, Path, description = "Webhook ID")
+    ),
+    responses(
+        (status = 204, description = "Webhook deleted along with its deliveries"),
+        (status = 404, description = "Webhook not found", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    tag = "webhooks"
+)]
+#[debug_handler]
+pub async fn delete_webhook(
+    State(service): State<Arc<NoteService>>,
+    l10n: Localizer,
+    Path(id): Path<i64>,
+) -> Response {
+    match service.delete_webhook(id).await {
+        Ok(true) => StatusCode::NO_CONTENT.into_response(),
+        Ok(false) => webhook_not_found(&l10n),
+        Err(e) => webhook_repository_error(&e, &l10n),
+    }
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct DeliveryParams {
+    /// Maximum number of deliveries, 50 by default, at most 500
+    #[serde(default = "default_page_size")]
+    pub limit: u32,
+}
+
+#[utoipa::path(
+    get,
+    path = "/webhooks/{id}/deliveries",
+    params(
+        ("id" = i64, Path, description = "Webhook ID"),
+        DeliveryParams
+    ),
+    responses(
+        (status = 200, description = "Latest deliveries of the webhook, newest first. Completed ones are kept for 7 days", body = Vec<WebhookDeliveryResponse>),
+        (status = 404, description = "Webhook not found", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    tag = "webhooks"
+)]
+#[debug_handler]
+pub async fn webhook_deliveries(
+    State(service): State<Arc<NoteService>>,
+    l10n: Localizer,
+    Path(id): Path<i64>,
+    Query(params): Query<DeliveryParams>,
+) -> Response {
+    let limit = params.limit.clamp(1, MAX_PAGE_SIZE);
+
+    match service.webhook_deliveries(id, limit.into()).await {
+        Ok(Some(deliveries)) => (StatusCode::OK, Json(deliveries)).into_response(),
+        Ok(None) => webhook_not_found(&l10n),
+        Err(e) => webhook_repository_error(&e, &l10n),
+    }
+}
+
+fn digest_error(e: &DigestError, l10n: &Localizer) -> Response {
+    match e {
+        DigestError::Invalid(invalid) => {
+            let key = match invalid {
+                InvalidDigest::Email => MessageKey::InvalidDigestEmail,
+                InvalidDigest::Schedule => MessageKey::InvalidDigestSchedule,
+            };
+            (StatusCode::BAD_REQUEST, ErrorResponse::new(key, l10n)).into_response()
+        }
+        DigestError::Database(e) => digest_repository_error(e, l10n),
+    }
+}
+
+fn digest_repository_error(e: &RepositoryError, l10n: &Localizer) -> Response {
+    tracing::error!("{}: {e}", MessageKey::DigestFailed.english());
+    (
+        StatusCode::INTERNAL_SERVER_ERROR,
+        ErrorResponse::new(MessageKey::DigestFailed, l10n),
+    )
+        .into_response()
+}
+
+fn digest_not_found(l10n: &Localizer) -> Response {
+    (
+        StatusCode::NOT_FOUND,
+        ErrorResponse::new(MessageKey::DigestNotFound, l10n),
+    )
+        .into_response()
+}
+
+#[utoipa::path(
+    post,
+    path = "/digests",
+    request_body = DigestRequest,
+    responses(
+        (status = 201, description = "Subscribed, the first digest covers the notes changed from now on", body = DigestResponse),
+        (status = 400, description = "Invalid email or schedule", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    tag = "digests"
+)]
+#[debug_handler]
+pub async fn create_digest(
+    State(service): State<Arc<NoteService>>,
+    l10n: Localizer,
+    JsonBody(request): JsonBody<DigestRequest>,
+) -> Response {
+    match service.create_digest(request).await {
+        Ok(digest) => (StatusCode::CREATED, Json(digest)).into_response(),
+        Err(e) => digest_error(&e, &l10n),
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/digests",
+    responses(
+        (status = 200, description = "All digest subscriptions", body = Vec<DigestResponse>),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    tag = "digests"
+)]
+#[debug_handler]
+pub async fn list_digests(State(service): State<Arc<NoteService>>, l10n: Localizer) -> Response {
+    match service.list_digests().await {
+        Ok(digests) => (StatusCode::OK, Json(digests)).into_response(),
+        Err(e) => digest_repository_error(&e, &l10n),
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/digests/{id}",
+    params(
+        ("id" = i64, Path, description = "Digest subscription ID")
+    ),
+    responses(
+        (status = 200, description = "Digest subscription found", body = DigestResponse),
+        (status = 404, description = "Digest subscription not found", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    tag = "digests"
+)]
+#[debug_handler]
+pub async fn get_digest(
+    State(service): State<Arc<NoteService>>,
+    l10n: Localizer,
+    Path(id): Path<i64>,
+) -> Response {
+    match service.get_digest(id).await {
+        Ok(Some(digest)) => (StatusCode::OK, Json(digest)).into_response(),
+        Ok(None) => digest_not_found(&l10n),
+        Err(e) => digest_repository_error(&e, &l10n),
+    }
+}
+
+#[utoipa::path(
+    put,
+    path = "/digests/{id}",
+    params(
+        ("id" = i64, Path, description = "Digest subscription ID")
+    ),
+    request_body = DigestRequest,
+    responses(
+        (status = 200, description = "Digest subscription updated, the next digest still covers the notes changed since the previous one", body = DigestResponse),
+        (status = 400, description = "Invalid email or schedule", body = ErrorResponse),
+        (status = 404, description = "Digest subscription not found", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    tag = "digests"
+)]
+#[debug_handler]
+pub async fn update_digest(
+    State(service): State<Arc<NoteService>>,
+    l10n: Localizer,
+    Path(id): Path<i64>,
+    JsonBody(request): JsonBody<DigestRequest>,
+) -> Response {
+    match service.update_digest(id, request).await {
+        Ok(Some(digest)) => (StatusCode::OK, Json(digest)).into_response(),
+        Ok(None) => digest_not_found(&l10n),
+        Err(e) => digest_error(&e, &l10n),
+    }
+}
+
+#[utoipa::path(
+    delete,
+    path = "/digests/{id}",
+    params(
+        ("id" = i64, Path, description = "Digest subscription ID")
+    ),
+    responses(
+        (status = 204, description = "Digest subscription deleted"),
+        (status = 404, description = "Digest subscription not found", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    tag = "digests"
+)]
+#[debug_handler]
+pub async fn delete_digest(
+    State(service): State<Arc<NoteService>>,
+    l10n: Localizer,
+    Path(id): Path<i64>,
+) -> Response {
+    match service.delete_digest(id).await {
+        Ok(true) => StatusCode::NO_CONTENT.into_response(),
+        Ok(false) => digest_not_found(&l10n),
+        Err(e) => digest_repository_error(&e, &l10n),
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/admin/migrations",
+    responses(
+        (status = 200, description = "Applied and pending schema migrations", body = MigrationStatusResponse),
+        (status = 401, description = "Missing or invalid admin token", body = ErrorResponse),
+        (status = 403, description = "Admin endpoints are disabled", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    tag = "admin"
+)]
+#[debug_handler]
+pub async fn migration_status(
+    State(service): State<Arc<NoteService>>,
+    l10n: Localizer,
+) -> Response {
+    match service.migration_status().await {
+        Ok(status) => (StatusCode::OK, Json(status)).into_response(),
+        Err(e) => {
+            tracing::error!("{}: {e}", MessageKey::MigrationStatusFailed.english());
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                ErrorResponse::new(MessageKey::MigrationStatusFailed, &l10n),
+            )
+                .into_response()
+        }
+    }
+}
+
+/// Largest number of notes a single generate request may create
+const MAX_GENERATE_COUNT: usize = 100_000;
+/// Largest content size of a generated note, in bytes
+const MAX_GENERATED_SIZE: usize = 64 * 1024;
+
+const fn default_min_size() -> usize {
+    16
+}
+
+const fn default_max_size() -> usize {
+    2048
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct GenerateParams {
+    /// Number of notes to create, at most 100000
+    pub count: usize,
+    /// Earliest creation time, a year before `to` by default
+    pub from: Option<DateTime<Utc>>,
+    /// Latest creation time, now by default
+    pub to: Option<DateTime<Utc>>,
+    /// Smallest content size in bytes
+    #[serde(default = "default_min_size")]
+    pub min_size: usize,
+    /// Largest content size in bytes, at most 65536
+    #[serde(default = "default_max_size")]
+    pub max_size: usize,
+}
+
+impl GenerateParams {
+    fn spec(&self) -> Option<FixtureSpec> {
+        let to = self.to.unwrap_or_else(Utc::now);
+        let from = self.from.unwrap_or(to - chrono::Duration::days(365));
+
+        let valid = (1..=MAX_GENERATE_COUNT).contains(&self.count)
+            && from <= to
+            && self.min_size <= self.max_size
+            && self.max_size <= MAX_GENERATED_SIZE;
+
+        valid.then_some(FixtureSpec {
+            from,
+            to,
+            min_size: self.min_size,
+            max_size: self.max_size,
+        })
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/admin/generate",
+    params(GenerateParams),
+    responses(
+        (status = 201, description = "Synthetic notes created", body = GenerateNotesResponse),
+        (status = 400, description = "Invalid generation parameters", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid admin token", body = ErrorResponse),
+        (status = 403, description = "Admin endpoints are disabled", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    tag = "admin"
+)]
+#[debug_handler]
+pub async fn generate_notes(
+    State(service): State<Arc<NoteService>>,
+    l10n: Localizer,
+    Query(params): Query<GenerateParams>,
+) -> Response {
+    let Some(spec) = params.spec() else {
+        return (
+            StatusCode::BAD_REQUEST,
+            ErrorResponse::new(MessageKey::InvalidGenerateParams, &l10n),
+        )
+            .into_response();
+    };
+
+    match service.generate_notes(params.count, spec).await {
+        Ok(created) => {
+            tracing::info!("Generated {created} synthetic notes");
+            (StatusCode::CREATED, Json(GenerateNotesResponse { created })).into_response()
+        }
+        Err(e) => {
+            tracing::error!("{}: {e}", MessageKey::GenerateFailed.english());
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                ErrorResponse::new(MessageKey::GenerateFailed, &l10n),
+            )
+                .into_response()
+        }
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/admin/backup",
+    responses(
+        (status = 201, description = "Snapshot of all notes written to the backup store", body = BackupResponse),
+        (status = 401, description = "Missing or invalid admin token", body = ErrorResponse),
+        (status = 403, description = "Admin endpoints are disabled", body = ErrorResponse),
+        (status = 409, description = "No backup store is configured", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    tag = "admin"
+)]
+#[debug_handler]
+pub async fn backup_notes(State(service): State<Arc<NoteService>>, l10n: Localizer) -> Response {
+    match service.backup_notes().await {
+        Ok(snapshot) => {
+            tracing::info!("Backed up {} notes to {}", snapshot.notes, snapshot.name);
+            let response = BackupResponse {
+                name: snapshot.name,
+                notes: snapshot.notes,
+                size: snapshot.size,
+            };
+            (StatusCode::CREATED, Json(response)).into_response()
+        }
+        Err(BackupError::Disabled) => (
+            StatusCode::CONFLICT,
+            ErrorResponse::new(MessageKey::BackupDisabled, &l10n),
+        )
+            .into_response(),
+        Err(e) => {
+            tracing::error!("{}: {e}", MessageKey::BackupFailed.english());
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                ErrorResponse::new(MessageKey::BackupFailed, &l10n),
+            )
+                .into_response()
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct SoapAuditParams {
+    /// Only requests made after this time
+    pub since: Option<DateTime<Utc>>,
+    /// Only requests that asked for this operation, e.g. `CreateNote`
+    pub operation: Option<String>,
+    /// Only requests answered with a fault
+    #[serde(default)]
+    pub faults_only: bool,
+    /// Maximum number of requests, 50 by default, at most 500
+    #[serde(default = "default_page_size")]
+    pub limit: u32,
+}
+
+#[utoipa::path(
+    get,
+    path = "/admin/soap-audit",
+    params(SoapAuditParams),
+    responses(
+        (status = 200, description = "Most recent SOAP requests, newest first. Requests are kept for 90 days", body = Vec<SoapAuditResponse>),
+        (status = 400, description = "Invalid parameters", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid admin token", body = ErrorResponse),
+        (status = 403, description = "Admin endpoints are disabled", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    tag = "admin"
+)]
+#[debug_handler]
+pub async fn soap_audit(
+    State(service): State<Arc<NoteService>>,
+    l10n: Localizer,
+    Query(params): Query<SoapAuditParams>,
+) -> Response {
+    let limit = params.limit.clamp(1, MAX_PAGE_SIZE);
+
+    match service
+        .soap_audit(
+            params.since,
+            params.operation.as_deref(),
+            params.faults_only,
+            limit.into(),
+        )
+        .await
+    {
+        Ok(entries) => (StatusCode::OK, Json(entries)).into_response(),
+        Err(e) => {
+            tracing::error!("{}: {e}", MessageKey::SoapAuditFailed.english());
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                ErrorResponse::new(MessageKey::SoapAuditFailed, &l10n),
+            )
+                .into_response()
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct UsersParams {
+    /// Only users of this tenant
+    pub tenant: Option<String>,
+    /// Maximum number of users, 50 by default, at most 500
+    #[serde(default = "default_page_size")]
+    pub limit: u32,
+}
+
+#[utoipa::path(
+    get,
+    path = "/admin/users",
+    params(UsersParams),
+    responses(
+        (status = 200, description = "Users added through the OIDC provider, most recently seen first", body = Vec<UserResponse>),
+        (status = 400, description = "Invalid parameters", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid admin token", body = ErrorResponse),
+        (status = 403, description = "Admin endpoints are disabled", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    tag = "admin"
+)]
+#[debug_handler]
+pub async fn list_users(
+    State(service): State<Arc<NoteService>>,
+    l10n: Localizer,
+    Query(params): Query<UsersParams>,
+) -> Response {
+    let limit = params.limit.clamp(1, MAX_PAGE_SIZE);
+
+    match service
+        .list_users(params.tenant.as_deref(), limit.into())
+        .await
+    {
+        Ok(users) => (StatusCode::OK, Json(users)).into_response(),
+        Err(e) => {
+            tracing::error!("{}: {e}", MessageKey::ListUsersFailed.english());
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                ErrorResponse::new(MessageKey::ListUsersFailed, &l10n),
+            )
+                .into_response()
+        }
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/me/usage",
+    responses(
+        (status = 200, description = "Requests the caller made in the current UTC minute and day against their quotas. Every instance counts the requests it serves on its own", body = UsageResponse),
+        (status = 401, description = "Missing or invalid credentials", body = ErrorResponse),
+        (status = 404, description = "Requests are not authenticated, usage is not tracked", body = ErrorResponse),
+        (status = 429, description = "A quota is used up", body = ErrorResponse)
+    ),
+    tag = "me"
+)]
+#[debug_handler]
+pub async fn my_usage(
+    Extension(quotas): Extension<Option<Arc<Quotas>>>,
+    principal: Option<Extension<Principal>>,
+    l10n: Localizer,
+) -> Response {
+    let Some(Extension(principal)) = principal else {
+        return (
+            StatusCode::NOT_FOUND,
+            ErrorResponse::new(MessageKey::NotAuthenticated, &l10n),
+        )
+            .into_response();
+    };
+
+    let usage = quotas.map(|quotas| quotas.usage(&principal));
+    let (minute, day) = usage.map_or((None, None), |usage| (usage.minute, usage.day));
+    let response = UsageResponse {
+        principal: principal.to_string(),
+        tenant: Tenant::current().to_string(),
+        minute,
+        day,
+    };
+    (StatusCode::OK, Json(response)).into_response()
+}
+
+/// `Retry-After` passed on to clients when the email service didn't suggest one
+const DEFAULT_RETRY_AFTER: Duration = Duration::from_secs(5);
+
+fn error_response(err: &OperationError, l10n: &Localizer) -> Response {
+    let error = ErrorResponse::new(err.message_key(), l10n);
+
+    match err {
+        OperationError::NotFound => (StatusCode::NOT_FOUND, error).into_response(),
+        OperationError::PreconditionFailed => {
+            (StatusCode::PRECONDITION_FAILED, error).into_response()
+        }
+        OperationError::InvalidArgument(_) => (StatusCode::BAD_REQUEST, error).into_response(),
+        OperationError::Duplicate(id) => {
+            (StatusCode::CONFLICT, error.with_duplicate_of(*id)).into_response()
+        }
+        OperationError::Database { source, .. } if source.is_transient() => {
+            (StatusCode::SERVICE_UNAVAILABLE, error).into_response()
+        }
+        OperationError::Database { .. } => {
+            (StatusCode::INTERNAL_SERVER_ERROR, error).into_response()
+        }
+        OperationError::Email(EmailError::Throttled { retry_after }) => {
+            let retry_after = retry_after.unwrap_or(DEFAULT_RETRY_AFTER).as_secs();
+            (
+                StatusCode::SERVICE_UNAVAILABLE,
+                [(header::RETRY_AFTER, retry_after.to_string())],
+                error,
+            )
+                .into_response()
+        }
+        OperationError::Email(e) => {
+            (StatusCode::BAD_GATEWAY, error.with_details(e)).into_response()
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct RetentionParams {
+    /// Rules to check instead of the configured ones, e.g. `archive:90,delete:365`
+    pub rules: Option<String>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/admin/retention",
+    params(RetentionParams),
+    responses(
+        (status = 200, description = "Number of notes each retention rule applies to now, nothing is changed", body = RetentionReport),
+        (status = 400, description = "Invalid rules", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid admin token", body = ErrorResponse),
+        (status = 403, description = "Admin endpoints are disabled", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    tag = "admin"
+)]
+#[debug_handler]
+pub async fn retention_report(
+    State(service): State<Arc<NoteService>>,
+    Extension(policy): Extension<Arc<RetentionPolicy>>,
+    l10n: Localizer,
+    Query(params): Query<RetentionParams>,
+) -> Response {
+    let policy = match params.rules.as_deref().map(parse_rules) {
+        None => policy.as_ref().clone(),
+        Some(Ok(rules)) => RetentionPolicy {
+            rules,
+            dry_run: true,
+        },
+        Some(Err(e)) => {
+            tracing::debug!("Rejected retention rules: {e}");
+            return (
+                StatusCode::BAD_REQUEST,
+                ErrorResponse::new(MessageKey::InvalidRetentionRules, &l10n),
+            )
+                .into_response();
+        }
+    };
+
+    match service.retention_report(&policy).await {
+        Ok(report) => (StatusCode::OK, Json(report)).into_response(),
+        Err(e) => {
+            tracing::error!("{}: {e}", MessageKey::RetentionFailed.english());
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                ErrorResponse::new(MessageKey::RetentionFailed, &l10n),
+            )
+                .into_response()
+        }
+    }
+}
+
+fn attachment_error(err: &AttachmentError, l10n: &Localizer) -> Response {
+    match err {
+        AttachmentError::Disabled => (
+            StatusCode::CONFLICT,
+            ErrorResponse::new(MessageKey::AttachmentsDisabled, l10n),
+        )
+            .into_response(),
+        AttachmentError::NotFound => (
+            StatusCode::NOT_FOUND,
+            ErrorResponse::new(MessageKey::AttachmentNotFound, l10n),
+        )
+            .into_response(),
+        AttachmentError::TooLarge { max_bytes } => (
+            StatusCode::PAYLOAD_TOO_LARGE,
+            ErrorResponse::new(MessageKey::AttachmentTooLarge, l10n)
+                .with_details(format_args!("limit is {max_bytes} bytes")),
+        )
+            .into_response(),
+        AttachmentError::Storage(_) | AttachmentError::Database(_) => {
+            tracing::error!("{}: {err}", MessageKey::AttachmentFailed.english());
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                ErrorResponse::new(MessageKey::AttachmentFailed, l10n),
+            )
+                .into_response()
+        }
+    }
+}
+
+/// File name safe to put in `Content-Disposition`, characters that aren't printable
+/// ASCII or would end the quoted name are replaced
+fn disposition_filename(filename: &str) -> String {
+    filename
+        .chars()
+        .map(|c| {
+            if c.is_ascii_graphic() && c != '"' && c != '\\' || c == ' ' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct UploadAttachmentParams {
+    /// Name of the file, `attachment` if omitted
+    pub filename: Option<String>,
+}
+
+#[utoipa::path(
+    post,
+    path = "/notes/{id}/attachments",
+    params(
+        ("id" = i64, Path, description = "Note ID"),
+        UploadAttachmentParams
+    ),
+    request_body(content = Vec<u8>, description = "The file, its `Content-Type` is kept",
+        content_type = "application/octet-stream"),
+    responses(
+        (status = 201, description = "File attached to the note", body = AttachmentResponse),
+        (status = 404, description = "Note not found", body = ErrorResponse),
+        (status = 409, description = "Attachments are not configured", body = ErrorResponse),
+        (status = 413, description = "File is too large", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    tag = "attachments"
+)]
+#[debug_handler]
+pub async fn upload_attachment(
+    State(service): State<Arc<NoteService>>,
+    l10n: Localizer,
+    Path(id): Path<i64>,
+    Query(params): Query<UploadAttachmentParams>,
+    headers: HeaderMap,
+    body: Body,
+) -> Response {
+    let filename = params
+        .filename
+        .map(|name| name.trim().to_string())
+        .filter(|name| !name.is_empty())
+        .unwrap_or_else(|| "attachment".to_string());
+    let content_type = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or("application/octet-stream")
+        .to_string();
+    let data = byte_stream(body.into_data_stream());
+
+    match service
+        .upload_attachment(id, filename, content_type, data)
+        .await
+    {
+        Ok(attachment) => (StatusCode::CREATED, Json(attachment)).into_response(),
+        Err(e) => attachment_error(&e, &l10n),
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/notes/{id}/attachments",
+    params(
+        ("id" = i64, Path, description = "Note ID")
+    ),
+    responses(
+        (status = 200, description = "Files attached to the note, oldest first", body = [AttachmentResponse]),
+        (status = 404, description = "Note not found", body = ErrorResponse),
+        (status = 409, description = "Attachments are not configured", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    tag = "attachments"
+)]
+#[debug_handler]
+pub async fn list_attachments(
+    State(service): State<Arc<NoteService>>,
+    l10n: Localizer,
+    Path(id): Path<i64>,
+) -> Response {
+    match service.list_attachments(id).await {
+        Ok(attachments) => (StatusCode::OK, Json(attachments)).into_response(),
+        Err(e) => attachment_error(&e, &l10n),
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/notes/{id}/attachments/{attachment_id}",
+    params(
+        ("id" = i64, Path, description = "Note ID"),
+        ("attachment_id" = i64, Path, description = "Attachment ID")
+    ),
+    responses(
+        (status = 200, description = "The file, with the `Content-Type` it was uploaded with"),
+        (status = 404, description = "Note or attachment not found", body = ErrorResponse),
+        (status = 409, description = "Attachments are not configured", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    tag = "attachments"
+)]
+#[debug_handler]
+pub async fn download_attachment(
+    State(service): State<Arc<NoteService>>,
+    l10n: Localizer,
+    Path((id, attachment_id)): Path<(i64, i64)>,
+) -> Response {
+    let (attachment, data) = match service.download_attachment(id, attachment_id).await {
+        Ok(download) => download,
+        Err(e) => return attachment_error(&e, &l10n),
+    };
+    let disposition = format!(
+        "attachment; filename=\"{}\"",
+        disposition_filename(&attachment.filename)
+    );
+
+    (
+        StatusCode::OK,
+        [
+            (header::CONTENT_TYPE, attachment.content_type),
+            (header::CONTENT_LENGTH, attachment.size.to_string()),
+            (header::CONTENT_DISPOSITION, disposition),
+            (header::X_CONTENT_TYPE_OPTIONS, "nosniff".to_string()),
+        ],
+        Body::from_stream(data),
+    )
+        .into_response()
+}
+
+#[utoipa::path(
+    delete,
+    path = "/notes/{id}/attachments/{attachment_id}",
+    params(
+        ("id" = i64, Path, description = "Note ID"),
+        ("attachment_id" = i64, Path, description = "Attachment ID")
+    ),
+    responses(
+        (status = 204, description = "Attachment and its file removed"),
+        (status = 404, description = "Note or attachment not found", body = ErrorResponse),
+        (status = 409, description = "Attachments are not configured", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    tag = "attachments"
+)]
+#[debug_handler]
+pub async fn delete_attachment(
+    State(service): State<Arc<NoteService>>,
+    l10n: Localizer,
+    Path((id, attachment_id)): Path<(i64, i64)>,
+) -> Response {
+    match service.delete_attachment(id, attachment_id).await {
+        Ok(()) => StatusCode::NO_CONTENT.into_response(),
+        Err(e) => attachment_error(&e, &l10n),
     }
 }
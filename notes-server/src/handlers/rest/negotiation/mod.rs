@@ -0,0 +1,140 @@
+use axum::{
+    body::Body,
+    extract::Request,
+    http::{HeaderMap, HeaderValue, StatusCode, header},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use serde_json::Value;
+
+/// Root element of XML representations
+const XML_ROOT: &str = "response";
+/// Element wrapping each item when the whole document is a list
+const XML_ITEM: &str = "item";
+
+/// Representation of response bodies, chosen from the `Accept` header
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ResponseFormat {
+    Json,
+    Xml,
+    MessagePack,
+}
+
+impl ResponseFormat {
+    fn from_media_type(media_type: &str) -> Option<Self> {
+        match media_type {
+            "application/json" | "application/*" | "*/*" => Some(Self::Json),
+            "application/xml" | "text/xml" => Some(Self::Xml),
+            "application/msgpack" | "application/x-msgpack" | "application/vnd.msgpack" => {
+                Some(Self::MessagePack)
+            }
+            _ => None,
+        }
+    }
+
+    /// The supported media type with the highest `q` value. Clients not sending `Accept`,
+    /// or accepting none of the supported types, get JSON
+    fn from_headers(headers: &HeaderMap) -> Self {
+        let Some(accept) = headers.get(header::ACCEPT).and_then(|v| v.to_str().ok()) else {
+            return Self::Json;
+        };
+
+        let mut candidates: Vec<(f32, Self)> = accept
+            .split(',')
+            .filter_map(|range| {
+                let mut params = range.split(';');
+                let media_type = params.next()?.trim().to_lowercase();
+                let quality = params
+                    .filter_map(|p| p.trim().strip_prefix("q="))
+                    .find_map(|q| q.parse().ok())
+                    .unwrap_or(1.0);
+                Self::from_media_type(&media_type)
+                    .filter(|_| quality > 0.0)
+                    .map(|format| (quality, format))
+            })
+            .collect();
+        // Stable, so equally preferred types keep the client's order
+        candidates.sort_by(|a, b| b.0.total_cmp(&a.0));
+
+        candidates.first().map_or(Self::Json, |(_, format)| *format)
+    }
+
+    const fn content_type(self) -> &'static str {
+        match self {
+            Self::Json => "application/json",
+            Self::Xml => "application/xml; charset=utf-8",
+            Self::MessagePack => "application/msgpack",
+        }
+    }
+
+    fn encode(self, value: &Value) -> Result<Vec<u8>, String> {
+        match self {
+            Self::Json => serde_json::to_vec(value).map_err(|e| e.to_string()),
+            Self::Xml => {
+                // A document has a single root, so lists are wrapped
+                let wrapped;
+                let value = if value.is_array() {
+                    wrapped = serde_json::json!({ XML_ITEM: value });
+                    &wrapped
+                } else {
+                    value
+                };
+                quick_xml::se::to_string_with_root(XML_ROOT, value)
+                    .map(String::into_bytes)
+                    .map_err(|e| e.to_string())
+            }
+            Self::MessagePack => rmp_serde::to_vec_named(value).map_err(|e| e.to_string()),
+        }
+    }
+}
+
+/// JSON documents returned by handlers. Downloads (`Content-Disposition`) keep the format
+/// requested explicitly, and streams are never buffered
+fn is_json_document(response: &Response) -> bool {
+    let headers = response.headers();
+    !headers.contains_key(header::CONTENT_DISPOSITION)
+        && headers
+            .get(header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .is_some_and(|v| v.starts_with("application/json"))
+}
+
+/// Re-encodes JSON responses as XML or ``MessagePack`` when the client's `Accept` header
+/// prefers them, so handlers only ever produce JSON
+pub async fn negotiate_format(request: Request, next: Next) -> Response {
+    let format = ResponseFormat::from_headers(request.headers());
+    let mut response = next.run(request).await;
+
+    if !is_json_document(&response) {
+        return response;
+    }
+    response
+        .headers_mut()
+        .append(header::VARY, HeaderValue::from_static("accept"));
+    if format == ResponseFormat::Json {
+        return response;
+    }
+
+    let (mut parts, body) = response.into_parts();
+    let encoded = match axum::body::to_bytes(body, usize::MAX).await {
+        Ok(bytes) => serde_json::from_slice::<Value>(&bytes)
+            .map_err(|e| e.to_string())
+            .and_then(|value| format.encode(&value)),
+        Err(e) => Err(e.to_string()),
+    };
+
+    match encoded {
+        Ok(encoded) => {
+            parts.headers.insert(
+                header::CONTENT_TYPE,
+                HeaderValue::from_static(format.content_type()),
+            );
+            parts.headers.remove(header::CONTENT_LENGTH);
+            Response::from_parts(parts, Body::from(encoded))
+        }
+        Err(e) => {
+            tracing::error!("Failed to encode response as {format:?}: {e}");
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
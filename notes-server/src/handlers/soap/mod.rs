@@ -1,535 +1,300 @@
-use std::sync::Arc;
+mod notes;
+mod registry;
+mod version;
 
 use axum::{
+    Extension,
     body::Bytes,
-    extract::State,
-    http::StatusCode,
+    extract::{ConnectInfo, State},
+    http::{HeaderMap, StatusCode, header},
     response::{IntoResponse, Response},
 };
-use serde::{Deserialize, Serialize};
+use soap_envelope::{Fault, FaultCode};
 
-use crate::{dto, service::NoteService};
-
-// Request envelope
-
-#[derive(Debug, Deserialize, Serialize)]
-#[serde(rename = "Envelope")]
-#[serde(rename_all = "camelCase")]
-pub struct SoapEnvelope {
-    #[serde(rename = "Body")]
-    pub body: SoapBody,
-}
-
-// Request body
-
-#[derive(Debug, Deserialize, Serialize)]
-#[serde(rename_all = "camelCase")]
-pub struct SoapBody {
-    /// ``CreateNote`` operation request
-    #[serde(rename = "CreateNote")]
-    pub create: Option<CreateNoteRequest>,
-
-    /// ``GetOneNote`` operation request
-    #[serde(rename = "GetNote")]
-    pub get_one: Option<GetOneNoteRequest>,
-
-    /// ``GetAllNotes`` operation request
-    #[serde(rename = "GetAllNotes")]
-    pub get_all: Option<GetAllNotesRequest>,
-
-    /// ``UpdateNote`` operation request
-    #[serde(rename = "UpdateNote")]
-    pub update: Option<UpdateNoteRequest>,
-
-    /// ``DeleteNote`` operation request
-    #[serde(rename = "DeleteNote")]
-    pub delete: Option<DeleteNoteRequest>,
-}
-
-// Request content variants
-
-#[derive(Debug, Deserialize, Serialize)]
-#[serde(rename_all = "camelCase")]
-pub struct CreateNoteRequest {
-    #[serde(rename = "Content")]
-    pub content: String,
-}
-
-#[derive(Debug, Deserialize, Serialize)]
-#[serde(rename_all = "camelCase")]
-pub struct GetOneNoteRequest {
-    #[serde(rename = "Id")]
-    pub id: i64,
-}
-
-#[derive(Debug, Deserialize, Serialize)]
-pub struct GetAllNotesRequest;
-
-#[derive(Debug, Deserialize, Serialize)]
-#[serde(rename_all = "camelCase")]
-pub struct UpdateNoteRequest {
-    #[serde(rename = "Id")]
-    pub id: i64,
+use std::{
+    net::SocketAddr,
+    sync::{Arc, LazyLock},
+    time::Instant,
+};
 
-    #[serde(rename = "Content")]
-    pub content: String,
-}
+use crate::{
+    i18n::{Localizer, MessageKey},
+    models::NewSoapAuditEntry,
+    operations::OperationError,
+    service::NoteService,
+};
+use registry::{Registry, SoapError};
+use version::{SoapVersion, VersionError};
 
-#[derive(Debug, Deserialize, Serialize)]
-#[serde(rename_all = "camelCase")]
-pub struct DeleteNoteRequest {
-    #[serde(rename = "Id")]
-    pub id: i64,
-}
+static OPERATIONS: LazyLock<Registry> = LazyLock::new(notes::registry);
 
-// Enum for all operation types
-enum NoteOperationRequest {
-    Create(CreateNoteRequest),
-    GetOne(GetOneNoteRequest),
-    GetAll,
-    Update(UpdateNoteRequest),
-    Delete(DeleteNoteRequest),
-}
+/// Which SOAP requests are recorded in the audit trail
+#[derive(Debug, Clone, Copy)]
+pub struct SoapAudit {
+    pub enabled: bool,
+    /// Also store the request envelopes, which contain note contents
+    pub capture_envelopes: bool,
+}
+
+/// Request elements to execute along with their operation names: the one named by the
+/// action, or every element in the `Body` when there is no action
+fn select_operations<'a>(
+    elements: &[(String, &'a str)],
+    action: Option<&str>,
+) -> Result<Vec<(String, &'a str)>, MessageKey> {
+    let selected: Vec<_> = match action {
+        None => elements.to_vec(),
+        Some(action) if !OPERATIONS.contains(action) => Vec::new(),
+        Some(action) => {
+            return elements
+                .iter()
+                .find(|(name, _)| name == action)
+                .map(|(name, element)| vec![(name.clone(), *element)])
+                .ok_or(MessageKey::SoapActionMismatch);
+        }
+    };
 
-fn to_operation(body: SoapBody) -> Option<NoteOperationRequest> {
-    if let Some(c) = body.create {
-        return Some(NoteOperationRequest::Create(c));
-    }
-    if let Some(g) = body.get_one {
-        return Some(NoteOperationRequest::GetOne(g));
-    }
-    if let Some(_g) = body.get_all {
-        return Some(NoteOperationRequest::GetAll);
-    }
-    if let Some(u) = body.update {
-        return Some(NoteOperationRequest::Update(u));
+    if selected.is_empty() || !selected.iter().all(|(name, _)| OPERATIONS.contains(name)) {
+        return Err(MessageKey::UnsupportedOperation);
     }
-    if let Some(d) = body.delete {
-        return Some(NoteOperationRequest::Delete(d));
-    }
-    None
-}
-
-// Common response elements
-
-#[derive(Debug, Serialize)]
-pub struct NoteResponseXml {
-    #[serde(rename = "m:Id")]
-    pub id: i64,
-
-    #[serde(rename = "m:Content")]
-    pub content: String,
-}
-
-// CreateResponse
-
-#[derive(Debug, Serialize)]
-#[serde(rename = "m:CreateNoteResponse")]
-pub struct CreateNoteResponse {
-    #[serde(rename = "@xmlns:m")]
-    pub m_ns: String,
-    #[serde(rename = "m:Note")]
-    pub note: NoteResponseXml,
-}
-
-// GetOneResponse
-
-#[derive(Debug, Serialize)]
-#[serde(rename = "m:GetOneNoteResponse")]
-pub struct GetOneNoteResponse {
-    #[serde(rename = "@xmlns:m")]
-    pub m_ns: String,
-    #[serde(rename = "m:Note")]
-    pub note: NoteResponseXml,
-}
-
-// GetAllResponse
-
-#[derive(Debug, Serialize)]
-#[serde(rename = "m:GetAllNotesResponse")]
-pub struct GetAllNotesResponse {
-    #[serde(rename = "@xmlns:m")]
-    pub m_ns: String,
-    #[serde(rename = "m:Note")]
-    pub notes: Vec<NoteResponseXml>,
-}
-
-// UpdateResponse
-
-#[derive(Debug, Serialize)]
-#[serde(rename = "m:UpdateNoteResponse")]
-pub struct UpdateNoteResponse {
-    #[serde(rename = "@xmlns:m")]
-    pub m_ns: String,
-    #[serde(rename = "m:Note")]
-    pub note: NoteResponseXml,
-}
-
-// DeleteResponse
+    Ok(selected)
+}
+
+/// Operation name from the `SOAPAction` header (SOAP 1.1) or the `action` parameter of
+/// the content type (SOAP 1.2). Actions may be URIs, the last segment names the operation.
+/// An empty action means the intent is given by the body only
+fn requested_action(headers: &HeaderMap, version: SoapVersion) -> Option<String> {
+    let action = match version {
+        SoapVersion::Soap11 => headers.get("SOAPAction")?.to_str().ok()?.to_string(),
+        SoapVersion::Soap12 => headers
+            .get(header::CONTENT_TYPE)?
+            .to_str()
+            .ok()?
+            .split(';')
+            .skip(1)
+            .find_map(|param| {
+                let (name, value) = param.split_once('=')?;
+                name.trim()
+                    .eq_ignore_ascii_case("action")
+                    .then(|| value.trim().to_string())
+            })?,
+    };
 
-#[derive(Debug, Serialize)]
-#[serde(rename = "m:DeleteNoteResponse")]
-pub struct DeleteNoteResponse {
-    #[serde(rename = "@xmlns:m")]
-    pub m_ns: String,
+    let action = action.trim().trim_matches('"');
+    action
+        .rsplit(['/', '#', ':'])
+        .next()
+        .filter(|name| !name.is_empty())
+        .map(ToString::to_string)
 }
 
 /// Main SOAP handler entrypoint
-pub async fn handle_request(State(service): State<Arc<NoteService>>, body: Bytes) -> Response {
-    let Ok(body_str) = std::str::from_utf8(&body) else {
-        return (StatusCode::BAD_REQUEST, "Request body must be valid UTF-8").into_response();
+pub async fn handle_request(
+    State(service): State<Arc<NoteService>>,
+    Extension(audit): Extension<SoapAudit>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    l10n: Localizer,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    let started = Instant::now();
+    let mut operations = Vec::new();
+    let (version, outcome) = process(&service, &headers, &body, &mut operations).await;
+
+    let response = match &outcome {
+        Ok(content) => build_ok_response(version, content),
+        Err(failure) => failure.response(version, &l10n),
     };
 
-    let envelope: SoapEnvelope = match serde_xml_rs::from_str(body_str) {
-        Ok(env) => env,
-        Err(e) => {
-            tracing::error!("Failed to deserialize SOAP envelope: {e}");
-            let fault_xml = build_soap_fault(
-                SoapFaultCode::Client,
-                "Invalid SOAP XML envelope: request body could not be parsed",
-            );
-            return (
-                StatusCode::BAD_REQUEST,
-                [("Content-Type", "text/xml; charset=utf-8")],
-                fault_xml,
-            )
-                .into_response();
-        }
-    };
-
-    match to_operation(envelope.body) {
-        Some(NoteOperationRequest::Create(c)) => handle_create_note(&service, c).await,
-        Some(NoteOperationRequest::GetOne(g)) => handle_get_one_note(&service, g).await,
-        Some(NoteOperationRequest::GetAll) => handle_get_all_notes(&service).await,
-        Some(NoteOperationRequest::Update(u)) => handle_update_note(&service, u).await,
-        Some(NoteOperationRequest::Delete(d)) => handle_delete_note(&service, d).await,
-        None => {
-            let fault_xml = build_soap_fault(SoapFaultCode::Client, "Unsupported operation");
-            (
-                StatusCode::BAD_REQUEST,
-                [("Content-Type", "text/xml; charset=utf-8")],
-                fault_xml,
-            )
-                .into_response()
-        }
+    if audit.enabled {
+        let entry = NewSoapAuditEntry {
+            operations,
+            caller: peer.ip().to_string(),
+            forwarded_for: headers
+                .get("x-forwarded-for")
+                .and_then(|v| v.to_str().ok())
+                .map(ToString::to_string),
+            soap_version: match version {
+                SoapVersion::Soap11 => "1.1",
+                SoapVersion::Soap12 => "1.2",
+            }
+            .to_string(),
+            fault_code: outcome
+                .err()
+                .map(|failure| failure.code.name(version).to_string()),
+            latency_ms: i64::try_from(started.elapsed().as_millis()).unwrap_or(i64::MAX),
+            envelope: audit
+                .capture_envelopes
+                .then(|| String::from_utf8_lossy(&body).into_owned()),
+        };
+        tokio::spawn(async move { service.record_soap_audit(entry).await });
     }
-}
 
-/// Common SOAP 1.1 fault codes.
-#[derive(Debug, Clone, Copy)]
-#[allow(dead_code)]
-enum SoapFaultCode {
-    /// The message was incorrectly formed or contained incorrect information.
-    Client,
-    /// The message could not be processed for reasons not directly attributable to the client.
-    Server,
-    /// An immediate child element of the Header was not understood.
-    MustUnderstand,
-    /// The SOAP Envelope namespace is not supported.
-    VersionMismatch,
+    response
 }
 
-impl SoapFaultCode {
-    const fn as_str(self) -> &'static str {
-        match self {
-            Self::Client => "Client",
-            Self::Server => "Server",
-            Self::MustUnderstand => "MustUnderstand",
-            Self::VersionMismatch => "VersionMismatch",
+/// Version to answer in and the `Body` content of the response, or why it is a fault.
+/// `operations` receives the names of the operations the request asked for
+async fn process(
+    service: &NoteService,
+    headers: &HeaderMap,
+    body: &[u8],
+    operations: &mut Vec<String>,
+) -> (SoapVersion, Result<String, Failure>) {
+    let Ok(body) = std::str::from_utf8(body) else {
+        let version = version::declared_version(headers).unwrap_or(SoapVersion::Soap11);
+        return (version, Err(Failure::client(MessageKey::InvalidUtf8)));
+    };
+
+    match version::detect(headers, body) {
+        Ok(version) => (
+            version,
+            execute(service, headers, body, version, operations).await,
+        ),
+        Err(VersionError::NotAnEnvelope { version }) => {
+            (version, Err(Failure::client(MessageKey::InvalidEnvelope)))
         }
+        Err(VersionError::Mismatch { version, reason }) => (
+            version,
+            Err(Failure {
+                code: FaultCode::VersionMismatch,
+                ..Failure::client(reason)
+            }),
+        ),
     }
 }
 
-fn handle_serialization_error(e: &String) -> Response {
-    tracing::error!("Failed to serialize SOAP response: {e}");
-    let fault_xml = build_soap_fault(SoapFaultCode::Server, "Failed to serialize SOAP response");
-    (
-        StatusCode::INTERNAL_SERVER_ERROR,
-        [("Content-Type", "text/xml; charset=utf-8")],
-        fault_xml,
-    )
-        .into_response()
-}
-
-fn handle_internal_error(err: &tokio_postgres::Error, custom_error_string: &str) -> Response {
-    tracing::error!("{custom_error_string}: {err}");
-    let fault_xml = build_soap_fault(SoapFaultCode::Server, custom_error_string);
-    (
-        StatusCode::INTERNAL_SERVER_ERROR,
-        [("Content-Type", "text/xml; charset=utf-8")],
-        fault_xml,
-    )
-        .into_response()
-}
-
-fn handle_not_found_error() -> Response {
-    tracing::error!("Note not found");
-    let fault_xml = build_soap_fault(SoapFaultCode::Server, "Note not found");
-    (
-        StatusCode::NOT_FOUND,
-        [("Content-Type", "text/xml; charset=utf-8")],
-        fault_xml,
-    )
-        .into_response()
-}
-
-fn build_ok_response(xml_body: String) -> Response {
-    (
-        StatusCode::OK,
-        [("Content-Type", "text/xml; charset=utf-8")],
-        xml_body,
-    )
-        .into_response()
-}
-
-fn build_soap_fault(fault_code: SoapFaultCode, fault_string: &str) -> String {
-    format!(
-        r#"<?xml version="1.0" encoding="UTF-8"?>
-<soap:Envelope xmlns:soap="http://schemas.xmlsoap.org/soap/envelope/" soap:encodingStyle="http://www.w3.org/2003/05/soap-encoding">
-  <soap:Body>
-    <soap:Fault>
-      <faultcode>{fault_code}</faultcode>
-      <faultstring>{fault_string}</faultstring>
-    </soap:Fault>
-  </soap:Body>
-</soap:Envelope>"#,
-        fault_code = fault_code.as_str()
-    )
-}
-
-#[derive(Debug, Serialize)]
-#[serde(rename = "soap:Envelope", rename_all = "kebab-case")]
-struct CreateNoteEnvelope {
-    #[serde(rename = "@xmlns:soap")]
-    soap_ns: String,
-    #[serde(rename = "@soap:encodingStyle")]
-    encoding_style: String,
-    #[serde(rename = "soap:Body")]
-    body: CreateNoteBody,
-}
-
-#[derive(Debug, Serialize)]
-struct CreateNoteBody {
-    #[serde(rename = "m:CreateNoteResponse")]
-    response: CreateNoteResponse,
-}
-
-async fn handle_create_note(service: &NoteService, req: CreateNoteRequest) -> Response {
-    let dto_req = dto::CreateNoteRequest {
-        content: req.content,
+/// Executes the operations of the envelope in order, returning their response elements
+async fn execute(
+    service: &NoteService,
+    headers: &HeaderMap,
+    body: &str,
+    version: SoapVersion,
+    operations: &mut Vec<String>,
+) -> Result<String, Failure> {
+    let Some(elements) = soap_envelope::body_elements(body) else {
+        tracing::error!("Failed to parse SOAP envelope body");
+        return Err(Failure::client(MessageKey::InvalidEnvelope));
     };
-
-    match service.create_note(dto_req).await {
-        Ok(note) => {
-            let response = CreateNoteResponse {
-                m_ns: "https://notes-server/soap/v1".to_string(),
-                note: NoteResponseXml {
-                    id: note.id,
-                    content: note.content,
-                },
-            };
-
-            let envelope = CreateNoteEnvelope {
-                soap_ns: "http://www.w3.org/2003/05/soap-envelope".to_string(),
-                encoding_style: "http://www.w3.org/2003/05/soap-encoding".to_string(),
-                body: CreateNoteBody { response },
-            };
-
-            let xml_body = match quick_xml::se::to_string(&envelope) {
-                Ok(s) => s,
-                Err(e) => return handle_serialization_error(&format!("{e}")),
-            };
-
-            build_ok_response(xml_body)
+    let action = requested_action(headers, version);
+    let selected = select_operations(&elements, action.as_deref()).map_err(Failure::client)?;
+    operations.extend(selected.iter().map(|(name, _)| name.clone()));
+
+    // Every request element is deserialized before anything is executed,
+    // so a malformed element does not leave the batch half-applied
+    let mut prepared = Vec::with_capacity(selected.len());
+    for (name, element) in selected {
+        match OPERATIONS.prepare(&name, element) {
+            Some(Ok(operation)) => prepared.push((name, operation)),
+            Some(Err(e)) => {
+                tracing::error!("Failed to deserialize SOAP {name} request: {e}");
+                return Err(Failure::client(MessageKey::InvalidEnvelope));
+            }
+            None => return Err(Failure::client(MessageKey::UnsupportedOperation)),
         }
-        Err(e) => handle_internal_error(&e, "Failed to create note"),
     }
-}
-
-#[derive(Debug, Serialize)]
-#[serde(rename = "soap:Envelope")]
-struct GetOneNoteEnvelope {
-    #[serde(rename = "@xmlns:soap")]
-    soap_ns: String,
-    #[serde(rename = "@soap:encodingStyle")]
-    encoding_style: String,
-    #[serde(rename = "soap:Body")]
-    body: GetOneNoteBody,
-}
-
-#[derive(Debug, Serialize)]
-struct GetOneNoteBody {
-    #[serde(rename = "m:GetOneNoteResponse")]
-    response: GetOneNoteResponse,
-}
-
-async fn handle_get_one_note(service: &NoteService, req: GetOneNoteRequest) -> Response {
-    match service.get_one_note(req.id).await {
-        Ok(Some(note)) => {
-            let response = GetOneNoteResponse {
-                m_ns: "https://notes-server/soap/v1".to_string(),
-                note: NoteResponseXml {
-                    id: note.id,
-                    content: note.content,
-                },
-            };
-
-            let envelope = GetOneNoteEnvelope {
-                soap_ns: "http://www.w3.org/2003/05/soap-envelope".to_string(),
-                encoding_style: "http://www.w3.org/2003/05/soap-encoding".to_string(),
-                body: GetOneNoteBody { response },
-            };
 
-            let xml_body = match quick_xml::se::to_string(&envelope) {
-                Ok(s) => s,
-                Err(e) => return handle_serialization_error(&format!("{e}")),
-            };
-
-            build_ok_response(xml_body)
+    let batch = prepared.len() > 1;
+    let mut responses = String::new();
+    for (index, (name, operation)) in prepared.into_iter().enumerate() {
+        // Operations are not atomic: the ones before a failed operation stay applied
+        // and the fault tells which operation failed
+        match operation(service).await {
+            Ok(response) => responses.push_str(&response),
+            Err(SoapError::Operation(e)) => {
+                let detail = batch.then(|| failed_operation_detail(index, &name));
+                return Err(Failure::operation(&e, detail));
+            }
+            Err(SoapError::Serialization(e)) => {
+                tracing::error!("Failed to serialize SOAP response: {e}");
+                return Err(Failure {
+                    status: StatusCode::INTERNAL_SERVER_ERROR,
+                    code: FaultCode::Server,
+                    key: MessageKey::SerializationFailed,
+                    detail: None,
+                });
+            }
         }
-        Ok(None) => handle_not_found_error(),
-        Err(e) => handle_internal_error(&e, "Failed to get note"),
     }
-}
 
-#[derive(Debug, Serialize)]
-#[serde(rename = "soap:Envelope")]
-struct GetAllNotesEnvelope {
-    #[serde(rename = "@xmlns:soap")]
-    soap_ns: String,
-    #[serde(rename = "@soap:encodingStyle")]
-    encoding_style: String,
-    #[serde(rename = "soap:Body")]
-    body: GetAllNotesBody,
+    Ok(responses)
 }
 
-#[derive(Debug, Serialize)]
-struct GetAllNotesBody {
-    #[serde(rename = "m:GetAllNotesResponse")]
-    response: GetAllNotesResponse,
+/// Fault detail naming the operation of a batch that failed, by its position in the `Body`
+fn failed_operation_detail(index: usize, name: &str) -> String {
+    format!(
+        r#"<m:FailedOperation xmlns:m="{namespace}"><m:Index>{index}</m:Index><m:Name>{name}</m:Name></m:FailedOperation>"#,
+        namespace = registry::NAMESPACE,
+        name = quick_xml::escape::escape(name),
+    )
 }
 
-async fn handle_get_all_notes(service: &NoteService) -> Response {
-    match service.get_all_notes().await {
-        Ok(notes) => {
-            let notes_xml: Vec<NoteResponseXml> = notes
-                .into_iter()
-                .map(|note| NoteResponseXml {
-                    id: note.id,
-                    content: note.content,
-                })
-                .collect();
-
-            let response = GetAllNotesResponse {
-                m_ns: "https://notes-server/soap/v1".to_string(),
-                notes: notes_xml,
-            };
-
-            let envelope = GetAllNotesEnvelope {
-                soap_ns: "http://www.w3.org/2003/05/soap-envelope".to_string(),
-                encoding_style: "http://www.w3.org/2003/05/soap-encoding".to_string(),
-                body: GetAllNotesBody { response },
-            };
-
-            let xml_body = match quick_xml::se::to_string(&envelope) {
-                Ok(s) => s,
-                Err(e) => return handle_serialization_error(&format!("{e}")),
-            };
+/// Why a request is answered with a fault
+struct Failure {
+    status: StatusCode,
+    code: FaultCode,
+    key: MessageKey,
+    /// Markup placed in the fault detail
+    detail: Option<String>,
+}
 
-            build_ok_response(xml_body)
+impl Failure {
+    const fn client(key: MessageKey) -> Self {
+        Self {
+            status: StatusCode::BAD_REQUEST,
+            code: FaultCode::Client,
+            key,
+            detail: None,
         }
-        Err(e) => handle_internal_error(&e, "Failed to get note"),
     }
-}
-
-#[derive(Debug, Serialize)]
-#[serde(rename = "soap:Envelope")]
-struct UpdateNoteEnvelope {
-    #[serde(rename = "@xmlns:soap")]
-    soap_ns: String,
-    #[serde(rename = "@soap:encodingStyle")]
-    encoding_style: String,
-    #[serde(rename = "soap:Body")]
-    body: UpdateNoteBody,
-}
-
-#[derive(Debug, Serialize)]
-struct UpdateNoteBody {
-    #[serde(rename = "m:UpdateNoteResponse")]
-    response: UpdateNoteResponse,
-}
-
-async fn handle_update_note(service: &NoteService, req: UpdateNoteRequest) -> Response {
-    let dto_req = dto::UpdateNoteRequest {
-        content: req.content,
-    };
-
-    match service.update_note(req.id, dto_req).await {
-        Ok(Some(note)) => {
-            let response = UpdateNoteResponse {
-                m_ns: "https://notes-server/soap/v1".to_string(),
-                note: NoteResponseXml {
-                    id: note.id,
-                    content: note.content,
-                },
-            };
 
-            let envelope = UpdateNoteEnvelope {
-                soap_ns: "http://www.w3.org/2003/05/soap-envelope".to_string(),
-                encoding_style: "http://www.w3.org/2003/05/soap-encoding".to_string(),
-                body: UpdateNoteBody { response },
-            };
-
-            let xml_body = match quick_xml::se::to_string(&envelope) {
-                Ok(s) => s,
-                Err(e) => return handle_serialization_error(&format!("{e}")),
-            };
-
-            build_ok_response(xml_body)
+    const fn operation(err: &OperationError, detail: Option<String>) -> Self {
+        let (status, code) = match err {
+            OperationError::NotFound => (StatusCode::NOT_FOUND, FaultCode::Server),
+            OperationError::PreconditionFailed => {
+                (StatusCode::PRECONDITION_FAILED, FaultCode::Client)
+            }
+            OperationError::InvalidArgument(_) => (StatusCode::BAD_REQUEST, FaultCode::Client),
+            OperationError::Duplicate(_) => (StatusCode::CONFLICT, FaultCode::Client),
+            OperationError::Database { source, .. } if source.is_transient() => {
+                (StatusCode::SERVICE_UNAVAILABLE, FaultCode::Server)
+            }
+            OperationError::Database { .. } => {
+                (StatusCode::INTERNAL_SERVER_ERROR, FaultCode::Server)
+            }
+            OperationError::Email(_) => (StatusCode::BAD_GATEWAY, FaultCode::Server),
+        };
+
+        Self {
+            status,
+            code,
+            key: err.message_key(),
+            detail,
         }
-        Ok(None) => handle_not_found_error(),
-        Err(e) => handle_internal_error(&e, "Failed to update note"),
     }
-}
-
-#[derive(Debug, Serialize)]
-#[serde(rename = "soap:Envelope")]
-struct DeleteNoteEnvelope {
-    #[serde(rename = "@xmlns:soap")]
-    soap_ns: String,
-    #[serde(rename = "@soap:encodingStyle")]
-    encoding_style: String,
-    #[serde(rename = "soap:Body")]
-    body: DeleteNoteBody,
-}
 
-#[derive(Debug, Serialize)]
-struct DeleteNoteBody {
-    #[serde(rename = "m:DeleteNoteResponse")]
-    response: DeleteNoteResponse,
+    fn response(&self, version: SoapVersion, l10n: &Localizer) -> Response {
+        let (language, message) = l10n.get_with_language(self.key);
+        let fault = Fault::new(self.code, &message).language(&language);
+        let fault = match &self.detail {
+            Some(detail) => fault.detail(detail),
+            None => fault,
+        };
+
+        (
+            self.status,
+            [(header::CONTENT_TYPE, version.content_type())],
+            fault.to_envelope(version),
+        )
+            .into_response()
+    }
 }
 
-async fn handle_delete_note(service: &NoteService, req: DeleteNoteRequest) -> Response {
-    match service.delete_note(req.id).await {
-        Ok(true) => {
-            let response = DeleteNoteResponse {
-                m_ns: "https://notes-server/soap/v1".to_string(),
-            };
-
-            let envelope = DeleteNoteEnvelope {
-                soap_ns: "http://www.w3.org/2003/05/soap-envelope".to_string(),
-                encoding_style: "http://www.w3.org/2003/05/soap-encoding".to_string(),
-                body: DeleteNoteBody { response },
-            };
-
-            let xml_body = match quick_xml::se::to_string(&envelope) {
-                Ok(s) => s,
-                Err(e) => return handle_serialization_error(&format!("{e}")),
-            };
-
-            build_ok_response(xml_body)
-        }
-        Ok(false) => handle_not_found_error(),
-        Err(e) => handle_internal_error(&e, "Failed to delete note"),
-    }
+fn build_ok_response(version: SoapVersion, response: &str) -> Response {
+    (
+        StatusCode::OK,
+        [(header::CONTENT_TYPE, version.content_type())],
+        soap_envelope::envelope(version, response),
+    )
+        .into_response()
 }
@@ -0,0 +1,334 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use super::registry::{Registry, SoapOperation};
+use crate::{
+    dto,
+    i18n::MessageKey,
+    models::{MetadataFilter, NoteOrder},
+    operations::{self, Operation, OperationError},
+    service::NoteService,
+};
+
+/// All operations of the notes SOAP endpoint
+pub fn registry() -> Registry {
+    Registry::default()
+        .register::<CreateNote>()
+        .register::<GetNote>()
+        .register::<GetAllNotes>()
+        .register::<GetNotesPage>()
+        .register::<UpdateNote>()
+        .register::<DeleteNote>()
+}
+
+// Common response elements
+
+#[derive(Debug, Serialize)]
+pub struct NoteResponseXml {
+    #[serde(rename = "m:Id")]
+    pub id: i64,
+
+    #[serde(rename = "m:Content")]
+    pub content: String,
+
+    /// `xsd:dateTime`, as are the other timestamps
+    #[serde(rename = "m:CreatedAt")]
+    pub created_at: DateTime<Utc>,
+
+    #[serde(rename = "m:UpdatedAt")]
+    pub updated_at: DateTime<Utc>,
+
+    #[serde(rename = "m:ExpiresAt", skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<DateTime<Utc>>,
+
+    #[serde(rename = "m:RemindAt", skip_serializing_if = "Option::is_none")]
+    pub remind_at: Option<DateTime<Utc>>,
+
+    /// Client-defined fields as a JSON object, omitted when there are none
+    #[serde(rename = "m:Metadata", skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<String>,
+
+    #[serde(rename = "m:Position")]
+    pub position: i64,
+}
+
+impl From<dto::NoteResponse> for NoteResponseXml {
+    fn from(note: dto::NoteResponse) -> Self {
+        Self {
+            id: note.id,
+            content: note.content,
+            created_at: note.created_at,
+            updated_at: note.updated_at,
+            expires_at: note.expires_at,
+            remind_at: note.remind_at,
+            metadata: (!note.metadata.is_empty())
+                .then(|| serde_json::Value::Object(note.metadata).to_string()),
+            position: note.position,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct NoteElement {
+    #[serde(rename = "m:Note")]
+    pub note: NoteResponseXml,
+}
+
+impl From<dto::NoteResponse> for NoteElement {
+    fn from(note: dto::NoteResponse) -> Self {
+        Self { note: note.into() }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct NoteList {
+    #[serde(rename = "m:Note")]
+    pub notes: Vec<NoteResponseXml>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Empty {}
+
+// Operations
+
+#[derive(Debug, Deserialize)]
+pub struct CreateNote {
+    #[serde(rename = "Content")]
+    pub content: String,
+
+    #[serde(rename = "ExpiresAt", default)]
+    pub expires_at: Option<DateTime<Utc>>,
+
+    #[serde(rename = "RemindAt", default)]
+    pub remind_at: Option<DateTime<Utc>>,
+}
+
+#[async_trait]
+impl SoapOperation for CreateNote {
+    const NAME: &'static str = "CreateNote";
+    const RESPONSE: &'static str = "m:CreateNoteResponse";
+    type Response = NoteElement;
+
+    async fn execute(self, service: &NoteService) -> Result<NoteElement, OperationError> {
+        let request = dto::CreateNoteRequest {
+            content: self.content,
+            expires_at: self.expires_at,
+            remind_at: self.remind_at,
+        };
+
+        operations::CreateNote(request)
+            .execute(service)
+            .await
+            .map(Into::into)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct GetNote {
+    #[serde(rename = "Id")]
+    pub id: i64,
+}
+
+#[async_trait]
+impl SoapOperation for GetNote {
+    const NAME: &'static str = "GetNote";
+    const RESPONSE: &'static str = "m:GetOneNoteResponse";
+    type Response = NoteElement;
+
+    async fn execute(self, service: &NoteService) -> Result<NoteElement, OperationError> {
+        (operations::GetNote { id: self.id })
+            .execute(service)
+            .await
+            .map(Into::into)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct GetAllNotes {}
+
+#[async_trait]
+impl SoapOperation for GetAllNotes {
+    const NAME: &'static str = "GetAllNotes";
+    const RESPONSE: &'static str = "m:GetAllNotesResponse";
+    type Response = NoteList;
+
+    async fn execute(self, service: &NoteService) -> Result<NoteList, OperationError> {
+        let notes = operations::GetAllNotes.execute(service).await?;
+
+        Ok(NoteList {
+            notes: notes.into_iter().map(Into::into).collect(),
+        })
+    }
+}
+
+/// Page size used when `PageSize` is omitted
+const DEFAULT_PAGE_SIZE: u32 = 50;
+/// Largest page a client may request
+const MAX_PAGE_SIZE: u32 = 500;
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub enum SortBy {
+    Id,
+    Position,
+}
+
+impl From<SortBy> for NoteOrder {
+    fn from(sort_by: SortBy) -> Self {
+        match sort_by {
+            SortBy::Id => Self::Id,
+            SortBy::Position => Self::Position,
+        }
+    }
+}
+
+/// A page of notes, continued by passing the `NextPageToken` of the response
+/// back with the same filters and order
+#[derive(Debug, Deserialize)]
+pub struct GetNotesPage {
+    #[serde(rename = "PageSize", default)]
+    pub page_size: Option<u32>,
+
+    /// Opaque to clients, currently the number of notes to skip
+    #[serde(rename = "PageToken", default)]
+    pub page_token: Option<String>,
+
+    /// Only notes whose metadata contains this JSON object
+    #[serde(rename = "Metadata", default)]
+    pub metadata: Option<String>,
+
+    /// Only notes having all of these metadata fields, one element per field
+    #[serde(rename = "HasMetadata", default)]
+    pub has_metadata: Vec<String>,
+
+    #[serde(rename = "SortBy", default)]
+    pub sort_by: Option<SortBy>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct NotesPage {
+    #[serde(rename = "m:Note")]
+    pub notes: Vec<NoteResponseXml>,
+
+    /// Number of notes matching the filters across all pages
+    #[serde(rename = "m:Total")]
+    pub total: i64,
+
+    /// Omitted on the last page
+    #[serde(rename = "m:NextPageToken", skip_serializing_if = "Option::is_none")]
+    pub next_page_token: Option<String>,
+}
+
+#[async_trait]
+impl SoapOperation for GetNotesPage {
+    const NAME: &'static str = "GetNotesPage";
+    const RESPONSE: &'static str = "m:GetNotesPageResponse";
+    type Response = NotesPage;
+
+    async fn execute(self, service: &NoteService) -> Result<NotesPage, OperationError> {
+        let limit = self
+            .page_size
+            .unwrap_or(DEFAULT_PAGE_SIZE)
+            .clamp(1, MAX_PAGE_SIZE);
+        let offset = match self.page_token.as_deref().map(str::trim) {
+            None | Some("") => 0,
+            Some(token) => token
+                .parse::<u32>()
+                .map_err(|_| OperationError::InvalidArgument(MessageKey::InvalidPageToken))?,
+        };
+        let contains = match &self.metadata {
+            Some(metadata) => Some(
+                serde_json::from_str::<serde_json::Value>(metadata)
+                    .ok()
+                    .filter(serde_json::Value::is_object)
+                    .ok_or(OperationError::InvalidArgument(
+                        MessageKey::InvalidMetadataFilter,
+                    ))?,
+            ),
+            None => None,
+        };
+        let has_keys = (!self.has_metadata.is_empty()).then_some(self.has_metadata);
+
+        let op = operations::ListNotes {
+            limit: limit.into(),
+            offset: offset.into(),
+            filter: MetadataFilter { contains, has_keys },
+            order: self.sort_by.map(NoteOrder::from).unwrap_or_default(),
+            include_archived: false,
+        };
+        let page = op.execute(service).await?;
+
+        let next_offset = i64::from(offset) + i64::from(limit);
+        Ok(NotesPage {
+            notes: page.items.into_iter().map(Into::into).collect(),
+            total: page.total,
+            next_page_token: (next_offset < page.total).then(|| next_offset.to_string()),
+        })
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateNote {
+    #[serde(rename = "Id")]
+    pub id: i64,
+
+    #[serde(rename = "Content")]
+    pub content: String,
+
+    #[serde(rename = "ExpiresAt", default)]
+    pub expires_at: Option<DateTime<Utc>>,
+
+    #[serde(rename = "RemindAt", default)]
+    pub remind_at: Option<DateTime<Utc>>,
+
+    #[serde(rename = "ClearExpiresAt", default)]
+    pub clear_expires_at: bool,
+
+    #[serde(rename = "ClearRemindAt", default)]
+    pub clear_remind_at: bool,
+}
+
+#[async_trait]
+impl SoapOperation for UpdateNote {
+    const NAME: &'static str = "UpdateNote";
+    const RESPONSE: &'static str = "m:UpdateNoteResponse";
+    type Response = NoteElement;
+
+    async fn execute(self, service: &NoteService) -> Result<NoteElement, OperationError> {
+        let op = operations::UpdateNote {
+            id: self.id,
+            request: dto::UpdateNoteRequest {
+                content: self.content,
+                expires_at: self.expires_at,
+                remind_at: self.remind_at,
+                clear_expires_at: self.clear_expires_at,
+                clear_remind_at: self.clear_remind_at,
+            },
+            expected_versions: None,
+        };
+
+        op.execute(service).await.map(Into::into)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DeleteNote {
+    #[serde(rename = "Id")]
+    pub id: i64,
+}
+
+#[async_trait]
+impl SoapOperation for DeleteNote {
+    const NAME: &'static str = "DeleteNote";
+    const RESPONSE: &'static str = "m:DeleteNoteResponse";
+    type Response = Empty;
+
+    async fn execute(self, service: &NoteService) -> Result<Empty, OperationError> {
+        let op = operations::DeleteNote {
+            id: self.id,
+            expected_versions: None,
+        };
+
+        op.execute(service).await.map(|()| Empty {})
+    }
+}
@@ -0,0 +1,97 @@
+use async_trait::async_trait;
+use futures_util::future::BoxFuture;
+use serde::{Serialize, de::DeserializeOwned};
+
+use std::collections::HashMap;
+
+use crate::{operations::OperationError, service::NoteService};
+
+/// Namespace of the operation elements, bound to the `m` prefix in responses
+pub const NAMESPACE: &str = "https://notes-server/soap/v1";
+
+/// A SOAP operation, deserialized from its request element in the envelope `Body`
+#[async_trait]
+pub trait SoapOperation: DeserializeOwned + Send + 'static {
+    /// Local name of the request element, also accepted as the SOAP action
+    const NAME: &'static str;
+    /// Qualified name of the response element
+    const RESPONSE: &'static str;
+
+    /// Children of the response element
+    type Response: Serialize + Send;
+
+    async fn execute(self, service: &NoteService) -> Result<Self::Response, OperationError>;
+}
+
+/// Why a prepared operation produced no response element
+#[derive(Debug)]
+pub enum SoapError {
+    Operation(OperationError),
+    Serialization(quick_xml::DeError),
+}
+
+#[derive(Serialize)]
+struct ResponseElement<'a, T> {
+    #[serde(rename = "@xmlns:m")]
+    m_ns: &'static str,
+    #[serde(flatten)]
+    content: &'a T,
+}
+
+/// A deserialized operation, runs it and returns its response element
+pub type Prepared =
+    Box<dyn for<'a> FnOnce(&'a NoteService) -> BoxFuture<'a, Result<String, SoapError>> + Send>;
+
+/// Deserializes the request element of an operation
+type Parser = fn(&str) -> Result<Prepared, quick_xml::DeError>;
+
+fn prepare<O: SoapOperation>(element: &str) -> Result<Prepared, quick_xml::DeError> {
+    let request: O = quick_xml::de::from_str(element)?;
+
+    Ok(Box::new(move |service: &NoteService| {
+        Box::pin(async move {
+            let response = request
+                .execute(service)
+                .await
+                .map_err(SoapError::Operation)?;
+
+            quick_xml::se::to_string_with_root(
+                O::RESPONSE,
+                &ResponseElement {
+                    m_ns: NAMESPACE,
+                    content: &response,
+                },
+            )
+            .map_err(SoapError::Serialization)
+        }) as BoxFuture<'_, _>
+    }))
+}
+
+/// Operations by their request element name
+#[derive(Default)]
+pub struct Registry {
+    parsers: HashMap<&'static str, Parser>,
+}
+
+impl Registry {
+    #[must_use]
+    pub fn register<O: SoapOperation>(mut self) -> Self {
+        self.parsers.insert(O::NAME, prepare::<O>);
+        self
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.parsers.contains_key(name)
+    }
+
+    /// Deserializes the request element of the operation named `name`,
+    /// `None` if there is no such operation
+    pub fn prepare(
+        &self,
+        name: &str,
+        element: &str,
+    ) -> Option<Result<Prepared, quick_xml::DeError>> {
+        let parser = self.parsers.get(name)?;
+        Some(parser(element))
+    }
+}
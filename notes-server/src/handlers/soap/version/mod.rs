@@ -0,0 +1,54 @@
+use axum::http::{HeaderMap, header};
+pub use soap_envelope::SoapVersion;
+
+use crate::i18n::MessageKey;
+
+/// Why the envelope version of a request could not be accepted
+#[derive(Debug, Clone, Copy)]
+pub enum VersionError {
+    /// The document is not an `Envelope` element, the fault uses `version`
+    NotAnEnvelope { version: SoapVersion },
+    /// Envelope namespace or content type don't name a version this server speaks,
+    /// or name different ones. The fault uses `version`
+    Mismatch {
+        version: SoapVersion,
+        reason: MessageKey,
+    },
+}
+
+/// Version implied by the `Content-Type` header, if it names a SOAP media type
+pub fn declared_version(headers: &HeaderMap) -> Option<SoapVersion> {
+    headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .and_then(SoapVersion::from_content_type)
+}
+
+/// Detects the envelope version from the namespace of the request's `Envelope`,
+/// checking that the `Content-Type` agrees when it names a SOAP version
+pub fn detect(headers: &HeaderMap, body: &str) -> Result<SoapVersion, VersionError> {
+    let declared = declared_version(headers);
+    // Without a usable envelope, faults follow the content type (SOAP 1.1 by default)
+    let fallback = declared.unwrap_or(SoapVersion::Soap11);
+
+    let Some((namespace, _)) =
+        soap_envelope::root_element(body).filter(|(_, name)| name == "Envelope")
+    else {
+        return Err(VersionError::NotAnEnvelope { version: fallback });
+    };
+
+    let Some(version) = namespace.as_deref().and_then(SoapVersion::from_namespace) else {
+        return Err(VersionError::Mismatch {
+            version: fallback,
+            reason: MessageKey::UnsupportedSoapVersion,
+        });
+    };
+
+    match declared {
+        Some(declared) if declared != version => Err(VersionError::Mismatch {
+            version,
+            reason: MessageKey::SoapVersionMismatch,
+        }),
+        _ => Ok(version),
+    }
+}
@@ -0,0 +1,579 @@
+use axum::{extract::FromRequestParts, http::request::Parts};
+
+use std::{collections::HashMap, convert::Infallible, fs, path::Path, sync::Arc};
+
+/// Language used when none of the client's languages has a translation
+const DEFAULT_LANGUAGE: &str = "en";
+
+/// User-facing messages shared by all protocol handlers
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MessageKey {
+    NoteNotFound,
+    NoteModified,
+    CreateFailed,
+    GetFailed,
+    GetAllFailed,
+    UpdateFailed,
+    DeleteFailed,
+    NotesSent,
+    NoteSent,
+    EmailFailed,
+    InvalidExpiration,
+    InvalidReminder,
+    InvalidUtf8,
+    InvalidEnvelope,
+    UnsupportedOperation,
+    SerializationFailed,
+    ExpectedJson,
+    InvalidJson,
+    UnknownFields,
+    RpcInvalidRequest,
+    RpcMethodNotFound,
+    RpcInvalidParams,
+    MigrationStatusFailed,
+    BodyTooLarge,
+    AdminDisabled,
+    AdminUnauthorized,
+    InvalidGenerateParams,
+    GenerateFailed,
+    RateLimited,
+    DuplicateFailed,
+    InvalidLinkExpiration,
+    ShareLinkFailed,
+    InvalidMetadataFilter,
+    TemplateNotFound,
+    TemplateFailed,
+    InvalidReorder,
+    ReorderFailed,
+    ActivityFailed,
+    InvalidRequest,
+    UnsupportedSoapVersion,
+    SoapVersionMismatch,
+    SoapActionMismatch,
+    InvalidPageToken,
+    SoapAuditFailed,
+    DeadlineExceeded,
+    CursorRequiresIdOrder,
+    EmptySearchQuery,
+    SearchFailed,
+    BackupDisabled,
+    BackupFailed,
+    InvalidApiKey,
+    EmptyContent,
+    ContentTooLarge,
+    WebhookNotFound,
+    InvalidWebhookUrl,
+    WebhookFailed,
+    InvalidRetentionRules,
+    RetentionFailed,
+    NothingToUndo,
+    UndoFailed,
+    AttachmentsDisabled,
+    AttachmentNotFound,
+    AttachmentTooLarge,
+    AttachmentFailed,
+    DigestNotFound,
+    InvalidDigestEmail,
+    InvalidDigestSchedule,
+    DigestFailed,
+    IdenticalNoteExists,
+    DuplicateClustersFailed,
+    InvalidToken,
+    AuthUnavailable,
+    ListUsersFailed,
+    QuotaExceeded,
+    NotAuthenticated,
+    ClientNotAllowed,
+    InvalidSignature,
+}
+
+impl MessageKey {
+    const ALL: [Self; 77] = [
+        Self::NoteNotFound,
+        Self::NoteModified,
+        Self::CreateFailed,
+        Self::GetFailed,
+        Self::GetAllFailed,
+        Self::UpdateFailed,
+        Self::DeleteFailed,
+        Self::NotesSent,
+        Self::NoteSent,
+        Self::EmailFailed,
+        Self::InvalidExpiration,
+        Self::InvalidReminder,
+        Self::InvalidUtf8,
+        Self::InvalidEnvelope,
+        Self::UnsupportedOperation,
+        Self::SerializationFailed,
+        Self::ExpectedJson,
+        Self::InvalidJson,
+        Self::UnknownFields,
+        Self::RpcInvalidRequest,
+        Self::RpcMethodNotFound,
+        Self::RpcInvalidParams,
+        Self::MigrationStatusFailed,
+        Self::BodyTooLarge,
+        Self::AdminDisabled,
+        Self::AdminUnauthorized,
+        Self::InvalidGenerateParams,
+        Self::GenerateFailed,
+        Self::RateLimited,
+        Self::DuplicateFailed,
+        Self::InvalidLinkExpiration,
+        Self::ShareLinkFailed,
+        Self::InvalidMetadataFilter,
+        Self::TemplateNotFound,
+        Self::TemplateFailed,
+        Self::InvalidReorder,
+        Self::ReorderFailed,
+        Self::ActivityFailed,
+        Self::InvalidRequest,
+        Self::UnsupportedSoapVersion,
+        Self::SoapVersionMismatch,
+        Self::SoapActionMismatch,
+        Self::InvalidPageToken,
+        Self::SoapAuditFailed,
+        Self::DeadlineExceeded,
+        Self::CursorRequiresIdOrder,
+        Self::EmptySearchQuery,
+        Self::SearchFailed,
+        Self::BackupDisabled,
+        Self::BackupFailed,
+        Self::InvalidApiKey,
+        Self::EmptyContent,
+        Self::ContentTooLarge,
+        Self::WebhookNotFound,
+        Self::InvalidWebhookUrl,
+        Self::WebhookFailed,
+        Self::InvalidRetentionRules,
+        Self::RetentionFailed,
+        Self::NothingToUndo,
+        Self::UndoFailed,
+        Self::AttachmentsDisabled,
+        Self::AttachmentNotFound,
+        Self::AttachmentTooLarge,
+        Self::AttachmentFailed,
+        Self::DigestNotFound,
+        Self::InvalidDigestEmail,
+        Self::InvalidDigestSchedule,
+        Self::DigestFailed,
+        Self::IdenticalNoteExists,
+        Self::DuplicateClustersFailed,
+        Self::InvalidToken,
+        Self::AuthUnavailable,
+        Self::ListUsersFailed,
+        Self::QuotaExceeded,
+        Self::NotAuthenticated,
+        Self::ClientNotAllowed,
+        Self::InvalidSignature,
+    ];
+
+    /// Key used in message catalog files
+    pub const fn key(self) -> &'static str {
+        match self {
+            Self::NoteNotFound => "note_not_found",
+            Self::NoteModified => "note_modified",
+            Self::CreateFailed => "create_failed",
+            Self::GetFailed => "get_failed",
+            Self::GetAllFailed => "get_all_failed",
+            Self::UpdateFailed => "update_failed",
+            Self::DeleteFailed => "delete_failed",
+            Self::NotesSent => "notes_sent",
+            Self::NoteSent => "note_sent",
+            Self::EmailFailed => "email_failed",
+            Self::InvalidExpiration => "invalid_expiration",
+            Self::InvalidReminder => "invalid_reminder",
+            Self::InvalidUtf8 => "invalid_utf8",
+            Self::InvalidEnvelope => "invalid_envelope",
+            Self::UnsupportedOperation => "unsupported_operation",
+            Self::SerializationFailed => "serialization_failed",
+            Self::ExpectedJson => "expected_json",
+            Self::InvalidJson => "invalid_json",
+            Self::UnknownFields => "unknown_fields",
+            Self::RpcInvalidRequest => "rpc_invalid_request",
+            Self::RpcMethodNotFound => "rpc_method_not_found",
+            Self::RpcInvalidParams => "rpc_invalid_params",
+            Self::MigrationStatusFailed => "migration_status_failed",
+            Self::BodyTooLarge => "body_too_large",
+            Self::AdminDisabled => "admin_disabled",
+            Self::AdminUnauthorized => "admin_unauthorized",
+            Self::InvalidGenerateParams => "invalid_generate_params",
+            Self::GenerateFailed => "generate_failed",
+            Self::RateLimited => "rate_limited",
+            Self::DuplicateFailed => "duplicate_failed",
+            Self::InvalidLinkExpiration => "invalid_link_expiration",
+            Self::ShareLinkFailed => "share_link_failed",
+            Self::InvalidMetadataFilter => "invalid_metadata_filter",
+            Self::TemplateNotFound => "template_not_found",
+            Self::TemplateFailed => "template_failed",
+            Self::InvalidReorder => "invalid_reorder",
+            Self::ReorderFailed => "reorder_failed",
+            Self::ActivityFailed => "activity_failed",
+            Self::InvalidRequest => "invalid_request",
+            Self::UnsupportedSoapVersion => "unsupported_soap_version",
+            Self::SoapVersionMismatch => "soap_version_mismatch",
+            Self::SoapActionMismatch => "soap_action_mismatch",
+            Self::InvalidPageToken => "invalid_page_token",
+            Self::SoapAuditFailed => "soap_audit_failed",
+            Self::DeadlineExceeded => "deadline_exceeded",
+            Self::CursorRequiresIdOrder => "cursor_requires_id_order",
+            Self::EmptySearchQuery => "empty_search_query",
+            Self::SearchFailed => "search_failed",
+            Self::BackupDisabled => "backup_disabled",
+            Self::BackupFailed => "backup_failed",
+            Self::InvalidApiKey => "invalid_api_key",
+            Self::EmptyContent => "empty_content",
+            Self::ContentTooLarge => "content_too_large",
+            Self::WebhookNotFound => "webhook_not_found",
+            Self::InvalidWebhookUrl => "invalid_webhook_url",
+            Self::WebhookFailed => "webhook_failed",
+            Self::InvalidRetentionRules => "invalid_retention_rules",
+            Self::RetentionFailed => "retention_failed",
+            Self::NothingToUndo => "nothing_to_undo",
+            Self::UndoFailed => "undo_failed",
+            Self::AttachmentsDisabled => "attachments_disabled",
+            Self::AttachmentNotFound => "attachment_not_found",
+            Self::AttachmentTooLarge => "attachment_too_large",
+            Self::AttachmentFailed => "attachment_failed",
+            Self::DigestNotFound => "digest_not_found",
+            Self::InvalidDigestEmail => "invalid_digest_email",
+            Self::InvalidDigestSchedule => "invalid_digest_schedule",
+            Self::DigestFailed => "digest_failed",
+            Self::IdenticalNoteExists => "identical_note_exists",
+            Self::DuplicateClustersFailed => "duplicate_clusters_failed",
+            Self::InvalidToken => "invalid_token",
+            Self::AuthUnavailable => "auth_unavailable",
+            Self::ListUsersFailed => "list_users_failed",
+            Self::QuotaExceeded => "quota_exceeded",
+            Self::NotAuthenticated => "not_authenticated",
+            Self::ClientNotAllowed => "client_not_allowed",
+            Self::InvalidSignature => "invalid_signature",
+        }
+    }
+
+    /// Built-in English text, also used in logs
+    pub const fn english(self) -> &'static str {
+        match self {
+            Self::NoteNotFound => "Note not found",
+            Self::NoteModified => "Note was modified",
+            Self::CreateFailed => "Failed to create note",
+            Self::GetFailed => "Failed to get note",
+            Self::GetAllFailed => "Failed to get all notes",
+            Self::UpdateFailed => "Failed to update note",
+            Self::DeleteFailed => "Failed to delete note",
+            Self::NotesSent => "Notes sent successfully",
+            Self::NoteSent => "Note sent successfully",
+            Self::EmailFailed => "Failed to send email",
+            Self::InvalidExpiration => "Invalid expiration time",
+            Self::InvalidReminder => "Invalid reminder time",
+            Self::InvalidUtf8 => "Request body must be valid UTF-8",
+            Self::InvalidEnvelope => "Invalid SOAP XML envelope: request body could not be parsed",
+            Self::UnsupportedOperation => "Unsupported operation",
+            Self::SerializationFailed => "Failed to serialize SOAP response",
+            Self::ExpectedJson => "Expected request with `Content-Type: application/json`",
+            Self::InvalidJson => "Failed to parse the request body",
+            Self::UnknownFields => "Unknown fields in the request body",
+            Self::RpcInvalidRequest => "Invalid Request",
+            Self::RpcMethodNotFound => "Method not found",
+            Self::RpcInvalidParams => "Invalid params",
+            Self::MigrationStatusFailed => "Failed to get migration status",
+            Self::BodyTooLarge => "Request body is too large",
+            Self::AdminDisabled => "Admin endpoints are disabled",
+            Self::AdminUnauthorized => "A valid admin token is required",
+            Self::InvalidGenerateParams => "Invalid fixture generation parameters",
+            Self::GenerateFailed => "Failed to generate notes",
+            Self::RateLimited => "Too many requests, slow down",
+            Self::DuplicateFailed => "Failed to duplicate note",
+            Self::InvalidLinkExpiration => "Link expiration time must be in the future",
+            Self::ShareLinkFailed => "Failed to create share link",
+            Self::InvalidMetadataFilter => "Metadata filter must be a JSON object",
+            Self::TemplateNotFound => "Template not found",
+            Self::TemplateFailed => "Failed to access templates",
+            Self::InvalidReorder => "Note IDs must be a non-empty list without duplicates",
+            Self::ReorderFailed => "Failed to reorder notes",
+            Self::ActivityFailed => "Failed to get recent activity",
+            Self::InvalidRequest => "Invalid request parameters",
+            Self::UnsupportedSoapVersion => "Only SOAP 1.1 and 1.2 envelopes are supported",
+            Self::SoapVersionMismatch => "SOAP envelope version doesn't match the Content-Type",
+            Self::SoapActionMismatch => "SOAP action doesn't match the operation in the body",
+            Self::InvalidPageToken => "Page token is invalid",
+            Self::SoapAuditFailed => "Failed to get the SOAP audit trail",
+            Self::DeadlineExceeded => "Request deadline exceeded",
+            Self::CursorRequiresIdOrder => {
+                "The after parameter can only be used with notes sorted by ID"
+            }
+            Self::EmptySearchQuery => "Search query must not be empty",
+            Self::SearchFailed => "Failed to search notes",
+            Self::BackupDisabled => "Backups are not configured",
+            Self::BackupFailed => "Failed to back up notes",
+            Self::InvalidApiKey => "A valid API key is required",
+            Self::EmptyContent => "Note content must not be empty",
+            Self::ContentTooLarge => "Note content is too large",
+            Self::WebhookNotFound => "Webhook not found",
+            Self::InvalidWebhookUrl => "Webhook URL must be an absolute http or https URL",
+            Self::WebhookFailed => "Failed to access webhooks",
+            Self::InvalidRetentionRules => {
+                "Retention rules must be a comma-separated list of archive:<days> and delete:<days>"
+            }
+            Self::RetentionFailed => "Failed to check the retention rules",
+            Self::NothingToUndo => "There is no recent delete to undo",
+            Self::UndoFailed => "Failed to undo the delete",
+            Self::AttachmentsDisabled => "Attachments are not configured",
+            Self::AttachmentNotFound => "Note or attachment not found",
+            Self::AttachmentTooLarge => "Attachment is too large",
+            Self::AttachmentFailed => "Failed to access attachments",
+            Self::DigestNotFound => "Digest subscription not found",
+            Self::InvalidDigestEmail => "Email must be an address like name@example.com",
+            Self::InvalidDigestSchedule => {
+                "Schedule must be a cron expression that runs again, e.g. 0 8 * * Mon-Fri"
+            }
+            Self::DigestFailed => "Failed to access digest subscriptions",
+            Self::IdenticalNoteExists => "A note with the same content already exists",
+            Self::DuplicateClustersFailed => "Failed to find duplicate notes",
+            Self::InvalidToken => "A valid access token is required",
+            Self::AuthUnavailable => "Failed to verify the access token",
+            Self::ListUsersFailed => "Failed to list users",
+            Self::QuotaExceeded => "Request quota exceeded, try again later",
+            Self::NotAuthenticated => "Requests are not authenticated, usage is not tracked",
+            Self::ClientNotAllowed => "Client certificate is not allowed to call this server",
+            Self::InvalidSignature => "Request signature is missing or invalid",
+        }
+    }
+
+    const fn russian(self) -> &'static str {
+        match self {
+            Self::NoteNotFound => "Записка не найдена",
+            Self::NoteModified => "Записка была изменена",
+            Self::CreateFailed => "Не удалось создать записку",
+            Self::GetFailed => "Не удалось получить записку",
+            Self::GetAllFailed => "Не удалось получить записки",
+            Self::UpdateFailed => "Не удалось изменить записку",
+            Self::DeleteFailed => "Не удалось удалить записку",
+            Self::NotesSent => "Записки успешно отправлены",
+            Self::NoteSent => "Записка успешно отправлена",
+            Self::EmailFailed => "Не удалось отправить письмо",
+            Self::InvalidExpiration => "Некорректное время истечения",
+            Self::InvalidReminder => "Некорректное время напоминания",
+            Self::InvalidUtf8 => "Тело запроса должно быть в UTF-8",
+            Self::InvalidEnvelope => {
+                "Некорректный SOAP XML конверт: не удалось разобрать тело запроса"
+            }
+            Self::UnsupportedOperation => "Неподдерживаемая операция",
+            Self::SerializationFailed => "Не удалось сериализовать SOAP ответ",
+            Self::ExpectedJson => "Ожидается запрос с `Content-Type: application/json`",
+            Self::InvalidJson => "Не удалось разобрать тело запроса",
+            Self::UnknownFields => "Неизвестные поля в теле запроса",
+            Self::RpcInvalidRequest => "Некорректный запрос",
+            Self::RpcMethodNotFound => "Метод не найден",
+            Self::RpcInvalidParams => "Некорректные параметры",
+            Self::MigrationStatusFailed => "Не удалось получить статус миграций",
+            Self::BodyTooLarge => "Слишком большое тело запроса",
+            Self::AdminDisabled => "Административные методы отключены",
+            Self::AdminUnauthorized => "Требуется действительный токен администратора",
+            Self::InvalidGenerateParams => "Некорректные параметры генерации записок",
+            Self::GenerateFailed => "Не удалось сгенерировать записки",
+            Self::RateLimited => "Слишком много запросов, повторите позже",
+            Self::DuplicateFailed => "Не удалось скопировать записку",
+            Self::InvalidLinkExpiration => "Время истечения ссылки должно быть в будущем",
+            Self::ShareLinkFailed => "Не удалось создать ссылку",
+            Self::InvalidMetadataFilter => "Фильтр по метаданным должен быть JSON объектом",
+            Self::TemplateNotFound => "Шаблон не найден",
+            Self::TemplateFailed => "Не удалось обратиться к шаблонам",
+            Self::InvalidReorder => "Список ID записок должен быть непустым и без повторов",
+            Self::ReorderFailed => "Не удалось изменить порядок записок",
+            Self::ActivityFailed => "Не удалось получить последние изменения",
+            Self::InvalidRequest => "Некорректные параметры запроса",
+            Self::UnsupportedSoapVersion => "Поддерживаются только конверты SOAP 1.1 и 1.2",
+            Self::SoapVersionMismatch => "Версия SOAP конверта не совпадает с Content-Type",
+            Self::SoapActionMismatch => "SOAP action не совпадает с операцией в теле запроса",
+            Self::InvalidPageToken => "Некорректный токен страницы",
+            Self::SoapAuditFailed => "Не удалось получить журнал SOAP запросов",
+            Self::DeadlineExceeded => "Истек срок выполнения запроса",
+            Self::CursorRequiresIdOrder => {
+                "Параметр after можно использовать только при сортировке по ID"
+            }
+            Self::EmptySearchQuery => "Поисковый запрос не должен быть пустым",
+            Self::SearchFailed => "Не удалось выполнить поиск записок",
+            Self::BackupDisabled => "Резервное копирование не настроено",
+            Self::BackupFailed => "Не удалось создать резервную копию записок",
+            Self::InvalidApiKey => "Требуется действительный API-ключ",
+            Self::EmptyContent => "Содержимое записки не должно быть пустым",
+            Self::ContentTooLarge => "Содержимое записки слишком большое",
+            Self::WebhookNotFound => "Вебхук не найден",
+            Self::InvalidWebhookUrl => "URL вебхука должен быть абсолютным http или https адресом",
+            Self::WebhookFailed => "Не удалось обратиться к вебхукам",
+            Self::InvalidRetentionRules => {
+                "Правила хранения должны быть списком archive:<дни> и delete:<дни> через запятую"
+            }
+            Self::RetentionFailed => "Не удалось проверить правила хранения",
+            Self::NothingToUndo => "Нет недавнего удаления, которое можно отменить",
+            Self::UndoFailed => "Не удалось отменить удаление",
+            Self::AttachmentsDisabled => "Вложения не настроены",
+            Self::AttachmentNotFound => "Записка или вложение не найдены",
+            Self::AttachmentTooLarge => "Вложение слишком большое",
+            Self::AttachmentFailed => "Не удалось обратиться к вложениям",
+            Self::DigestNotFound => "Подписка на дайджест не найдена",
+            Self::InvalidDigestEmail => "Email должен быть адресом вида name@example.com",
+            Self::InvalidDigestSchedule => {
+                "Расписание должно быть выражением cron, которое еще сработает, например 0 8 * * Mon-Fri"
+            }
+            Self::DigestFailed => "Не удалось обратиться к подпискам на дайджест",
+            Self::IdenticalNoteExists => "Записка с таким же содержимым уже существует",
+            Self::DuplicateClustersFailed => "Не удалось найти дубликаты записок",
+            Self::InvalidToken => "Требуется действительный токен доступа",
+            Self::AuthUnavailable => "Не удалось проверить токен доступа",
+            Self::ListUsersFailed => "Не удалось получить список пользователей",
+            Self::QuotaExceeded => "Превышена квота запросов, повторите позже",
+            Self::NotAuthenticated => "Запросы не аутентифицируются, использование не учитывается",
+            Self::ClientNotAllowed => "Клиентский сертификат не допущен к этому серверу",
+            Self::InvalidSignature => "Подпись запроса отсутствует или недействительна",
+        }
+    }
+}
+
+/// Message catalog file format: language -> message key -> text
+type CatalogFile = HashMap<String, HashMap<String, String>>;
+
+/// Translations of all user-facing messages, per language
+#[derive(Debug, Clone)]
+pub struct Catalog {
+    messages: HashMap<String, HashMap<MessageKey, String>>,
+}
+
+impl Default for Catalog {
+    /// Built-in English and Russian messages
+    fn default() -> Self {
+        let builtin = |text: fn(MessageKey) -> &'static str| {
+            MessageKey::ALL
+                .iter()
+                .map(|&key| (key, text(key).to_string()))
+                .collect()
+        };
+
+        Self {
+            messages: HashMap::from([
+                ("en".to_string(), builtin(MessageKey::english)),
+                ("ru".to_string(), builtin(MessageKey::russian)),
+            ]),
+        }
+    }
+}
+
+impl Catalog {
+    /// Built-in catalog with messages from the YAML file at `path` layered on top,
+    /// so a file may override single messages or add whole languages
+    pub fn load(path: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+        let contents = fs::read_to_string(path)?;
+        let file: CatalogFile = serde_yaml::from_str(&contents)?;
+
+        let mut catalog = Self::default();
+        for (language, messages) in file {
+            let translations = catalog.messages.entry(language.to_lowercase()).or_default();
+            for (key, text) in messages {
+                if let Some(&message_key) = MessageKey::ALL.iter().find(|k| k.key() == key) {
+                    translations.insert(message_key, text);
+                } else {
+                    tracing::warn!("Unknown message key '{key}' in {}", path.display());
+                }
+            }
+        }
+
+        Ok(catalog)
+    }
+
+    /// Looks the message up in the given languages in order of preference,
+    /// falling back to the default language. Returns the language found along with the message
+    pub fn message<'a>(&'a self, key: MessageKey, languages: &'a [String]) -> (&'a str, &'a str) {
+        languages
+            .iter()
+            .map(String::as_str)
+            .chain(std::iter::once(DEFAULT_LANGUAGE))
+            .find_map(|language| {
+                self.messages
+                    .get(language)
+                    .and_then(|m| m.get(&key))
+                    .map(|message| (language, message.as_str()))
+                    .or_else(|| {
+                        // "en-US" falls back to "en"
+                        let primary = language.split('-').next()?;
+                        self.messages
+                            .get(primary)
+                            .and_then(|m| m.get(&key))
+                            .map(|message| (primary, message.as_str()))
+                    })
+            })
+            .unwrap_or_else(|| (DEFAULT_LANGUAGE, key.english()))
+    }
+}
+
+/// Parses an ``Accept-Language`` header into language tags ordered by preference
+pub fn parse_accept_language(header: &str) -> Vec<String> {
+    let mut languages: Vec<(String, f32)> = header
+        .split(',')
+        .filter_map(|part| {
+            let mut pieces = part.trim().split(';');
+            let tag = pieces.next()?.trim().to_lowercase();
+            if tag.is_empty() || tag == "*" {
+                return None;
+            }
+            let quality = pieces
+                .find_map(|p| p.trim().strip_prefix("q="))
+                .and_then(|q| q.parse().ok())
+                .unwrap_or(1.0);
+            // q=0 marks the language as not acceptable
+            (quality > 0.0).then_some((tag, quality))
+        })
+        .collect();
+
+    // Stable sort keeps the header order for equal weights
+    languages.sort_by(|a, b| b.1.total_cmp(&a.1));
+    languages.into_iter().map(|(tag, _)| tag).collect()
+}
+
+/// Catalog bound to the languages of a single request
+#[derive(Debug, Clone)]
+pub struct Localizer {
+    catalog: Arc<Catalog>,
+    languages: Vec<String>,
+}
+
+impl Localizer {
+    pub fn new(catalog: Arc<Catalog>, accept_language: Option<&str>) -> Self {
+        Self {
+            catalog,
+            languages: accept_language
+                .map(parse_accept_language)
+                .unwrap_or_default(),
+        }
+    }
+
+    pub fn get(&self, key: MessageKey) -> String {
+        self.catalog.message(key, &self.languages).1.to_string()
+    }
+
+    /// Message along with the language tag it is in
+    pub fn get_with_language(&self, key: MessageKey) -> (String, String) {
+        let (language, message) = self.catalog.message(key, &self.languages);
+        (language.to_string(), message.to_string())
+    }
+}
+
+/// Builds a `Localizer` from the catalog installed as a request extension
+/// (the built-in one when absent) and the ``Accept-Language`` header
+impl<S: Send + Sync> FromRequestParts<S> for Localizer {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let catalog = parts
+            .extensions
+            .get::<Arc<Catalog>>()
+            .cloned()
+            .unwrap_or_default();
+        let accept_language = parts
+            .headers
+            .get(axum::http::header::ACCEPT_LANGUAGE)
+            .and_then(|v| v.to_str().ok());
+
+        Ok(Self::new(catalog, accept_language))
+    }
+}
@@ -0,0 +1,258 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use tokio::task::JoinSet;
+use tokio_util::sync::CancellationToken;
+
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    str::FromStr,
+    sync::{Arc, Mutex, PoisonError},
+    time::{Duration, Instant},
+};
+
+/// Why a run of a job failed, it's run again on schedule
+pub type JobError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Periodic work run by the `JobRunner`
+#[async_trait]
+pub trait Job: Send + Sync {
+    /// Name of the job in logs, metrics and `JOB_<NAME>_SCHEDULE`
+    fn name(&self) -> &'static str;
+
+    /// Whether the first run is right after startup rather than once the schedule
+    /// comes due. Only applies to interval schedules
+    fn runs_on_start(&self) -> bool {
+        true
+    }
+
+    /// Does the work once
+    async fn run(&self) -> Result<(), JobError>;
+}
+
+/// When a job runs
+#[derive(Debug, Clone)]
+pub enum Schedule {
+    /// This long after the previous run ended
+    Interval(Duration),
+    /// At the times of the cron expression, in UTC
+    Cron(Box<cron::Schedule>),
+}
+
+impl Schedule {
+    /// A number of seconds, or a cron expression with or without the seconds field,
+    /// e.g. `0 3 * * *`
+    pub fn parse(value: &str) -> Option<Self> {
+        let value = value.trim();
+        if let Ok(secs) = value.parse::<u64>() {
+            return (secs > 0).then(|| Self::Interval(Duration::from_secs(secs)));
+        }
+
+        let expression = if value.split_whitespace().count() == 5 {
+            format!("0 {value}")
+        } else {
+            value.to_string()
+        };
+        cron::Schedule::from_str(&expression)
+            .ok()
+            .map(|schedule| Self::Cron(Box::new(schedule)))
+    }
+
+    /// How long to wait for the next run, `None` if there is none
+    fn next_delay(&self) -> Option<Duration> {
+        match self {
+            Self::Interval(interval) => Some(*interval),
+            Self::Cron(schedule) => {
+                let next = schedule.upcoming(Utc).next()?;
+                Some((next - Utc::now()).to_std().unwrap_or_default())
+            }
+        }
+    }
+
+    const fn is_interval(&self) -> bool {
+        matches!(self, Self::Interval(_))
+    }
+}
+
+#[derive(Debug, Default)]
+struct JobStats {
+    succeeded: u64,
+    failed: u64,
+    running: bool,
+    last_duration: f64,
+    last_success: Option<DateTime<Utc>>,
+}
+
+/// Runs of every registered job, exposed on `/metrics`
+#[derive(Debug, Default)]
+pub struct JobMetrics {
+    jobs: Mutex<BTreeMap<&'static str, JobStats>>,
+}
+
+impl JobMetrics {
+    fn update(&self, job: &'static str, update: impl FnOnce(&mut JobStats)) {
+        let mut jobs = self.jobs.lock().unwrap_or_else(PoisonError::into_inner);
+        update(jobs.entry(job).or_default());
+        drop(jobs);
+    }
+
+    fn started(&self, job: &'static str) {
+        self.update(job, |stats| stats.running = true);
+    }
+
+    fn finished(&self, job: &'static str, elapsed: Duration, succeeded: bool) {
+        self.update(job, |stats| {
+            stats.running = false;
+            stats.last_duration = elapsed.as_secs_f64();
+            if succeeded {
+                stats.succeeded += 1;
+                stats.last_success = Some(Utc::now());
+            } else {
+                stats.failed += 1;
+            }
+        });
+    }
+
+    /// Counters and gauges in the Prometheus text exposition format
+    pub fn render(&self) -> String {
+        let jobs = self.jobs.lock().unwrap_or_else(PoisonError::into_inner);
+
+        let mut out = String::from(
+            "# HELP notes_job_runs_total Finished runs of background jobs, by job and outcome\n\
+             # TYPE notes_job_runs_total counter\n",
+        );
+        for (job, stats) in jobs.iter() {
+            let _ = writeln!(
+                out,
+                "notes_job_runs_total{{job=\"{job}\",outcome=\"success\"}} {}\n\
+                 notes_job_runs_total{{job=\"{job}\",outcome=\"failure\"}} {}",
+                stats.succeeded, stats.failed,
+            );
+        }
+
+        out.push_str(
+            "# HELP notes_job_running Whether the job is running\n\
+             # TYPE notes_job_running gauge\n",
+        );
+        for (job, stats) in jobs.iter() {
+            let _ = writeln!(
+                out,
+                "notes_job_running{{job=\"{job}\"}} {}",
+                u8::from(stats.running)
+            );
+        }
+
+        out.push_str(
+            "# HELP notes_job_last_duration_seconds Time the last finished run took\n\
+             # TYPE notes_job_last_duration_seconds gauge\n",
+        );
+        for (job, stats) in jobs.iter() {
+            let _ = writeln!(
+                out,
+                "notes_job_last_duration_seconds{{job=\"{job}\"}} {}",
+                stats.last_duration
+            );
+        }
+
+        out.push_str(
+            "# HELP notes_job_last_success_timestamp_seconds Unix time the last successful run ended\n\
+             # TYPE notes_job_last_success_timestamp_seconds gauge\n",
+        );
+        for (job, stats) in jobs.iter() {
+            if let Some(last_success) = stats.last_success {
+                let _ = writeln!(
+                    out,
+                    "notes_job_last_success_timestamp_seconds{{job=\"{job}\"}} {}",
+                    last_success.timestamp()
+                );
+            }
+        }
+        drop(jobs);
+
+        out
+    }
+}
+
+/// Runs the registered jobs on their schedules, each in its own task. A job's runs
+/// never overlap: the next one is only scheduled once the previous one ended
+#[derive(Default)]
+pub struct JobRunner {
+    metrics: Arc<JobMetrics>,
+    stop: CancellationToken,
+    tasks: JoinSet<()>,
+}
+
+impl JobRunner {
+    pub fn metrics(&self) -> Arc<JobMetrics> {
+        self.metrics.clone()
+    }
+
+    /// Starts running the job on the schedule
+    pub fn register(&mut self, job: Arc<dyn Job>, schedule: Schedule) {
+        tracing::info!("Job {} is scheduled: {}", job.name(), describe(&schedule));
+        self.metrics.update(job.name(), |_| {});
+        self.tasks.spawn(run_scheduled(
+            job,
+            schedule,
+            self.metrics.clone(),
+            self.stop.clone(),
+        ));
+    }
+
+    /// Stops scheduling runs and waits up to `grace` for the running ones to end,
+    /// the ones still running then are aborted
+    pub async fn shutdown(mut self, grace: Duration) {
+        self.stop.cancel();
+        let finished = tokio::time::timeout(grace, async {
+            while self.tasks.join_next().await.is_some() {}
+        })
+        .await;
+        if finished.is_err() {
+            tracing::warn!("Aborting {} jobs still running", self.tasks.len());
+            self.tasks.shutdown().await;
+        }
+    }
+}
+
+fn describe(schedule: &Schedule) -> String {
+    match schedule {
+        Schedule::Interval(interval) => format!("every {}s", interval.as_secs()),
+        Schedule::Cron(schedule) => format!("at '{schedule}' UTC"),
+    }
+}
+
+async fn run_scheduled(
+    job: Arc<dyn Job>,
+    schedule: Schedule,
+    metrics: Arc<JobMetrics>,
+    stop: CancellationToken,
+) {
+    let name = job.name();
+    let mut wait = !(schedule.is_interval() && job.runs_on_start());
+    loop {
+        if wait {
+            let Some(delay) = schedule.next_delay() else {
+                tracing::info!("Job {name} has no more runs scheduled");
+                return;
+            };
+            tokio::select! {
+                () = stop.cancelled() => return,
+                () = tokio::time::sleep(delay) => {}
+            }
+        }
+        wait = true;
+
+        // A run that started is left to end, shutdown waits for it
+        metrics.started(name);
+        let started = Instant::now();
+        let result = job.run().await;
+        metrics.finished(name, started.elapsed(), result.is_ok());
+        if let Err(e) = result {
+            tracing::error!("Job {name} failed: {e}");
+        }
+
+        if stop.is_cancelled() {
+            return;
+        }
+    }
+}
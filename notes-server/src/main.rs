@@ -1,112 +1,1367 @@
+mod attachments;
+mod backup;
+mod cache;
+mod cli;
 mod dto;
+mod email;
 mod handlers;
+mod i18n;
+mod jobs;
+mod middleware;
 mod models;
+mod mtls;
+mod oidc;
+mod operations;
+mod outbox;
 mod repository;
 mod service;
+mod signing;
+mod tenant;
+mod webhooks;
 
 use axum::{
-    Router,
-    http::StatusCode,
+    Extension, Router,
+    extract::{DefaultBodyLimit, State},
+    http::{StatusCode, header},
     response::{IntoResponse, Response},
-    routing::{any, delete, get, post, put},
+    routing::{any, delete, get, patch, post, put},
 };
 
-use std::{env, sync::Arc};
+use axum_server::tls_rustls::{RustlsAcceptor, RustlsConfig};
+use std::{env, fs, io, net::SocketAddr, path::Path, process::ExitCode, sync::Arc, time::Duration};
 
 use handlers::rest;
-use repository::Repository;
+use repository::{ContentCipher, Repository};
+use secrets::{Secret, Secrets};
 
+use tonic::transport::{Certificate, Identity, ServerTlsConfig};
+use tower::Layer;
 use tower_http::trace::TraceLayer;
 use utoipa::OpenApi;
 use utoipa_swagger_ui::SwaggerUi;
 
-use service::NoteService;
+use attachments::{Attachments, local::LocalAttachmentStore, s3::S3AttachmentStore};
+use backup::BackupStore;
+use cache::{LocalCache, NoteCache};
+use email::HttpEmailClient;
+use i18n::Catalog;
+use jobs::{JobMetrics, JobRunner, Schedule};
+use middleware::{AdminToken, BodyLimit, CorsConfig, Quota, Quotas, RateLimit, RateLimiter};
+use mtls::{AllowedClients, ClientIdentityAcceptor};
+use oidc::{OidcAuthenticator, OidcConfig};
+use outbox::{
+    EventSink, LogSink, OutboxRelay, encoding::EventEncoding, kafka::KafkaSink, nats::NatsSink,
+};
+use service::{
+    ContentRules, DuplicatePolicy, NoteService, RetentionAction, RetentionPolicy, RetentionRule,
+    Sanitization, parse_rules,
+};
+use signing::RequestSigning;
+use tenant::TenantKeys;
+use webhooks::WebhookDispatcher;
 
-use crate::handlers::{grpc, soap};
+use crate::handlers::{
+    grpc::{
+        self, GrpcNoteService,
+        metrics::{GrpcMetrics, Metered, MetricsLayer},
+        notes::note_service_server::NoteServiceServer,
+        tenant::{TenantLayer, TenantScoped},
+    },
+    jsonrpc, soap,
+};
 
 #[tokio::main]
-async fn main() {
+async fn main() -> ExitCode {
     // Log setup
     tracing_subscriber::fmt::init();
 
+    // Both rustls backends end up enabled in the dependency tree, so the one used
+    // by the gRPC listener has to be chosen explicitly
+    let _ = rustls::crypto::aws_lc_rs::default_provider().install_default();
+
     // Fetch env variables
-    let database_dsn =
-        env::var("PG_DSN").expect("database dsn must be provided as an ENV variable");
+    let secrets = secrets_from_env().await;
+    let database_config = database_config_from_env(&secrets).await;
 
-    // Repository creation and migration
-    let repo = Repository::new(database_dsn).await.unwrap_or_else(|e| {
-        tracing::error!("Failed to establish database connection: {e}");
-        panic!("failed to establish database connection: {e}");
-    });
-    let repo_ptr = Arc::new(tokio::sync::Mutex::new(repo));
+    // Command line calls manage the database instead of serving
+    let args: Vec<String> = env::args().skip(1).collect();
+    if let Some(exit_code) = cli::run(&args, &database_config).await {
+        return exit_code;
+    }
 
-    repo_ptr.lock().await.migrate().await.unwrap_or_else(|e| {
-        tracing::error!("Failed to migrate database: {e}");
-        panic!("failed to migrate database: {e}");
-    });
+    let repo = Arc::new(repository(database_config, &secrets).await);
+    spawn_repository_tasks(&repo).await;
+
+    let catalog = Arc::new(catalog_from_env());
+
+    let json_parsing = json_parsing_from_env();
+    let body_limit = body_limit_from_env();
+    let cors = cors_from_env();
+    let rate_limiter = rate_limit_from_env().map(|limit| Arc::new(RateLimiter::new(limit)));
+    let soap_audit = soap_audit_from_env();
+    let retention = Arc::new(retention_policy_from_env());
+    let access = access_from_env(&repo, &secrets).await;
+    let tls = tls_from_env(&secrets).await;
+    // Every secret was read, so the renewal knows all of their leases
+    spawn_secret_renewal(&secrets);
 
     // Service creation
-    let service = Arc::new(NoteService::new(repo_ptr.clone()));
+    let cache = cache_from_env().await;
+    let local_cache = local_cache_from_env();
+    let service = Arc::new(NoteService::new(
+        repo,
+        email_client_from_env(&tls.client),
+        cache.clone(),
+        local_cache.clone(),
+        backups_from_env(),
+        attachments_from_env(),
+        content_rules_from_env(),
+    ));
+
+    let jobs = spawn_background_tasks(&service, &retention);
+    spawn_pruning(rate_limiter.as_ref(), access.quotas.as_ref());
+
+    let grpc_service =
+        grpc::create_grpc_server(service.clone(), catalog.clone(), grpc_limits_from_env());
+    let grpc_metrics = MetricsLayer::new(Arc::new(GrpcMetrics::default()));
+    let grpc_tenant = TenantLayer::new(
+        access.tenant_keys.clone(),
+        access.oidc.clone(),
+        access.quotas.clone(),
+        access.allowed_clients.clone(),
+        access.signing.clone(),
+        catalog.clone(),
+    );
+
+    let router = http_router(
+        &service,
+        &catalog,
+        json_parsing,
+        body_limit,
+        cors.as_ref(),
+        access.admin_token,
+        rate_limiter.as_ref(),
+    )
+    .route(
+        "/metrics",
+        get(metrics).with_state((grpc_metrics.metrics(), jobs.metrics(), cache, local_cache)),
+    )
+    .merge(grpc_web_router(
+        grpc_metrics.layer(grpc_tenant.layer(grpc_service.clone())),
+        body_limit,
+        cors.as_ref(),
+        rate_limiter.as_ref(),
+    ))
+    .layer(Extension(soap_audit))
+    .layer(Extension(retention))
+    .layer(Extension(access.tenant_keys))
+    .layer(Extension(access.oidc))
+    .layer(Extension(access.quotas));
+
+    let http_addr = "0.0.0.0:8000".parse().unwrap();
+
+    // gRPC server setup
+    let grpc_addr = "0.0.0.0:50051".parse().unwrap();
+
+    let grpc_server = grpc_server_builder(tls.grpc)
+        .layer(TraceLayer::new_for_grpc())
+        .layer(grpc_metrics)
+        .layer(grpc_tenant)
+        .add_service(grpc_service)
+        .serve(grpc_addr);
+
+    tracing::info!("gRPC server starting, listening on {}", grpc_addr);
+    tracing::info!("Servers are ready to accept connections");
+
+    // Run both servers concurrently
+    tokio::select! {
+        result = serve_http(http_addr, router, tls.http, access.allowed_clients, access.signing, catalog) => {
+            if let Err(e) = result {
+                tracing::error!("HTTP server error: {e}");
+                panic!("failed to start HTTP server: {e}");
+            }
+        }
+        result = grpc_server => {
+            if let Err(e) = result {
+                tracing::error!("gRPC server error: {e}");
+                panic!("failed to start gRPC server: {e}");
+            }
+        }
+        () = shutdown_signal() => tracing::info!("Shutting down"),
+    }
+
+    // Running jobs get to finish, so e.g. a backup isn't left half written
+    let grace = interval_from_env("JOB_SHUTDOWN_GRACE_SECS", Duration::from_secs(30));
+    jobs.shutdown(grace).await;
+
+    ExitCode::SUCCESS
+}
+
+/// Periodically forgets the clients of the rate limiter and the principals of the quotas
+/// that start over anyway, so they don't grow with every client ever seen
+fn spawn_pruning(rate_limiter: Option<&Arc<RateLimiter>>, quotas: Option<&Arc<Quotas>>) {
+    if let Some(rate_limiter) = rate_limiter {
+        tokio::spawn(rate_limiter.clone().run_pruning(Duration::from_mins(1)));
+    }
+    if let Some(quotas) = quotas {
+        tokio::spawn(quotas.clone().run_pruning(Duration::from_hours(1)));
+    }
+}
+
+/// Note templates of the caller's tenant
+fn template_router() -> Router<Arc<NoteService>> {
+    Router::new()
+        .route("/templates", post(rest::create_template))
+        .route("/templates", get(rest::list_templates))
+        .route("/templates/{id}", get(rest::get_template))
+        .route("/templates/{id}", put(rest::update_template))
+        .route("/templates/{id}", delete(rest::delete_template))
+        .route(
+            "/notes/from-template/{template_id}",
+            post(rest::create_note_from_template),
+        )
+}
+
+/// Webhook subscriptions of the caller's tenant
+fn webhook_router() -> Router<Arc<NoteService>> {
+    Router::new()
+        .route("/webhooks", post(rest::create_webhook))
+        .route("/webhooks", get(rest::list_webhooks))
+        .route("/webhooks/{id}", get(rest::get_webhook))
+        .route("/webhooks/{id}", put(rest::update_webhook))
+        .route("/webhooks/{id}", delete(rest::delete_webhook))
+        .route("/webhooks/{id}/deliveries", get(rest::webhook_deliveries))
+}
+
+/// Digest subscriptions of the caller's tenant
+fn digest_router() -> Router<Arc<NoteService>> {
+    Router::new()
+        .route("/digests", post(rest::create_digest))
+        .route("/digests", get(rest::list_digests))
+        .route("/digests/{id}", get(rest::get_digest))
+        .route("/digests/{id}", put(rest::update_digest))
+        .route("/digests/{id}", delete(rest::delete_digest))
+}
+
+/// Attachments of the notes of the caller's tenant. Uploads are streamed to the
+/// attachment store, so instead of the request body limit the attachment size limit
+/// applies to them
+fn attachment_router(
+    service: &Arc<NoteService>,
+    catalog: &Arc<Catalog>,
+    cors: Option<&CorsConfig>,
+    rate_limiter: Option<&Arc<RateLimiter>>,
+) -> Router {
+    let router = Router::new()
+        .route("/notes/{id}/attachments", post(rest::upload_attachment))
+        .route("/notes/{id}/attachments", get(rest::list_attachments))
+        .route(
+            "/notes/{id}/attachments/{attachment_id}",
+            get(rest::download_attachment),
+        )
+        .route(
+            "/notes/{id}/attachments/{attachment_id}",
+            delete(rest::delete_attachment),
+        )
+        .route_layer(axum::middleware::from_fn(middleware::resolve_tenant))
+        .with_state(service.clone())
+        .layer(axum::middleware::from_fn(rest::structured_rejections))
+        .layer(axum::middleware::from_fn_with_state(
+            rate_limiter.cloned(),
+            middleware::rate_limit,
+        ))
+        .layer(DefaultBodyLimit::disable())
+        .layer(Extension(catalog.clone()))
+        .layer(TraceLayer::new_for_http());
+
+    match cors {
+        Some(cors) => router.layer(cors.layer()),
+        None => router,
+    }
+}
+
+/// REST, SOAP and JSON-RPC routes served on the HTTP port
+fn http_router(
+    service: &Arc<NoteService>,
+    catalog: &Arc<Catalog>,
+    json_parsing: rest::JsonParsing,
+    body_limit: BodyLimit,
+    cors: Option<&CorsConfig>,
+    admin_token: AdminToken,
+    rate_limiter: Option<&Arc<RateLimiter>>,
+) -> Router {
+    // Admin routes that change data or expose callers require the admin token
+    let admin_router = Router::new()
+        .route("/admin/generate", post(rest::generate_notes))
+        .route("/admin/backup", post(rest::backup_notes))
+        .route("/admin/soap-audit", get(rest::soap_audit))
+        .route("/admin/users", get(rest::list_users))
+        .route("/admin/retention", get(rest::retention_report))
+        .route("/admin/migrations", get(rest::migration_status))
+        .route_layer(axum::middleware::from_fn_with_state(
+            admin_token,
+            middleware::require_admin,
+        ));
 
     // REST router config
     let rest_router = Router::new()
         .route("/notes", post(rest::create_note))
         .route("/notes/{id}", put(rest::update_note))
         .route("/notes/{id}", delete(rest::delete_note))
+        .route("/notes/undo", post(rest::undo_delete))
         .route("/notes/{id}", get(rest::get_one_note))
         .route("/notes", get(rest::get_all_notes))
+        .route("/notes/export", get(rest::export_notes))
+        .route("/notes/search", get(rest::search_notes))
+        .route("/notes/events", get(rest::note_events))
+        .route("/activity", get(rest::recent_activity))
         .route("/share", post(rest::share_notes))
+        .route("/notes/{id}/share", post(rest::share_note))
+        .route("/notes/{id}/metadata", patch(rest::patch_metadata))
+        .route("/notes/{id}/duplicate", post(rest::duplicate_note))
+        .route("/notes/duplicates", get(rest::duplicate_clusters))
+        .route("/notes/reorder", post(rest::reorder_notes))
+        .route("/notes/{id}/share-link", post(rest::create_share_link))
+        .route("/me/usage", get(rest::my_usage))
+        .merge(template_router())
+        .merge(webhook_router())
+        .merge(digest_router())
+        // Shared links are public, admin routes are not tied to a tenant
+        .route_layer(axum::middleware::from_fn(middleware::resolve_tenant))
+        .route("/shared/{token}", get(rest::get_shared_note))
+        .merge(admin_router)
         .merge(
             SwaggerUi::new("/swagger-ui")
                 .config(utoipa_swagger_ui::Config::new(["/api-doc/openapi.json"]))
                 .url("/api-doc/openapi.json", rest::ApiDoc::openapi()),
         )
         .with_state(service.clone())
+        .layer(axum::middleware::from_fn(rest::structured_rejections))
+        .layer(axum::middleware::from_fn(rest::negotiate_format))
+        .layer(axum::middleware::from_fn_with_state(
+            body_limit,
+            middleware::limit_body,
+        ))
+        .layer(axum::middleware::from_fn_with_state(
+            rate_limiter.cloned(),
+            middleware::rate_limit,
+        ))
+        .layer(DefaultBodyLimit::max(body_limit.0))
+        .layer(Extension(catalog.clone()))
+        .layer(Extension(json_parsing))
+        .layer(http_compression::layer())
         .layer(TraceLayer::new_for_http());
 
+    // Outermost, so preflight requests are answered before any other layer
+    let rest_router = match cors {
+        Some(cors) => rest_router.layer(cors.layer()),
+        None => rest_router,
+    };
+
     // SOAP router config
     let soap_router = Router::new()
         .route("/", post(soap::handle_request))
+        .route_layer(axum::middleware::from_fn(middleware::resolve_tenant))
         .with_state(service.clone())
+        .layer(axum::middleware::from_fn_with_state(
+            body_limit,
+            middleware::limit_body,
+        ))
+        .layer(axum::middleware::from_fn_with_state(
+            rate_limiter.cloned(),
+            middleware::rate_limit,
+        ))
+        .layer(DefaultBodyLimit::max(body_limit.0))
+        .layer(Extension(catalog.clone()))
         .layer(TraceLayer::new_for_http());
 
-    let router = Router::new()
+    // JSON-RPC router config
+    let jsonrpc_router = Router::new()
+        .route("/", post(jsonrpc::handle_request))
+        .route_layer(axum::middleware::from_fn(middleware::resolve_tenant))
+        .with_state(service.clone())
+        .layer(axum::middleware::from_fn_with_state(
+            body_limit,
+            middleware::limit_body,
+        ))
+        .layer(axum::middleware::from_fn_with_state(
+            rate_limiter.cloned(),
+            middleware::rate_limit,
+        ))
+        .layer(DefaultBodyLimit::max(body_limit.0))
+        .layer(Extension(catalog.clone()))
+        .layer(TraceLayer::new_for_http());
+
+    Router::new()
         .route("/", any(health_check))
+        .route("/livez", get(liveness))
+        .route("/readyz", get(readiness).with_state(service.clone()))
+        .route("/ready", get(readiness).with_state(service.clone()))
         .merge(rest_router)
-        .nest("/soap", soap_router);
+        .merge(attachment_router(service, catalog, cors, rate_limiter))
+        .nest("/soap", soap_router)
+        .nest("/rpc", jsonrpc_router)
+}
 
-    let http_listener = tokio::net::TcpListener::bind("0.0.0.0:8000").await.unwrap();
-    let http_addr = http_listener.local_addr().unwrap();
+/// gRPC-Web routes, browsers call the gRPC API through them on the HTTP port
+fn grpc_web_router(
+    grpc_service: Metered<TenantScoped<NoteServiceServer<GrpcNoteService>>>,
+    body_limit: BodyLimit,
+    cors: Option<&CorsConfig>,
+    rate_limiter: Option<&Arc<RateLimiter>>,
+) -> Router {
+    let router = Router::new()
+        .route(
+            "/notes.v1.NoteService/{method}",
+            post(grpc::web::handle_request),
+        )
+        .with_state(grpc_service)
+        .layer(axum::middleware::from_fn_with_state(
+            body_limit,
+            middleware::limit_body,
+        ))
+        .layer(axum::middleware::from_fn_with_state(
+            rate_limiter.cloned(),
+            middleware::rate_limit,
+        ))
+        .layer(DefaultBodyLimit::max(body_limit.0))
+        .layer(TraceLayer::new_for_http());
 
-    // gRPC server setup
-    let grpc_addr = "0.0.0.0:50051".parse().unwrap();
-    let grpc_service = grpc::create_grpc_server(service.clone());
+    match cors {
+        Some(cors) => router.layer(cors.layer()),
+        None => router,
+    }
+}
 
-    let grpc_server = tonic::transport::Server::builder()
-        .add_service(grpc_service)
-        .serve(grpc_addr);
+/// Limits of the gRPC messages in bytes from `GRPC_MAX_DECODING_MESSAGE_BYTES`
+/// and `GRPC_MAX_ENCODING_MESSAGE_BYTES`
+fn grpc_limits_from_env() -> grpc::MessageLimits {
+    let size = |name| env::var(name).ok().and_then(|v| v.parse().ok());
 
-    tracing::info!("REST/SOAP server starting, listening on {}", http_addr);
-    tracing::info!("gRPC server starting, listening on {}", grpc_addr);
-    tracing::info!("Servers are ready to accept connections");
+    grpc::MessageLimits {
+        max_decoding_size: size("GRPC_MAX_DECODING_MESSAGE_BYTES"),
+        max_encoding_size: size("GRPC_MAX_ENCODING_MESSAGE_BYTES"),
+    }
+}
 
-    // Run both servers concurrently
-    tokio::select! {
-        result = axum::serve(http_listener, router) => {
-            if let Err(e) = result {
-                tracing::error!("HTTP server error: {e}");
-                panic!("failed to start HTTP server: {e}");
+/// Serves the HTTP listener, over TLS when `HTTP_TLS_CERT_PATH` and `HTTP_TLS_KEY_PATH` are
+/// set. With `HTTP_TLS_CLIENT_CA_PATH` clients must present a certificate signed by that CA.
+/// Every route, health checks included, is only open to the allowed clients and, when
+/// requests must be signed, to signed requests
+async fn serve_http(
+    addr: SocketAddr,
+    router: Router,
+    tls: Option<RustlsConfig>,
+    allowed_clients: Option<Arc<AllowedClients>>,
+    signing: Option<Arc<RequestSigning>>,
+    catalog: Arc<Catalog>,
+) -> io::Result<()> {
+    let make_service = router
+        .layer(axum::middleware::from_fn_with_state(
+            signing,
+            middleware::verify_signature,
+        ))
+        .layer(axum::middleware::from_fn_with_state(
+            allowed_clients,
+            middleware::allow_clients,
+        ))
+        .layer(Extension(catalog))
+        .into_make_service_with_connect_info::<SocketAddr>();
+    let listener = tokio::net::TcpListener::bind(addr).await?;
+    tracing::info!("REST/SOAP server starting, listening on {}", addr);
+    let Some(tls) = tls else {
+        return axum::serve(listener, make_service).await;
+    };
+
+    let acceptor = ClientIdentityAcceptor::new(RustlsAcceptor::new(tls));
+    axum_server::from_tcp(listener.into_std()?)?
+        .acceptor(acceptor)
+        .serve(make_service)
+        .await
+}
+
+/// TLS of the listeners and of the calls to other services. The certificates and keys
+/// are secrets: the variables below name files as before, or they can be read from the
+/// environment or Vault (see `secrets_from_env`)
+struct Tls {
+    http: Option<RustlsConfig>,
+    grpc: Option<ServerTlsConfig>,
+    client: pki::ClientTls,
+}
+
+async fn tls_from_env(secrets: &Secrets) -> Tls {
+    let http = http_tls_from_env(secrets).await.unwrap_or_else(|e| {
+        tracing::error!("Failed to load HTTP TLS settings: {e}");
+        panic!("failed to load HTTP TLS settings: {e}");
+    });
+    let grpc = grpc_tls_from_env(secrets).await.unwrap_or_else(|e| {
+        tracing::error!("Failed to load gRPC TLS settings: {e}");
+        panic!("failed to load gRPC TLS settings: {e}");
+    });
+    let client = client_tls_from_env(secrets).await.unwrap_or_else(|e| {
+        tracing::error!("Failed to load email service TLS settings: {e}");
+        panic!("failed to load email service TLS settings: {e}");
+    });
+
+    Tls { http, grpc, client }
+}
+
+/// Certificate and key named `<prefix>_CERT` and `<prefix>_KEY`, `None` if neither is set
+async fn identity_from_env(
+    secrets: &Secrets,
+    prefix: &str,
+) -> Result<Option<(Secret, Secret)>, String> {
+    let cert = secrets.get(&format!("{prefix}_CERT")).await;
+    let key = secrets.get(&format!("{prefix}_KEY")).await;
+    match (
+        cert.map_err(|e| e.to_string())?,
+        key.map_err(|e| e.to_string())?,
+    ) {
+        (Some(cert), Some(key)) => Ok(Some((cert, key))),
+        (None, None) => Ok(None),
+        _ => Err(format!(
+            "{prefix}_CERT_PATH and {prefix}_KEY_PATH must be set together"
+        )),
+    }
+}
+
+fn read_file(name: &str) -> Result<Option<Vec<u8>>, String> {
+    env::var(name)
+        .ok()
+        .map(|path| fs::read(&path).map_err(|e| format!("Failed to read {name} '{path}': {e}")))
+        .transpose()
+}
+
+/// TLS for the HTTP listener, enabled when `HTTP_TLS_CERT_PATH` and `HTTP_TLS_KEY_PATH` are
+/// set. With `HTTP_TLS_CLIENT_CA_PATH` clients must present a certificate signed by that CA
+async fn http_tls_from_env(secrets: &Secrets) -> Result<Option<RustlsConfig>, String> {
+    let Some((cert, key)) = identity_from_env(secrets, "HTTP_TLS").await? else {
+        if env::var_os("TLS_ALLOWED_CLIENTS").is_some() {
+            tracing::warn!(
+                "HTTP listener has no TLS, all its requests are rejected by TLS_ALLOWED_CLIENTS"
+            );
+        }
+        return Ok(None);
+    };
+    let (cert, key) = (
+        cert.into_inner().into_bytes(),
+        key.into_inner().into_bytes(),
+    );
+
+    let Some(client_ca) = read_file("HTTP_TLS_CLIENT_CA_PATH")? else {
+        tracing::info!("HTTP server uses TLS");
+        return RustlsConfig::from_pem(cert, key)
+            .await
+            .map(Some)
+            .map_err(|e| e.to_string());
+    };
+    tracing::info!("HTTP server uses TLS, clients need a certificate");
+    let config = pki::server_config_from_pem(&cert, &key, &client_ca).map_err(|e| e.to_string())?;
+    Ok(Some(RustlsConfig::from_config(Arc::new(config))))
+}
+
+/// TLS of the calls to the email service: `TLS_CA_PATH` verifies it, the certificate of
+/// `TLS_CLIENT_CERT_PATH` and `TLS_CLIENT_KEY_PATH` is presented when it requires one
+async fn client_tls_from_env(secrets: &Secrets) -> Result<pki::ClientTls, String> {
+    let ca = read_file("TLS_CA_PATH")?;
+    let identity = identity_from_env(secrets, "TLS_CLIENT").await?;
+    pki::ClientTls::from_pem(
+        ca.as_deref(),
+        identity
+            .as_ref()
+            .map(|(cert, key)| (cert.expose().as_bytes(), key.expose().as_bytes())),
+    )
+    .map_err(|e| e.to_string())
+}
+
+/// gRPC server builder, serving over TLS when it is configured. Concurrent requests per
+/// connection are limited by `GRPC_CONCURRENCY_LIMIT_PER_CONNECTION`, and TCP keepalive
+/// probes are sent every `GRPC_TCP_KEEPALIVE_SECS` when set. HTTP/2 keepalive pings, flow
+/// control windows and concurrent streams are tuned by the `GRPC_HTTP2_*` variables,
+/// tonic's defaults apply to the ones not set
+fn grpc_server_builder(tls: Option<ServerTlsConfig>) -> tonic::transport::Server {
+    let secs = |name| number_from_env(name).map(Duration::from_secs);
+
+    let mut builder = tonic::transport::Server::builder()
+        .tcp_keepalive(secs("GRPC_TCP_KEEPALIVE_SECS"))
+        .http2_keepalive_interval(secs("GRPC_HTTP2_KEEPALIVE_INTERVAL_SECS"))
+        .http2_keepalive_timeout(secs("GRPC_HTTP2_KEEPALIVE_TIMEOUT_SECS"))
+        .initial_stream_window_size(number_from_env::<u32>("GRPC_HTTP2_STREAM_WINDOW_BYTES"))
+        .initial_connection_window_size(number_from_env::<u32>(
+            "GRPC_HTTP2_CONNECTION_WINDOW_BYTES",
+        ))
+        .max_concurrent_streams(number_from_env::<u32>("GRPC_HTTP2_MAX_CONCURRENT_STREAMS"));
+    if let Some(limit) = number_from_env("GRPC_CONCURRENCY_LIMIT_PER_CONNECTION") {
+        builder = builder.concurrency_limit_per_connection(limit);
+    }
+
+    let Some(tls) = tls else {
+        return builder;
+    };
+
+    tracing::info!("gRPC server uses TLS");
+    builder.tls_config(tls).unwrap_or_else(|e| {
+        tracing::error!("Invalid gRPC TLS config: {e}");
+        panic!("invalid gRPC TLS config: {e}");
+    })
+}
+
+/// TLS for the gRPC listener, enabled when `GRPC_TLS_CERT_PATH` and `GRPC_TLS_KEY_PATH` are set.
+/// With `GRPC_TLS_CLIENT_CA_PATH` clients must present a certificate signed by that CA,
+/// unless `GRPC_TLS_CLIENT_AUTH_OPTIONAL=true`, which only verifies the ones that do
+async fn grpc_tls_from_env(secrets: &Secrets) -> Result<Option<ServerTlsConfig>, String> {
+    let Some((cert, key)) = identity_from_env(secrets, "GRPC_TLS").await? else {
+        return Ok(None);
+    };
+
+    let identity = Identity::from_pem(cert.expose(), key.expose());
+    let mut config = ServerTlsConfig::new().identity(identity);
+    if let Some(client_ca) = read_file("GRPC_TLS_CLIENT_CA_PATH")? {
+        config = config
+            .client_ca_root(Certificate::from_pem(client_ca))
+            .client_auth_optional(
+                env::var("GRPC_TLS_CLIENT_AUTH_OPTIONAL").is_ok_and(|v| v == "true"),
+            );
+    }
+
+    Ok(Some(config))
+}
+
+/// Who may call the server and how much: the admin token, tenant API keys, OIDC, quotas,
+/// allowed client certificates and signing keys
+struct Access {
+    admin_token: AdminToken,
+    tenant_keys: Option<Arc<TenantKeys>>,
+    oidc: Option<Arc<OidcAuthenticator>>,
+    quotas: Option<Arc<Quotas>>,
+    allowed_clients: Option<Arc<AllowedClients>>,
+    signing: Option<Arc<RequestSigning>>,
+}
+
+async fn access_from_env(repo: &Arc<Repository>, secrets: &Secrets) -> Access {
+    Access {
+        admin_token: AdminToken(
+            secret(secrets, "ADMIN_TOKEN")
+                .await
+                .map(|token| token.into_inner().into()),
+        ),
+        tenant_keys: tenant_keys_from_env(secrets).await,
+        oidc: oidc_from_env(repo, secrets).await,
+        quotas: quota_from_env().map(|quota| Arc::new(Quotas::new(quota))),
+        allowed_clients: allowed_clients_from_env(),
+        signing: signing_from_env(secrets).await,
+    }
+}
+
+/// Names of the client certificates allowed to call the server from `TLS_ALLOWED_CLIENTS`,
+/// separated by commas, e.g. the side-cars. Calls without one of them are rejected, so the
+/// listeners need to verify client certificates (`*_TLS_CLIENT_CA_PATH`)
+fn allowed_clients_from_env() -> Option<Arc<AllowedClients>> {
+    let spec = env::var("TLS_ALLOWED_CLIENTS").ok()?;
+
+    let allowed = AllowedClients::parse(&spec).unwrap_or_else(|e| {
+        tracing::error!("Invalid TLS_ALLOWED_CLIENTS: {e}");
+        panic!("invalid TLS_ALLOWED_CLIENTS: {e}");
+    });
+    tracing::info!("Only the allowed client certificates may call the server");
+    Some(Arc::new(allowed))
+}
+
+/// Keys requests must be signed with (see `RequestSigning`), from the `REQUEST_SIGNING_KEYS`
+/// secret of `id=secret` pairs separated by commas. Signatures are accepted for
+/// `REQUEST_SIGNING_MAX_SKEW_SECS` (5 minutes by default) around their timestamp
+async fn signing_from_env(secrets: &Secrets) -> Option<Arc<RequestSigning>> {
+    let spec = secret(secrets, "REQUEST_SIGNING_KEYS").await?;
+    let max_skew = interval_from_env("REQUEST_SIGNING_MAX_SKEW_SECS", Duration::from_mins(5));
+
+    let signing = RequestSigning::parse(spec.expose(), max_skew).unwrap_or_else(|e| {
+        tracing::error!("Invalid REQUEST_SIGNING_KEYS: {e}");
+        panic!("invalid REQUEST_SIGNING_KEYS: {e}");
+    });
+    tracing::info!("Requests must be signed with one of the signing keys");
+    Some(Arc::new(signing))
+}
+
+/// Message catalog from the file at `MESSAGES_CATALOG_PATH`, the built-in one if unset
+fn catalog_from_env() -> Catalog {
+    env::var("MESSAGES_CATALOG_PATH").map_or_else(
+        |_| Catalog::default(),
+        |path| {
+            Catalog::load(Path::new(&path)).unwrap_or_else(|e| {
+                tracing::error!("Failed to load message catalog: {e}");
+                panic!("failed to load message catalog: {e}");
+            })
+        },
+    )
+}
+
+/// Unknown fields in JSON bodies are rejected only in strict mode
+fn json_parsing_from_env() -> rest::JsonParsing {
+    match env::var("JSON_PARSING_MODE").as_deref() {
+        Ok("strict") => rest::JsonParsing::Strict,
+        Ok("lenient") | Err(_) => rest::JsonParsing::Lenient,
+        Ok(other) => {
+            tracing::warn!("Unknown JSON_PARSING_MODE '{other}', using lenient parsing");
+            rest::JsonParsing::Lenient
+        }
+    }
+}
+
+/// Comma-separated list from the env variable `name`, `default` if unset
+fn list_from_env(name: &str, default: &[&str]) -> Vec<String> {
+    env::var(name).map_or_else(
+        |_| default.iter().map(ToString::to_string).collect(),
+        |v| {
+            v.split(',')
+                .map(str::trim)
+                .filter(|s| !s.is_empty())
+                .map(ToString::to_string)
+                .collect()
+        },
+    )
+}
+
+/// CORS is enabled only when `CORS_ALLOWED_ORIGINS` is set
+fn cors_from_env() -> Option<CorsConfig> {
+    env::var("CORS_ALLOWED_ORIGINS").ok()?;
+
+    Some(CorsConfig {
+        origins: list_from_env("CORS_ALLOWED_ORIGINS", &[]),
+        methods: list_from_env("CORS_ALLOWED_METHODS", &["GET", "POST", "PUT", "DELETE"]),
+        headers: list_from_env(
+            "CORS_ALLOWED_HEADERS",
+            &[
+                "content-type",
+                "accept-language",
+                "if-match",
+                "x-grpc-web",
+                "x-user-agent",
+                "grpc-timeout",
+                "x-api-key",
+            ],
+        ),
+    })
+}
+
+/// Rate limiting is enabled only when `RATE_LIMIT_PER_SECOND` is set
+fn rate_limit_from_env() -> Option<RateLimit> {
+    let per_second: f64 = env::var("RATE_LIMIT_PER_SECOND").ok()?.parse().ok()?;
+    if per_second <= 0.0 {
+        tracing::warn!("RATE_LIMIT_PER_SECOND must be positive, rate limiting is disabled");
+        return None;
+    }
+
+    let burst = env::var("RATE_LIMIT_BURST")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or_else(|| (per_second * 2.0).max(1.0));
+    let trust_forwarded_for = env::var("RATE_LIMIT_TRUST_FORWARDED_FOR").is_ok_and(|v| v == "true");
+
+    Some(RateLimit {
+        per_second,
+        burst,
+        trust_forwarded_for,
+    })
+}
+
+/// Quotas of each API key or user, enabled by `QUOTA_PER_MINUTE` and/or `QUOTA_PER_DAY`.
+/// Only authenticated requests are counted
+fn quota_from_env() -> Option<Quota> {
+    let quota = Quota {
+        per_minute: number_from_env::<u64>("QUOTA_PER_MINUTE").filter(|&n| n > 0),
+        per_day: number_from_env::<u64>("QUOTA_PER_DAY").filter(|&n| n > 0),
+    };
+    if quota.per_minute.is_none() && quota.per_day.is_none() {
+        return None;
+    }
+
+    let describe = |limit: Option<u64>| limit.map_or_else(|| "unlimited".into(), |n| n.to_string());
+    tracing::info!(
+        "Quotas are enabled: {} requests per minute, {} per day",
+        describe(quota.per_minute),
+        describe(quota.per_day)
+    );
+    Some(quota)
+}
+
+/// Note content encryption at rest, enabled by the secret `NOTES_ENCRYPTION_KEY`
+async fn cipher_from_env(secrets: &Secrets) -> Option<ContentCipher> {
+    secret(secrets, "NOTES_ENCRYPTION_KEY").await.map(|key| {
+        ContentCipher::from_base64_key(key.expose()).unwrap_or_else(|e| {
+            tracing::error!("Invalid NOTES_ENCRYPTION_KEY: {e}");
+            panic!("invalid NOTES_ENCRYPTION_KEY: {e}");
+        })
+    })
+}
+
+/// API keys of the tenants from the secret `TENANT_API_KEYS`, as `key=tenant` pairs
+/// separated by commas. Without it every request belongs to the default tenant
+async fn tenant_keys_from_env(secrets: &Secrets) -> Option<Arc<TenantKeys>> {
+    let spec = secret(secrets, "TENANT_API_KEYS").await?;
+
+    let keys = TenantKeys::parse(spec.expose()).unwrap_or_else(|e| {
+        tracing::error!("Invalid TENANT_API_KEYS: {e}");
+        panic!("invalid TENANT_API_KEYS: {e}");
+    });
+    tracing::info!("Multi-tenancy is enabled, requests need an API key");
+    Some(Arc::new(keys))
+}
+
+/// Authentication through an OIDC provider, enabled by `OIDC_ISSUER` along with
+/// `OIDC_CLIENT_ID`. `OIDC_CLIENT_SECRET` is only needed for tokens signed with it,
+/// `OIDC_TENANT_CLAIM` names the claim with the tenant of new users. The provider's keys
+/// are used for `OIDC_JWKS_TTL_SECS` seconds (an hour by default)
+async fn oidc_from_env(
+    repo: &Arc<Repository>,
+    secrets: &Secrets,
+) -> Option<Arc<OidcAuthenticator>> {
+    let issuer = env::var("OIDC_ISSUER").ok()?;
+    let client_id = env::var("OIDC_CLIENT_ID").unwrap_or_else(|_| {
+        tracing::error!("OIDC_CLIENT_ID is required with OIDC_ISSUER");
+        panic!("OIDC_CLIENT_ID is required with OIDC_ISSUER");
+    });
+
+    let config = OidcConfig {
+        issuer,
+        client_id,
+        client_secret: secret(secrets, "OIDC_CLIENT_SECRET")
+            .await
+            .map(Secret::into_inner),
+        tenant_claim: env::var("OIDC_TENANT_CLAIM").ok(),
+        jwks_ttl: interval_from_env("OIDC_JWKS_TTL_SECS", Duration::from_hours(1)),
+    };
+    tracing::info!(
+        "OIDC is enabled, requests need a token issued by {}",
+        config.issuer
+    );
+    Some(Arc::new(OidcAuthenticator::new(config, repo.clone())))
+}
+
+/// Client of the email service at `EMAIL_SERVICE_URL`
+fn email_client_from_env(tls: &pki::ClientTls) -> Arc<HttpEmailClient> {
+    let email_service_url =
+        env::var("EMAIL_SERVICE_URL").unwrap_or_else(|_| "http://localhost:8001".to_string());
+
+    Arc::new(HttpEmailClient::new(email_service_url, tls))
+}
+
+/// Redis cache of notes, enabled by `REDIS_URL`. Entries live for `REDIS_CACHE_TTL_SECS`
+/// seconds (60 by default)
+async fn cache_from_env() -> Option<Arc<NoteCache>> {
+    let url = env::var("REDIS_URL").ok()?;
+    let ttl = interval_from_env("REDIS_CACHE_TTL_SECS", Duration::from_mins(1));
+
+    let cache = NoteCache::connect(&url, ttl).await.unwrap_or_else(|e| {
+        tracing::error!("Failed to connect to Redis: {e}");
+        panic!("failed to connect to Redis: {e}");
+    });
+    tracing::info!("Note cache is enabled, entries live for {}s", ttl.as_secs());
+    Some(Arc::new(cache))
+}
+
+/// In-process cache of notes and first listing pages, enabled by `LOCAL_CACHE_CAPACITY`
+/// (entries per kind). Entries live for `LOCAL_CACHE_TTL_SECS` seconds (30 by default)
+fn local_cache_from_env() -> Option<Arc<LocalCache>> {
+    let capacity = number_from_env::<u64>("LOCAL_CACHE_CAPACITY").filter(|&c| c > 0)?;
+    let ttl = interval_from_env("LOCAL_CACHE_TTL_SECS", Duration::from_secs(30));
+
+    tracing::info!(
+        "Local note cache is enabled, {capacity} entries living for {}s",
+        ttl.as_secs()
+    );
+    Some(Arc::new(LocalCache::new(capacity, ttl)))
+}
+
+/// Store for note snapshots, the directory `BACKUP_DIR` or the S3-compatible bucket
+/// `BACKUP_S3_BUCKET` (credentials and endpoint from the standard `AWS_*` variables).
+/// Snapshots are named under `BACKUP_PREFIX`, the newest `BACKUP_RETAIN` (7 by default)
+/// are kept
+fn backups_from_env() -> Option<Arc<BackupStore>> {
+    let prefix = env::var("BACKUP_PREFIX").unwrap_or_default();
+    let retain = number_from_env("BACKUP_RETAIN").unwrap_or(7).max(1);
+
+    let store = if let Ok(dir) = env::var("BACKUP_DIR") {
+        BackupStore::local(&dir, &prefix, retain)
+    } else if let Ok(bucket) = env::var("BACKUP_S3_BUCKET") {
+        BackupStore::s3(&bucket, &prefix, retain)
+    } else {
+        return None;
+    };
+
+    let store = store.unwrap_or_else(|e| {
+        tracing::error!("Invalid backup settings: {e}");
+        panic!("invalid backup settings: {e}");
+    });
+    tracing::info!("Note backups are enabled, keeping the newest {retain} snapshots");
+    Some(Arc::new(store))
+}
+
+/// Attachments in the local `ATTACHMENT_DIR` or in the `ATTACHMENT_S3_BUCKET` bucket
+/// under `ATTACHMENT_S3_PREFIX`, up to `ATTACHMENT_MAX_BYTES` each (25 MiB by default).
+/// Attachments are disabled when neither is set
+fn attachments_from_env() -> Option<Attachments> {
+    let store: Arc<dyn attachments::AttachmentStore> = if let Ok(dir) = env::var("ATTACHMENT_DIR") {
+        Arc::new(LocalAttachmentStore::new(&dir).unwrap_or_else(|e| {
+            panic!("invalid ATTACHMENT_DIR: {e}");
+        }))
+    } else if let Ok(bucket) = env::var("ATTACHMENT_S3_BUCKET") {
+        let prefix = env::var("ATTACHMENT_S3_PREFIX").unwrap_or_default();
+        Arc::new(
+            S3AttachmentStore::new(&bucket, &prefix).unwrap_or_else(|e| {
+                panic!("invalid attachment S3 settings: {e}");
+            }),
+        )
+    } else {
+        return None;
+    };
+
+    tracing::info!("Note attachments are enabled, stored in {}", store.name());
+    Some(Attachments {
+        store,
+        max_bytes: number_from_env("ATTACHMENT_MAX_BYTES").unwrap_or(25 * 1024 * 1024),
+    })
+}
+
+/// SOAP requests are audited unless `SOAP_AUDIT_ENABLED=false`, envelopes are
+/// captured only with `SOAP_AUDIT_CAPTURE_ENVELOPES=true`
+fn soap_audit_from_env() -> soap::SoapAudit {
+    soap::SoapAudit {
+        enabled: env::var("SOAP_AUDIT_ENABLED").map_or(true, |v| v != "false"),
+        capture_envelopes: env::var("SOAP_AUDIT_CAPTURE_ENVELOPES").is_ok_and(|v| v == "true"),
+    }
+}
+
+/// Retention rules from `RETENTION_RULES`, e.g. `archive:90,delete:365`.
+/// `ARCHIVE_AFTER_DAYS` is still honoured as an `archive` rule, and with
+/// `RETENTION_DRY_RUN=true` the rules are only reported, never applied
+fn retention_policy_from_env() -> RetentionPolicy {
+    let mut rules = env::var("RETENTION_RULES").map_or_else(
+        |_| Vec::new(),
+        |spec| parse_rules(&spec).unwrap_or_else(|e| panic!("invalid RETENTION_RULES: {e}")),
+    );
+    if let Some(days) = number_from_env::<i64>("ARCHIVE_AFTER_DAYS").filter(|&days| days > 0) {
+        rules.push(RetentionRule {
+            action: RetentionAction::Archive,
+            after_days: days,
+        });
+    }
+    RetentionPolicy {
+        rules,
+        dry_run: env::var("RETENTION_DRY_RUN").is_ok_and(|v| v == "true"),
+    }
+}
+
+/// Request body limit in bytes from `MAX_REQUEST_BODY_BYTES`
+fn body_limit_from_env() -> BodyLimit {
+    env::var("MAX_REQUEST_BODY_BYTES")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .map_or_else(BodyLimit::default, BodyLimit)
+}
+
+/// Content limits from `MAX_NOTE_CONTENT_BYTES` and HTML sanitization from
+/// `CONTENT_SANITIZATION` (`strict`, `lenient` or `off`). An unknown sanitization mode
+/// stops the server, rather than storing the HTML unsanitized
+fn content_rules_from_env() -> ContentRules {
+    let defaults = ContentRules::default();
+    let sanitization = env::var("CONTENT_SANITIZATION").map_or(defaults.sanitization, |value| {
+        Sanitization::parse(&value).unwrap_or_else(|| {
+            tracing::error!(
+                "Unknown CONTENT_SANITIZATION '{value}', expected strict, lenient or off"
+            );
+            panic!("unknown CONTENT_SANITIZATION '{value}'");
+        })
+    });
+    let duplicates = env::var("DUPLICATE_NOTES").map_or(defaults.duplicates, |value| {
+        DuplicatePolicy::parse(&value).unwrap_or_else(|| {
+            tracing::warn!("Unknown DUPLICATE_NOTES '{value}', allowing duplicates");
+            defaults.duplicates
+        })
+    });
+    ContentRules {
+        max_bytes: number_from_env("MAX_NOTE_CONTENT_BYTES").unwrap_or(defaults.max_bytes),
+        sanitization,
+        duplicates,
+    }
+}
+
+/// Number from the env variable `name`, `None` if unset or invalid
+fn number_from_env<T: std::str::FromStr>(name: &str) -> Option<T> {
+    env::var(name).ok().and_then(|v| v.parse().ok())
+}
+
+/// Interval in seconds from the env variable `name`, `default` if unset or invalid
+fn interval_from_env(name: &str, default: Duration) -> Duration {
+    env::var(name)
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .map_or(default, Duration::from_secs)
+}
+
+/// Database connection settings, either the whole `PG_DSN` or `PG_HOST`, `PG_PORT` (5432
+/// by default), `PG_USER` (`postgres` by default) and `PG_DATABASE` (named after the user
+/// by default). `PG_DSN`, `PG_USER` and `PG_PASSWORD` are secrets, e.g. the password can
+/// be read from `PG_PASSWORD_FILE` or both the user and the password from the dynamic
+/// credentials of Vault's database engine
+async fn database_config_from_env(secrets: &Secrets) -> tokio_postgres::Config {
+    let mut config = match secret(secrets, "PG_DSN").await {
+        Some(dsn) => dsn.expose().parse().unwrap_or_else(|e| {
+            tracing::error!("Invalid PG_DSN: {e}");
+            panic!("invalid PG_DSN: {e}");
+        }),
+        None => database_parts_from_env(secrets).await,
+    };
+
+    if let Some(password) = secret(secrets, "PG_PASSWORD").await {
+        config.password(password.expose());
+    }
+
+    config
+}
+
+async fn database_parts_from_env(secrets: &Secrets) -> tokio_postgres::Config {
+    let host = env::var("PG_HOST").expect("PG_DSN or PG_HOST must be provided as an ENV variable");
+    let user = secret(secrets, "PG_USER")
+        .await
+        .map_or_else(|| "postgres".to_string(), Secret::into_inner);
+
+    let mut config = tokio_postgres::Config::new();
+    config
+        .host(&host)
+        .port(number_from_env("PG_PORT").unwrap_or(5432))
+        .user(&user);
+    if let Ok(database) = env::var("PG_DATABASE") {
+        config.dbname(&database);
+    }
+    config
+}
+
+/// Where the secrets are read from: for a secret `NAME` the file at `NAME_FILE` (`_PATH`
+/// for certificates and keys), Vault with `NAME_VAULT=<path>#<field>` or the `NAME` variable.
+/// Vault is used when `VAULT_ADDR` is set, its leases are renewed while the server runs
+/// (see `spawn_secret_renewal`)
+async fn secrets_from_env() -> Secrets {
+    Secrets::from_env().await.unwrap_or_else(|e| {
+        tracing::error!("Failed to connect to Vault: {e}");
+        panic!("failed to connect to Vault: {e}");
+    })
+}
+
+/// Renews the leases of the Vault token and the secrets read from Vault
+fn spawn_secret_renewal(secrets: &Secrets) {
+    if let Some(vault) = secrets.vault() {
+        tokio::spawn(vault.clone().run_renewal());
+    }
+}
+
+/// The secret, `None` when it's not set
+async fn secret(secrets: &Secrets, name: &str) -> Option<Secret> {
+    secrets.get(name).await.unwrap_or_else(|e| {
+        tracing::error!("Failed to read {name}: {e}");
+        panic!("failed to read {name}: {e}");
+    })
+}
+
+/// Applies pending migrations, unless `AUTO_MIGRATE=false`. Deployments that run
+/// `notes-server migrate --up` themselves turn it off, then pending ones are only reported
+async fn migrate_on_start(repo: &mut Repository) {
+    if env::var("AUTO_MIGRATE").map_or(true, |v| v != "false") {
+        repo.migrate().await.unwrap_or_else(|e| {
+            tracing::error!("Failed to migrate database: {e}");
+            panic!("failed to migrate database: {e}");
+        });
+    } else {
+        match repo.migration_status().await {
+            Ok((_, pending)) if pending.is_empty() => {}
+            Ok((_, pending)) => tracing::warn!(
+                "AUTO_MIGRATE is off and {} migrations are pending, run `notes-server migrate --up`",
+                pending.len()
+            ),
+            Err(e) => tracing::error!("Failed to check pending migrations: {e}"),
+        }
+    }
+}
+
+/// Connected and migrated repository. With encryption at rest enabled, notes stored
+/// before it was are encrypted first
+async fn repository(database_config: tokio_postgres::Config, secrets: &Secrets) -> Repository {
+    // Note content encryption at rest
+    let cipher = cipher_from_env(secrets).await;
+    let encryption_enabled = cipher.is_some();
+
+    // Repository creation and migration
+    let mut repo = Repository::new(database_config, number_from_env("PG_POOL_SIZE"), cipher)
+        .await
+        .unwrap_or_else(|e| {
+            tracing::error!("Failed to establish database connection: {e}");
+            panic!("failed to establish database connection: {e}");
+        });
+
+    migrate_on_start(&mut repo).await;
+
+    if encryption_enabled {
+        let encrypted = repo.encrypt_plaintext_notes().await.unwrap_or_else(|e| {
+            tracing::error!("Failed to encrypt existing notes: {e}");
+            panic!("failed to encrypt existing notes: {e}");
+        });
+        tracing::info!("Note encryption is enabled, encrypted {encrypted} plaintext notes");
+    }
+    repo
+}
+
+/// Hashes the content of notes written before content hashes were stored, in the
+/// background as it takes a while on large tables. Until then they aren't found as duplicates
+/// Background work of the repository: reconnecting, relaying the outbox, delivering
+/// webhooks and hashing the content of notes stored before hashes were kept
+async fn spawn_repository_tasks(repo: &Arc<Repository>) {
+    tokio::spawn(repo.clone().run_reconnect());
+    spawn_outbox_relay(repo).await;
+    spawn_webhook_dispatcher(repo);
+    spawn_content_hashing(repo);
+}
+
+fn spawn_content_hashing(repo: &Arc<Repository>) {
+    let repo = repo.clone();
+    tokio::spawn(async move {
+        match repo.hash_unhashed_notes().await {
+            Ok(0) => {}
+            Ok(hashed) => tracing::info!("Hashed the content of {hashed} notes"),
+            Err(e) => tracing::error!("Failed to hash the content of existing notes: {e}"),
+        }
+    });
+}
+
+/// Passes committed note changes from the outbox on to the sinks in `OUTBOX_SINKS`
+async fn spawn_outbox_relay(repo: &Arc<Repository>) {
+    let mut sinks: Vec<Arc<dyn EventSink>> = Vec::new();
+    for name in list_from_env("OUTBOX_SINKS", &[]) {
+        match name.as_str() {
+            "log" => sinks.push(Arc::new(LogSink)),
+            "kafka" => {
+                if let Some(sink) = kafka_sink_from_env() {
+                    sinks.push(Arc::new(sink));
+                }
+            }
+            "nats" => {
+                if let Some(sink) = nats_sink_from_env().await {
+                    sinks.push(Arc::new(sink));
+                }
             }
+            _ => tracing::warn!("Ignoring unknown outbox sink '{name}'"),
         }
-        result = grpc_server => {
-            if let Err(e) = result {
-                tracing::error!("gRPC server error: {e}");
-                panic!("failed to start gRPC server: {e}");
+    }
+
+    let interval = interval_from_env("OUTBOX_RELAY_INTERVAL_SECS", Duration::from_secs(1));
+    tokio::spawn(OutboxRelay::new(repo.clone(), sinks).run(interval));
+}
+
+/// Sends the changes queued for webhooks, every `WEBHOOK_DELIVERY_INTERVAL_SECS`
+fn spawn_webhook_dispatcher(repo: &Arc<Repository>) {
+    let interval = interval_from_env("WEBHOOK_DELIVERY_INTERVAL_SECS", Duration::from_secs(1));
+    tokio::spawn(WebhookDispatcher::new(repo.clone()).run(interval));
+}
+
+/// Kafka sink from `KAFKA_BROKERS` (`localhost:9092` by default), `KAFKA_TOPIC`
+/// (`note-changes` by default) and `KAFKA_ENCODING` (`json` or `protobuf`)
+fn kafka_sink_from_env() -> Option<KafkaSink> {
+    let brokers = env::var("KAFKA_BROKERS").unwrap_or_else(|_| "localhost:9092".to_string());
+    let topic = env::var("KAFKA_TOPIC").unwrap_or_else(|_| "note-changes".to_string());
+    let encoding = event_encoding_from_env("KAFKA_ENCODING");
+
+    KafkaSink::new(&brokers, topic, encoding)
+        .inspect_err(|e| tracing::error!("Failed to create the Kafka producer: {e}"))
+        .ok()
+}
+
+/// NATS sink from `NATS_URL` (`localhost:4222` by default), `NATS_STREAM` (`NOTES` by
+/// default), `NATS_SUBJECT_PREFIX` (`notes` by default) and `NATS_ENCODING`
+async fn nats_sink_from_env() -> Option<NatsSink> {
+    let url = env::var("NATS_URL").unwrap_or_else(|_| "localhost:4222".to_string());
+    let stream = env::var("NATS_STREAM").unwrap_or_else(|_| "NOTES".to_string());
+    let prefix = env::var("NATS_SUBJECT_PREFIX").unwrap_or_else(|_| "notes".to_string());
+    let encoding = event_encoding_from_env("NATS_ENCODING");
+
+    NatsSink::connect(&url, stream, prefix, encoding)
+        .await
+        .inspect_err(|e| tracing::error!("Failed to create the NATS client: {e}"))
+        .ok()
+}
+
+/// Encoding of published changes from the env variable `name`, JSON if unset or invalid
+fn event_encoding_from_env(name: &str) -> EventEncoding {
+    let Ok(value) = env::var(name) else {
+        return EventEncoding::default();
+    };
+    EventEncoding::parse(&value).unwrap_or_else(|| {
+        tracing::warn!("Unknown {name} '{value}', publishing JSON");
+        EventEncoding::default()
+    })
+}
+
+/// Spawns the change feed and registers the periodic jobs, the returned runner stops them
+fn spawn_background_tasks(service: &Arc<NoteService>, retention: &RetentionPolicy) -> JobRunner {
+    // Change stream events, including changes made through other instances
+    tokio::spawn(service.clone().run_change_feed(Duration::from_secs(5)));
+
+    let mut jobs = JobRunner::default();
+
+    // Expired notes, tombstones and old log entries
+    let cleanup = schedule_from_env(
+        "cleanup",
+        "EXPIRED_NOTES_CLEANUP_INTERVAL_SECS",
+        Duration::from_mins(1),
+    );
+    if let Some(schedule) = cleanup {
+        jobs.register(Arc::new(service::jobs::Cleanup(service.clone())), schedule);
+    }
+
+    // Retention rules are enforced, or only reported in dry-run mode, if any are set
+    let retention_schedule = schedule_from_env(
+        "retention",
+        "RETENTION_INTERVAL_SECS",
+        interval_from_env("ARCHIVE_INTERVAL_SECS", Duration::from_hours(1)),
+    );
+    if let Some(schedule) = retention_schedule.filter(|_| !retention.rules.is_empty()) {
+        let job = service::jobs::Retention {
+            service: service.clone(),
+            policy: retention.clone(),
+        };
+        jobs.register(Arc::new(job), schedule);
+    }
+
+    // Attachments of deleted notes and files of interrupted uploads
+    let attachments = schedule_from_env(
+        "attachments",
+        "ATTACHMENT_CLEANUP_INTERVAL_SECS",
+        Duration::from_hours(1),
+    );
+    if let Some(schedule) = attachments.filter(|_| service.attachments_enabled()) {
+        let job = service::jobs::AttachmentCleanup(service.clone());
+        jobs.register(Arc::new(job), schedule);
+    }
+
+    // Snapshots of the notes, only taken when there is a place to keep them
+    let backup = schedule_from_env("backup", "BACKUP_INTERVAL_SECS", Duration::from_hours(24));
+    if let Some(schedule) = backup.filter(|_| service.backups_enabled()) {
+        jobs.register(Arc::new(service::jobs::Backup(service.clone())), schedule);
+    }
+
+    // Digests of the changed notes, for the subscriptions that are due
+    let digests = schedule_from_env(
+        "digests",
+        "DIGEST_POLL_INTERVAL_SECS",
+        Duration::from_mins(1),
+    );
+    if let Some(schedule) = digests {
+        jobs.register(Arc::new(service::jobs::Digests(service.clone())), schedule);
+    }
+
+    // Reminders are only sent when there is an address to send them to
+    if let Ok(reminder_email) = env::var("REMINDER_EMAIL") {
+        let reminders = schedule_from_env(
+            "reminders",
+            "REMINDER_POLL_INTERVAL_SECS",
+            Duration::from_secs(30),
+        );
+        if let Some(schedule) = reminders {
+            let job = service::jobs::Reminders {
+                service: service.clone(),
+                to: reminder_email,
+            };
+            jobs.register(Arc::new(job), schedule);
+        }
+    } else {
+        tracing::info!("REMINDER_EMAIL is not set, note reminders are disabled");
+    }
+
+    jobs
+}
+
+/// Schedule of the job `name` from `JOB_<NAME>_SCHEDULE`: a number of seconds, a cron
+/// expression in UTC, or `off` to not run the job. Without it the job runs every
+/// `legacy` seconds, `default` if that's unset too
+fn schedule_from_env(name: &str, legacy: &str, default: Duration) -> Option<Schedule> {
+    let var = format!("JOB_{}_SCHEDULE", name.to_uppercase());
+    let interval = interval_from_env(legacy, default);
+    let Ok(value) = env::var(&var) else {
+        return Some(Schedule::Interval(interval));
+    };
+    if value == "off" {
+        tracing::info!("Job {name} is turned off by {var}");
+        return None;
+    }
+    Some(Schedule::parse(&value).unwrap_or_else(|| {
+        tracing::warn!(
+            "Invalid {var} '{value}', running every {}s",
+            interval.as_secs()
+        );
+        Schedule::Interval(interval)
+    }))
+}
+
+/// Resolves once the process is asked to stop, by Ctrl+C or `SIGTERM`
+async fn shutdown_signal() {
+    let interrupt = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            tracing::error!("Failed to listen for Ctrl+C: {e}");
+            std::future::pending::<()>().await;
+        }
+    };
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                terminate.recv().await;
+            }
+            Err(e) => {
+                tracing::error!("Failed to listen for SIGTERM: {e}");
+                std::future::pending::<()>().await;
             }
         }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        () = interrupt => {}
+        () = terminate => {}
     }
 }
 
 async fn health_check() -> Response {
     (StatusCode::OK, "Hello from notes server!").into_response()
 }
+
+/// Liveness probe, the process is up and serving HTTP
+async fn liveness() -> Response {
+    (StatusCode::OK, "OK").into_response()
+}
+
+/// Readiness probe, fails while the database doesn't answer or migrations are pending
+async fn readiness(State(service): State<Arc<NoteService>>) -> Response {
+    match service.check_ready().await {
+        Ok(()) => (StatusCode::OK, "Ready").into_response(),
+        Err(e) => (StatusCode::SERVICE_UNAVAILABLE, e.to_string()).into_response(),
+    }
+}
+
+/// gRPC and job metrics and the caches whose counters are exported, if enabled
+type MetricsState = (
+    Arc<GrpcMetrics>,
+    Arc<JobMetrics>,
+    Option<Arc<NoteCache>>,
+    Option<Arc<LocalCache>>,
+);
+
+async fn metrics(State((metrics, jobs, cache, local_cache)): State<MetricsState>) -> Response {
+    let mut body = metrics.render();
+    body.push_str(&jobs.render());
+    if let Some(cache) = cache {
+        body.push_str(&cache.render());
+    }
+    if let Some(local_cache) = local_cache {
+        body.push_str(&local_cache.render());
+    }
+
+    ([(header::CONTENT_TYPE, "text/plain; version=0.0.4")], body).into_response()
+}
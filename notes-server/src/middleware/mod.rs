@@ -0,0 +1,638 @@
+use axum::{
+    Extension,
+    body::Body,
+    extract::{ConnectInfo, Request, State},
+    http::{HeaderMap, HeaderName, HeaderValue, Method, StatusCode, header},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use chrono::{DateTime, Utc};
+use http_body_util::LengthLimitError;
+use tower_http::cors::{AllowHeaders, AllowMethods, AllowOrigin, CorsLayer};
+
+use std::{
+    collections::HashMap,
+    error::Error,
+    net::{IpAddr, SocketAddr},
+    sync::{Arc, Mutex, PoisonError},
+    time::{Duration, Instant},
+};
+
+use crate::{
+    dto::QuotaWindow,
+    handlers::rest::ErrorResponse,
+    i18n::{Localizer, MessageKey},
+    mtls::{AllowedClients, ClientIdentity},
+    oidc::{self, OidcAuthenticator, OidcError},
+    signing::{ContentVerified, RequestSigning, SignedBy},
+    tenant::{Principal, TenantKeys},
+};
+
+/// Largest accepted request body, in bytes
+#[derive(Debug, Clone, Copy)]
+pub struct BodyLimit(pub usize);
+
+impl Default for BodyLimit {
+    /// Same as axum's default limit for buffered extractors
+    fn default() -> Self {
+        Self(2 * 1024 * 1024)
+    }
+}
+
+/// Token admin endpoints expect as `Authorization: Bearer <token>`.
+/// Without one the admin endpoints are disabled
+#[derive(Debug, Clone, Default)]
+pub struct AdminToken(pub Option<Arc<str>>);
+
+/// Lets the request through only when it carries the admin token
+pub async fn require_admin(
+    State(token): State<AdminToken>,
+    l10n: Localizer,
+    request: Request,
+    next: Next,
+) -> Response {
+    let Some(expected) = token.0 else {
+        return (
+            StatusCode::FORBIDDEN,
+            ErrorResponse::new(MessageKey::AdminDisabled, &l10n),
+        )
+            .into_response();
+    };
+
+    let provided = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "));
+    if provided != Some(&*expected) {
+        return (
+            StatusCode::UNAUTHORIZED,
+            [(header::WWW_AUTHENTICATE, "Bearer")],
+            ErrorResponse::new(MessageKey::AdminUnauthorized, &l10n),
+        )
+            .into_response();
+    }
+
+    next.run(request).await
+}
+
+/// Answers `403` unless the client certificate of the connection is one of the allowed
+/// ones. Does nothing when no clients are listed
+pub async fn allow_clients(
+    State(allowed): State<Option<Arc<AllowedClients>>>,
+    l10n: Localizer,
+    request: Request,
+    next: Next,
+) -> Response {
+    let Some(allowed) = allowed else {
+        return next.run(request).await;
+    };
+
+    let client = ClientIdentity::of_request(&request);
+    if !allowed.allows(client.as_ref()) {
+        let client = client.map_or_else(|| "none".to_string(), |client| client.to_string());
+        tracing::warn!(%client, route = request.uri().path(), "Client certificate not allowed");
+        return (
+            StatusCode::FORBIDDEN,
+            ErrorResponse::new(MessageKey::ClientNotAllowed, &l10n),
+        )
+            .into_response();
+    }
+
+    next.run(request).await
+}
+
+/// Answers `401` unless the request is signed with one of the signing keys, with a fresh
+/// timestamp and a nonce not seen before. The body is checked against its signed hash as
+/// it's read, a mismatch fails reading it. Does nothing when signing isn't configured
+pub async fn verify_signature(
+    State(signing): State<Option<Arc<RequestSigning>>>,
+    l10n: Localizer,
+    request: Request,
+    next: Next,
+) -> Response {
+    let Some(signing) = signing else {
+        return next.run(request).await;
+    };
+
+    let (mut parts, body) = request.into_parts();
+    match signing.verify(&parts.method, &parts.uri, &parts.headers) {
+        Ok((signed_by, content_sha256)) => {
+            parts.extensions.insert(signed_by);
+            let body = Body::new(ContentVerified::new(body, content_sha256));
+            next.run(Request::from_parts(parts, body)).await
+        }
+        Err(e) => {
+            tracing::warn!(route = parts.uri.path(), "Request signature rejected: {e}");
+            (
+                StatusCode::UNAUTHORIZED,
+                ErrorResponse::new(MessageKey::InvalidSignature, &l10n).with_details(e),
+            )
+                .into_response()
+        }
+    }
+}
+
+/// Runs the request as the tenant of its credentials: the user of its OIDC bearer token,
+/// or its `X-Api-Key`. Answers `401` when they're missing or invalid. Every request
+/// belongs to the default tenant when neither OIDC nor keys are configured, its caller
+/// then is the service of its signing key or client certificate if any. The caller is available to
+/// handlers as the `Principal` extension and its requests count against the quotas
+pub async fn resolve_tenant(
+    Extension(keys): Extension<Option<Arc<TenantKeys>>>,
+    Extension(oidc): Extension<Option<Arc<OidcAuthenticator>>>,
+    Extension(quotas): Extension<Option<Arc<Quotas>>>,
+    l10n: Localizer,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    let resolved = if let Some(oidc) = &oidc
+        && let Some(token) = oidc::bearer_token(request.headers())
+    {
+        match oidc.authenticate(token).await {
+            Ok(resolved) => Some(resolved),
+            Err(e) => return oidc_failure(&e, &l10n),
+        }
+    } else {
+        keys.as_ref()
+            .and_then(|keys| keys.resolve(request.headers()))
+    };
+
+    if let Some((tenant, principal)) = resolved {
+        request.extensions_mut().insert(principal.clone());
+        return tenant
+            .scope(admit(quotas.as_deref(), &principal, &l10n, request, next))
+            .await;
+    }
+
+    if keys.is_none() && oidc.is_none() {
+        let signed_by = request
+            .extensions()
+            .get::<SignedBy>()
+            .map(SignedBy::principal);
+        let Some(principal) = signed_by
+            .or_else(|| ClientIdentity::of_request(&request).map(|client| client.principal()))
+        else {
+            return next.run(request).await;
+        };
+        request.extensions_mut().insert(principal.clone());
+        admit(quotas.as_deref(), &principal, &l10n, request, next).await
+    } else if oidc.is_some() {
+        (
+            StatusCode::UNAUTHORIZED,
+            [(header::WWW_AUTHENTICATE, "Bearer")],
+            ErrorResponse::new(MessageKey::InvalidToken, &l10n),
+        )
+            .into_response()
+    } else {
+        (
+            StatusCode::UNAUTHORIZED,
+            ErrorResponse::new(MessageKey::InvalidApiKey, &l10n),
+        )
+            .into_response()
+    }
+}
+
+/// `401` for a rejected token, `503` when the provider or the database couldn't be reached
+fn oidc_failure(e: &OidcError, l10n: &Localizer) -> Response {
+    if e.is_rejection() {
+        return (
+            StatusCode::UNAUTHORIZED,
+            [(header::WWW_AUTHENTICATE, r#"Bearer error="invalid_token""#)],
+            ErrorResponse::new(MessageKey::InvalidToken, l10n).with_details(e),
+        )
+            .into_response();
+    }
+
+    tracing::error!("Failed to authenticate the request: {e}");
+    (
+        StatusCode::SERVICE_UNAVAILABLE,
+        ErrorResponse::new(MessageKey::AuthUnavailable, l10n),
+    )
+        .into_response()
+}
+
+fn payload_too_large(l10n: &Localizer, limit: BodyLimit) -> Response {
+    (
+        StatusCode::PAYLOAD_TOO_LARGE,
+        ErrorResponse::new(MessageKey::BodyTooLarge, l10n)
+            .with_details(format_args!("limit is {} bytes", limit.0)),
+    )
+        .into_response()
+}
+
+/// Rejects bodies larger than the limit with `413` and a JSON error before they reach
+/// the handlers. Declared lengths are checked upfront, the rest are buffered up to the limit
+pub async fn limit_body(
+    State(limit): State<BodyLimit>,
+    l10n: Localizer,
+    request: Request,
+    next: Next,
+) -> Response {
+    let declared_length = request
+        .headers()
+        .get(header::CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<usize>().ok());
+    if declared_length.is_some_and(|length| length > limit.0) {
+        return payload_too_large(&l10n, limit);
+    }
+
+    let (parts, body) = request.into_parts();
+    let bytes = match axum::body::to_bytes(body, limit.0).await {
+        Ok(bytes) => bytes,
+        Err(e) if e.source().is_some_and(<dyn Error>::is::<LengthLimitError>) => {
+            return payload_too_large(&l10n, limit);
+        }
+        Err(e) => {
+            return (
+                StatusCode::BAD_REQUEST,
+                ErrorResponse::new(MessageKey::InvalidRequest, &l10n).with_details(e),
+            )
+                .into_response();
+        }
+    };
+
+    next.run(Request::from_parts(parts, Body::from(bytes)))
+        .await
+}
+
+/// Cross-origin access to the REST API, each list either explicit values or `*` for any
+#[derive(Debug, Clone, Default)]
+pub struct CorsConfig {
+    pub origins: Vec<String>,
+    pub methods: Vec<String>,
+    pub headers: Vec<String>,
+}
+
+fn is_any(values: &[String]) -> bool {
+    values.iter().any(|v| v == "*")
+}
+
+fn parse_all<T: std::str::FromStr>(values: &[String], what: &str) -> Vec<T> {
+    values
+        .iter()
+        .filter_map(|value| {
+            let parsed = value.parse().ok();
+            if parsed.is_none() {
+                tracing::warn!("Ignoring invalid CORS {what} '{value}'");
+            }
+            parsed
+        })
+        .collect()
+}
+
+impl CorsConfig {
+    pub fn layer(&self) -> CorsLayer {
+        let allow_origin = if is_any(&self.origins) {
+            AllowOrigin::any()
+        } else {
+            AllowOrigin::list(parse_all::<HeaderValue>(&self.origins, "origin"))
+        };
+        let allow_methods = if is_any(&self.methods) {
+            AllowMethods::any()
+        } else {
+            AllowMethods::list(parse_all::<Method>(&self.methods, "method"))
+        };
+        let allow_headers = if is_any(&self.headers) {
+            AllowHeaders::any()
+        } else {
+            AllowHeaders::list(parse_all::<HeaderName>(&self.headers, "header"))
+        };
+
+        CorsLayer::new()
+            .allow_origin(allow_origin)
+            .allow_methods(allow_methods)
+            .allow_headers(allow_headers)
+            // Needed by clients for optimistic locking, pagination, backoff and quotas,
+            // and by gRPC-Web clients for the status of trailers-only responses
+            .expose_headers([
+                header::ETAG,
+                header::LINK,
+                header::RETRY_AFTER,
+                HeaderName::from_static("grpc-status"),
+                HeaderName::from_static("grpc-message"),
+                HeaderName::from_static("x-ratelimit-limit-minute"),
+                HeaderName::from_static("x-ratelimit-remaining-minute"),
+                HeaderName::from_static("x-ratelimit-limit-day"),
+                HeaderName::from_static("x-ratelimit-remaining-day"),
+            ])
+    }
+}
+
+/// Token bucket parameters, the same for every client
+#[derive(Debug, Clone, Copy)]
+pub struct RateLimit {
+    /// Tokens added to a bucket per second, i.e. the sustained request rate
+    pub per_second: f64,
+    /// Bucket capacity, i.e. how many requests may arrive at once
+    pub burst: f64,
+    /// Identify clients by the first `X-Forwarded-For` address instead of the peer address.
+    /// Only safe when every request comes through a proxy that sets the header
+    pub trust_forwarded_for: bool,
+}
+
+struct Bucket {
+    tokens: f64,
+    updated_at: Instant,
+}
+
+/// Token buckets per client IP
+pub struct RateLimiter {
+    limit: RateLimit,
+    buckets: Mutex<HashMap<IpAddr, Bucket>>,
+}
+
+impl RateLimiter {
+    pub fn new(limit: RateLimit) -> Self {
+        Self {
+            limit,
+            buckets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Takes a token from the client's bucket, or returns how long until one is available
+    fn acquire(&self, client: IpAddr) -> Result<(), Duration> {
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock().unwrap_or_else(PoisonError::into_inner);
+        let bucket = buckets.entry(client).or_insert(Bucket {
+            tokens: self.limit.burst,
+            updated_at: now,
+        });
+
+        let refill = now.duration_since(bucket.updated_at).as_secs_f64() * self.limit.per_second;
+        bucket.tokens = (bucket.tokens + refill).min(self.limit.burst);
+        bucket.updated_at = now;
+
+        let outcome = if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64(
+                (1.0 - bucket.tokens) / self.limit.per_second,
+            ))
+        };
+        drop(buckets);
+
+        outcome
+    }
+
+    /// Periodically forgets clients whose buckets have refilled, so the map doesn't grow
+    /// with every address ever seen. A full bucket is the same as a new one
+    pub async fn run_pruning(self: Arc<Self>, interval: Duration) {
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+
+            let now = Instant::now();
+            let mut buckets = self.buckets.lock().unwrap_or_else(PoisonError::into_inner);
+            buckets.retain(|_, bucket| {
+                let refill =
+                    now.duration_since(bucket.updated_at).as_secs_f64() * self.limit.per_second;
+                bucket.tokens + refill < self.limit.burst
+            });
+        }
+    }
+
+    fn client_ip(&self, request: &Request, peer: SocketAddr) -> IpAddr {
+        if self.limit.trust_forwarded_for
+            && let Some(ip) = request
+                .headers()
+                .get("x-forwarded-for")
+                .and_then(|v| v.to_str().ok())
+                .and_then(|v| v.split(',').next())
+                .and_then(|v| v.trim().parse().ok())
+        {
+            return ip;
+        }
+        peer.ip()
+    }
+}
+
+/// Answers `429` with `Retry-After` once the client runs out of tokens.
+/// Does nothing when rate limiting is not configured
+pub async fn rate_limit(
+    State(limiter): State<Option<Arc<RateLimiter>>>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    l10n: Localizer,
+    request: Request,
+    next: Next,
+) -> Response {
+    let Some(limiter) = limiter else {
+        return next.run(request).await;
+    };
+
+    let client = limiter.client_ip(&request, peer);
+    if let Err(wait) = limiter.acquire(client) {
+        // Whole seconds, rounded up so a retry at that time succeeds
+        let retry_after = wait.as_secs() + u64::from(wait.subsec_nanos() > 0);
+        tracing::warn!(%client, route = request.uri().path(), "Rate limit exceeded");
+        return (
+            StatusCode::TOO_MANY_REQUESTS,
+            [(header::RETRY_AFTER, retry_after.to_string())],
+            ErrorResponse::new(MessageKey::RateLimited, &l10n),
+        )
+            .into_response();
+    }
+
+    next.run(request).await
+}
+
+/// Requests a principal may make per window, unlimited when absent
+#[derive(Debug, Clone, Copy)]
+pub struct Quota {
+    pub per_minute: Option<u64>,
+    pub per_day: Option<u64>,
+}
+
+const MINUTE_SECS: i64 = 60;
+const DAY_SECS: i64 = 86_400;
+
+/// Requests counted in a fixed window, e.g. one UTC minute
+#[derive(Debug, Clone, Copy, Default)]
+struct Window {
+    /// Number of the window since the Unix epoch
+    index: i64,
+    count: u64,
+}
+
+impl Window {
+    /// The window at `now`, starting over once this one has ended
+    const fn at(self, now: i64, length: i64) -> Self {
+        let index = now.div_euclid(length);
+        if self.index == index {
+            self
+        } else {
+            Self { index, count: 0 }
+        }
+    }
+
+    fn report(self, limit: u64, length: i64) -> QuotaWindow {
+        QuotaWindow {
+            limit,
+            used: self.count,
+            remaining: limit.saturating_sub(self.count),
+            resets_at: DateTime::from_timestamp((self.index + 1) * length, 0).unwrap_or_default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct Usage {
+    minute: Window,
+    day: Window,
+}
+
+/// Requests made in the current windows against the quotas that are set
+pub struct QuotaUsage {
+    pub minute: Option<QuotaWindow>,
+    pub day: Option<QuotaWindow>,
+}
+
+impl QuotaUsage {
+    fn windows(&self) -> impl Iterator<Item = (&'static str, &QuotaWindow)> {
+        [("minute", &self.minute), ("day", &self.day)]
+            .into_iter()
+            .filter_map(|(name, window)| window.as_ref().map(|window| (name, window)))
+    }
+
+    /// `X-RateLimit-Limit-<Window>` and `X-RateLimit-Remaining-<Window>` of each quota
+    fn headers(&self) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for (name, window) in self.windows() {
+            for (header, value) in [("limit", window.limit), ("remaining", window.remaining)] {
+                if let Ok(header) = HeaderName::try_from(format!("x-ratelimit-{header}-{name}")) {
+                    headers.insert(header, HeaderValue::from(value));
+                }
+            }
+        }
+        headers
+    }
+}
+
+/// Requests of each principal in the current UTC minute and day. Like the rate limiter,
+/// every instance counts the requests it serves on its own
+pub struct Quotas {
+    quota: Quota,
+    usage: Mutex<HashMap<Principal, Usage>>,
+}
+
+impl Quotas {
+    pub fn new(quota: Quota) -> Self {
+        Self {
+            quota,
+            usage: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn report(&self, usage: Usage) -> QuotaUsage {
+        QuotaUsage {
+            minute: self
+                .quota
+                .per_minute
+                .map(|limit| usage.minute.report(limit, MINUTE_SECS)),
+            day: self
+                .quota
+                .per_day
+                .map(|limit| usage.day.report(limit, DAY_SECS)),
+        }
+    }
+
+    /// Counts a request of the principal unless a quota is used up.
+    /// Returns whether it was counted and the usage after it
+    fn acquire(&self, principal: &Principal) -> (bool, QuotaUsage) {
+        let now = Utc::now().timestamp();
+        let mut usage = self.usage.lock().unwrap_or_else(PoisonError::into_inner);
+        let current = usage.entry(principal.clone()).or_default();
+        current.minute = current.minute.at(now, MINUTE_SECS);
+        current.day = current.day.at(now, DAY_SECS);
+
+        let allowed = self
+            .quota
+            .per_minute
+            .is_none_or(|limit| current.minute.count < limit)
+            && self
+                .quota
+                .per_day
+                .is_none_or(|limit| current.day.count < limit);
+        if allowed {
+            current.minute.count += 1;
+            current.day.count += 1;
+        }
+        let current = *current;
+        drop(usage);
+
+        (allowed, self.report(current))
+    }
+
+    /// Counts a request of the principal unless a quota is used up, returns whether
+    /// it was counted
+    pub fn try_acquire(&self, principal: &Principal) -> bool {
+        self.acquire(principal).0
+    }
+
+    /// Requests the principal made in the current windows
+    pub fn usage(&self, principal: &Principal) -> QuotaUsage {
+        let now = Utc::now().timestamp();
+        let usage = self.usage.lock().unwrap_or_else(PoisonError::into_inner);
+        let current = usage.get(principal).copied().unwrap_or_default();
+        drop(usage);
+
+        self.report(Usage {
+            minute: current.minute.at(now, MINUTE_SECS),
+            day: current.day.at(now, DAY_SECS),
+        })
+    }
+
+    /// Periodically forgets principals without requests today, a new day starts
+    /// their count over anyway
+    pub async fn run_pruning(self: Arc<Self>, interval: Duration) {
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+
+            let today = Utc::now().timestamp().div_euclid(DAY_SECS);
+            let mut usage = self.usage.lock().unwrap_or_else(PoisonError::into_inner);
+            usage.retain(|_, usage| usage.day.index == today);
+        }
+    }
+}
+
+/// Counts the request against the principal's quotas, answering `429` with
+/// `Retry-After` once one is used up. The quota headers are set either way
+async fn admit(
+    quotas: Option<&Quotas>,
+    principal: &Principal,
+    l10n: &Localizer,
+    request: Request,
+    next: Next,
+) -> Response {
+    let Some(quotas) = quotas else {
+        return next.run(request).await;
+    };
+
+    let (allowed, usage) = quotas.acquire(principal);
+    if !allowed {
+        let now = Utc::now();
+        let exhausted = usage
+            .windows()
+            .filter(|(_, window)| window.remaining == 0)
+            .max_by_key(|(_, window)| window.resets_at);
+        let (window, retry_after) = exhausted
+            .map(|(name, window)| (name, (window.resets_at - now).num_seconds().max(1)))
+            .unwrap_or_default();
+        tracing::warn!(%principal, window, "Quota exceeded");
+        return (
+            StatusCode::TOO_MANY_REQUESTS,
+            usage.headers(),
+            [(header::RETRY_AFTER, retry_after.to_string())],
+            ErrorResponse::new(MessageKey::QuotaExceeded, l10n)
+                .with_details(format_args!("per-{window} quota is used up")),
+        )
+            .into_response();
+    }
+
+    let mut response = next.run(request).await;
+    response.headers_mut().extend(usage.headers());
+    response
+}
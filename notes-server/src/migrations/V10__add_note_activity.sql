@@ -0,0 +1,11 @@
+-- ACTIVITY FEED
+-- Changes are kept after the note is deleted, so there is no foreign key
+
+CREATE TABLE note_activity (
+    id BIGSERIAL PRIMARY KEY,
+    note_id BIGINT NOT NULL,
+    operation TEXT NOT NULL,
+    occurred_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_note_activity_occurred_at ON note_activity(occurred_at);
@@ -0,0 +1,16 @@
+-- SOAP AUDIT TRAIL
+-- One entry per SOAP request, `operations` lists the operations it asked for
+
+CREATE TABLE soap_audit (
+    id BIGSERIAL PRIMARY KEY,
+    operations TEXT[] NOT NULL,
+    caller TEXT NOT NULL,
+    forwarded_for TEXT,
+    soap_version TEXT NOT NULL,
+    fault_code TEXT,
+    latency_ms BIGINT NOT NULL,
+    envelope TEXT,
+    occurred_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_soap_audit_occurred_at ON soap_audit(occurred_at);
@@ -0,0 +1,8 @@
+-- NOTE SEARCH
+-- Full-text index over the content. The simple configuration doesn't stem words,
+-- so notes in any language are matched the same way
+
+ALTER TABLE notes ADD COLUMN search TSVECTOR
+    GENERATED ALWAYS AS (to_tsvector('simple', content)) STORED;
+
+CREATE INDEX notes_search_idx ON notes USING GIN (search);
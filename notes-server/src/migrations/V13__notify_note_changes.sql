@@ -0,0 +1,17 @@
+-- CHANGE NOTIFICATIONS
+-- Every recorded change is sent to the note_changes channel, so each instance can pass
+-- changes made through the other instances on to its change stream subscribers
+
+CREATE OR REPLACE FUNCTION notify_note_change() RETURNS TRIGGER AS $$
+BEGIN
+    PERFORM pg_notify('note_changes', json_build_object(
+        'note_id', NEW.note_id,
+        'operation', NEW.operation,
+        'occurred_at', NEW.occurred_at
+    )::text);
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER notify_note_change AFTER INSERT ON note_activity
+    FOR EACH ROW EXECUTE FUNCTION notify_note_change();
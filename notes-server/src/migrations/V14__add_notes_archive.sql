@@ -0,0 +1,19 @@
+-- NOTE ARCHIVE
+-- Notes not modified for a long time are moved here to keep the notes table small.
+-- Only notes that never expire and have no pending reminder are archived
+
+CREATE TABLE notes_archive (
+    id BIGINT PRIMARY KEY,
+    content TEXT,
+    created_at TIMESTAMP WITH TIME ZONE,
+    updated_at TIMESTAMP WITH TIME ZONE,
+    expires_at TIMESTAMP WITH TIME ZONE,
+    remind_at TIMESTAMP WITH TIME ZONE,
+    metadata JSONB NOT NULL DEFAULT '{}',
+    position BIGINT NOT NULL,
+    archived_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX notes_archive_metadata_idx ON notes_archive USING GIN (metadata);
+
+CREATE INDEX idx_notes_updated_at ON notes(updated_at);
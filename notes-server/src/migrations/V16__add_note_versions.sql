@@ -0,0 +1,16 @@
+-- NOTE VERSIONS
+-- Every update bumps the note's version, which conditional writes compare against.
+-- Maintenance updates setting `notes.preserve_updated_at` keep it along with `updated_at`
+
+ALTER TABLE notes ADD COLUMN version BIGINT NOT NULL DEFAULT 1;
+ALTER TABLE notes_archive ADD COLUMN version BIGINT NOT NULL DEFAULT 1;
+
+CREATE OR REPLACE FUNCTION set_updated_at() RETURNS TRIGGER AS $$
+BEGIN
+    IF current_setting('notes.preserve_updated_at', true) IS DISTINCT FROM 'on' THEN
+        NEW.updated_at = NOW();
+        NEW.version = OLD.version + 1;
+    END IF;
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
@@ -0,0 +1,18 @@
+-- ACTIVITY ORIGIN
+-- Instances publish their own changes to their subscribers right away, the instance a
+-- change was made through is sent along so it can skip the notification
+
+ALTER TABLE note_activity ADD COLUMN origin TEXT;
+
+CREATE OR REPLACE FUNCTION notify_note_change() RETURNS TRIGGER AS $$
+BEGIN
+    PERFORM pg_notify('note_changes', json_build_object(
+        'note_id', NEW.note_id,
+        'operation', NEW.operation,
+        'occurred_at', NEW.occurred_at,
+        'tenant_id', NEW.tenant_id,
+        'origin', NEW.origin
+    )::text);
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
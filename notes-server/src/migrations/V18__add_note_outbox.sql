@@ -0,0 +1,44 @@
+-- OUTBOX
+-- Every change of a note is queued in the transaction making it, so the relay passes
+-- on exactly the committed changes, even if the instance crashes right after them.
+-- Writes setting `notes.preserve_updated_at` (re-encryption, reordering, archival)
+-- don't change the note and queue nothing
+
+CREATE TABLE note_outbox (
+    id BIGSERIAL PRIMARY KEY,
+    note_id BIGINT NOT NULL,
+    operation TEXT NOT NULL,
+    tenant_id TEXT NOT NULL,
+    -- Version of the note after the change, NULL when it was deleted
+    version BIGINT,
+    occurred_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    sent_at TIMESTAMP WITH TIME ZONE
+);
+
+CREATE INDEX note_outbox_pending_idx ON note_outbox (id) WHERE sent_at IS NULL;
+CREATE INDEX note_outbox_sent_at_idx ON note_outbox (sent_at) WHERE sent_at IS NOT NULL;
+
+CREATE OR REPLACE FUNCTION enqueue_note_change() RETURNS TRIGGER AS $$
+BEGIN
+    IF current_setting('notes.preserve_updated_at', true) = 'on' THEN
+        RETURN NULL;
+    END IF;
+
+    IF TG_OP = 'DELETE' THEN
+        INSERT INTO note_outbox (note_id, operation, tenant_id)
+        VALUES (OLD.id, 'deleted', OLD.tenant_id);
+    ELSE
+        INSERT INTO note_outbox (note_id, operation, tenant_id, version)
+        VALUES (
+            NEW.id,
+            CASE TG_OP WHEN 'INSERT' THEN 'created' ELSE 'updated' END,
+            NEW.tenant_id,
+            NEW.version
+        );
+    END IF;
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER enqueue_note_change AFTER INSERT OR UPDATE OR DELETE ON notes
+    FOR EACH ROW EXECUTE FUNCTION enqueue_note_change();
@@ -0,0 +1,47 @@
+-- WEBHOOKS
+-- Subscriptions of a tenant's callback URLs to its note changes, and the log of the
+-- requests made to them. Relayed outbox entries are fanned out to the deliveries of
+-- every matching subscription, which are retried until they succeed or run out of
+-- attempts
+
+CREATE TABLE webhooks (
+    id BIGSERIAL PRIMARY KEY,
+    tenant_id TEXT NOT NULL,
+    url TEXT NOT NULL,
+    -- Key of the HMAC signature of the requests
+    secret TEXT NOT NULL,
+    -- Operations the subscription receives, all of them when empty
+    events TEXT[] NOT NULL DEFAULT '{}',
+    active BOOLEAN NOT NULL DEFAULT TRUE,
+    -- Failed requests in a row, the circuit opens once there are too many
+    consecutive_failures INTEGER NOT NULL DEFAULT 0,
+    -- Nothing is sent to the URL until then
+    circuit_open_until TIMESTAMP WITH TIME ZONE,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_webhooks_tenant_id ON webhooks(tenant_id, id);
+
+CREATE TABLE webhook_deliveries (
+    id BIGSERIAL PRIMARY KEY,
+    webhook_id BIGINT NOT NULL REFERENCES webhooks(id) ON DELETE CASCADE,
+    -- Outbox entry of the change
+    event_id BIGINT NOT NULL,
+    operation TEXT NOT NULL,
+    payload JSONB NOT NULL,
+    -- `pending`, `delivered` or `failed` (out of attempts)
+    status TEXT NOT NULL DEFAULT 'pending',
+    attempts INTEGER NOT NULL DEFAULT 0,
+    next_attempt_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    last_status_code INTEGER,
+    last_error TEXT,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    completed_at TIMESTAMP WITH TIME ZONE
+);
+
+CREATE INDEX webhook_deliveries_due_idx ON webhook_deliveries (next_attempt_at)
+    WHERE status = 'pending';
+CREATE INDEX webhook_deliveries_webhook_idx ON webhook_deliveries (webhook_id, id);
+CREATE INDEX webhook_deliveries_completed_at_idx ON webhook_deliveries (completed_at)
+    WHERE completed_at IS NOT NULL;
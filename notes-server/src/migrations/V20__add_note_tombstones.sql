@@ -0,0 +1,19 @@
+-- NOTE TOMBSTONES
+-- Deleted notes are kept here for a short while so the last delete can be undone.
+-- Rows past the undo window are removed by the cleanup task
+
+CREATE TABLE note_tombstones (
+    id BIGINT PRIMARY KEY,
+    content TEXT,
+    created_at TIMESTAMP WITH TIME ZONE,
+    updated_at TIMESTAMP WITH TIME ZONE,
+    expires_at TIMESTAMP WITH TIME ZONE,
+    remind_at TIMESTAMP WITH TIME ZONE,
+    metadata JSONB NOT NULL DEFAULT '{}',
+    position BIGINT NOT NULL,
+    version BIGINT NOT NULL,
+    tenant_id TEXT NOT NULL,
+    deleted_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_note_tombstones_tenant_id ON note_tombstones(tenant_id, deleted_at);
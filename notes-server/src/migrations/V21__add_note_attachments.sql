@@ -0,0 +1,17 @@
+-- NOTE ATTACHMENTS
+-- Files attached to notes, their bytes are kept in the attachment store under
+-- `storage_key`. There is no foreign key, since the note may be archived or
+-- awaiting an undo: attachments of notes gone for good are removed by the cleanup task
+
+CREATE TABLE note_attachments (
+    id BIGSERIAL PRIMARY KEY,
+    tenant_id TEXT NOT NULL,
+    note_id BIGINT NOT NULL,
+    filename TEXT NOT NULL,
+    content_type TEXT NOT NULL,
+    size BIGINT NOT NULL,
+    storage_key TEXT NOT NULL UNIQUE,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_note_attachments_note_id ON note_attachments(tenant_id, note_id);
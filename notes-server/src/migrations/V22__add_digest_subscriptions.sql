@@ -0,0 +1,19 @@
+-- DIGEST SUBSCRIPTIONS
+-- Emails listing the notes created or updated since the previous digest, sent on a
+-- cron schedule. The next digest starts where `last_run_at` left off
+
+CREATE TABLE digest_subscriptions (
+    id BIGSERIAL PRIMARY KEY,
+    tenant_id TEXT NOT NULL,
+    email TEXT NOT NULL,
+    schedule TEXT NOT NULL,
+    active BOOLEAN NOT NULL DEFAULT TRUE,
+    last_run_at TIMESTAMP WITH TIME ZONE,
+    next_run_at TIMESTAMP WITH TIME ZONE NOT NULL,
+    last_error TEXT,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_digest_subscriptions_tenant_id ON digest_subscriptions(tenant_id, id);
+CREATE INDEX digest_subscriptions_due_idx ON digest_subscriptions (next_run_at) WHERE active;
@@ -0,0 +1,10 @@
+-- NOTE CONTENT HASHES
+-- Hash of the note content with case and whitespace normalized, to find identical notes.
+-- It's computed by the server, as the content may be encrypted. Notes written before
+-- are hashed in the background on startup
+
+ALTER TABLE notes ADD COLUMN content_hash TEXT;
+ALTER TABLE notes_archive ADD COLUMN content_hash TEXT;
+ALTER TABLE note_tombstones ADD COLUMN content_hash TEXT;
+
+CREATE INDEX idx_notes_content_hash ON notes(tenant_id, content_hash);
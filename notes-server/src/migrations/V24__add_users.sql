@@ -0,0 +1,18 @@
+-- USERS
+-- People signed in through the OIDC provider, added the first time one of their tokens
+-- is seen. They're identified by the token issuer and subject, the rest is refreshed
+-- from the token claims
+
+CREATE TABLE users (
+    id BIGSERIAL PRIMARY KEY,
+    issuer TEXT NOT NULL,
+    subject TEXT NOT NULL,
+    tenant_id TEXT NOT NULL,
+    email TEXT,
+    name TEXT,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    last_seen_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    UNIQUE (issuer, subject)
+);
+
+CREATE INDEX idx_users_tenant_id ON users(tenant_id, id);
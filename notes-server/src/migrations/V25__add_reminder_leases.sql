@@ -0,0 +1,6 @@
+-- REMINDER LEASES
+-- Due reminders are claimed by an instance until `reminder_lease_until`, so the other
+-- instances don't send them too. A reminder that failed to send is retried once its
+-- lease ends
+
+ALTER TABLE notes ADD COLUMN reminder_lease_until TIMESTAMP WITH TIME ZONE;
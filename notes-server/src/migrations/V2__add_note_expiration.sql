@@ -0,0 +1,5 @@
+-- NOTE EXPIRATION
+
+ALTER TABLE notes ADD COLUMN expires_at TIMESTAMP WITH TIME ZONE;
+
+CREATE INDEX notes_expires_at_idx ON notes (expires_at) WHERE expires_at IS NOT NULL;
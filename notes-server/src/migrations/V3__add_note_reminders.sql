@@ -0,0 +1,5 @@
+-- NOTE REMINDERS
+
+ALTER TABLE notes ADD COLUMN remind_at TIMESTAMP WITH TIME ZONE;
+
+CREATE INDEX notes_remind_at_idx ON notes (remind_at) WHERE remind_at IS NOT NULL;
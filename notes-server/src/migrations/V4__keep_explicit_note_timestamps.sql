@@ -0,0 +1,10 @@
+-- INSERT TRIGGER
+-- Timestamps given explicitly on insert (bulk imports, generated fixtures) are kept
+
+CREATE OR REPLACE FUNCTION set_created_at() RETURNS TRIGGER AS $$
+BEGIN
+    NEW.created_at = COALESCE(NEW.created_at, NOW());
+    NEW.updated_at = COALESCE(NEW.updated_at, NEW.created_at);
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
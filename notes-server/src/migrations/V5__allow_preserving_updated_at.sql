@@ -0,0 +1,12 @@
+-- UPDATE TRIGGER
+-- Maintenance updates that don't change the note (e.g. re-encrypting its content) set
+-- `notes.preserve_updated_at` for their transaction to keep the note's version
+
+CREATE OR REPLACE FUNCTION set_updated_at() RETURNS TRIGGER AS $$
+BEGIN
+    IF current_setting('notes.preserve_updated_at', true) IS DISTINCT FROM 'on' THEN
+        NEW.updated_at = NOW();
+    END IF;
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
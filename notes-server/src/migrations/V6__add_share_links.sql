@@ -0,0 +1,11 @@
+-- PUBLIC SHARE LINKS
+-- Only a hash of the token is stored, so the table can't be used to open the notes
+
+CREATE TABLE share_links (
+    token_hash BYTEA PRIMARY KEY,
+    note_id BIGINT NOT NULL REFERENCES notes (id) ON DELETE CASCADE,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    expires_at TIMESTAMP WITH TIME ZONE NOT NULL
+);
+
+CREATE INDEX share_links_expires_at_idx ON share_links (expires_at);
@@ -0,0 +1,6 @@
+-- NOTE METADATA
+-- Arbitrary client-defined fields, a JSON object
+
+ALTER TABLE notes ADD COLUMN metadata JSONB NOT NULL DEFAULT '{}';
+
+CREATE INDEX notes_metadata_idx ON notes USING GIN (metadata);
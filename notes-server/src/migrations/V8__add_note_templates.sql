@@ -0,0 +1,9 @@
+-- NOTE TEMPLATES
+
+CREATE TABLE note_templates (
+    id BIGSERIAL PRIMARY KEY,
+    name TEXT NOT NULL,
+    content TEXT NOT NULL,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
+);
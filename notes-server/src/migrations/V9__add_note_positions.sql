@@ -0,0 +1,16 @@
+-- MANUAL ORDERING
+-- Positions are unique and only their relative order matters. New notes are placed last,
+-- reordering permutes the positions the given notes already hold, so other notes never move
+
+CREATE SEQUENCE notes_position_seq OWNED BY notes.id;
+
+ALTER TABLE notes ADD COLUMN position BIGINT;
+-- Numbering the existing notes doesn't change them, their versions stay the same
+SET LOCAL notes.preserve_updated_at = 'on';
+UPDATE notes SET position = id;
+SELECT setval('notes_position_seq', GREATEST((SELECT MAX(id) FROM notes), 1));
+
+ALTER TABLE notes ALTER COLUMN position SET DEFAULT nextval('notes_position_seq');
+ALTER TABLE notes ALTER COLUMN position SET NOT NULL;
+
+CREATE INDEX idx_notes_position ON notes(position);
@@ -0,0 +1,3 @@
+-- ACTIVITY FEED
+
+DROP TABLE note_activity;
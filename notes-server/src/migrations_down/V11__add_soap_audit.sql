@@ -0,0 +1,3 @@
+-- SOAP AUDIT TRAIL
+
+DROP TABLE soap_audit;
@@ -0,0 +1,3 @@
+-- NOTE SEARCH
+
+ALTER TABLE notes DROP COLUMN search;
@@ -0,0 +1,5 @@
+-- CHANGE NOTIFICATIONS
+
+DROP TRIGGER notify_note_change ON note_activity;
+
+DROP FUNCTION notify_note_change();
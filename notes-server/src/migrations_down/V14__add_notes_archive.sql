@@ -0,0 +1,10 @@
+-- NOTE ARCHIVE
+-- Archived notes are moved back, keeping their timestamps
+
+INSERT INTO notes (id, content, created_at, updated_at, expires_at, remind_at, metadata, position)
+SELECT id, content, created_at, updated_at, expires_at, remind_at, metadata, position
+FROM notes_archive;
+
+DROP INDEX idx_notes_updated_at;
+
+DROP TABLE notes_archive;
@@ -0,0 +1,18 @@
+-- TENANTS
+-- The notes of all tenants are merged
+
+CREATE OR REPLACE FUNCTION notify_note_change() RETURNS TRIGGER AS $$
+BEGIN
+    PERFORM pg_notify('note_changes', json_build_object(
+        'note_id', NEW.note_id,
+        'operation', NEW.operation,
+        'occurred_at', NEW.occurred_at
+    )::text);
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+ALTER TABLE note_activity DROP COLUMN tenant_id;
+ALTER TABLE note_templates DROP COLUMN tenant_id;
+ALTER TABLE notes_archive DROP COLUMN tenant_id;
+ALTER TABLE notes DROP COLUMN tenant_id;
@@ -0,0 +1,14 @@
+-- NOTE VERSIONS
+-- Conditional writes go back to comparing `updated_at`
+
+CREATE OR REPLACE FUNCTION set_updated_at() RETURNS TRIGGER AS $$
+BEGIN
+    IF current_setting('notes.preserve_updated_at', true) IS DISTINCT FROM 'on' THEN
+        NEW.updated_at = NOW();
+    END IF;
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+ALTER TABLE notes_archive DROP COLUMN version;
+ALTER TABLE notes DROP COLUMN version;
@@ -0,0 +1,16 @@
+-- ACTIVITY ORIGIN
+-- Notifications no longer say which instance a change was made through
+
+CREATE OR REPLACE FUNCTION notify_note_change() RETURNS TRIGGER AS $$
+BEGIN
+    PERFORM pg_notify('note_changes', json_build_object(
+        'note_id', NEW.note_id,
+        'operation', NEW.operation,
+        'occurred_at', NEW.occurred_at,
+        'tenant_id', NEW.tenant_id
+    )::text);
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+ALTER TABLE note_activity DROP COLUMN origin;
@@ -0,0 +1,6 @@
+-- OUTBOX
+-- Changes that weren't relayed yet are lost
+
+DROP TRIGGER enqueue_note_change ON notes;
+DROP FUNCTION enqueue_note_change();
+DROP TABLE note_outbox;
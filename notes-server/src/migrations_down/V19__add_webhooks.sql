@@ -0,0 +1,5 @@
+-- WEBHOOKS
+-- Subscriptions and their delivery log are lost
+
+DROP TABLE webhook_deliveries;
+DROP TABLE webhooks;
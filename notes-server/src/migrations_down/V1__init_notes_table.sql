@@ -0,0 +1,6 @@
+-- DATABASE SCHEMA
+
+DROP TABLE notes;
+
+DROP FUNCTION set_updated_at();
+DROP FUNCTION set_created_at();
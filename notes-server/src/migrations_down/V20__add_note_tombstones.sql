@@ -0,0 +1,4 @@
+-- NOTE TOMBSTONES
+-- Deletes still within the undo window can no longer be undone
+
+DROP TABLE note_tombstones;
@@ -0,0 +1,4 @@
+-- NOTE ATTACHMENTS
+-- The stored files are left behind, remove them from the attachment store by hand
+
+DROP TABLE note_attachments;
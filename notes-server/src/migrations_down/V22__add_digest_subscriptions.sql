@@ -0,0 +1,4 @@
+-- DIGEST SUBSCRIPTIONS
+-- Subscriptions are lost, no more digests are sent
+
+DROP TABLE digest_subscriptions;
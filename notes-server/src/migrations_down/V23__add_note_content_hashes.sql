@@ -0,0 +1,8 @@
+-- NOTE CONTENT HASHES
+-- Identical notes can no longer be found
+
+DROP INDEX idx_notes_content_hash;
+
+ALTER TABLE note_tombstones DROP COLUMN content_hash;
+ALTER TABLE notes_archive DROP COLUMN content_hash;
+ALTER TABLE notes DROP COLUMN content_hash;
@@ -0,0 +1,4 @@
+-- USERS
+-- Users are provisioned again the next time they sign in
+
+DROP TABLE users;
@@ -0,0 +1,4 @@
+-- REMINDER LEASES
+-- Every instance sends the due reminders again
+
+ALTER TABLE notes DROP COLUMN reminder_lease_until;
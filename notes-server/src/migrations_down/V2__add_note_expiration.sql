@@ -0,0 +1,3 @@
+-- NOTE EXPIRATION
+
+ALTER TABLE notes DROP COLUMN expires_at;
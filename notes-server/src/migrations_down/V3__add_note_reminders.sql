@@ -0,0 +1,3 @@
+-- NOTE REMINDERS
+
+ALTER TABLE notes DROP COLUMN remind_at;
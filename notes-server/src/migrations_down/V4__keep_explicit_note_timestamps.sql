@@ -0,0 +1,10 @@
+-- INSERT TRIGGER
+-- Timestamps are always set by the database again
+
+CREATE OR REPLACE FUNCTION set_created_at() RETURNS TRIGGER AS $$
+BEGIN
+    NEW.created_at = NOW();
+    NEW.updated_at = NOW();
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
@@ -0,0 +1,9 @@
+-- UPDATE TRIGGER
+-- Every update changes the note's version again
+
+CREATE OR REPLACE FUNCTION set_updated_at() RETURNS TRIGGER AS $$
+BEGIN
+    NEW.updated_at = NOW();
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
@@ -0,0 +1,3 @@
+-- PUBLIC SHARE LINKS
+
+DROP TABLE share_links;
@@ -0,0 +1,3 @@
+-- NOTE METADATA
+
+ALTER TABLE notes DROP COLUMN metadata;
@@ -0,0 +1,3 @@
+-- NOTE TEMPLATES
+
+DROP TABLE note_templates;
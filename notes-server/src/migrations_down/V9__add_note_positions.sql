@@ -0,0 +1,5 @@
+-- MANUAL ORDERING
+
+ALTER TABLE notes DROP COLUMN position;
+
+DROP SEQUENCE notes_position_seq;
@@ -1,9 +1,283 @@
 use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+/// Client-defined fields of a note
+pub type Metadata = Map<String, Value>;
+
+/// Restricts note listings by metadata, no restriction when both are absent
+#[derive(Debug, Clone, Default)]
+pub struct MetadataFilter {
+    /// Metadata must contain this object
+    pub contains: Option<Value>,
+    /// Metadata must have all of these keys
+    pub has_keys: Option<Vec<String>>,
+}
+
+impl MetadataFilter {
+    /// Whether the filter lets every note through
+    pub const fn is_empty(&self) -> bool {
+        self.contains.is_none() && self.has_keys.is_none()
+    }
+}
 
-#[allow(dead_code)]
 pub struct Note {
     pub id: i64,
     pub content: String,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub remind_at: Option<DateTime<Utc>>,
+    pub metadata: Metadata,
+    pub position: i64,
+    pub version: i64,
+}
+
+/// Notes with the same content, ignoring case and whitespace
+pub struct DuplicateCluster {
+    /// Hash of the normalized content shared by the notes
+    pub content_hash: String,
+    /// IDs of the notes, oldest first
+    pub note_ids: Vec<i64>,
+}
+
+/// Order of note listings
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum NoteOrder {
+    #[default]
+    Id,
+    /// User-defined order maintained with reordering
+    Position,
+}
+
+/// A note to be inserted with explicit timestamps
+pub struct NewNote {
+    pub content: String,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// A note to be inserted with the fields clients set
+pub struct NoteDraft {
+    pub content: String,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub remind_at: Option<DateTime<Utc>>,
+}
+
+/// Reusable content new notes can be created from
+pub struct NoteTemplate {
+    pub id: i64,
+    pub name: String,
+    pub content: String,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// A public read-only link to a note
+pub struct ShareLink {
+    pub note_id: i64,
+    pub expires_at: DateTime<Utc>,
+}
+
+/// A recorded change of a note, `operation` is `created`, `updated` or `deleted`
+#[derive(Deserialize)]
+pub struct Activity {
+    pub note_id: i64,
+    pub operation: String,
+    pub occurred_at: DateTime<Utc>,
+    pub tenant_id: String,
+    /// Instance the change was made through, `None` for changes recorded before
+    /// instances were told apart
+    #[serde(default)]
+    pub origin: Option<String>,
+}
+
+/// A committed change of a note queued in the outbox, `operation` is `created`,
+/// `updated` or `deleted`
+#[derive(Debug, Clone, Serialize)]
+pub struct OutboxEvent {
+    pub id: i64,
+    pub note_id: i64,
+    pub operation: String,
+    pub tenant_id: String,
+    /// Version of the note after the change, `None` when it was deleted
+    pub version: Option<i64>,
+    pub occurred_at: DateTime<Utc>,
+}
+
+/// A tenant's subscription of a callback URL to its note changes
+pub struct Webhook {
+    pub id: i64,
+    pub url: String,
+    /// Operations sent to the URL, all of them when empty
+    pub events: Vec<String>,
+    pub active: bool,
+    /// Failed requests in a row
+    pub consecutive_failures: i32,
+    /// Nothing is sent to the URL until then, after too many failed requests
+    pub circuit_open_until: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// A webhook subscription to create or update, see `Webhook`
+pub struct WebhookDraft {
+    pub url: String,
+    pub events: Vec<String>,
+    pub active: bool,
+}
+
+/// A change sent, or to be sent, to a webhook
+pub struct WebhookDelivery {
+    pub id: i64,
+    /// Outbox entry of the change
+    pub event_id: i64,
+    pub operation: String,
+    pub payload: Value,
+    /// `pending`, `delivered` or `failed`
+    pub status: String,
+    pub attempts: i32,
+    pub next_attempt_at: DateTime<Utc>,
+    /// Status of the last response, `None` if there was none
+    pub last_status_code: Option<i32>,
+    pub last_error: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub completed_at: Option<DateTime<Utc>>,
+}
+
+/// Why a webhook request failed, and whether it is tried again
+pub struct DeliveryFailure {
+    /// Status of the response, `None` if there was none
+    pub status_code: Option<i32>,
+    pub error: String,
+    /// When to try again, `None` when out of attempts
+    pub retry_at: Option<DateTime<Utc>>,
+}
+
+/// A delivery claimed to be sent now, with what's needed to send it
+pub struct DueDelivery {
+    pub id: i64,
+    pub webhook_id: i64,
+    pub url: String,
+    pub secret: String,
+    pub operation: String,
+    pub payload: Value,
+    /// Attempts made before this one
+    pub attempts: i32,
+}
+
+/// A file attached to a note, its bytes are kept in the attachment store
+pub struct Attachment {
+    pub id: i64,
+    pub note_id: i64,
+    pub filename: String,
+    pub content_type: String,
+    /// Size in bytes
+    pub size: i64,
+    /// Key of the bytes in the attachment store
+    pub storage_key: String,
+    pub created_at: DateTime<Utc>,
+}
+
+/// An uploaded file to attach to a note, see `Attachment`
+pub struct AttachmentDraft {
+    pub filename: String,
+    pub content_type: String,
+    pub size: i64,
+    pub storage_key: String,
+}
+
+/// A tenant's subscription to emailed digests of its changed notes
+pub struct DigestSubscription {
+    pub id: i64,
+    pub email: String,
+    /// Cron expression of when the digest is sent, in UTC
+    pub schedule: String,
+    pub active: bool,
+    /// Up to when the notes were included in the previous digest
+    pub last_run_at: Option<DateTime<Utc>>,
+    pub next_run_at: DateTime<Utc>,
+    /// Why the last digest couldn't be sent, cleared once one is
+    pub last_error: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// A digest subscription to create or update, see `DigestSubscription`
+pub struct DigestDraft {
+    pub email: String,
+    pub schedule: String,
+    pub active: bool,
+    pub next_run_at: DateTime<Utc>,
+}
+
+/// A digest claimed to be sent now, with what's needed to send it
+pub struct DueDigest {
+    pub id: i64,
+    pub tenant_id: String,
+    pub email: String,
+    pub schedule: String,
+    /// Notes changed after this are included, the previous run or the subscription time
+    pub since: DateTime<Utc>,
+}
+
+/// A SOAP request in the audit trail
+pub struct SoapAuditEntry {
+    pub id: i64,
+    /// Operations requested in the envelope, empty if it was rejected before dispatch
+    pub operations: Vec<String>,
+    /// Address of the peer that sent the request
+    pub caller: String,
+    /// `X-Forwarded-For` of the request, as received
+    pub forwarded_for: Option<String>,
+    /// Envelope version of the response, `1.1` or `1.2`
+    pub soap_version: String,
+    /// Code of the returned fault, absent if the request succeeded
+    pub fault_code: Option<String>,
+    pub latency_ms: i64,
+    /// Request envelope, only captured when enabled
+    pub envelope: Option<String>,
+    pub occurred_at: DateTime<Utc>,
+}
+
+/// A SOAP request to record in the audit trail, see `SoapAuditEntry`
+pub struct NewSoapAuditEntry {
+    pub operations: Vec<String>,
+    pub caller: String,
+    pub forwarded_for: Option<String>,
+    pub soap_version: String,
+    pub fault_code: Option<String>,
+    pub latency_ms: i64,
+    pub envelope: Option<String>,
+}
+
+/// A schema migration, as embedded in the binary or recorded in the database
+#[derive(Debug, Clone)]
+pub struct Migration {
+    pub version: i64,
+    pub name: String,
+    pub applied_at: Option<DateTime<Utc>>,
+}
+
+/// Someone signed in through the OIDC provider
+#[derive(Debug, Clone)]
+pub struct User {
+    pub id: i64,
+    /// Issuer of the user's tokens
+    pub issuer: String,
+    /// `sub` claim of the user's tokens, unique for the issuer
+    pub subject: String,
+    pub tenant_id: String,
+    pub email: Option<String>,
+    pub name: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub last_seen_at: DateTime<Utc>,
+}
+
+/// A user as described by the claims of a token, see `User`
+pub struct NewUser {
+    pub issuer: String,
+    pub subject: String,
+    pub email: Option<String>,
+    pub name: Option<String>,
 }
@@ -0,0 +1,131 @@
+use axum::{Extension, extract::Request, middleware::AddExtension};
+use axum_server::{accept::Accept, tls_rustls::RustlsAcceptor};
+use futures_util::future::BoxFuture;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio_rustls::server::TlsStream;
+use tonic::transport::server::{TcpConnectInfo, TlsConnectInfo};
+use tower::Layer;
+use x509_parser::{certificate::X509Certificate, extensions::GeneralName, prelude::FromDer};
+
+use std::{collections::HashSet, fmt, io, sync::Arc};
+
+use crate::tenant::Principal;
+
+/// Name of the verified client certificate of a connection: its common name, or its
+/// first DNS name without one. The certificates issued by `pki` are named after
+/// the service, e.g. `server1-sidecar`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClientIdentity(Arc<str>);
+
+impl ClientIdentity {
+    fn from_certificate(der: &[u8]) -> Option<Self> {
+        let (_, cert) = X509Certificate::from_der(der).ok()?;
+        let common_name = cert
+            .subject()
+            .iter_common_name()
+            .find_map(|name| name.as_str().ok())
+            .map(str::to_owned);
+        let name = common_name.or_else(|| {
+            let names = cert.subject_alternative_name().ok()??;
+            names
+                .value
+                .general_names
+                .iter()
+                .find_map(|name| match name {
+                    GeneralName::DNSName(name) => Some((*name).to_owned()),
+                    _ => None,
+                })
+        })?;
+
+        Some(Self(name.into()))
+    }
+
+    /// Client of the request, `None` if it didn't present a certificate or the listener
+    /// has no TLS. The HTTP listener sets it with `ClientIdentityAcceptor`, on the gRPC
+    /// one it's read from tonic's connection info
+    pub fn of_request<B>(request: &Request<B>) -> Option<Self> {
+        let extensions = request.extensions();
+        if let Some(identity) = extensions.get::<Option<Self>>() {
+            return identity.clone();
+        }
+
+        let certs = extensions
+            .get::<TlsConnectInfo<TcpConnectInfo>>()?
+            .peer_certs()?;
+        Self::from_certificate(certs.first()?)
+    }
+
+    /// Service the client is, for requests that don't carry an API key or token
+    pub fn principal(&self) -> Principal {
+        Principal::service(&self.0)
+    }
+}
+
+impl fmt::Display for ClientIdentity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// Names of the client certificates allowed to call the server, e.g. the side-cars and
+/// the balancer, parsed from a comma separated list. The certificates also have to be
+/// signed by the client CA of the listener
+#[derive(Debug)]
+pub struct AllowedClients(HashSet<String>);
+
+impl AllowedClients {
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let names: HashSet<String> = spec
+            .split(',')
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+            .map(str::to_owned)
+            .collect();
+
+        if names.is_empty() {
+            return Err("no client names given".into());
+        }
+        Ok(Self(names))
+    }
+
+    /// Whether the client may call the server, never without a certificate
+    pub fn allows(&self, client: Option<&ClientIdentity>) -> bool {
+        client.is_some_and(|client| self.0.contains(&*client.0))
+    }
+}
+
+/// TLS acceptor of the HTTP listener that passes the client certificate's name
+/// on to the requests of the connection, see `ClientIdentity::of_request`
+#[derive(Clone)]
+pub struct ClientIdentityAcceptor(RustlsAcceptor);
+
+impl ClientIdentityAcceptor {
+    pub const fn new(inner: RustlsAcceptor) -> Self {
+        Self(inner)
+    }
+}
+
+impl<I, S> Accept<I, S> for ClientIdentityAcceptor
+where
+    I: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    S: Send + 'static,
+{
+    type Stream = TlsStream<I>;
+    type Service = AddExtension<S, Option<ClientIdentity>>;
+    type Future = BoxFuture<'static, io::Result<(Self::Stream, Self::Service)>>;
+
+    fn accept(&self, stream: I, service: S) -> Self::Future {
+        let acceptor = self.0.clone();
+        Box::pin(async move {
+            let (stream, service) = acceptor.accept(stream, service).await?;
+            let identity = stream
+                .get_ref()
+                .1
+                .peer_certificates()
+                .and_then(|certs| certs.first())
+                .and_then(|cert| ClientIdentity::from_certificate(cert));
+
+            Ok((stream, Extension(identity).layer(service)))
+        })
+    }
+}
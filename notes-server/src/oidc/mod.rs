@@ -0,0 +1,283 @@
+use axum::http::{HeaderMap, header};
+use jsonwebtoken::{
+    Algorithm, DecodingKey, Validation,
+    jwk::{Jwk, JwkSet},
+};
+use moka::sync::Cache;
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use tokio::sync::{OnceCell, RwLock};
+
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    models::NewUser,
+    repository::{Repository, RepositoryError},
+    tenant::{Principal, Tenant},
+};
+
+/// Longest wait for the provider to respond
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+/// Least time between fetches of the provider's keys, so tokens with made up key IDs
+/// don't cause a fetch each
+const MIN_REFRESH_INTERVAL: Duration = Duration::from_secs(30);
+/// How long a user isn't provisioned again after being seen
+const SEEN_USER_TTL: Duration = Duration::from_mins(5);
+/// Most users remembered as seen
+const SEEN_USER_CAPACITY: u64 = 10_000;
+
+/// Provider whose tokens are accepted
+#[derive(Debug, Clone)]
+pub struct OidcConfig {
+    /// Issuer URL, the `iss` of the tokens. The provider is discovered at
+    /// `<issuer>/.well-known/openid-configuration`
+    pub issuer: String,
+    /// Client ID of the server, the tokens must have it in `aud`
+    pub client_id: String,
+    /// Secret of the client, only needed to accept tokens signed with it (`HS*`)
+    pub client_secret: Option<String>,
+    /// Claim naming the tenant of a new user, every user belongs to the default
+    /// tenant without one
+    pub tenant_claim: Option<String>,
+    /// How long the provider's keys are used before being fetched again
+    pub jwks_ttl: Duration,
+}
+
+/// Why a bearer token wasn't accepted
+#[derive(Debug, thiserror::Error)]
+pub enum OidcError {
+    #[error("invalid token: {0}")]
+    InvalidToken(#[from] jsonwebtoken::errors::Error),
+
+    #[error("token is signed with an unknown key")]
+    UnknownKey,
+
+    #[error("token has no '{0}' claim naming the tenant")]
+    NoTenant(String),
+
+    #[error("failed to fetch the provider's keys: {0}")]
+    Provider(#[from] reqwest::Error),
+
+    #[error("failed to provision the user: {0}")]
+    Provisioning(#[from] RepositoryError),
+}
+
+impl OidcError {
+    /// Whether the token itself is at fault, otherwise it couldn't be checked
+    pub const fn is_rejection(&self) -> bool {
+        matches!(
+            self,
+            Self::InvalidToken(_) | Self::UnknownKey | Self::NoTenant(_)
+        )
+    }
+}
+
+/// Token of `Authorization: Bearer <token>`, in HTTP headers or gRPC metadata
+pub fn bearer_token(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get(header::AUTHORIZATION)?
+        .to_str()
+        .ok()?
+        .strip_prefix("Bearer ")
+        .map(str::trim)
+}
+
+#[derive(Deserialize)]
+struct Discovery {
+    jwks_uri: String,
+}
+
+#[derive(Deserialize)]
+struct Claims {
+    sub: String,
+    email: Option<String>,
+    name: Option<String>,
+    preferred_username: Option<String>,
+    #[serde(flatten)]
+    other: Map<String, Value>,
+}
+
+struct FetchedKeys {
+    keys: JwkSet,
+    fetched_at: Instant,
+}
+
+/// Accepts the bearer tokens of an external OIDC provider, e.g. Keycloak or Auth0,
+/// checking their signature with the provider's published keys (JWKS). The keys are
+/// cached and fetched again once stale or when a token is signed with one not seen yet,
+/// e.g. after the provider rotated them. Users are added the first time one of their
+/// tokens is seen and keep the tenant they were added to
+pub struct OidcAuthenticator {
+    config: OidcConfig,
+    repo: Arc<Repository>,
+    client: reqwest::Client,
+    jwks_uri: OnceCell<String>,
+    keys: RwLock<Option<FetchedKeys>>,
+    /// Tenants of the users seen lately by subject, so not every request writes
+    seen: Cache<String, Tenant>,
+}
+
+impl OidcAuthenticator {
+    pub fn new(config: OidcConfig, repo: Arc<Repository>) -> Self {
+        let client = reqwest::Client::builder()
+            .timeout(REQUEST_TIMEOUT)
+            .build()
+            .unwrap_or_else(|_| reqwest::Client::new());
+        let seen = Cache::builder()
+            .max_capacity(SEEN_USER_CAPACITY)
+            .time_to_live(SEEN_USER_TTL)
+            .build();
+
+        Self {
+            config,
+            repo,
+            client,
+            jwks_uri: OnceCell::new(),
+            keys: RwLock::new(None),
+            seen,
+        }
+    }
+
+    /// Checks the token and returns the tenant of its user and the user, provisioning
+    /// the user if they're new
+    pub async fn authenticate(&self, token: &str) -> Result<(Tenant, Principal), OidcError> {
+        let claims = self.verify(token).await?;
+        let principal = Principal::user(&claims.sub);
+        if let Some(tenant) = self.seen.get(&claims.sub) {
+            return Ok((tenant, principal));
+        }
+
+        let tenant = match &self.config.tenant_claim {
+            Some(claim) => claims
+                .other
+                .get(claim)
+                .and_then(Value::as_str)
+                .map(Tenant::from)
+                .ok_or_else(|| OidcError::NoTenant(claim.clone()))?,
+            None => Tenant::default(),
+        };
+        let user = NewUser {
+            issuer: self.config.issuer.clone(),
+            subject: claims.sub.clone(),
+            email: claims.email,
+            name: claims.name.or(claims.preferred_username),
+        };
+        let (user, created) = tenant.scope(self.repo.provision_user(&user)).await?;
+        if created {
+            tracing::info!(
+                "Provisioned user {} of tenant {}",
+                user.subject,
+                user.tenant_id
+            );
+        }
+
+        let tenant = Tenant::from(user.tenant_id.as_str());
+        self.seen.insert(claims.sub, tenant.clone());
+        Ok((tenant, principal))
+    }
+
+    /// Claims of the token once its signature, issuer, audience and lifetime are checked
+    async fn verify(&self, token: &str) -> Result<Claims, OidcError> {
+        let header = jsonwebtoken::decode_header(token)?;
+        let key = match header.alg {
+            Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512 => {
+                let secret = self
+                    .config
+                    .client_secret
+                    .as_ref()
+                    .ok_or(OidcError::UnknownKey)?;
+                DecodingKey::from_secret(secret.as_bytes())
+            }
+            _ => self.key(header.kid.as_deref()).await?,
+        };
+
+        let mut validation = Validation::new(header.alg);
+        validation.set_issuer(&[&self.config.issuer]);
+        validation.set_audience(&[&self.config.client_id]);
+        validation.set_required_spec_claims(&["exp", "iss", "aud", "sub"]);
+
+        Ok(jsonwebtoken::decode::<Claims>(token, &key, &validation)?.claims)
+    }
+
+    /// Provider's key with the ID, fetching the keys when they're stale or don't have it
+    async fn key(&self, kid: Option<&str>) -> Result<DecodingKey, OidcError> {
+        {
+            let fetched = self.keys.read().await;
+            if let Some(fetched) = &*fetched
+                && fetched.fetched_at.elapsed() < self.config.jwks_ttl
+            {
+                if let Some(jwk) = find_key(&fetched.keys, kid) {
+                    return Ok(DecodingKey::from_jwk(jwk)?);
+                }
+                if fetched.fetched_at.elapsed() < MIN_REFRESH_INTERVAL {
+                    return Err(OidcError::UnknownKey);
+                }
+            }
+        }
+
+        let mut fetched = self.keys.write().await;
+        // Another request may have fetched them while this one waited for the lock
+        if fetched
+            .as_ref()
+            .is_none_or(|fetched| fetched.fetched_at.elapsed() >= MIN_REFRESH_INTERVAL)
+        {
+            let keys = self.fetch_keys().await?;
+            tracing::info!("Fetched {} keys of the OIDC provider", keys.keys.len());
+            *fetched = Some(FetchedKeys {
+                keys,
+                fetched_at: Instant::now(),
+            });
+        }
+
+        let key = fetched
+            .as_ref()
+            .and_then(|fetched| find_key(&fetched.keys, kid))
+            .map(DecodingKey::from_jwk);
+        drop(fetched);
+        Ok(key.ok_or(OidcError::UnknownKey)??)
+    }
+
+    async fn fetch_keys(&self) -> Result<JwkSet, OidcError> {
+        let jwks_uri = self
+            .jwks_uri
+            .get_or_try_init(|| async {
+                let url = format!(
+                    "{}/.well-known/openid-configuration",
+                    self.config.issuer.trim_end_matches('/')
+                );
+                let discovery: Discovery = self
+                    .client
+                    .get(url)
+                    .send()
+                    .await?
+                    .error_for_status()?
+                    .json()
+                    .await?;
+                Ok::<_, OidcError>(discovery.jwks_uri)
+            })
+            .await?;
+
+        Ok(self
+            .client
+            .get(jwks_uri)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?)
+    }
+}
+
+/// Key with the ID, or the only key when the token names none
+fn find_key<'a>(keys: &'a JwkSet, kid: Option<&str>) -> Option<&'a Jwk> {
+    kid.map_or_else(
+        || match keys.keys.as_slice() {
+            [only] => Some(only),
+            _ => None,
+        },
+        |kid| keys.find(kid),
+    )
+}
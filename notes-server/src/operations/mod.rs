@@ -0,0 +1,508 @@
+use async_trait::async_trait;
+use chrono::Utc;
+use futures_util::{Stream, TryStreamExt};
+
+use crate::{
+    dto::{
+        CreateNoteRequest, CreateShareLinkRequest, NoteResponse, NotesPage, ShareLinkResponse,
+        UpdateNoteRequest,
+    },
+    email::EmailError,
+    i18n::MessageKey,
+    models::{Metadata, MetadataFilter, NoteOrder},
+    repository::{ConditionalWrite, RepositoryError},
+    service::{ContentError, NoteService, ShareError, WriteError},
+};
+
+/// Why an operation failed, independent of the protocol it was requested over
+#[derive(Debug, thiserror::Error)]
+pub enum OperationError {
+    #[error("note not found")]
+    NotFound,
+
+    #[error("note was modified")]
+    PreconditionFailed,
+
+    #[error("invalid argument: {}", .0.english())]
+    InvalidArgument(MessageKey),
+
+    #[error("an identical note {0} already exists")]
+    Duplicate(i64),
+
+    #[error("{}: {source}", .context.english())]
+    Database {
+        context: MessageKey,
+        source: RepositoryError,
+    },
+
+    #[error("failed to send email: {0}")]
+    Email(EmailError),
+}
+
+impl OperationError {
+    /// User-facing message describing the error
+    pub const fn message_key(&self) -> MessageKey {
+        match self {
+            Self::NotFound => MessageKey::NoteNotFound,
+            Self::PreconditionFailed => MessageKey::NoteModified,
+            Self::Duplicate(_) => MessageKey::IdenticalNoteExists,
+            Self::InvalidArgument(key) | Self::Database { context: key, .. } => *key,
+            Self::Email(_) => MessageKey::EmailFailed,
+        }
+    }
+
+    /// Internal details are logged here, adapters only see the classification
+    fn database(context: MessageKey) -> impl FnOnce(RepositoryError) -> Self {
+        move |source| {
+            tracing::error!("{}: {source}", context.english());
+            Self::Database { context, source }
+        }
+    }
+
+    fn write(context: MessageKey) -> impl FnOnce(WriteError) -> Self {
+        move |err| match err {
+            WriteError::InvalidContent(e) => e.into(),
+            WriteError::Duplicate(id) => Self::Duplicate(id),
+            WriteError::Database(e) => Self::database(context)(e),
+        }
+    }
+
+    fn share(context: MessageKey) -> impl FnOnce(ShareError) -> Self {
+        move |err| match err {
+            ShareError::NotFound => Self::NotFound,
+            ShareError::Database(e) => Self::database(context)(e),
+            ShareError::Email(e) => {
+                tracing::error!("Failed to send email: {e}");
+                Self::Email(e)
+            }
+        }
+    }
+}
+
+impl From<ContentError> for OperationError {
+    fn from(err: ContentError) -> Self {
+        Self::InvalidArgument(match err {
+            ContentError::Empty => MessageKey::EmptyContent,
+            ContentError::TooLarge { .. } => MessageKey::ContentTooLarge,
+            ContentError::ExpirationPassed => MessageKey::InvalidExpiration,
+        })
+    }
+}
+
+fn applied<T>(outcome: ConditionalWrite<T>) -> Result<T, OperationError> {
+    match outcome {
+        ConditionalWrite::Applied(value) => Ok(value),
+        ConditionalWrite::NotFound => Err(OperationError::NotFound),
+        ConditionalWrite::PreconditionFailed => Err(OperationError::PreconditionFailed),
+    }
+}
+
+/// A typed, protocol-agnostic command. REST, SOAP and gRPC handlers translate
+/// their requests into operations and map the output or `OperationError` back
+/// into their own wire format
+#[async_trait]
+pub trait Operation {
+    type Output;
+
+    async fn execute(self, service: &NoteService) -> Result<Self::Output, OperationError>;
+}
+
+pub struct CreateNote(pub CreateNoteRequest);
+
+#[async_trait]
+impl Operation for CreateNote {
+    type Output = NoteResponse;
+
+    async fn execute(self, service: &NoteService) -> Result<NoteResponse, OperationError> {
+        service
+            .create_note(self.0)
+            .await
+            .map_err(OperationError::write(MessageKey::CreateFailed))
+    }
+}
+
+/// Creates all notes at once, the output keeps the order of the requests
+pub struct BatchCreateNotes(pub Vec<CreateNoteRequest>);
+
+#[async_trait]
+impl Operation for BatchCreateNotes {
+    type Output = Vec<NoteResponse>;
+
+    async fn execute(self, service: &NoteService) -> Result<Vec<NoteResponse>, OperationError> {
+        service
+            .batch_create_notes(self.0)
+            .await
+            .map_err(OperationError::write(MessageKey::CreateFailed))
+    }
+}
+
+/// Why an import was aborted: reading the notes to import failed, or creating them did
+pub enum ImportError<E> {
+    Source(E),
+    Operation(OperationError),
+}
+
+impl<E> From<RepositoryError> for ImportError<E> {
+    fn from(e: RepositoryError) -> Self {
+        Self::Operation(OperationError::database(MessageKey::CreateFailed)(e))
+    }
+}
+
+impl<E> From<ContentError> for ImportError<E> {
+    fn from(e: ContentError) -> Self {
+        Self::Operation(e.into())
+    }
+}
+
+/// Creates the notes of all batches atomically, an import that fails midway creates
+/// nothing. Batches are inserted as they arrive, so the source can be a client stream
+pub struct ImportNotes<S>(pub S);
+
+impl<S, E> ImportNotes<S>
+where
+    S: Stream<Item = Result<Vec<CreateNoteRequest>, E>> + Send + 'static,
+    E: Send + 'static,
+{
+    pub async fn execute(self, service: &NoteService) -> Result<u64, ImportError<E>> {
+        service
+            .import_notes(self.0.map_err(ImportError::Source))
+            .await
+    }
+}
+
+pub struct GetNote {
+    pub id: i64,
+}
+
+#[async_trait]
+impl Operation for GetNote {
+    type Output = NoteResponse;
+
+    async fn execute(self, service: &NoteService) -> Result<NoteResponse, OperationError> {
+        service
+            .get_one_note(self.id)
+            .await
+            .map_err(OperationError::database(MessageKey::GetFailed))?
+            .ok_or(OperationError::NotFound)
+    }
+}
+
+pub struct DuplicateNote {
+    pub id: i64,
+}
+
+#[async_trait]
+impl Operation for DuplicateNote {
+    type Output = NoteResponse;
+
+    async fn execute(self, service: &NoteService) -> Result<NoteResponse, OperationError> {
+        service
+            .duplicate_note(self.id)
+            .await
+            .map_err(OperationError::database(MessageKey::DuplicateFailed))?
+            .ok_or(OperationError::NotFound)
+    }
+}
+
+/// How long a share link works when the request doesn't say
+const DEFAULT_SHARE_LINK_TTL: chrono::TimeDelta = chrono::TimeDelta::days(7);
+
+pub struct CreateShareLink {
+    pub note_id: i64,
+    pub request: CreateShareLinkRequest,
+}
+
+#[async_trait]
+impl Operation for CreateShareLink {
+    type Output = ShareLinkResponse;
+
+    async fn execute(self, service: &NoteService) -> Result<ShareLinkResponse, OperationError> {
+        let now = Utc::now();
+        let expires_at = self
+            .request
+            .expires_at
+            .unwrap_or(now + DEFAULT_SHARE_LINK_TTL);
+        if expires_at <= now {
+            return Err(OperationError::InvalidArgument(
+                MessageKey::InvalidLinkExpiration,
+            ));
+        }
+
+        let (token, link) = service
+            .create_share_link(self.note_id, expires_at)
+            .await
+            .map_err(OperationError::database(MessageKey::ShareLinkFailed))?
+            .ok_or(OperationError::NotFound)?;
+
+        Ok(ShareLinkResponse {
+            url: format!("/shared/{token}"),
+            token,
+            note_id: link.note_id,
+            expires_at: link.expires_at,
+        })
+    }
+}
+
+pub struct GetSharedNote {
+    pub token: String,
+}
+
+#[async_trait]
+impl Operation for GetSharedNote {
+    type Output = NoteResponse;
+
+    async fn execute(self, service: &NoteService) -> Result<NoteResponse, OperationError> {
+        service
+            .get_shared_note(&self.token)
+            .await
+            .map_err(OperationError::database(MessageKey::GetFailed))?
+            .ok_or(OperationError::NotFound)
+    }
+}
+
+pub struct GetAllNotes;
+
+#[async_trait]
+impl Operation for GetAllNotes {
+    type Output = Vec<NoteResponse>;
+
+    async fn execute(self, service: &NoteService) -> Result<Vec<NoteResponse>, OperationError> {
+        service
+            .get_all_notes()
+            .await
+            .map_err(OperationError::database(MessageKey::GetAllFailed))
+    }
+}
+
+/// All notes ordered by ID, streamed as they are fetched from the database
+pub struct StreamNotes;
+
+impl StreamNotes {
+    pub fn stream(
+        service: &NoteService,
+    ) -> impl Stream<Item = Result<NoteResponse, OperationError>> + Send + 'static {
+        service
+            .stream_notes(false)
+            .map_err(|e| OperationError::database(MessageKey::GetAllFailed)(e))
+    }
+}
+
+/// A page of up to `limit` notes matching `filter` in `order`, skipping the first `offset`
+pub struct ListNotes {
+    pub limit: i64,
+    pub offset: i64,
+    pub filter: MetadataFilter,
+    pub order: NoteOrder,
+    /// Whether notes moved to the archive are listed too
+    pub include_archived: bool,
+}
+
+#[async_trait]
+impl Operation for ListNotes {
+    type Output = NotesPage;
+
+    async fn execute(self, service: &NoteService) -> Result<NotesPage, OperationError> {
+        service
+            .list_notes(
+                self.limit,
+                self.offset,
+                &self.filter,
+                self.order,
+                self.include_archived,
+            )
+            .await
+            .map_err(OperationError::database(MessageKey::GetAllFailed))
+    }
+}
+
+/// A page of up to `limit` notes matching `filter` with ID greater than `after_id`, ordered by ID
+pub struct ListNotesAfter {
+    pub after_id: i64,
+    pub limit: i64,
+    pub filter: MetadataFilter,
+    /// Whether notes moved to the archive are listed too
+    pub include_archived: bool,
+}
+
+#[async_trait]
+impl Operation for ListNotesAfter {
+    type Output = NotesPage;
+
+    async fn execute(self, service: &NoteService) -> Result<NotesPage, OperationError> {
+        service
+            .list_notes_after(
+                self.after_id,
+                self.limit,
+                &self.filter,
+                self.include_archived,
+            )
+            .await
+            .map_err(OperationError::database(MessageKey::GetAllFailed))
+    }
+}
+
+/// Up to `limit` notes whose content matches `query`, best matches first
+pub struct SearchNotes {
+    pub query: String,
+    pub limit: i64,
+}
+
+#[async_trait]
+impl Operation for SearchNotes {
+    type Output = Vec<NoteResponse>;
+
+    async fn execute(self, service: &NoteService) -> Result<Vec<NoteResponse>, OperationError> {
+        let query = self.query.trim();
+        if query.is_empty() {
+            return Err(OperationError::InvalidArgument(
+                MessageKey::EmptySearchQuery,
+            ));
+        }
+
+        service
+            .search_notes(query, self.limit)
+            .await
+            .map_err(OperationError::database(MessageKey::SearchFailed))
+    }
+}
+
+/// Puts the notes in the order of `ids`, the notes swap the positions they held
+pub struct ReorderNotes {
+    pub ids: Vec<i64>,
+}
+
+#[async_trait]
+impl Operation for ReorderNotes {
+    type Output = ();
+
+    async fn execute(self, service: &NoteService) -> Result<(), OperationError> {
+        let mut unique = self.ids.clone();
+        unique.sort_unstable();
+        unique.dedup();
+        if self.ids.is_empty() || unique.len() != self.ids.len() {
+            return Err(OperationError::InvalidArgument(MessageKey::InvalidReorder));
+        }
+
+        let reordered = service
+            .reorder_notes(&self.ids)
+            .await
+            .map_err(OperationError::database(MessageKey::ReorderFailed))?;
+        if reordered {
+            Ok(())
+        } else {
+            Err(OperationError::NotFound)
+        }
+    }
+}
+
+/// Updates a note, only if its `version` is one of `expected_versions` when given
+pub struct UpdateNote {
+    pub id: i64,
+    pub request: UpdateNoteRequest,
+    pub expected_versions: Option<Vec<i64>>,
+}
+
+#[async_trait]
+impl Operation for UpdateNote {
+    type Output = NoteResponse;
+
+    async fn execute(self, service: &NoteService) -> Result<NoteResponse, OperationError> {
+        service
+            .update_note_if_match(self.id, self.request, self.expected_versions.as_deref())
+            .await
+            .map_err(OperationError::write(MessageKey::UpdateFailed))
+            .and_then(applied)
+    }
+}
+
+/// Merges fields into a note's metadata, only if its `version` is one of
+/// `expected_versions` when given
+pub struct PatchMetadata {
+    pub id: i64,
+    pub patch: Metadata,
+    pub expected_versions: Option<Vec<i64>>,
+}
+
+#[async_trait]
+impl Operation for PatchMetadata {
+    type Output = NoteResponse;
+
+    async fn execute(self, service: &NoteService) -> Result<NoteResponse, OperationError> {
+        service
+            .patch_metadata_if_match(self.id, self.patch, self.expected_versions.as_deref())
+            .await
+            .map_err(OperationError::database(MessageKey::UpdateFailed))
+            .and_then(applied)
+    }
+}
+
+/// Deletes a note, only if its `version` is one of `expected_versions` when given
+pub struct DeleteNote {
+    pub id: i64,
+    pub expected_versions: Option<Vec<i64>>,
+}
+
+#[async_trait]
+impl Operation for DeleteNote {
+    type Output = ();
+
+    async fn execute(self, service: &NoteService) -> Result<(), OperationError> {
+        service
+            .delete_note_if_match(self.id, self.expected_versions.as_deref())
+            .await
+            .map_err(OperationError::database(MessageKey::DeleteFailed))
+            .and_then(applied)
+    }
+}
+
+/// Deletes all notes at once, the output holds the IDs of the notes that existed
+pub struct BatchDeleteNotes {
+    pub ids: Vec<i64>,
+}
+
+#[async_trait]
+impl Operation for BatchDeleteNotes {
+    type Output = Vec<i64>;
+
+    async fn execute(self, service: &NoteService) -> Result<Vec<i64>, OperationError> {
+        service
+            .batch_delete_notes(&self.ids)
+            .await
+            .map_err(OperationError::database(MessageKey::DeleteFailed))
+    }
+}
+
+/// Emails all notes to `email`
+pub struct ShareNotes {
+    pub email: String,
+}
+
+#[async_trait]
+impl Operation for ShareNotes {
+    type Output = ();
+
+    async fn execute(self, service: &NoteService) -> Result<(), OperationError> {
+        service
+            .share_notes(self.email)
+            .await
+            .map_err(OperationError::share(MessageKey::GetAllFailed))
+    }
+}
+
+/// Emails a single note to `email`
+pub struct ShareNote {
+    pub id: i64,
+    pub email: String,
+}
+
+#[async_trait]
+impl Operation for ShareNote {
+    type Output = ();
+
+    async fn execute(self, service: &NoteService) -> Result<(), OperationError> {
+        service
+            .share_note(self.id, self.email)
+            .await
+            .map_err(OperationError::share(MessageKey::GetFailed))
+    }
+}
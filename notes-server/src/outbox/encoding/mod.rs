@@ -0,0 +1,56 @@
+use notes_proto::notes::v1::{NoteChange, NoteOperation};
+use prost::Message;
+
+use crate::{handlers::grpc::to_timestamp, models::OutboxEvent};
+
+/// Wire format of the changes published to message brokers
+#[derive(Debug, Clone, Copy, Default)]
+pub enum EventEncoding {
+    /// The outbox entry as a JSON object
+    #[default]
+    Json,
+    /// `notes.v1.NoteChange` message
+    Protobuf,
+}
+
+impl EventEncoding {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "json" => Some(Self::Json),
+            "protobuf" => Some(Self::Protobuf),
+            _ => None,
+        }
+    }
+
+    pub const fn content_type(self) -> &'static str {
+        match self {
+            Self::Json => "application/json",
+            Self::Protobuf => "application/x-protobuf",
+        }
+    }
+
+    pub fn encode(self, event: &OutboxEvent) -> Result<Vec<u8>, serde_json::Error> {
+        match self {
+            Self::Json => serde_json::to_vec(event),
+            Self::Protobuf => Ok(to_proto(event).encode_to_vec()),
+        }
+    }
+}
+
+fn to_proto(event: &OutboxEvent) -> NoteChange {
+    let operation = match event.operation.as_str() {
+        "created" => NoteOperation::Created,
+        "updated" => NoteOperation::Updated,
+        "deleted" => NoteOperation::Deleted,
+        _ => NoteOperation::Unspecified,
+    };
+
+    NoteChange {
+        sequence: event.id,
+        note_id: event.note_id,
+        operation: operation.into(),
+        tenant_id: event.tenant_id.clone(),
+        version: event.version,
+        occurred_at: Some(to_timestamp(event.occurred_at)),
+    }
+}
@@ -0,0 +1,79 @@
+use async_trait::async_trait;
+use rdkafka::{
+    ClientConfig,
+    error::KafkaError,
+    message::{Header, OwnedHeaders},
+    producer::{FutureProducer, FutureRecord},
+};
+
+use super::{EventSink, SinkError, encoding::EventEncoding};
+use crate::models::OutboxEvent;
+
+/// Publishes the changes to a Kafka topic, keyed by note id so the changes of a note
+/// stay in one partition and are consumed in order
+pub struct KafkaSink {
+    producer: FutureProducer,
+    topic: String,
+    encoding: EventEncoding,
+}
+
+impl KafkaSink {
+    /// Producer for the comma-separated `brokers`. Idempotence is on, so retried sends
+    /// don't duplicate or reorder the records within a partition
+    pub fn new(brokers: &str, topic: String, encoding: EventEncoding) -> Result<Self, KafkaError> {
+        let producer = ClientConfig::new()
+            .set("bootstrap.servers", brokers)
+            .set("enable.idempotence", "true")
+            .set("message.timeout.ms", "30000")
+            .create()?;
+
+        Ok(Self {
+            producer,
+            topic,
+            encoding,
+        })
+    }
+}
+
+#[async_trait]
+impl EventSink for KafkaSink {
+    fn name(&self) -> &'static str {
+        "kafka"
+    }
+
+    async fn publish(&self, events: &[OutboxEvent]) -> Result<(), SinkError> {
+        // Enqueue the whole batch first, then wait for every delivery
+        let mut deliveries = Vec::with_capacity(events.len());
+        for event in events {
+            let key = event.note_id.to_string();
+            let payload = self.encoding.encode(event)?;
+            let headers = OwnedHeaders::new()
+                .insert(Header {
+                    key: "tenant",
+                    value: Some(event.tenant_id.as_str()),
+                })
+                .insert(Header {
+                    key: "content-type",
+                    value: Some(self.encoding.content_type()),
+                });
+            let record = FutureRecord::to(&self.topic)
+                .key(&key)
+                .payload(&payload)
+                .headers(headers);
+
+            let delivery = self
+                .producer
+                .send_result(record)
+                .map_err(|(e, _)| Box::new(e) as SinkError)?;
+            deliveries.push(delivery);
+        }
+
+        for delivery in deliveries {
+            delivery
+                .await
+                .map_err(|_| "delivery was cancelled")?
+                .map_err(|(e, _)| Box::new(e) as SinkError)?;
+        }
+        Ok(())
+    }
+}
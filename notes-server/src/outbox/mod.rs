@@ -0,0 +1,123 @@
+pub mod encoding;
+pub mod kafka;
+pub mod nats;
+
+use async_trait::async_trait;
+
+use std::{sync::Arc, time::Duration};
+
+use crate::{
+    models::OutboxEvent,
+    repository::{Repository, RepositoryError},
+};
+
+/// Most changes passed on to the sinks at a time
+const RELAY_BATCH_SIZE: i64 = 500;
+
+/// Why a sink didn't take a batch of changes
+pub type SinkError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Errors of relaying a batch of changes, the batch is relayed again on the next run
+#[derive(Debug, thiserror::Error)]
+pub enum RelayError {
+    #[error("failed to access the outbox: {0}")]
+    Database(#[from] RepositoryError),
+
+    #[error("sink '{sink}' failed: {source}")]
+    Sink {
+        sink: &'static str,
+        source: SinkError,
+    },
+}
+
+/// Downstream system the committed note changes are passed on to
+#[async_trait]
+pub trait EventSink: Send + Sync {
+    /// Name of the sink in logs and in `OUTBOX_SINKS`
+    fn name(&self) -> &'static str;
+
+    /// Publishes the changes in order, failing if any of them wasn't taken
+    async fn publish(&self, events: &[OutboxEvent]) -> Result<(), SinkError>;
+}
+
+/// Writes the changes to the log, e.g. to see what the other sinks receive
+pub struct LogSink;
+
+#[async_trait]
+impl EventSink for LogSink {
+    fn name(&self) -> &'static str {
+        "log"
+    }
+
+    async fn publish(&self, events: &[OutboxEvent]) -> Result<(), SinkError> {
+        for event in events {
+            tracing::info!("Note change: {}", serde_json::to_string(event)?);
+        }
+        Ok(())
+    }
+}
+
+/// Passes the changes queued in the outbox on to the sinks. A batch is marked sent in
+/// the transaction that locked it, once every sink has taken it, so changes are
+/// delivered at least once: a batch that any sink fails to take is delivered to all of
+/// them again. Several instances can relay at the same time, each takes other changes.
+/// The changes are also queued for the webhooks subscribed to them, in the same
+/// transaction, and sent by the `WebhookDispatcher`
+pub struct OutboxRelay {
+    repo: Arc<Repository>,
+    sinks: Vec<Arc<dyn EventSink>>,
+}
+
+impl OutboxRelay {
+    /// Relay to `sinks`, with none the changes are only marked sent
+    pub fn new(repo: Arc<Repository>, sinks: Vec<Arc<dyn EventSink>>) -> Self {
+        Self { repo, sinks }
+    }
+
+    /// Relays the oldest pending changes, returning how many there were
+    async fn relay_batch(&self) -> Result<usize, RelayError> {
+        let sinks = self.sinks.clone();
+        self.repo
+            .transaction(|tx| {
+                Box::pin(async move {
+                    let events = tx.pending_outbox(RELAY_BATCH_SIZE).await?;
+                    if events.is_empty() {
+                        return Ok(0);
+                    }
+
+                    for sink in &sinks {
+                        sink.publish(&events)
+                            .await
+                            .map_err(|source| RelayError::Sink {
+                                sink: sink.name(),
+                                source,
+                            })?;
+                    }
+
+                    tx.enqueue_webhook_deliveries(&events).await?;
+                    let ids: Vec<i64> = events.iter().map(|event| event.id).collect();
+                    tx.mark_outbox_sent(&ids).await?;
+                    Ok(events.len())
+                })
+            })
+            .await
+    }
+
+    /// Relays all pending changes every `interval`, runs until the process exits
+    pub async fn run(self, interval: Duration) {
+        let mut interval = tokio::time::interval(interval);
+        loop {
+            interval.tick().await;
+            loop {
+                match self.relay_batch().await {
+                    Ok(0) => break,
+                    Ok(count) => tracing::debug!("Relayed {count} note changes"),
+                    Err(e) => {
+                        tracing::error!("Failed to relay note changes: {e}");
+                        break;
+                    }
+                }
+            }
+        }
+    }
+}
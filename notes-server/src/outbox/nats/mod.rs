@@ -0,0 +1,97 @@
+use async_nats::{
+    ConnectError, ConnectOptions, HeaderMap,
+    jetstream::{self, stream},
+};
+use async_trait::async_trait;
+use tokio::sync::OnceCell;
+
+use super::{EventSink, SinkError, encoding::EventEncoding};
+use crate::models::OutboxEvent;
+
+/// Publishes the changes to a `JetStream` stream, one subject per operation
+/// (`<prefix>.created`, `<prefix>.updated`, `<prefix>.deleted`) so consumers can
+/// subscribe to only the changes they need
+pub struct NatsSink {
+    jetstream: jetstream::Context,
+    stream: String,
+    subject_prefix: String,
+    encoding: EventEncoding,
+    /// Set once the stream is known to exist
+    stream_ready: OnceCell<()>,
+}
+
+impl NatsSink {
+    /// Client for the NATS server at `url`. The connection is made in the background,
+    /// so the server doesn't have to be up when the sink is created
+    pub async fn connect(
+        url: &str,
+        stream: String,
+        subject_prefix: String,
+        encoding: EventEncoding,
+    ) -> Result<Self, ConnectError> {
+        let client = ConnectOptions::new()
+            .retry_on_initial_connect()
+            .connect(url)
+            .await?;
+
+        Ok(Self {
+            jetstream: jetstream::new(client),
+            stream,
+            subject_prefix,
+            encoding,
+            stream_ready: OnceCell::new(),
+        })
+    }
+
+    /// Creates the stream capturing every subject of the sink unless it exists
+    async fn ensure_stream(&self) -> Result<(), SinkError> {
+        self.stream_ready
+            .get_or_try_init(|| async {
+                self.jetstream
+                    .get_or_create_stream(stream::Config {
+                        name: self.stream.clone(),
+                        subjects: vec![format!("{}.>", self.subject_prefix)],
+                        ..Default::default()
+                    })
+                    .await
+                    .map(|_| ())
+            })
+            .await?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl EventSink for NatsSink {
+    fn name(&self) -> &'static str {
+        "nats"
+    }
+
+    async fn publish(&self, events: &[OutboxEvent]) -> Result<(), SinkError> {
+        self.ensure_stream().await?;
+
+        // Publish the whole batch first, then wait for every acknowledgement
+        let mut acks = Vec::with_capacity(events.len());
+        for event in events {
+            let subject = format!("{}.{}", self.subject_prefix, event.operation);
+            let mut headers = HeaderMap::new();
+            // Lets JetStream drop the copies of a change relayed again after a failure
+            headers.insert("Nats-Msg-Id", event.id.to_string());
+            headers.insert("Note-Id", event.note_id.to_string());
+            headers.insert("Tenant", event.tenant_id.as_str());
+            headers.insert("Content-Type", self.encoding.content_type());
+
+            let payload = self.encoding.encode(event)?;
+            let ack = self
+                .jetstream
+                .publish_with_headers(subject, headers, payload.into())
+                .await?;
+            acks.push(ack);
+        }
+
+        for ack in acks {
+            ack.await?;
+        }
+        Ok(())
+    }
+}
@@ -1,2 +1,112 @@
 use refinery::embed_migrations;
 embed_migrations!("src/migrations");
+
+/// Scripts undoing the migration of the same version, kept outside `src/migrations`
+/// as refinery only runs migrations forward
+const DOWN_MIGRATIONS: &[(i64, &str)] = &[
+    (
+        1,
+        include_str!("../../migrations_down/V1__init_notes_table.sql"),
+    ),
+    (
+        2,
+        include_str!("../../migrations_down/V2__add_note_expiration.sql"),
+    ),
+    (
+        3,
+        include_str!("../../migrations_down/V3__add_note_reminders.sql"),
+    ),
+    (
+        4,
+        include_str!("../../migrations_down/V4__keep_explicit_note_timestamps.sql"),
+    ),
+    (
+        5,
+        include_str!("../../migrations_down/V5__allow_preserving_updated_at.sql"),
+    ),
+    (
+        6,
+        include_str!("../../migrations_down/V6__add_share_links.sql"),
+    ),
+    (
+        7,
+        include_str!("../../migrations_down/V7__add_note_metadata.sql"),
+    ),
+    (
+        8,
+        include_str!("../../migrations_down/V8__add_note_templates.sql"),
+    ),
+    (
+        9,
+        include_str!("../../migrations_down/V9__add_note_positions.sql"),
+    ),
+    (
+        10,
+        include_str!("../../migrations_down/V10__add_note_activity.sql"),
+    ),
+    (
+        11,
+        include_str!("../../migrations_down/V11__add_soap_audit.sql"),
+    ),
+    (
+        12,
+        include_str!("../../migrations_down/V12__add_note_search.sql"),
+    ),
+    (
+        13,
+        include_str!("../../migrations_down/V13__notify_note_changes.sql"),
+    ),
+    (
+        14,
+        include_str!("../../migrations_down/V14__add_notes_archive.sql"),
+    ),
+    (
+        15,
+        include_str!("../../migrations_down/V15__add_tenants.sql"),
+    ),
+    (
+        16,
+        include_str!("../../migrations_down/V16__add_note_versions.sql"),
+    ),
+    (
+        17,
+        include_str!("../../migrations_down/V17__add_activity_origin.sql"),
+    ),
+    (
+        18,
+        include_str!("../../migrations_down/V18__add_note_outbox.sql"),
+    ),
+    (
+        19,
+        include_str!("../../migrations_down/V19__add_webhooks.sql"),
+    ),
+    (
+        20,
+        include_str!("../../migrations_down/V20__add_note_tombstones.sql"),
+    ),
+    (
+        21,
+        include_str!("../../migrations_down/V21__add_note_attachments.sql"),
+    ),
+    (
+        22,
+        include_str!("../../migrations_down/V22__add_digest_subscriptions.sql"),
+    ),
+    (
+        23,
+        include_str!("../../migrations_down/V23__add_note_content_hashes.sql"),
+    ),
+    (24, include_str!("../../migrations_down/V24__add_users.sql")),
+    (
+        25,
+        include_str!("../../migrations_down/V25__add_reminder_leases.sql"),
+    ),
+];
+
+/// Script undoing the migration with this version
+pub fn down_migration(version: i64) -> Option<&'static str> {
+    DOWN_MIGRATIONS
+        .iter()
+        .find(|(v, _)| *v == version)
+        .map(|(_, script)| *script)
+}
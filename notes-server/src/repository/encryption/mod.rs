@@ -0,0 +1,80 @@
+use base64::{Engine, engine::general_purpose::STANDARD};
+use ring::{
+    aead::{AES_256_GCM, Aad, LessSafeKey, NONCE_LEN, Nonce, UnboundKey},
+    rand::{SecureRandom, SystemRandom},
+};
+
+/// Marks stored content as ciphertext, rows without it were written in plaintext
+pub const ENCRYPTED_PREFIX: &str = "enc:v1:";
+
+#[derive(Debug, thiserror::Error)]
+pub enum CipherError {
+    #[error("encryption key must be 32 bytes encoded in base64")]
+    InvalidKey,
+
+    #[error("stored content could not be decrypted")]
+    Decrypt,
+}
+
+/// AES-256-GCM encryption of note content. Stored as the prefix followed by
+/// base64 of the random nonce, the ciphertext and the tag
+pub struct ContentCipher {
+    key: LessSafeKey,
+    rng: SystemRandom,
+}
+
+impl ContentCipher {
+    pub fn from_base64_key(key: &str) -> Result<Self, CipherError> {
+        let key = STANDARD
+            .decode(key.trim())
+            .map_err(|_| CipherError::InvalidKey)?;
+        let key = UnboundKey::new(&AES_256_GCM, &key).map_err(|_| CipherError::InvalidKey)?;
+
+        Ok(Self {
+            key: LessSafeKey::new(key),
+            rng: SystemRandom::new(),
+        })
+    }
+
+    /// # Panics
+    /// If the system random number generator fails, as no content may be stored unencrypted
+    pub fn encrypt(&self, plaintext: &str) -> String {
+        let mut nonce = [0u8; NONCE_LEN];
+        self.rng
+            .fill(&mut nonce)
+            .expect("system random number generator failed");
+
+        let mut sealed = plaintext.as_bytes().to_vec();
+        self.key
+            .seal_in_place_append_tag(
+                Nonce::assume_unique_for_key(nonce),
+                Aad::empty(),
+                &mut sealed,
+            )
+            .expect("note content exceeds the AES-GCM message size limit");
+
+        let mut payload = nonce.to_vec();
+        payload.extend_from_slice(&sealed);
+        format!("{ENCRYPTED_PREFIX}{}", STANDARD.encode(payload))
+    }
+
+    /// Plaintext written before encryption was enabled is returned as is
+    pub fn decrypt(&self, stored: &str) -> Result<String, CipherError> {
+        let Some(encoded) = stored.strip_prefix(ENCRYPTED_PREFIX) else {
+            return Ok(stored.to_string());
+        };
+
+        let mut payload = STANDARD.decode(encoded).map_err(|_| CipherError::Decrypt)?;
+        if payload.len() < NONCE_LEN {
+            return Err(CipherError::Decrypt);
+        }
+        let mut sealed = payload.split_off(NONCE_LEN);
+        let nonce = Nonce::try_assume_unique_for_key(&payload).map_err(|_| CipherError::Decrypt)?;
+
+        let plaintext = self
+            .key
+            .open_in_place(nonce, Aad::empty(), &mut sealed)
+            .map_err(|_| CipherError::Decrypt)?;
+        String::from_utf8(plaintext.to_vec()).map_err(|_| CipherError::Decrypt)
+    }
+}
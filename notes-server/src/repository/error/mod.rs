@@ -0,0 +1,96 @@
+use deadpool_postgres::{BuildError, PoolError};
+use tokio_postgres::error::SqlState;
+
+/// Underlying error, kept for logs without exposing the driver types
+type Cause = Box<dyn std::error::Error + Send + Sync>;
+
+/// Why a repository call failed, classified so callers don't depend on the driver
+#[derive(Debug, thiserror::Error)]
+pub enum RepositoryError {
+    /// A record the write refers to doesn't exist
+    #[error("referenced record not found")]
+    NotFound,
+
+    #[error("duplicate value violates {constraint}")]
+    UniqueViolation { constraint: String },
+
+    /// The database can't be reached, calls fail fast until `run_reconnect` gets
+    /// a connection again
+    #[error("database connection lost: {0}")]
+    ConnectionLost(Cause),
+
+    /// No connection was freed in time, or the statement was cancelled or couldn't lock
+    #[error("database call timed out: {0}")]
+    Timeout(Cause),
+
+    #[error("invalid connection pool: {0}")]
+    Configuration(Cause),
+
+    #[error(transparent)]
+    Migration(Cause),
+
+    #[error("migration V{0} has no down script and can't be reverted")]
+    Irreversible(i64),
+
+    #[error(transparent)]
+    Other(Cause),
+}
+
+impl RepositoryError {
+    /// Whether the call may succeed when retried later
+    pub const fn is_transient(&self) -> bool {
+        matches!(self, Self::ConnectionLost(_) | Self::Timeout(_))
+    }
+}
+
+impl From<tokio_postgres::Error> for RepositoryError {
+    fn from(e: tokio_postgres::Error) -> Self {
+        let Some(code) = e.code() else {
+            return if e.is_closed() {
+                Self::ConnectionLost(e.into())
+            } else {
+                Self::Other(e.into())
+            };
+        };
+
+        if *code == SqlState::UNIQUE_VIOLATION {
+            let constraint = e
+                .as_db_error()
+                .and_then(|db_error| db_error.constraint())
+                .unwrap_or("a unique constraint")
+                .to_string();
+            Self::UniqueViolation { constraint }
+        } else if *code == SqlState::FOREIGN_KEY_VIOLATION {
+            Self::NotFound
+        } else if *code == SqlState::QUERY_CANCELED || *code == SqlState::LOCK_NOT_AVAILABLE {
+            Self::Timeout(e.into())
+        } else if code.code().starts_with("08") || code.code().starts_with("57P") {
+            // Connection exceptions and server shutdowns
+            Self::ConnectionLost(e.into())
+        } else {
+            Self::Other(e.into())
+        }
+    }
+}
+
+impl From<PoolError> for RepositoryError {
+    fn from(e: PoolError) -> Self {
+        match e {
+            PoolError::Backend(e) => Self::ConnectionLost(e.into()),
+            PoolError::Timeout(_) => Self::Timeout(e.into()),
+            e => Self::Other(e.into()),
+        }
+    }
+}
+
+impl From<BuildError> for RepositoryError {
+    fn from(e: BuildError) -> Self {
+        Self::Configuration(e.into())
+    }
+}
+
+impl From<refinery::Error> for RepositoryError {
+    fn from(e: refinery::Error) -> Self {
+        Self::Migration(e.into())
+    }
+}
@@ -1,30 +1,380 @@
 mod embedded;
+mod encryption;
+mod error;
+#[cfg(test)]
+mod tests;
+mod transaction;
 
-use embedded::migrations;
+pub use encryption::ContentCipher;
+pub use error::RepositoryError;
+pub use transaction::Transaction;
 
-use tokio_postgres::{Client, NoTls};
+use embedded::{down_migration, migrations};
+use encryption::ENCRYPTED_PREFIX;
 
-use crate::models::Note;
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
+use chrono::{DateTime, Utc};
+use deadpool_postgres::{
+    GenericClient, Manager, ManagerConfig, Object, Pool, PoolError, RecyclingMethod,
+};
+use futures_util::{Stream, StreamExt, future, future::BoxFuture, stream};
+use rand::{Rng, rng};
+use ring::digest::{SHA256, digest};
+use tokio::sync::{Notify, mpsc};
+use tokio_postgres::{AsyncMessage, Config, NoTls, Row, types::ToSql};
+
+use std::{
+    borrow::Cow,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::Duration,
+};
+
+use crate::{
+    models::{
+        Activity, Attachment, AttachmentDraft, DeliveryFailure, DigestDraft, DigestSubscription,
+        DueDelivery, DueDigest, DuplicateCluster, Metadata, MetadataFilter, Migration, NewNote,
+        NewSoapAuditEntry, NewUser, Note, NoteDraft, NoteOrder, NoteTemplate, ShareLink,
+        SoapAuditEntry, User, Webhook, WebhookDelivery, WebhookDraft,
+    },
+    tenant::Tenant,
+};
+
+/// Columns selected for every note query, read by `note_from_row`. `content_hash` isn't
+/// read, it's selected so notes moved between tables keep it
+const NOTE_COLUMNS: &str = "id, content, created_at, updated_at, expires_at, remind_at, \
+                            metadata, position, version, content_hash";
+
+/// Filters out notes whose expiration time has passed
+const NOT_EXPIRED: &str = "(expires_at IS NULL OR expires_at > NOW())";
+
+/// Notes table to list from, optionally together with the archived notes
+fn notes_source(include_archived: bool) -> String {
+    if include_archived {
+        format!(
+            "(SELECT {NOTE_COLUMNS}, tenant_id FROM notes \
+             UNION ALL SELECT {NOTE_COLUMNS}, tenant_id FROM notes_archive) notes"
+        )
+    } else {
+        "notes".to_string()
+    }
+}
+
+/// Condition applying a `MetadataFilter`, whose object to contain and required keys
+/// are bound to the `contains` and `has_keys` placeholders
+fn metadata_matches(contains: usize, has_keys: usize) -> String {
+    format!(
+        "(${contains}::jsonb IS NULL OR metadata @> ${contains}) \
+         AND (${has_keys}::text[] IS NULL OR metadata ?& ${has_keys})"
+    )
+}
+
+/// Channel the `note_activity` trigger sends every recorded change to
+const CHANGES_CHANNEL: &str = "note_changes";
+
+/// Delay before the first reconnection attempt, doubled after every failed one
+const RECONNECT_MIN_BACKOFF: Duration = Duration::from_millis(500);
+/// Longest delay between reconnection attempts
+const RECONNECT_MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+/// Number of plaintext notes encrypted per statement when encryption is enabled
+const ENCRYPT_BATCH_SIZE: i64 = 500;
+
+/// Number of notes without a content hash hashed per statement on startup
+const HASH_BATCH_SIZE: i64 = 500;
+
+/// Hash of the content with case ignored and every run of whitespace taken as a single
+/// space, so notes differing only in those are identical. It's computed from the
+/// plaintext, the same whether the content is encrypted or not
+fn content_hash(content: &str) -> String {
+    let normalized = content
+        .to_lowercase()
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ");
+    URL_SAFE_NO_PAD.encode(digest(&SHA256, normalized.as_bytes()))
+}
+
+/// Columns selected for every template query, read by `template_from_row`
+const TEMPLATE_COLUMNS: &str = "id, name, content, created_at, updated_at";
+
+fn template_from_row(row: &Row) -> NoteTemplate {
+    NoteTemplate {
+        id: row.get("id"),
+        name: row.get("name"),
+        content: row.get("content"),
+        created_at: row.get("created_at"),
+        updated_at: row.get("updated_at"),
+    }
+}
+
+/// Columns selected for every webhook query, read by `webhook_from_row`
+/// The signing secret is only read to send requests
+const WEBHOOK_COLUMNS: &str =
+    "id, url, events, active, consecutive_failures, circuit_open_until, created_at, updated_at";
+
+fn webhook_from_row(row: &Row) -> Webhook {
+    Webhook {
+        id: row.get("id"),
+        url: row.get("url"),
+        events: row.get("events"),
+        active: row.get("active"),
+        consecutive_failures: row.get("consecutive_failures"),
+        circuit_open_until: row.get("circuit_open_until"),
+        created_at: row.get("created_at"),
+        updated_at: row.get("updated_at"),
+    }
+}
+
+const ATTACHMENT_COLUMNS: &str =
+    "id, note_id, filename, content_type, size, storage_key, created_at";
+
+fn attachment_from_row(row: &Row) -> Attachment {
+    Attachment {
+        id: row.get("id"),
+        note_id: row.get("note_id"),
+        filename: row.get("filename"),
+        content_type: row.get("content_type"),
+        size: row.get("size"),
+        storage_key: row.get("storage_key"),
+        created_at: row.get("created_at"),
+    }
+}
+
+const DIGEST_COLUMNS: &str = "id, email, schedule, active, last_run_at, next_run_at, \
+                              last_error, created_at, updated_at";
+
+fn digest_from_row(row: &Row) -> DigestSubscription {
+    DigestSubscription {
+        id: row.get("id"),
+        email: row.get("email"),
+        schedule: row.get("schedule"),
+        active: row.get("active"),
+        last_run_at: row.get("last_run_at"),
+        next_run_at: row.get("next_run_at"),
+        last_error: row.get("last_error"),
+        created_at: row.get("created_at"),
+        updated_at: row.get("updated_at"),
+    }
+}
+
+const USER_COLUMNS: &str = "id, issuer, subject, tenant_id, email, name, created_at, last_seen_at";
+
+fn user_from_row(row: &Row) -> User {
+    User {
+        id: row.get("id"),
+        issuer: row.get("issuer"),
+        subject: row.get("subject"),
+        tenant_id: row.get("tenant_id"),
+        email: row.get("email"),
+        name: row.get("name"),
+        created_at: row.get("created_at"),
+        last_seen_at: row.get("last_seen_at"),
+    }
+}
+
+fn webhook_delivery_from_row(row: &Row) -> WebhookDelivery {
+    WebhookDelivery {
+        id: row.get("id"),
+        event_id: row.get("event_id"),
+        operation: row.get("operation"),
+        payload: row.get("payload"),
+        status: row.get("status"),
+        attempts: row.get("attempts"),
+        next_attempt_at: row.get("next_attempt_at"),
+        last_status_code: row.get("last_status_code"),
+        last_error: row.get("last_error"),
+        created_at: row.get("created_at"),
+        completed_at: row.get("completed_at"),
+    }
+}
+
+fn tenant_from_row(row: &Row) -> Tenant {
+    Tenant::from(row.get::<_, &str>("tenant_id"))
+}
+
+fn migration_from_refinery(migration: &refinery::Migration) -> Migration {
+    Migration {
+        version: i64::from(migration.version()),
+        name: migration.name().to_string(),
+        applied_at: migration
+            .applied_on()
+            .and_then(|time| DateTime::from_timestamp(time.unix_timestamp(), time.nanosecond())),
+    }
+}
+
+/// Outcome of a write guarded by a `version` precondition
+pub enum ConditionalWrite<T> {
+    /// The precondition held (or none was given) and the write went through
+    Applied(T),
+    /// No note with the given ID exists
+    NotFound,
+    /// The note exists but was modified since the expected version, e.g. by a concurrent editor
+    PreconditionFailed,
+}
 
 pub struct Repository {
-    client: Client,
+    pool: Pool,
+    /// Connection settings of the pool, reused for the change feed connection
+    config: Config,
+    /// Encrypts note content before it is stored, when configured
+    cipher: Option<ContentCipher>,
+    /// Migrations that were pending when this process started and were applied by it
+    startup_migrations: Vec<Migration>,
+    /// Set when connecting fails, until `run_reconnect` gets a connection again
+    degraded: AtomicBool,
+    /// Wakes `run_reconnect` when the connection is lost
+    connection_lost: Notify,
 }
 
 impl Repository {
-    pub async fn new(database_dsn: String) -> Result<Self, tokio_postgres::Error> {
-        let (client, con) = tokio_postgres::connect(&database_dsn, NoTls).await?;
+    /// Connects to the database with a pool of up to `pool_size` connections, deadpool's
+    /// default when `None`. One connection is opened upfront, so an unreachable database
+    /// fails here rather than on the first request
+    pub async fn new(
+        config: Config,
+        pool_size: Option<usize>,
+        cipher: Option<ContentCipher>,
+    ) -> Result<Self, RepositoryError> {
+        let manager = Manager::from_config(
+            config.clone(),
+            NoTls,
+            ManagerConfig {
+                recycling_method: RecyclingMethod::Fast,
+            },
+        );
+        let mut builder = Pool::builder(manager);
+        if let Some(size) = pool_size {
+            builder = builder.max_size(size);
+        }
+        let pool = builder.build()?;
 
-        tokio::spawn(async move {
-            if let Err(e) = con.await {
-                tracing::error!("connection error: {}", e);
+        let repo = Self {
+            pool,
+            config,
+            cipher,
+            startup_migrations: Vec::new(),
+            degraded: AtomicBool::new(false),
+            connection_lost: Notify::new(),
+        };
+        drop(repo.client().await?);
+        Ok(repo)
+    }
+
+    /// A connection from the pool, waiting for one to be returned when all are in use.
+    /// Fails right away while the database is unreachable, only `run_reconnect` tries
+    /// to connect then, so requests don't pile up connection attempts
+    async fn client(&self) -> Result<Object, RepositoryError> {
+        if self.degraded.load(Ordering::Relaxed) {
+            return Err(RepositoryError::ConnectionLost(
+                "reconnecting in the background".into(),
+            ));
+        }
+
+        self.pool.get().await.map_err(|e| {
+            if matches!(e, PoolError::Backend(_)) && !self.degraded.swap(true, Ordering::Relaxed) {
+                tracing::error!("Lost database connection: {e}");
+                self.connection_lost.notify_one();
             }
-        });
+            e.into()
+        })
+    }
+
+    /// Runs a trivial query to check that the database answers
+    pub async fn ping(&self) -> Result<(), RepositoryError> {
+        self.client().await?.execute("SELECT 1", &[]).await?;
+        Ok(())
+    }
+
+    /// Reconnects whenever the connection is lost, retrying with exponential backoff
+    /// and jitter so replicas don't hit a recovering database at once. Runs until the
+    /// process exits
+    pub async fn run_reconnect(self: Arc<Self>) {
+        loop {
+            self.connection_lost.notified().await;
+
+            let mut backoff = RECONNECT_MIN_BACKOFF;
+            loop {
+                let delay = rng().random_range(backoff / 2..=backoff);
+                tokio::time::sleep(delay).await;
+                match self.pool.get().await {
+                    Ok(_) => break,
+                    Err(e) => {
+                        tracing::warn!("Failed to reconnect to the database: {e}");
+                        backoff = (backoff * 2).min(RECONNECT_MAX_BACKOFF);
+                    }
+                }
+            }
+
+            self.degraded.store(false, Ordering::Relaxed);
+            tracing::info!("Database connection restored");
+        }
+    }
+
+    /// Like `Client::query`, for the hot CRUD statements. Each pooled connection prepares
+    /// the statement text once and reuses it on later calls, so Postgres doesn't parse and
+    /// plan it again and the prepare round trip is saved. The other `*_cached` methods
+    /// do the same. The text must not embed values, every distinct text is cached
+    async fn query_cached(
+        &self,
+        sql: &str,
+        params: &[&(dyn ToSql + Sync)],
+    ) -> Result<Vec<Row>, RepositoryError> {
+        let client = self.client().await?;
+        let statement = client.prepare_cached(sql).await?;
+        Ok(client.query(&statement, params).await?)
+    }
+
+    async fn query_one_cached(
+        &self,
+        sql: &str,
+        params: &[&(dyn ToSql + Sync)],
+    ) -> Result<Row, RepositoryError> {
+        let client = self.client().await?;
+        let statement = client.prepare_cached(sql).await?;
+        Ok(client.query_one(&statement, params).await?)
+    }
+
+    async fn query_opt_cached(
+        &self,
+        sql: &str,
+        params: &[&(dyn ToSql + Sync)],
+    ) -> Result<Option<Row>, RepositoryError> {
+        let client = self.client().await?;
+        let statement = client.prepare_cached(sql).await?;
+        Ok(client.query_opt(&statement, params).await?)
+    }
 
-        Ok(Self { client })
+    async fn execute_cached(
+        &self,
+        sql: &str,
+        params: &[&(dyn ToSql + Sync)],
+    ) -> Result<u64, RepositoryError> {
+        let client = self.client().await?;
+        let statement = client.prepare_cached(sql).await?;
+        Ok(client.execute(&statement, params).await?)
+    }
+
+    /// Runs `f` in a transaction, committed if it succeeds and rolled back if it fails
+    /// or is dropped before completion, so multi-step writes are never left half done.
+    /// The closure returns a boxed future, e.g. `|tx| Box::pin(async move { ... })`
+    pub async fn transaction<T, E, F>(&self, f: F) -> Result<T, E>
+    where
+        F: for<'t> FnOnce(&'t Transaction<'t>) -> BoxFuture<'t, Result<T, E>> + Send,
+        E: From<RepositoryError>,
+    {
+        let mut client = self.client().await?;
+        let transaction = Transaction::begin(self, &mut client).await?;
+        let output = f(&transaction).await?;
+        transaction.commit().await?;
+        Ok(output)
     }
 
-    pub async fn migrate(&mut self) -> Result<(), refinery::Error> {
-        let migrations_report = migrations::runner().run_async(&mut self.client).await?;
+    pub async fn migrate(&mut self) -> Result<(), RepositoryError> {
+        let mut client = self.client().await?;
+        let migrations_report = migrations::runner().run_async(&mut **client).await?;
 
         for migration in migrations_report.applied_migrations() {
             tracing::info!(
@@ -33,87 +383,1883 @@ impl Repository {
                 migration.version()
             );
         }
+        self.startup_migrations = migrations_report
+            .applied_migrations()
+            .iter()
+            .map(migration_from_refinery)
+            .collect();
 
         tracing::info!("DB migrations finished!");
 
         Ok(())
     }
 
-    pub async fn create_note(&self, content: String) -> Result<Note, tokio_postgres::Error> {
-        let row = self.client.query_one(
-            "INSERT INTO notes (content) VALUES ($1) RETURNING id, content, created_at, updated_at",
-            &[&content],
-        ).await?;
+    /// Migrations recorded in the database, and embedded ones that are not
+    pub async fn migration_status(
+        &self,
+    ) -> Result<(Vec<Migration>, Vec<Migration>), RepositoryError> {
+        let runner = migrations::runner();
+        let mut client = self.client().await?;
+        let applied = runner.get_applied_migrations_async(&mut **client).await?;
+
+        let pending = runner
+            .get_migrations()
+            .iter()
+            .filter(|m| !applied.iter().any(|a| a.version() == m.version()))
+            .map(migration_from_refinery)
+            .collect();
+
+        Ok((
+            applied.iter().map(migration_from_refinery).collect(),
+            pending,
+        ))
+    }
+
+    /// Reverts the `count` most recently applied migrations with their down scripts,
+    /// newest first, and returns them. All of them are reverted in one transaction,
+    /// so if any script fails the schema stays as it was
+    pub async fn revert_migrations(&self, count: usize) -> Result<Vec<Migration>, RepositoryError> {
+        let (mut applied, _) = self.migration_status().await?;
+        applied.sort_by_key(|migration| std::cmp::Reverse(migration.version));
+        applied.truncate(count);
+
+        let mut client = self.client().await?;
+        let transaction = client.transaction().await?;
+        for migration in &applied {
+            let script = down_migration(migration.version)
+                .ok_or(RepositoryError::Irreversible(migration.version))?;
+            transaction.batch_execute(script).await?;
+            transaction
+                .execute(
+                    "DELETE FROM refinery_schema_history WHERE version = $1::bigint",
+                    &[&migration.version],
+                )
+                .await?;
+        }
+        transaction.commit().await?;
+
+        Ok(applied)
+    }
+
+    pub fn startup_migrations(&self) -> &[Migration] {
+        &self.startup_migrations
+    }
+
+    /// Encrypts notes stored in plaintext, e.g. before encryption was enabled, returning
+    /// how many were encrypted. Notes keep their `updated_at` and `version`, as they are not changed
+    pub async fn encrypt_plaintext_notes(&self) -> Result<u64, RepositoryError> {
+        let Some(cipher) = &self.cipher else {
+            return Ok(0);
+        };
+
+        let mut client = self.client().await?;
+        let mut encrypted = 0;
+        loop {
+            let transaction = client.transaction().await?;
+            transaction
+                .execute("SET LOCAL notes.preserve_updated_at = 'on'", &[])
+                .await?;
+
+            let rows = transaction
+                .query(
+                    "SELECT id, content FROM notes \
+                     WHERE content IS NOT NULL AND content NOT LIKE $1 \
+                     ORDER BY id LIMIT $2",
+                    &[&format!("{ENCRYPTED_PREFIX}%"), &ENCRYPT_BATCH_SIZE],
+                )
+                .await?;
+            if rows.is_empty() {
+                return Ok(encrypted);
+            }
+
+            let ids: Vec<i64> = rows.iter().map(|row| row.get("id")).collect();
+            let contents: Vec<String> = rows
+                .iter()
+                .map(|row| cipher.encrypt(row.get("content")))
+                .collect();
+            encrypted += transaction
+                .execute(
+                    "UPDATE notes SET content = batch.content \
+                     FROM UNNEST($1::bigint[], $2::text[]) AS batch(id, content) \
+                     WHERE notes.id = batch.id",
+                    &[&ids, &contents],
+                )
+                .await?;
+            transaction.commit().await?;
+        }
+    }
+
+    /// Hashes the content of notes written before content hashes were stored, returning
+    /// how many were hashed. Notes keep their `updated_at` and `version`, as they are not changed
+    pub async fn hash_unhashed_notes(&self) -> Result<u64, RepositoryError> {
+        let mut client = self.client().await?;
+        let mut updated = 0;
+        // Notes are walked by ID, so every batch starts where the previous one ended
+        let mut after = 0_i64;
+        loop {
+            let transaction = client.transaction().await?;
+            transaction
+                .execute("SET LOCAL notes.preserve_updated_at = 'on'", &[])
+                .await?;
+
+            let rows = transaction
+                .query(
+                    &format!(
+                        "SELECT {NOTE_COLUMNS} FROM notes \
+                         WHERE id > $1 AND content_hash IS NULL AND content IS NOT NULL \
+                         ORDER BY id LIMIT $2"
+                    ),
+                    &[&after, &HASH_BATCH_SIZE],
+                )
+                .await?;
+            if rows.is_empty() {
+                return Ok(updated);
+            }
+
+            let ids: Vec<i64> = rows.iter().map(|row| row.get("id")).collect();
+            after = ids[ids.len() - 1];
+            let hashes: Vec<String> = rows
+                .iter()
+                .map(|row| content_hash(&self.note_from_row(row).content))
+                .collect();
+            updated += transaction
+                .execute(
+                    "UPDATE notes SET content_hash = batch.content_hash \
+                     FROM UNNEST($1::bigint[], $2::text[]) AS batch(id, content_hash) \
+                     WHERE notes.id = batch.id",
+                    &[&ids, &hashes],
+                )
+                .await?;
+            transaction.commit().await?;
+        }
+    }
+
+    /// Content as it is written to the database
+    fn seal<'a>(&self, content: &'a str) -> Cow<'a, str> {
+        self.cipher
+            .as_ref()
+            .map_or(Cow::Borrowed(content), |cipher| {
+                Cow::Owned(cipher.encrypt(content))
+            })
+    }
+
+    fn note_from_row(&self, row: &Row) -> Note {
+        let stored: String = row.get("content");
+        let content = match &self.cipher {
+            Some(cipher) => cipher.decrypt(&stored).unwrap_or_else(|e| {
+                // The stored value is passed on rather than failing every request touching
+                // the note, it can't be mistaken for the content as it keeps the prefix
+                tracing::error!("Failed to decrypt note {}: {e}", row.get::<_, i64>("id"));
+                stored
+            }),
+            None => stored,
+        };
 
-        Ok(Note {
+        Note {
             id: row.get("id"),
-            content: row.get("content"),
+            content,
             created_at: row.get("created_at"),
             updated_at: row.get("updated_at"),
-        })
+            expires_at: row.get("expires_at"),
+            remind_at: row.get("remind_at"),
+            metadata: match row.get("metadata") {
+                serde_json::Value::Object(metadata) => metadata,
+                _ => Metadata::new(),
+            },
+            position: row.get("position"),
+            version: row.get("version"),
+        }
+    }
+
+    pub async fn create_note(
+        &self,
+        content: String,
+        expires_at: Option<DateTime<Utc>>,
+        remind_at: Option<DateTime<Utc>>,
+    ) -> Result<Note, RepositoryError> {
+        let row = self
+            .query_one_cached(
+                &format!(
+                    "INSERT INTO notes (content, expires_at, remind_at, tenant_id, content_hash) \
+                     VALUES ($1, $2, $3, $4, $5) RETURNING {NOTE_COLUMNS}"
+                ),
+                &[
+                    &self.seal(&content),
+                    &expires_at,
+                    &remind_at,
+                    &Tenant::current().as_str(),
+                    &content_hash(&content),
+                ],
+            )
+            .await?;
+
+        Ok(self.note_from_row(&row))
+    }
+
+    /// Inserts all notes with a single statement, returning the number of rows written
+    pub async fn create_notes(&self, notes: &[NewNote]) -> Result<u64, RepositoryError> {
+        let contents: Vec<Cow<str>> = notes.iter().map(|n| self.seal(&n.content)).collect();
+        let hashes: Vec<String> = notes.iter().map(|n| content_hash(&n.content)).collect();
+        let created_at: Vec<DateTime<Utc>> = notes.iter().map(|n| n.created_at).collect();
+        let updated_at: Vec<DateTime<Utc>> = notes.iter().map(|n| n.updated_at).collect();
+
+        Ok(self
+            .client()
+            .await?
+            .execute(
+                "INSERT INTO notes (content, created_at, updated_at, content_hash, tenant_id) \
+                 SELECT *, $5 FROM UNNEST(\
+                     $1::text[], $2::timestamptz[], $3::timestamptz[], $4::text[]\
+                 )",
+                &[
+                    &contents,
+                    &created_at,
+                    &updated_at,
+                    &hashes,
+                    &Tenant::current().as_str(),
+                ],
+            )
+            .await?)
+    }
+
+    /// Inserts all notes with a single statement, returning them in the order given.
+    /// For imports that only need the IDs, `Transaction::copy_notes` is much faster
+    pub async fn batch_create_notes(
+        &self,
+        notes: &[NoteDraft],
+    ) -> Result<Vec<Note>, RepositoryError> {
+        let client = self.client().await?;
+        let contents: Vec<Cow<str>> = notes.iter().map(|n| self.seal(&n.content)).collect();
+        let hashes: Vec<String> = notes.iter().map(|n| content_hash(&n.content)).collect();
+        let expires_at: Vec<Option<DateTime<Utc>>> = notes.iter().map(|n| n.expires_at).collect();
+        let remind_at: Vec<Option<DateTime<Utc>>> = notes.iter().map(|n| n.remind_at).collect();
+
+        let statement = client
+            .prepare_cached(&format!(
+                "INSERT INTO notes (content, expires_at, remind_at, content_hash, tenant_id) \
+                 SELECT content, expires_at, remind_at, content_hash, $5 \
+                 FROM UNNEST($1::text[], $2::timestamptz[], $3::timestamptz[], $4::text[]) \
+                 WITH ORDINALITY AS batch(content, expires_at, remind_at, content_hash, n) \
+                 ORDER BY n \
+                 RETURNING {NOTE_COLUMNS}"
+            ))
+            .await?;
+        let rows = client
+            .query(
+                &statement,
+                &[
+                    &contents,
+                    &expires_at,
+                    &remind_at,
+                    &hashes,
+                    &Tenant::current().as_str(),
+                ],
+            )
+            .await?;
+
+        // IDs are assigned in the order of the batch, RETURNING doesn't guarantee any order
+        let mut notes: Vec<Note> = rows.iter().map(|row| self.note_from_row(row)).collect();
+        notes.sort_by_key(|note| note.id);
+        Ok(notes)
+    }
+
+    /// ID of the tenant's oldest note with the same content, ignoring case and whitespace
+    pub async fn find_identical_note(&self, content: &str) -> Result<Option<i64>, RepositoryError> {
+        let row = self
+            .query_opt_cached(
+                &format!(
+                    "SELECT id FROM notes \
+                     WHERE tenant_id = $1 AND content_hash = $2 AND {NOT_EXPIRED} \
+                     ORDER BY id LIMIT 1"
+                ),
+                &[&Tenant::current().as_str(), &content_hash(content)],
+            )
+            .await?;
+
+        Ok(row.map(|row| row.get("id")))
+    }
+
+    /// Up to `limit` groups of the tenant's notes with the same content, largest first
+    pub async fn duplicate_clusters(
+        &self,
+        limit: i64,
+    ) -> Result<Vec<DuplicateCluster>, RepositoryError> {
+        let rows = self
+            .query_cached(
+                &format!(
+                    "SELECT content_hash, array_agg(id ORDER BY id) AS note_ids FROM notes \
+                     WHERE tenant_id = $1 AND content_hash IS NOT NULL AND {NOT_EXPIRED} \
+                     GROUP BY content_hash HAVING count(*) > 1 \
+                     ORDER BY count(*) DESC, min(id) LIMIT $2"
+                ),
+                &[&Tenant::current().as_str(), &limit],
+            )
+            .await?;
+
+        Ok(rows
+            .iter()
+            .map(|row| DuplicateCluster {
+                content_hash: row.get("content_hash"),
+                note_ids: row.get("note_ids"),
+            })
+            .collect())
+    }
+
+    /// Copies the note's content and expiration time into a new note. The reminder is not
+    /// copied, so it isn't sent twice. Returns `None` if there is no such note
+    pub async fn duplicate_note(&self, id: i64) -> Result<Option<Note>, RepositoryError> {
+        let row = self
+            .query_opt_cached(
+                &format!(
+                    "INSERT INTO notes (content, expires_at, tenant_id, content_hash) \
+                     SELECT content, expires_at, tenant_id, content_hash FROM notes \
+                     WHERE id = $1 AND tenant_id = $2 AND {NOT_EXPIRED} \
+                     RETURNING {NOTE_COLUMNS}"
+                ),
+                &[&id, &Tenant::current().as_str()],
+            )
+            .await?;
+
+        Ok(row.as_ref().map(|row| self.note_from_row(row)))
     }
 
-    pub async fn update_note(
+    /// Updates the note content, and the expiration and reminder times if given:
+    /// `Some(None)` removes them, `None` keeps the current ones. When `expected_versions`
+    /// is given, the update only applies if the current `version` equals one of them.
+    pub async fn update_note_if_version(
         &self,
         id: i64,
         content: String,
-    ) -> Result<Option<Note>, tokio_postgres::Error> {
-        let row = self.client.query_opt(
-            "UPDATE notes SET content = $1 WHERE id = $2 RETURNING id, content, created_at, updated_at",
-            &[&content, &id],
-        ).await?;
+        expires_at: Option<Option<DateTime<Utc>>>,
+        remind_at: Option<Option<DateTime<Utc>>>,
+        expected_versions: Option<&[i64]>,
+    ) -> Result<ConditionalWrite<Note>, RepositoryError> {
+        let row = self
+            .query_opt_cached(
+                &format!(
+                    "UPDATE notes SET content = $1, \
+                     expires_at = CASE WHEN $8 THEN $2 ELSE expires_at END, \
+                     remind_at = CASE WHEN $9 THEN $3 ELSE remind_at END, content_hash = $7 \
+                     WHERE id = $4 AND tenant_id = $6 AND {NOT_EXPIRED} \
+                     AND ($5::bigint[] IS NULL OR version = ANY($5)) \
+                     RETURNING {NOTE_COLUMNS}"
+                ),
+                &[
+                    &self.seal(&content),
+                    &expires_at.flatten(),
+                    &remind_at.flatten(),
+                    &id,
+                    &expected_versions,
+                    &Tenant::current().as_str(),
+                    &content_hash(&content),
+                    &expires_at.is_some(),
+                    &remind_at.is_some(),
+                ],
+            )
+            .await?;
 
-        Ok(row.map(|row| Note {
-            id: row.get("id"),
-            content: row.get("content"),
-            created_at: row.get("created_at"),
-            updated_at: row.get("updated_at"),
-        }))
+        match row {
+            Some(row) => Ok(ConditionalWrite::Applied(self.note_from_row(&row))),
+            None => self.missing_or_modified(id).await,
+        }
+    }
+
+    /// Sets the `set` metadata fields and removes the `remove` ones. When
+    /// `expected_versions` is given, the change only applies if the current
+    /// `version` equals one of them.
+    pub async fn patch_metadata(
+        &self,
+        id: i64,
+        set: Metadata,
+        remove: &[String],
+        expected_versions: Option<&[i64]>,
+    ) -> Result<ConditionalWrite<Note>, RepositoryError> {
+        let row = self
+            .query_opt_cached(
+                &format!(
+                    "UPDATE notes SET metadata = (metadata || $1::jsonb) - $2::text[] \
+                     WHERE id = $3 AND tenant_id = $5 AND {NOT_EXPIRED} \
+                     AND ($4::bigint[] IS NULL OR version = ANY($4)) \
+                     RETURNING {NOTE_COLUMNS}"
+                ),
+                &[
+                    &serde_json::Value::Object(set),
+                    &remove,
+                    &id,
+                    &expected_versions,
+                    &Tenant::current().as_str(),
+                ],
+            )
+            .await?;
+
+        match row {
+            Some(row) => Ok(ConditionalWrite::Applied(self.note_from_row(&row))),
+            None => self.missing_or_modified(id).await,
+        }
     }
 
-    pub async fn delete_note(&self, id: i64) -> Result<bool, tokio_postgres::Error> {
+    /// Deletes the note, keeping a tombstone so the delete can be undone. When
+    /// `expected_versions` is given, the delete only applies if the current
+    /// `version` equals one of them.
+    pub async fn delete_note(
+        &self,
+        id: i64,
+        expected_versions: Option<&[i64]>,
+    ) -> Result<ConditionalWrite<()>, RepositoryError> {
         let rows = self
-            .client
-            .execute("DELETE FROM notes WHERE id = $1", &[&id])
+            .execute_cached(
+                &format!(
+                    "WITH deleted AS (\
+                         DELETE FROM notes WHERE id = $1 AND tenant_id = $3 AND {NOT_EXPIRED} \
+                         AND ($2::bigint[] IS NULL OR version = ANY($2)) \
+                         RETURNING {NOTE_COLUMNS}, tenant_id\
+                     ) \
+                     INSERT INTO note_tombstones ({NOTE_COLUMNS}, tenant_id) \
+                     SELECT {NOTE_COLUMNS}, tenant_id FROM deleted"
+                ),
+                &[&id, &expected_versions, &Tenant::current().as_str()],
+            )
             .await?;
 
-        Ok(rows == 1)
+        if rows == 1 {
+            return Ok(ConditionalWrite::Applied(()));
+        }
+
+        self.missing_or_modified(id).await
+    }
+
+    /// Deletes the notes with a single statement, returning the IDs of the ones that existed.
+    /// Their tombstones share the delete time, so they are restored together
+    pub async fn batch_delete_notes(&self, ids: &[i64]) -> Result<Vec<i64>, RepositoryError> {
+        let rows = self
+            .query_cached(
+                &format!(
+                    "WITH deleted AS (\
+                         DELETE FROM notes WHERE id = ANY($1) AND tenant_id = $2 AND {NOT_EXPIRED} \
+                         RETURNING {NOTE_COLUMNS}, tenant_id\
+                     ) \
+                     INSERT INTO note_tombstones ({NOTE_COLUMNS}, tenant_id) \
+                     SELECT {NOTE_COLUMNS}, tenant_id FROM deleted RETURNING id"
+                ),
+                &[&ids, &Tenant::current().as_str()],
+            )
+            .await?;
+
+        Ok(rows.iter().map(|row| row.get("id")).collect())
+    }
+
+    /// Moves the notes of the tenant's most recent delete made after `since` back from
+    /// their tombstones, share links are not restored
+    pub async fn restore_last_deleted(
+        &self,
+        since: DateTime<Utc>,
+    ) -> Result<Vec<Note>, RepositoryError> {
+        let rows = self
+            .query_cached(
+                &format!(
+                    "WITH restored AS (\
+                         DELETE FROM note_tombstones WHERE tenant_id = $1 AND deleted_at = (\
+                             SELECT max(deleted_at) FROM note_tombstones \
+                             WHERE tenant_id = $1 AND deleted_at > $2\
+                         ) RETURNING {NOTE_COLUMNS}, tenant_id\
+                     ) \
+                     INSERT INTO notes ({NOTE_COLUMNS}, tenant_id) \
+                     SELECT {NOTE_COLUMNS}, tenant_id FROM restored ORDER BY id \
+                     RETURNING {NOTE_COLUMNS}"
+                ),
+                &[&Tenant::current().as_str(), &since],
+            )
+            .await?;
+
+        Ok(rows.iter().map(|row| self.note_from_row(row)).collect())
+    }
+
+    /// Removes the tombstones of notes of any tenant deleted before `before`
+    pub async fn delete_note_tombstones_before(
+        &self,
+        before: DateTime<Utc>,
+    ) -> Result<u64, RepositoryError> {
+        self.execute_cached(
+            "DELETE FROM note_tombstones WHERE deleted_at < $1",
+            &[&before],
+        )
+        .await
     }
 
-    pub async fn get_one_note(&self, id: i64) -> Result<Option<Note>, tokio_postgres::Error> {
+    async fn missing_or_modified<T>(
+        &self,
+        id: i64,
+    ) -> Result<ConditionalWrite<T>, RepositoryError> {
         let row = self
-            .client
-            .query_opt(
-                "SELECT id, content, created_at, updated_at FROM notes WHERE id = $1",
-                &[&id],
+            .query_one_cached(
+                &format!(
+                    "SELECT EXISTS(\
+                         SELECT 1 FROM notes WHERE id = $1 AND tenant_id = $2 AND {NOT_EXPIRED}\
+                     )"
+                ),
+                &[&id, &Tenant::current().as_str()],
             )
             .await?;
 
-        Ok(row.map(|row| Note {
-            id: row.get("id"),
-            content: row.get("content"),
-            created_at: row.get("created_at"),
-            updated_at: row.get("updated_at"),
-        }))
+        if row.get::<_, bool>(0) {
+            Ok(ConditionalWrite::PreconditionFailed)
+        } else {
+            Ok(ConditionalWrite::NotFound)
+        }
+    }
+
+    pub async fn get_one_note(&self, id: i64) -> Result<Option<Note>, RepositoryError> {
+        let row = self
+            .query_opt_cached(
+                &format!(
+                    "SELECT {NOTE_COLUMNS} FROM notes \
+                     WHERE id = $1 AND tenant_id = $2 AND {NOT_EXPIRED}"
+                ),
+                &[&id, &Tenant::current().as_str()],
+            )
+            .await?;
+
+        Ok(row.as_ref().map(|row| self.note_from_row(row)))
     }
 
-    pub async fn get_all_notes(&self) -> Result<Vec<Note>, tokio_postgres::Error> {
+    pub async fn get_all_notes(&self) -> Result<Vec<Note>, RepositoryError> {
         let rows = self
-            .client
-            .query("SELECT id, content, created_at, updated_at FROM notes", &[])
+            .client()
+            .await?
+            .query(
+                &format!("SELECT {NOTE_COLUMNS} FROM notes WHERE tenant_id = $1 AND {NOT_EXPIRED}"),
+                &[&Tenant::current().as_str()],
+            )
             .await?;
 
-        let mut vec: Vec<Note> = Vec::new();
+        Ok(rows.iter().map(|row| self.note_from_row(row)).collect())
+    }
 
-        for row in rows {
-            vec.push(Note {
-                id: row.get("id"),
-                content: row.get("content"),
-                created_at: row.get("created_at"),
-                updated_at: row.get("updated_at"),
-            });
+    /// Returns up to `limit` notes matching the filter ordered by ID, skipping the
+    /// first `offset`, along with the total number of matching notes
+    pub async fn list_notes(
+        &self,
+        limit: i64,
+        offset: i64,
+        filter: &MetadataFilter,
+        order: NoteOrder,
+        include_archived: bool,
+    ) -> Result<(Vec<Note>, i64), RepositoryError> {
+        let order_by = match order {
+            NoteOrder::Id => "id",
+            NoteOrder::Position => "position",
+        };
+        let rows = self
+            .query_cached(
+                &format!(
+                    "SELECT {NOTE_COLUMNS} FROM {} \
+                     WHERE tenant_id = $5 AND {NOT_EXPIRED} AND {} \
+                     ORDER BY {order_by} LIMIT $1 OFFSET $2",
+                    notes_source(include_archived),
+                    metadata_matches(3, 4)
+                ),
+                &[
+                    &limit,
+                    &offset,
+                    &filter.contains,
+                    &filter.has_keys,
+                    &Tenant::current().as_str(),
+                ],
+            )
+            .await?;
+        let total = self.count_notes(filter, include_archived).await?;
+
+        Ok((
+            rows.iter().map(|row| self.note_from_row(row)).collect(),
+            total,
+        ))
+    }
+
+    /// Number of notes matching the filter
+    pub async fn count_notes(
+        &self,
+        filter: &MetadataFilter,
+        include_archived: bool,
+    ) -> Result<i64, RepositoryError> {
+        let row = self
+            .query_one_cached(
+                &format!(
+                    "SELECT COUNT(*) FROM {} WHERE tenant_id = $3 AND {NOT_EXPIRED} AND {}",
+                    notes_source(include_archived),
+                    metadata_matches(1, 2)
+                ),
+                &[
+                    &filter.contains,
+                    &filter.has_keys,
+                    &Tenant::current().as_str(),
+                ],
+            )
+            .await?;
+
+        Ok(row.get(0))
+    }
+
+    /// Puts the notes in the order of `ids` by permuting the positions they hold, so the
+    /// rest of the notes keep their places. Returns `false` without changing anything if
+    /// some of the notes don't exist. `ids` must not contain duplicates
+    pub async fn reorder_notes(&self, ids: &[i64]) -> Result<bool, RepositoryError> {
+        let mut client = self.client().await?;
+        let transaction = client.transaction().await?;
+        // Moving a note doesn't change it, its version stays the same
+        transaction
+            .execute("SET LOCAL notes.preserve_updated_at = 'on'", &[])
+            .await?;
+
+        let reordered = transaction
+            .execute(
+                &format!(
+                    "WITH wanted AS (\
+                         SELECT id, ord FROM UNNEST($1::bigint[]) WITH ORDINALITY AS t(id, ord)\
+                     ), slots AS (\
+                         SELECT position, ROW_NUMBER() OVER (ORDER BY position) AS ord \
+                         FROM notes WHERE id = ANY($1) AND tenant_id = $2 AND {NOT_EXPIRED}\
+                     ) \
+                     UPDATE notes SET position = slots.position \
+                     FROM wanted JOIN slots USING (ord) WHERE notes.id = wanted.id"
+                ),
+                &[&ids, &Tenant::current().as_str()],
+            )
+            .await?;
+        if usize::try_from(reordered).ok() != Some(ids.len()) {
+            return Ok(false);
         }
 
-        Ok(vec)
+        transaction.commit().await?;
+        Ok(true)
+    }
+
+    /// Returns up to `limit` notes matching the filter with ID greater than `after_id`,
+    /// ordered by ID. Unlike an offset, the key seeks straight to the page through the
+    /// primary key index, so deep pages are as fast as the first one
+    pub async fn get_notes_page(
+        &self,
+        after_id: i64,
+        limit: i64,
+        filter: &MetadataFilter,
+        include_archived: bool,
+    ) -> Result<Vec<Note>, RepositoryError> {
+        let rows = self
+            .query_cached(
+                &format!(
+                    "SELECT {NOTE_COLUMNS} FROM {} \
+                     WHERE id > $1 AND tenant_id = $5 AND {NOT_EXPIRED} AND {} \
+                     ORDER BY id LIMIT $2",
+                    notes_source(include_archived),
+                    metadata_matches(3, 4)
+                ),
+                &[
+                    &after_id,
+                    &limit,
+                    &filter.contains,
+                    &filter.has_keys,
+                    &Tenant::current().as_str(),
+                ],
+            )
+            .await?;
+
+        Ok(rows.iter().map(|row| self.note_from_row(row)).collect())
+    }
+
+    /// All notes of the current tenant ordered by ID, archived ones too if `include_archived`,
+    /// decoded row by row as Postgres sends them rather than collected first. The response
+    /// buffer of a connection is bounded, so a slow consumer pauses the query and memory
+    /// stays flat whatever the table size. The connection stays checked out of the pool
+    /// until the stream ends or is dropped
+    pub async fn stream_notes(
+        self: Arc<Self>,
+        include_archived: bool,
+    ) -> Result<impl Stream<Item = Result<Note, RepositoryError>> + Send + 'static, RepositoryError>
+    {
+        let client = self.client().await?;
+        let statement = client
+            .prepare_cached(&format!(
+                "SELECT {NOTE_COLUMNS} FROM {} WHERE tenant_id = $1 AND {NOT_EXPIRED} ORDER BY id",
+                notes_source(include_archived)
+            ))
+            .await?;
+        let rows = client
+            .query_raw(&statement, [Tenant::current().as_str()])
+            .await?;
+
+        Ok(rows.map(move |row| {
+            // Owned by the stream, so the connection isn't reused while rows are pending
+            let _client = &client;
+            Ok(self.note_from_row(&row?))
+        }))
+    }
+
+    /// Up to `limit` notes of all tenants, archived ones included, with ID greater than
+    /// `after_id` along with their tenants, ordered by ID. Used for backups
+    pub async fn get_all_tenants_page(
+        &self,
+        after_id: i64,
+        limit: i64,
+    ) -> Result<Vec<(Tenant, Note)>, RepositoryError> {
+        let rows = self
+            .query_cached(
+                &format!(
+                    "SELECT {NOTE_COLUMNS}, tenant_id FROM {} \
+                     WHERE id > $1 AND {NOT_EXPIRED} ORDER BY id LIMIT $2",
+                    notes_source(true)
+                ),
+                &[&after_id, &limit],
+            )
+            .await?;
+
+        Ok(rows
+            .iter()
+            .map(|row| (tenant_from_row(row), self.note_from_row(row)))
+            .collect())
+    }
+
+    /// Up to `limit` notes matching the web search syntax `query` (quoted phrases, `or`,
+    /// `-word`), best matches first. Encrypted content is not indexed, so it never matches
+    pub async fn search_notes(
+        &self,
+        query: &str,
+        limit: i64,
+    ) -> Result<Vec<Note>, RepositoryError> {
+        let rows = self
+            .client()
+            .await?
+            .query(
+                &format!(
+                    "SELECT {NOTE_COLUMNS} FROM notes, websearch_to_tsquery('simple', $1) query \
+                     WHERE search @@ query AND tenant_id = $3 AND {NOT_EXPIRED} \
+                     ORDER BY ts_rank(search, query) DESC, id LIMIT $2"
+                ),
+                &[&query, &limit, &Tenant::current().as_str()],
+            )
+            .await?;
+
+        Ok(rows.iter().map(|row| self.note_from_row(row)).collect())
+    }
+
+    /// Claims up to `limit` notes of any tenant whose reminder time has come, along with
+    /// their tenants, oldest reminders first. They aren't claimed again until `lease_until`,
+    /// so other instances don't send the same reminders, and a reminder that failed to
+    /// send is retried once its lease ends
+    pub async fn claim_due_reminders(
+        &self,
+        limit: i64,
+        lease_until: DateTime<Utc>,
+    ) -> Result<Vec<(Tenant, Note)>, RepositoryError> {
+        let mut client = self.client().await?;
+        let transaction = client.transaction().await?;
+        // Claiming a reminder doesn't change the note, its version stays the same
+        transaction
+            .execute("SET LOCAL notes.preserve_updated_at = 'on'", &[])
+            .await?;
+
+        let rows = transaction
+            .query(
+                &format!(
+                    "UPDATE notes SET reminder_lease_until = $2 WHERE id IN (\
+                         SELECT id FROM notes \
+                         WHERE remind_at IS NOT NULL AND remind_at <= NOW() AND {NOT_EXPIRED} \
+                         AND (reminder_lease_until IS NULL OR reminder_lease_until <= NOW()) \
+                         ORDER BY remind_at LIMIT $1 FOR UPDATE SKIP LOCKED\
+                     ) RETURNING {NOTE_COLUMNS}, tenant_id"
+                ),
+                &[&limit, &lease_until],
+            )
+            .await?;
+        transaction.commit().await?;
+
+        let mut due: Vec<(Tenant, Note)> = rows
+            .iter()
+            .map(|row| (tenant_from_row(row), self.note_from_row(row)))
+            .collect();
+        // `RETURNING` keeps no order
+        due.sort_by_key(|(_, note)| (note.remind_at, note.id));
+        Ok(due)
+    }
+
+    /// Marks the reminder as sent and ends its lease, returns whether the reminder was
+    /// cleared. Clearing it changes the note like any other update. The reminder stays
+    /// if it was rescheduled since `remind_at` was read, so the new time is not lost, and
+    /// then only the lease ends, which leaves the note as it is
+    pub async fn clear_reminder(
+        &self,
+        id: i64,
+        remind_at: DateTime<Utc>,
+    ) -> Result<bool, RepositoryError> {
+        let mut client = self.client().await?;
+        let transaction = client.transaction().await?;
+        let cleared = transaction
+            .execute(
+                "UPDATE notes SET remind_at = NULL, reminder_lease_until = NULL \
+                 WHERE id = $1 AND remind_at = $2",
+                &[&id, &remind_at],
+            )
+            .await?
+            > 0;
+
+        if !cleared {
+            transaction
+                .execute("SET LOCAL notes.preserve_updated_at = 'on'", &[])
+                .await?;
+            transaction
+                .execute(
+                    "UPDATE notes SET reminder_lease_until = NULL WHERE id = $1",
+                    &[&id],
+                )
+                .await?;
+        }
+        transaction.commit().await?;
+
+        Ok(cleared)
+    }
+
+    pub async fn create_template(
+        &self,
+        name: &str,
+        content: &str,
+    ) -> Result<NoteTemplate, RepositoryError> {
+        let row = self
+            .client()
+            .await?
+            .query_one(
+                &format!(
+                    "INSERT INTO note_templates (name, content, tenant_id) VALUES ($1, $2, $3) \
+                     RETURNING {TEMPLATE_COLUMNS}"
+                ),
+                &[&name, &content, &Tenant::current().as_str()],
+            )
+            .await?;
+
+        Ok(template_from_row(&row))
+    }
+
+    pub async fn list_templates(&self) -> Result<Vec<NoteTemplate>, RepositoryError> {
+        let rows = self
+            .client()
+            .await?
+            .query(
+                &format!(
+                    "SELECT {TEMPLATE_COLUMNS} FROM note_templates \
+                     WHERE tenant_id = $1 ORDER BY id"
+                ),
+                &[&Tenant::current().as_str()],
+            )
+            .await?;
+
+        Ok(rows.iter().map(template_from_row).collect())
+    }
+
+    pub async fn get_template(&self, id: i64) -> Result<Option<NoteTemplate>, RepositoryError> {
+        let row = self
+            .client()
+            .await?
+            .query_opt(
+                &format!(
+                    "SELECT {TEMPLATE_COLUMNS} FROM note_templates \
+                     WHERE id = $1 AND tenant_id = $2"
+                ),
+                &[&id, &Tenant::current().as_str()],
+            )
+            .await?;
+
+        Ok(row.as_ref().map(template_from_row))
+    }
+
+    pub async fn update_template(
+        &self,
+        id: i64,
+        name: &str,
+        content: &str,
+    ) -> Result<Option<NoteTemplate>, RepositoryError> {
+        let row = self
+            .client()
+            .await?
+            .query_opt(
+                &format!(
+                    "UPDATE note_templates SET name = $1, content = $2, updated_at = NOW() \
+                     WHERE id = $3 AND tenant_id = $4 RETURNING {TEMPLATE_COLUMNS}"
+                ),
+                &[&name, &content, &id, &Tenant::current().as_str()],
+            )
+            .await?;
+
+        Ok(row.as_ref().map(template_from_row))
+    }
+
+    /// Returns whether the template existed
+    pub async fn delete_template(&self, id: i64) -> Result<bool, RepositoryError> {
+        let rows = self
+            .client()
+            .await?
+            .execute(
+                "DELETE FROM note_templates WHERE id = $1 AND tenant_id = $2",
+                &[&id, &Tenant::current().as_str()],
+            )
+            .await?;
+
+        Ok(rows == 1)
+    }
+
+    pub async fn create_webhook(
+        &self,
+        webhook: &WebhookDraft,
+        secret: &str,
+    ) -> Result<Webhook, RepositoryError> {
+        let row = self
+            .client()
+            .await?
+            .query_one(
+                &format!(
+                    "INSERT INTO webhooks (url, secret, events, active, tenant_id) \
+                     VALUES ($1, $2, $3, $4, $5) RETURNING {WEBHOOK_COLUMNS}"
+                ),
+                &[
+                    &webhook.url,
+                    &secret,
+                    &webhook.events,
+                    &webhook.active,
+                    &Tenant::current().as_str(),
+                ],
+            )
+            .await?;
+
+        Ok(webhook_from_row(&row))
+    }
+
+    pub async fn list_webhooks(&self) -> Result<Vec<Webhook>, RepositoryError> {
+        let rows = self
+            .client()
+            .await?
+            .query(
+                &format!("SELECT {WEBHOOK_COLUMNS} FROM webhooks WHERE tenant_id = $1 ORDER BY id"),
+                &[&Tenant::current().as_str()],
+            )
+            .await?;
+
+        Ok(rows.iter().map(webhook_from_row).collect())
+    }
+
+    pub async fn get_webhook(&self, id: i64) -> Result<Option<Webhook>, RepositoryError> {
+        let row = self
+            .client()
+            .await?
+            .query_opt(
+                &format!("SELECT {WEBHOOK_COLUMNS} FROM webhooks WHERE id = $1 AND tenant_id = $2"),
+                &[&id, &Tenant::current().as_str()],
+            )
+            .await?;
+
+        Ok(row.as_ref().map(webhook_from_row))
+    }
+
+    /// Also closes the circuit, so a fixed URL is tried again right away
+    pub async fn update_webhook(
+        &self,
+        id: i64,
+        webhook: &WebhookDraft,
+    ) -> Result<Option<Webhook>, RepositoryError> {
+        let row = self
+            .client()
+            .await?
+            .query_opt(
+                &format!(
+                    "UPDATE webhooks SET url = $1, events = $2, active = $3, \
+                     consecutive_failures = 0, circuit_open_until = NULL, updated_at = NOW() \
+                     WHERE id = $4 AND tenant_id = $5 RETURNING {WEBHOOK_COLUMNS}"
+                ),
+                &[
+                    &webhook.url,
+                    &webhook.events,
+                    &webhook.active,
+                    &id,
+                    &Tenant::current().as_str(),
+                ],
+            )
+            .await?;
+
+        Ok(row.as_ref().map(webhook_from_row))
+    }
+
+    /// Returns whether the webhook existed, its deliveries are removed with it
+    pub async fn delete_webhook(&self, id: i64) -> Result<bool, RepositoryError> {
+        let rows = self
+            .client()
+            .await?
+            .execute(
+                "DELETE FROM webhooks WHERE id = $1 AND tenant_id = $2",
+                &[&id, &Tenant::current().as_str()],
+            )
+            .await?;
+
+        Ok(rows == 1)
+    }
+
+    /// Records the stored file as an attachment of the note, `None` if the note
+    /// doesn't exist
+    pub async fn create_attachment(
+        &self,
+        note_id: i64,
+        attachment: &AttachmentDraft,
+    ) -> Result<Option<Attachment>, RepositoryError> {
+        let row = self
+            .query_opt_cached(
+                &format!(
+                    "INSERT INTO note_attachments \
+                     (tenant_id, note_id, filename, content_type, size, storage_key) \
+                     SELECT tenant_id, id, $2, $3, $4, $5 FROM notes \
+                     WHERE id = $1 AND tenant_id = $6 AND {NOT_EXPIRED} \
+                     RETURNING {ATTACHMENT_COLUMNS}"
+                ),
+                &[
+                    &note_id,
+                    &attachment.filename,
+                    &attachment.content_type,
+                    &attachment.size,
+                    &attachment.storage_key,
+                    &Tenant::current().as_str(),
+                ],
+            )
+            .await?;
+
+        Ok(row.as_ref().map(attachment_from_row))
+    }
+
+    pub async fn list_attachments(&self, note_id: i64) -> Result<Vec<Attachment>, RepositoryError> {
+        let rows = self
+            .query_cached(
+                &format!(
+                    "SELECT {ATTACHMENT_COLUMNS} FROM note_attachments \
+                     WHERE tenant_id = $1 AND note_id = $2 ORDER BY id"
+                ),
+                &[&Tenant::current().as_str(), &note_id],
+            )
+            .await?;
+
+        Ok(rows.iter().map(attachment_from_row).collect())
+    }
+
+    pub async fn get_attachment(
+        &self,
+        note_id: i64,
+        id: i64,
+    ) -> Result<Option<Attachment>, RepositoryError> {
+        let row = self
+            .query_opt_cached(
+                &format!(
+                    "SELECT {ATTACHMENT_COLUMNS} FROM note_attachments \
+                     WHERE tenant_id = $1 AND note_id = $2 AND id = $3"
+                ),
+                &[&Tenant::current().as_str(), &note_id, &id],
+            )
+            .await?;
+
+        Ok(row.as_ref().map(attachment_from_row))
+    }
+
+    /// Returns the storage key of the removed attachment, `None` if it didn't exist
+    pub async fn delete_attachment(
+        &self,
+        note_id: i64,
+        id: i64,
+    ) -> Result<Option<String>, RepositoryError> {
+        let row = self
+            .query_opt_cached(
+                "DELETE FROM note_attachments \
+                 WHERE tenant_id = $1 AND note_id = $2 AND id = $3 RETURNING storage_key",
+                &[&Tenant::current().as_str(), &note_id, &id],
+            )
+            .await?;
+
+        Ok(row.map(|row| row.get("storage_key")))
+    }
+
+    /// Removes up to `limit` attachments of any tenant whose note is gone for good, i.e.
+    /// neither current, archived nor awaiting an undo, returning their storage keys
+    pub async fn delete_orphaned_attachments(
+        &self,
+        limit: i64,
+    ) -> Result<Vec<String>, RepositoryError> {
+        let rows = self
+            .query_cached(
+                "DELETE FROM note_attachments WHERE id IN (\
+                     SELECT a.id FROM note_attachments a \
+                     WHERE NOT EXISTS (\
+                         SELECT 1 FROM notes n WHERE n.id = a.note_id AND n.tenant_id = a.tenant_id\
+                     ) AND NOT EXISTS (\
+                         SELECT 1 FROM notes_archive n \
+                         WHERE n.id = a.note_id AND n.tenant_id = a.tenant_id\
+                     ) AND NOT EXISTS (\
+                         SELECT 1 FROM note_tombstones n \
+                         WHERE n.id = a.note_id AND n.tenant_id = a.tenant_id\
+                     ) \
+                     LIMIT $1 FOR UPDATE SKIP LOCKED\
+                 ) RETURNING storage_key",
+                &[&limit],
+            )
+            .await?;
+
+        Ok(rows.iter().map(|row| row.get("storage_key")).collect())
+    }
+
+    /// Those of `keys` some attachment of any tenant is stored under
+    pub async fn existing_attachment_keys(
+        &self,
+        keys: &[String],
+    ) -> Result<Vec<String>, RepositoryError> {
+        let rows = self
+            .query_cached(
+                "SELECT storage_key FROM note_attachments WHERE storage_key = ANY($1)",
+                &[&keys],
+            )
+            .await?;
+
+        Ok(rows.iter().map(|row| row.get("storage_key")).collect())
+    }
+
+    pub async fn create_digest(
+        &self,
+        digest: &DigestDraft,
+    ) -> Result<DigestSubscription, RepositoryError> {
+        let row = self
+            .query_one_cached(
+                &format!(
+                    "INSERT INTO digest_subscriptions \
+                     (email, schedule, active, next_run_at, tenant_id) \
+                     VALUES ($1, $2, $3, $4, $5) RETURNING {DIGEST_COLUMNS}"
+                ),
+                &[
+                    &digest.email,
+                    &digest.schedule,
+                    &digest.active,
+                    &digest.next_run_at,
+                    &Tenant::current().as_str(),
+                ],
+            )
+            .await?;
+
+        Ok(digest_from_row(&row))
+    }
+
+    pub async fn list_digests(&self) -> Result<Vec<DigestSubscription>, RepositoryError> {
+        let rows = self
+            .query_cached(
+                &format!(
+                    "SELECT {DIGEST_COLUMNS} FROM digest_subscriptions \
+                     WHERE tenant_id = $1 ORDER BY id"
+                ),
+                &[&Tenant::current().as_str()],
+            )
+            .await?;
+
+        Ok(rows.iter().map(digest_from_row).collect())
+    }
+
+    pub async fn get_digest(&self, id: i64) -> Result<Option<DigestSubscription>, RepositoryError> {
+        let row = self
+            .query_opt_cached(
+                &format!(
+                    "SELECT {DIGEST_COLUMNS} FROM digest_subscriptions \
+                     WHERE id = $1 AND tenant_id = $2"
+                ),
+                &[&id, &Tenant::current().as_str()],
+            )
+            .await?;
+
+        Ok(row.as_ref().map(digest_from_row))
+    }
+
+    /// The next digest still starts where the previous one left off
+    pub async fn update_digest(
+        &self,
+        id: i64,
+        digest: &DigestDraft,
+    ) -> Result<Option<DigestSubscription>, RepositoryError> {
+        let row = self
+            .query_opt_cached(
+                &format!(
+                    "UPDATE digest_subscriptions \
+                     SET email = $1, schedule = $2, active = $3, next_run_at = $4, \
+                     updated_at = NOW() \
+                     WHERE id = $5 AND tenant_id = $6 RETURNING {DIGEST_COLUMNS}"
+                ),
+                &[
+                    &digest.email,
+                    &digest.schedule,
+                    &digest.active,
+                    &digest.next_run_at,
+                    &id,
+                    &Tenant::current().as_str(),
+                ],
+            )
+            .await?;
+
+        Ok(row.as_ref().map(digest_from_row))
+    }
+
+    /// Returns whether the subscription existed
+    pub async fn delete_digest(&self, id: i64) -> Result<bool, RepositoryError> {
+        let rows = self
+            .execute_cached(
+                "DELETE FROM digest_subscriptions WHERE id = $1 AND tenant_id = $2",
+                &[&id, &Tenant::current().as_str()],
+            )
+            .await?;
+
+        Ok(rows == 1)
+    }
+
+    /// Claims up to `limit` active digests of any tenant that are due. They aren't due
+    /// again until `lease_until`, so a digest interrupted by a crash is sent later and
+    /// other instances don't send it meanwhile
+    pub async fn claim_due_digests(
+        &self,
+        limit: i64,
+        lease_until: DateTime<Utc>,
+    ) -> Result<Vec<DueDigest>, RepositoryError> {
+        let rows = self
+            .query_cached(
+                "UPDATE digest_subscriptions SET next_run_at = $2 WHERE id IN (\
+                     SELECT id FROM digest_subscriptions \
+                     WHERE active AND next_run_at <= NOW() \
+                     ORDER BY next_run_at LIMIT $1 FOR UPDATE SKIP LOCKED\
+                 ) RETURNING id, tenant_id, email, schedule, \
+                 COALESCE(last_run_at, created_at) AS since",
+                &[&limit, &lease_until],
+            )
+            .await?;
+
+        Ok(rows
+            .iter()
+            .map(|row| DueDigest {
+                id: row.get("id"),
+                tenant_id: row.get("tenant_id"),
+                email: row.get("email"),
+                schedule: row.get("schedule"),
+                since: row.get("since"),
+            })
+            .collect())
+    }
+
+    /// Records that the digest covers the notes changed up to `ran_at`. Without a
+    /// `next_run_at` the schedule has no more runs and the subscription is deactivated
+    pub async fn complete_digest(
+        &self,
+        id: i64,
+        ran_at: DateTime<Utc>,
+        next_run_at: Option<DateTime<Utc>>,
+    ) -> Result<(), RepositoryError> {
+        self.execute_cached(
+            "UPDATE digest_subscriptions SET last_run_at = $2, \
+             next_run_at = COALESCE($3, next_run_at), active = active AND $3 IS NOT NULL, \
+             last_error = NULL WHERE id = $1",
+            &[&id, &ran_at, &next_run_at],
+        )
+        .await?;
+
+        Ok(())
+    }
+
+    /// Records why the digest wasn't sent, it is tried again at `retry_at` with the
+    /// same notes and any changed meanwhile
+    pub async fn fail_digest(
+        &self,
+        id: i64,
+        error: &str,
+        retry_at: DateTime<Utc>,
+    ) -> Result<(), RepositoryError> {
+        self.execute_cached(
+            "UPDATE digest_subscriptions SET last_error = $2, next_run_at = $3 WHERE id = $1",
+            &[&id, &error, &retry_at],
+        )
+        .await?;
+
+        Ok(())
+    }
+
+    /// Up to `limit` notes of the current tenant changed after `since` and up to
+    /// `until`, in the order they changed, along with how many there are in total
+    pub async fn changed_notes(
+        &self,
+        since: DateTime<Utc>,
+        until: DateTime<Utc>,
+        limit: i64,
+    ) -> Result<(Vec<Note>, i64), RepositoryError> {
+        let rows = self
+            .query_cached(
+                &format!(
+                    "SELECT {NOTE_COLUMNS}, count(*) OVER () AS total FROM notes \
+                     WHERE tenant_id = $1 AND updated_at > $2 AND updated_at <= $3 \
+                     AND {NOT_EXPIRED} ORDER BY updated_at, id LIMIT $4"
+                ),
+                &[&Tenant::current().as_str(), &since, &until, &limit],
+            )
+            .await?;
+
+        let total = rows.first().map_or(0, |row| row.get("total"));
+        Ok((
+            rows.iter().map(|row| self.note_from_row(row)).collect(),
+            total,
+        ))
+    }
+
+    /// Up to `limit` latest deliveries of the webhook, newest first
+    /// Adds the user as a member of the current tenant the first time they're seen,
+    /// afterwards refreshes their email and name. Also returns whether the user is new.
+    /// A user stays in the tenant they were added to
+    pub async fn provision_user(&self, user: &NewUser) -> Result<(User, bool), RepositoryError> {
+        let row = self
+            .query_one_cached(
+                &format!(
+                    "INSERT INTO users (issuer, subject, email, name, tenant_id) \
+                     VALUES ($1, $2, $3, $4, $5) \
+                     ON CONFLICT (issuer, subject) DO UPDATE \
+                     SET email = EXCLUDED.email, name = EXCLUDED.name, last_seen_at = NOW() \
+                     RETURNING {USER_COLUMNS}, xmax = 0 AS created"
+                ),
+                &[
+                    &user.issuer,
+                    &user.subject,
+                    &user.email,
+                    &user.name,
+                    &Tenant::current().as_str(),
+                ],
+            )
+            .await?;
+
+        Ok((user_from_row(&row), row.get("created")))
+    }
+
+    /// Users of the tenant, or of every tenant, most recently seen first
+    pub async fn list_users(
+        &self,
+        tenant: Option<&str>,
+        limit: i64,
+    ) -> Result<Vec<User>, RepositoryError> {
+        let rows = self
+            .query_cached(
+                &format!(
+                    "SELECT {USER_COLUMNS} FROM users \
+                     WHERE ($1::text IS NULL OR tenant_id = $1) \
+                     ORDER BY last_seen_at DESC, id DESC LIMIT $2"
+                ),
+                &[&tenant, &limit],
+            )
+            .await?;
+
+        Ok(rows.iter().map(user_from_row).collect())
+    }
+
+    pub async fn list_webhook_deliveries(
+        &self,
+        webhook_id: i64,
+        limit: i64,
+    ) -> Result<Vec<WebhookDelivery>, RepositoryError> {
+        let rows = self
+            .client()
+            .await?
+            .query(
+                "SELECT d.id, d.event_id, d.operation, d.payload, d.status, d.attempts, \
+                 d.next_attempt_at, d.last_status_code, d.last_error, d.created_at, \
+                 d.completed_at FROM webhook_deliveries d JOIN webhooks w ON w.id = d.webhook_id \
+                 WHERE d.webhook_id = $1 AND w.tenant_id = $2 ORDER BY d.id DESC LIMIT $3",
+                &[&webhook_id, &Tenant::current().as_str(), &limit],
+            )
+            .await?;
+
+        Ok(rows.iter().map(webhook_delivery_from_row).collect())
+    }
+
+    /// Up to `limit` oldest deliveries due now, of any tenant, skipping webhooks that are
+    /// inactive or whose circuit is open. They aren't due again until `lease_until`, so
+    /// other instances don't send them meanwhile
+    pub async fn claim_webhook_deliveries(
+        &self,
+        limit: i64,
+        lease_until: DateTime<Utc>,
+    ) -> Result<Vec<DueDelivery>, RepositoryError> {
+        let rows = self
+            .query_cached(
+                "UPDATE webhook_deliveries d SET next_attempt_at = $2 FROM webhooks w \
+                 WHERE w.id = d.webhook_id AND d.id IN ( \
+                     SELECT d.id FROM webhook_deliveries d JOIN webhooks w ON w.id = d.webhook_id \
+                     WHERE d.status = 'pending' AND d.next_attempt_at <= NOW() AND w.active \
+                     AND (w.circuit_open_until IS NULL OR w.circuit_open_until <= NOW()) \
+                     ORDER BY d.id LIMIT $1 FOR UPDATE OF d SKIP LOCKED) \
+                 RETURNING d.id, d.webhook_id, w.url, w.secret, d.operation, d.payload, d.attempts",
+                &[&limit, &lease_until],
+            )
+            .await?;
+
+        Ok(rows
+            .iter()
+            .map(|row| DueDelivery {
+                id: row.get("id"),
+                webhook_id: row.get("webhook_id"),
+                url: row.get("url"),
+                secret: row.get("secret"),
+                operation: row.get("operation"),
+                payload: row.get("payload"),
+                attempts: row.get("attempts"),
+            })
+            .collect())
+    }
+
+    /// Marks the delivery as delivered and closes the circuit of its webhook
+    pub async fn complete_webhook_delivery(
+        &self,
+        id: i64,
+        status_code: i32,
+    ) -> Result<(), RepositoryError> {
+        self.execute_cached(
+            "WITH delivery AS ( \
+                 UPDATE webhook_deliveries SET status = 'delivered', attempts = attempts + 1, \
+                 last_status_code = $2, last_error = NULL, completed_at = NOW() \
+                 WHERE id = $1 RETURNING webhook_id) \
+             UPDATE webhooks SET consecutive_failures = 0, circuit_open_until = NULL \
+             WHERE id = (SELECT webhook_id FROM delivery)",
+            &[&id, &status_code],
+        )
+        .await?;
+
+        Ok(())
+    }
+
+    /// Records the failed attempt, the delivery is failed for good unless it's retried.
+    /// The circuit of its webhook opens until `open_circuit_until` once the webhook has
+    /// failed `failure_threshold` times in a row
+    pub async fn fail_webhook_delivery(
+        &self,
+        id: i64,
+        failure: &DeliveryFailure,
+        failure_threshold: i32,
+        open_circuit_until: DateTime<Utc>,
+    ) -> Result<(), RepositoryError> {
+        self.execute_cached(
+            "WITH delivery AS ( \
+                 UPDATE webhook_deliveries SET attempts = attempts + 1, \
+                 last_status_code = $2, last_error = $3, \
+                 status = CASE WHEN $4::timestamptz IS NULL THEN 'failed' ELSE 'pending' END, \
+                 next_attempt_at = COALESCE($4, next_attempt_at), \
+                 completed_at = CASE WHEN $4::timestamptz IS NULL THEN NOW() END \
+                 WHERE id = $1 RETURNING webhook_id) \
+             UPDATE webhooks SET consecutive_failures = consecutive_failures + 1, \
+             circuit_open_until = CASE WHEN consecutive_failures + 1 >= $5 THEN $6 \
+                 ELSE circuit_open_until END \
+             WHERE id = (SELECT webhook_id FROM delivery)",
+            &[
+                &id,
+                &failure.status_code,
+                &failure.error,
+                &failure.retry_at,
+                &failure_threshold,
+                &open_circuit_until,
+            ],
+        )
+        .await?;
+
+        Ok(())
+    }
+
+    /// Removes deliveries completed before `before`, returning how many were removed
+    pub async fn delete_webhook_deliveries_before(
+        &self,
+        before: DateTime<Utc>,
+    ) -> Result<u64, RepositoryError> {
+        self.execute_cached(
+            "DELETE FROM webhook_deliveries WHERE completed_at < $1",
+            &[&before],
+        )
+        .await
+    }
+
+    /// Stores a link to the note, returns `None` if there is no such note
+    pub async fn create_share_link(
+        &self,
+        note_id: i64,
+        token_hash: &[u8],
+        expires_at: DateTime<Utc>,
+    ) -> Result<Option<ShareLink>, RepositoryError> {
+        let row = self
+            .client()
+            .await?
+            .query_opt(
+                &format!(
+                    "INSERT INTO share_links (token_hash, note_id, expires_at) \
+                     SELECT $1, id, $3 FROM notes \
+                     WHERE id = $2 AND tenant_id = $4 AND {NOT_EXPIRED} \
+                     RETURNING note_id, expires_at"
+                ),
+                &[
+                    &token_hash,
+                    &note_id,
+                    &expires_at,
+                    &Tenant::current().as_str(),
+                ],
+            )
+            .await?;
+
+        Ok(row.map(|row| ShareLink {
+            note_id: row.get("note_id"),
+            expires_at: row.get("expires_at"),
+        }))
+    }
+
+    /// The note behind a link that hasn't expired, whichever tenant it belongs to
+    pub async fn get_shared_note(
+        &self,
+        token_hash: &[u8],
+    ) -> Result<Option<Note>, RepositoryError> {
+        let row = self
+            .client()
+            .await?
+            .query_opt(
+                &format!(
+                    "SELECT {NOTE_COLUMNS} FROM notes WHERE id = (\
+                         SELECT note_id FROM share_links \
+                         WHERE token_hash = $1 AND expires_at > NOW()\
+                     ) AND {NOT_EXPIRED}"
+                ),
+                &[&token_hash],
+            )
+            .await?;
+
+        Ok(row.as_ref().map(|row| self.note_from_row(row)))
+    }
+
+    /// Removes links whose expiration time has passed, returning how many were removed
+    pub async fn delete_expired_share_links(&self) -> Result<u64, RepositoryError> {
+        Ok(self
+            .client()
+            .await?
+            .execute("DELETE FROM share_links WHERE expires_at <= NOW()", &[])
+            .await?)
+    }
+
+    /// Appends a change of each of the notes, made through the `origin` instance,
+    /// to the activity feed
+    pub async fn record_activity(
+        &self,
+        note_ids: &[i64],
+        operation: &str,
+        origin: &str,
+    ) -> Result<(), RepositoryError> {
+        self.client()
+            .await?
+            .execute(
+                "INSERT INTO note_activity (note_id, operation, tenant_id, origin) \
+                 SELECT UNNEST($1::bigint[]), $2, $3, $4",
+                &[&note_ids, &operation, &Tenant::current().as_str(), &origin],
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    /// Changes recorded from now on by any instance sharing the database, received over
+    /// a dedicated connection outside the pool. The stream ends when it is lost
+    pub async fn listen_changes(
+        &self,
+    ) -> Result<impl Stream<Item = Activity> + Send + 'static, RepositoryError> {
+        let (client, mut connection) = self.config.connect(NoTls).await?;
+        let (sender, receiver) = mpsc::unbounded_channel();
+        // Notifications arrive through the connection, which also has to be polled for
+        // LISTEN to complete. The task ends along with the stream, which owns the client
+        tokio::spawn(async move {
+            let mut messages = stream::poll_fn(|cx| connection.poll_message(cx));
+            while let Some(message) = messages.next().await {
+                match message {
+                    Ok(AsyncMessage::Notification(notification)) => {
+                        if sender.send(notification).is_err() {
+                            break;
+                        }
+                    }
+                    Ok(_) => {}
+                    Err(e) => {
+                        tracing::error!("Change feed connection failed: {e}");
+                        break;
+                    }
+                }
+            }
+        });
+        client
+            .batch_execute(&format!("LISTEN {CHANGES_CHANNEL}"))
+            .await?;
+
+        let notifications = stream::unfold((client, receiver), |(client, mut receiver)| async {
+            let notification = receiver.recv().await?;
+            Some((notification, (client, receiver)))
+        });
+
+        Ok(notifications.filter_map(|notification| {
+            future::ready(
+                serde_json::from_str(notification.payload())
+                    .inspect_err(|e| tracing::error!("Invalid change notification: {e}"))
+                    .ok(),
+            )
+        }))
+    }
+
+    /// Up to `limit` most recent changes made after `since` (any time when `None`),
+    /// newest first
+    pub async fn recent_activity(
+        &self,
+        since: Option<DateTime<Utc>>,
+        limit: i64,
+    ) -> Result<Vec<Activity>, RepositoryError> {
+        let rows = self
+            .client()
+            .await?
+            .query(
+                "SELECT note_id, operation, occurred_at, tenant_id, origin FROM note_activity \
+                 WHERE tenant_id = $3 AND ($1::timestamptz IS NULL OR occurred_at > $1) \
+                 ORDER BY occurred_at DESC, id DESC LIMIT $2",
+                &[&since, &limit, &Tenant::current().as_str()],
+            )
+            .await?;
+
+        Ok(rows
+            .iter()
+            .map(|row| Activity {
+                note_id: row.get("note_id"),
+                operation: row.get("operation"),
+                occurred_at: row.get("occurred_at"),
+                tenant_id: row.get("tenant_id"),
+                origin: row.get("origin"),
+            })
+            .collect())
+    }
+
+    /// Removes outbox entries relayed before `before`, returning how many were removed
+    pub async fn delete_sent_outbox_before(
+        &self,
+        before: DateTime<Utc>,
+    ) -> Result<u64, RepositoryError> {
+        self.execute_cached("DELETE FROM note_outbox WHERE sent_at < $1", &[&before])
+            .await
+    }
+
+    /// Removes activity recorded before `before`, returning how many entries were removed
+    pub async fn delete_activity_before(
+        &self,
+        before: DateTime<Utc>,
+    ) -> Result<u64, RepositoryError> {
+        Ok(self
+            .client()
+            .await?
+            .execute(
+                "DELETE FROM note_activity WHERE occurred_at < $1",
+                &[&before],
+            )
+            .await?)
+    }
+
+    /// Appends a SOAP request to the audit trail
+    pub async fn record_soap_audit(
+        &self,
+        entry: &NewSoapAuditEntry,
+    ) -> Result<(), RepositoryError> {
+        self.client().await?
+            .execute(
+                "INSERT INTO soap_audit \
+                 (operations, caller, forwarded_for, soap_version, fault_code, latency_ms, envelope) \
+                 VALUES ($1, $2, $3, $4, $5, $6, $7)",
+                &[
+                    &entry.operations,
+                    &entry.caller,
+                    &entry.forwarded_for,
+                    &entry.soap_version,
+                    &entry.fault_code,
+                    &entry.latency_ms,
+                    &entry.envelope,
+                ],
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    /// Up to `limit` most recent SOAP requests made after `since` (any time when `None`),
+    /// that asked for `operation` and, when `faults_only`, were answered with a fault.
+    /// Newest first
+    pub async fn soap_audit(
+        &self,
+        since: Option<DateTime<Utc>>,
+        operation: Option<&str>,
+        faults_only: bool,
+        limit: i64,
+    ) -> Result<Vec<SoapAuditEntry>, RepositoryError> {
+        let rows = self
+            .client()
+            .await?
+            .query(
+                "SELECT id, operations, caller, forwarded_for, soap_version, fault_code, \
+                 latency_ms, envelope, occurred_at FROM soap_audit \
+                 WHERE ($1::timestamptz IS NULL OR occurred_at > $1) \
+                 AND ($2::text IS NULL OR $2 = ANY(operations)) \
+                 AND (NOT $3 OR fault_code IS NOT NULL) \
+                 ORDER BY occurred_at DESC, id DESC LIMIT $4",
+                &[&since, &operation, &faults_only, &limit],
+            )
+            .await?;
+
+        Ok(rows
+            .iter()
+            .map(|row| SoapAuditEntry {
+                id: row.get("id"),
+                operations: row.get("operations"),
+                caller: row.get("caller"),
+                forwarded_for: row.get("forwarded_for"),
+                soap_version: row.get("soap_version"),
+                fault_code: row.get("fault_code"),
+                latency_ms: row.get("latency_ms"),
+                envelope: row.get("envelope"),
+                occurred_at: row.get("occurred_at"),
+            })
+            .collect())
+    }
+
+    /// Removes SOAP audit entries recorded before `before`, returning how many were removed
+    pub async fn delete_soap_audit_before(
+        &self,
+        before: DateTime<Utc>,
+    ) -> Result<u64, RepositoryError> {
+        Ok(self
+            .client()
+            .await?
+            .execute("DELETE FROM soap_audit WHERE occurred_at < $1", &[&before])
+            .await?)
+    }
+
+    /// Permanently removes notes of any tenant whose expiration time has passed,
+    /// returning their ids along with their tenants
+    pub async fn delete_expired_notes(&self) -> Result<Vec<(Tenant, i64)>, RepositoryError> {
+        let rows = self
+            .client()
+            .await?
+            .query(
+                "DELETE FROM notes WHERE expires_at IS NOT NULL AND expires_at <= NOW() \
+                 RETURNING id, tenant_id",
+                &[],
+            )
+            .await?;
+
+        Ok(rows
+            .iter()
+            .map(|row| (tenant_from_row(row), row.get("id")))
+            .collect())
+    }
+
+    /// Moves up to `limit` notes of any tenant not modified since `before` to the archive,
+    /// returning their ids along with their tenants. Notes that expire or have a pending reminder stay, the background jobs need them.
+    /// Share links of the archived notes are removed. Archiving doesn't change the notes,
+    /// so nothing is queued in the outbox
+    pub async fn archive_notes(
+        &self,
+        before: DateTime<Utc>,
+        limit: i64,
+    ) -> Result<Vec<(Tenant, i64)>, RepositoryError> {
+        let mut client = self.client().await?;
+        let transaction = client.transaction().await?;
+        transaction
+            .execute("SET LOCAL notes.preserve_updated_at = 'on'", &[])
+            .await?;
+
+        let rows = transaction
+            .query(
+                &format!(
+                    "WITH moved AS (\
+                         DELETE FROM notes WHERE id IN (\
+                             SELECT id FROM notes \
+                             WHERE updated_at < $1 AND expires_at IS NULL AND remind_at IS NULL \
+                             ORDER BY id LIMIT $2 FOR UPDATE SKIP LOCKED\
+                         ) RETURNING {NOTE_COLUMNS}, tenant_id\
+                     ) \
+                     INSERT INTO notes_archive ({NOTE_COLUMNS}, tenant_id) \
+                     SELECT {NOTE_COLUMNS}, tenant_id FROM moved RETURNING id, tenant_id"
+                ),
+                &[&before, &limit],
+            )
+            .await?;
+        transaction.commit().await?;
+
+        Ok(rows
+            .iter()
+            .map(|row| (tenant_from_row(row), row.get("id")))
+            .collect())
+    }
+
+    /// Number of notes of any tenant `archive_notes` would move for `before`
+    pub async fn count_archivable_notes(
+        &self,
+        before: DateTime<Utc>,
+    ) -> Result<i64, RepositoryError> {
+        let row = self
+            .query_one_cached(
+                "SELECT count(*) FROM notes \
+                 WHERE updated_at < $1 AND expires_at IS NULL AND remind_at IS NULL",
+                &[&before],
+            )
+            .await?;
+
+        Ok(row.get(0))
+    }
+
+    /// Number of notes of any tenant, archived ones too, not modified since `before`
+    pub async fn count_notes_modified_before(
+        &self,
+        before: DateTime<Utc>,
+    ) -> Result<i64, RepositoryError> {
+        let row = self
+            .query_one_cached(
+                "SELECT (SELECT count(*) FROM notes WHERE updated_at < $1) \
+                 + (SELECT count(*) FROM notes_archive WHERE updated_at < $1)",
+                &[&before],
+            )
+            .await?;
+
+        Ok(row.get(0))
+    }
+
+    /// Permanently removes up to `limit` notes of any tenant not modified since `before`,
+    /// returning their ids along with their tenants
+    pub async fn delete_notes_modified_before(
+        &self,
+        before: DateTime<Utc>,
+        limit: i64,
+    ) -> Result<Vec<(Tenant, i64)>, RepositoryError> {
+        let rows = self
+            .query_cached(
+                "DELETE FROM notes WHERE id IN (\
+                     SELECT id FROM notes WHERE updated_at < $1 \
+                     ORDER BY id LIMIT $2 FOR UPDATE SKIP LOCKED\
+                 ) RETURNING id, tenant_id",
+                &[&before, &limit],
+            )
+            .await?;
+
+        Ok(rows
+            .iter()
+            .map(|row| (tenant_from_row(row), row.get("id")))
+            .collect())
+    }
+
+    /// Permanently removes up to `limit` archived notes of any tenant not modified since
+    /// `before`, returning how many were removed
+    pub async fn delete_archived_notes_modified_before(
+        &self,
+        before: DateTime<Utc>,
+        limit: i64,
+    ) -> Result<u64, RepositoryError> {
+        self.execute_cached(
+            "DELETE FROM notes_archive WHERE id IN (\
+                 SELECT id FROM notes_archive WHERE updated_at < $1 \
+                 ORDER BY id LIMIT $2 FOR UPDATE SKIP LOCKED\
+             )",
+            &[&before, &limit],
+        )
+        .await
     }
 }
@@ -0,0 +1,1321 @@
+// Integration tests of the repository against a disposable Postgres started with
+// testcontainers. They need a running Docker daemon, so they are ignored by default:
+// `cargo test -p notes-server -- --ignored`
+
+use chrono::{Duration, Utc};
+use futures_util::StreamExt;
+use serde_json::json;
+use testcontainers_modules::{
+    postgres::Postgres,
+    testcontainers::{ContainerAsync, ImageExt, runners::AsyncRunner},
+};
+use tokio_postgres::Config;
+
+use super::{ConditionalWrite, ContentCipher, Repository, RepositoryError};
+use crate::{
+    models::{
+        AttachmentDraft, DeliveryFailure, DigestDraft, Metadata, MetadataFilter, NewNote,
+        NewSoapAuditEntry, NewUser, NoteDraft, NoteOrder, WebhookDraft,
+    },
+    tenant::Tenant,
+};
+
+/// The migrations need Postgres 12 or newer
+const POSTGRES_TAG: &str = "16-alpine";
+
+/// Key of the encryption tests, 32 zero bytes
+const TEST_KEY: &str = "AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA=";
+
+/// A migrated database in its own container, removed when dropped
+struct TestDb {
+    repo: Repository,
+    config: Config,
+    container: ContainerAsync<Postgres>,
+}
+
+impl TestDb {
+    async fn start() -> Self {
+        let container = Postgres::default()
+            .with_tag(POSTGRES_TAG)
+            .start()
+            .await
+            .expect("failed to start Postgres, is Docker running?");
+        let host = container.get_host().await.expect("no container host");
+        let port = container
+            .get_host_port_ipv4(5432)
+            .await
+            .expect("Postgres port is not published");
+
+        let mut config = Config::new();
+        config
+            .host(host.to_string())
+            .port(port)
+            .user("postgres")
+            .password("postgres")
+            .dbname("postgres");
+
+        let mut repo = Repository::new(config.clone(), Some(4), None)
+            .await
+            .expect("failed to connect");
+        repo.migrate().await.expect("failed to migrate");
+
+        Self {
+            repo,
+            config,
+            container,
+        }
+    }
+
+    /// Another repository over the same database, encrypting the content it writes
+    async fn encrypted_repo(&self) -> Repository {
+        let cipher = ContentCipher::from_base64_key(TEST_KEY).expect("invalid test key");
+        Repository::new(self.config.clone(), Some(2), Some(cipher))
+            .await
+            .expect("failed to connect")
+    }
+}
+
+fn draft(content: &str) -> NoteDraft {
+    NoteDraft {
+        content: content.to_string(),
+        expires_at: None,
+        remind_at: None,
+    }
+}
+
+fn metadata(value: serde_json::Value) -> Metadata {
+    match value {
+        serde_json::Value::Object(metadata) => metadata,
+        _ => Metadata::new(),
+    }
+}
+
+fn ids_of(notes: &[crate::models::Note]) -> Vec<i64> {
+    notes.iter().map(|note| note.id).collect()
+}
+
+#[tokio::test]
+#[ignore = "needs Docker"]
+async fn note_lifecycle() {
+    let db = TestDb::start().await;
+    let repo = &db.repo;
+
+    let note = repo
+        .create_note("first".into(), None, None)
+        .await
+        .expect("create");
+    assert_eq!(note.content, "first");
+    assert_eq!(
+        repo.get_one_note(note.id)
+            .await
+            .expect("get")
+            .map(|n| n.content),
+        Some("first".into())
+    );
+
+    let updated = repo
+        .update_note_if_version(note.id, "second".into(), None, None, None)
+        .await
+        .expect("update");
+    let ConditionalWrite::Applied(updated) = updated else {
+        panic!("update was not applied");
+    };
+    assert_eq!(updated.content, "second");
+    assert!(updated.updated_at >= note.updated_at);
+    assert_eq!(updated.version, note.version + 1);
+
+    let hour = Utc::now() + Duration::hours(1);
+    let timed = repo
+        .update_note_if_version(
+            note.id,
+            "second".into(),
+            Some(Some(hour)),
+            Some(Some(hour)),
+            None,
+        )
+        .await
+        .expect("update");
+    assert!(
+        matches!(timed, ConditionalWrite::Applied(n) if n.expires_at.is_some() && n.remind_at.is_some())
+    );
+    let cleared = repo
+        .update_note_if_version(note.id, "second".into(), Some(None), Some(None), None)
+        .await
+        .expect("update");
+    assert!(
+        matches!(cleared, ConditionalWrite::Applied(n) if n.expires_at.is_none() && n.remind_at.is_none())
+    );
+
+    let patched = repo
+        .patch_metadata(note.id, metadata(json!({"tag": "work"})), &[], None)
+        .await
+        .expect("patch");
+    assert!(matches!(patched, ConditionalWrite::Applied(n) if n.metadata["tag"] == "work"));
+    let patched = repo
+        .patch_metadata(note.id, Metadata::new(), &["tag".into()], None)
+        .await
+        .expect("patch");
+    assert!(matches!(patched, ConditionalWrite::Applied(n) if n.metadata.is_empty()));
+
+    assert_eq!(repo.get_all_notes().await.expect("get all").len(), 1);
+
+    let deleted = repo.delete_note(note.id, None).await.expect("delete");
+    assert!(matches!(deleted, ConditionalWrite::Applied(())));
+    assert!(repo.get_one_note(note.id).await.expect("get").is_none());
+}
+
+#[tokio::test]
+#[ignore = "needs Docker"]
+async fn conditional_writes_report_missing_and_modified_notes() {
+    let db = TestDb::start().await;
+    let repo = &db.repo;
+    let note = repo
+        .create_note("note".into(), None, None)
+        .await
+        .expect("create");
+    let stale = [note.version - 1];
+
+    let update = repo
+        .update_note_if_version(note.id, "new".into(), None, None, Some(&stale))
+        .await
+        .expect("update");
+    assert!(matches!(update, ConditionalWrite::PreconditionFailed));
+    let patch = repo
+        .patch_metadata(note.id, Metadata::new(), &[], Some(&stale))
+        .await
+        .expect("patch");
+    assert!(matches!(patch, ConditionalWrite::PreconditionFailed));
+    let delete = repo
+        .delete_note(note.id, Some(&stale))
+        .await
+        .expect("delete");
+    assert!(matches!(delete, ConditionalWrite::PreconditionFailed));
+
+    let missing = note.id + 1000;
+    let update = repo
+        .update_note_if_version(missing, "new".into(), None, None, None)
+        .await
+        .expect("update");
+    assert!(matches!(update, ConditionalWrite::NotFound));
+    let patch = repo
+        .patch_metadata(missing, Metadata::new(), &[], None)
+        .await
+        .expect("patch");
+    assert!(matches!(patch, ConditionalWrite::NotFound));
+    let delete = repo.delete_note(missing, None).await.expect("delete");
+    assert!(matches!(delete, ConditionalWrite::NotFound));
+
+    // The current version still matches
+    let delete = repo
+        .delete_note(note.id, Some(&[note.version]))
+        .await
+        .expect("delete");
+    assert!(matches!(delete, ConditionalWrite::Applied(())));
+}
+
+#[tokio::test]
+#[ignore = "needs Docker"]
+async fn concurrent_editors_of_a_version_conflict() {
+    let db = TestDb::start().await;
+    let repo = &db.repo;
+    let note = repo
+        .create_note("draft".into(), None, None)
+        .await
+        .expect("create");
+
+    let first = repo
+        .update_note_if_version(note.id, "first".into(), None, None, Some(&[note.version]))
+        .await
+        .expect("update");
+    let ConditionalWrite::Applied(first) = first else {
+        panic!("first edit was not applied");
+    };
+    assert_eq!(first.version, note.version + 1);
+
+    // The second editor started from the same version and must not overwrite the first
+    let second = repo
+        .update_note_if_version(note.id, "second".into(), None, None, Some(&[note.version]))
+        .await
+        .expect("update");
+    assert!(matches!(second, ConditionalWrite::PreconditionFailed));
+    assert_eq!(
+        repo.get_one_note(note.id)
+            .await
+            .expect("get")
+            .map(|n| (n.content, n.version)),
+        Some(("first".into(), first.version))
+    );
+
+    // Reordering keeps the version, like maintenance updates do
+    repo.reorder_notes(&[note.id]).await.expect("reorder");
+    let current = repo
+        .get_one_note(note.id)
+        .await
+        .expect("get")
+        .expect("note exists");
+    assert_eq!(current.version, first.version);
+}
+
+#[tokio::test]
+#[ignore = "needs Docker"]
+async fn expired_notes_are_hidden_and_removed() {
+    let db = TestDb::start().await;
+    let repo = &db.repo;
+    let past = Utc::now() - Duration::minutes(1);
+    let expired = repo
+        .create_note("expired".into(), Some(past), None)
+        .await
+        .expect("create");
+    let kept = repo
+        .create_note("kept".into(), Some(Utc::now() + Duration::hours(1)), None)
+        .await
+        .expect("create");
+
+    assert!(repo.get_one_note(expired.id).await.expect("get").is_none());
+    assert_eq!(
+        ids_of(&repo.get_all_notes().await.expect("get all")),
+        [kept.id]
+    );
+    let update = repo
+        .update_note_if_version(expired.id, "new".into(), None, None, None)
+        .await
+        .expect("update");
+    assert!(matches!(update, ConditionalWrite::NotFound));
+
+    let removed = repo.delete_expired_notes().await.expect("delete expired");
+    assert_eq!(removed, [(Tenant::default(), expired.id)]);
+    assert!(
+        repo.delete_expired_notes()
+            .await
+            .expect("delete expired")
+            .is_empty()
+    );
+}
+
+#[tokio::test]
+#[ignore = "needs Docker"]
+async fn batches_keep_their_order() {
+    let db = TestDb::start().await;
+    let repo = &db.repo;
+
+    let notes = repo
+        .batch_create_notes(&[draft("a"), draft("b"), draft("c")])
+        .await
+        .expect("batch create");
+    let contents: Vec<&str> = notes.iter().map(|note| note.content.as_str()).collect();
+    assert_eq!(contents, ["a", "b", "c"]);
+
+    let missing = notes[2].id + 1000;
+    let mut deleted = repo
+        .batch_delete_notes(&[notes[0].id, notes[2].id, missing])
+        .await
+        .expect("batch delete");
+    deleted.sort_unstable();
+    assert_eq!(deleted, [notes[0].id, notes[2].id]);
+    assert_eq!(
+        ids_of(&repo.get_all_notes().await.expect("get all")),
+        [notes[1].id]
+    );
+
+    let now = Utc::now();
+    let created = repo
+        .create_notes(&[NewNote {
+            content: "generated".into(),
+            created_at: now - Duration::days(1),
+            updated_at: now - Duration::days(1),
+        }])
+        .await
+        .expect("create notes");
+    assert_eq!(created, 1);
+}
+
+#[tokio::test]
+#[ignore = "needs Docker"]
+async fn listings_filter_order_and_seek() {
+    let db = TestDb::start().await;
+    let repo = &db.repo;
+    let notes = repo
+        .batch_create_notes(&[draft("a"), draft("b"), draft("c")])
+        .await
+        .expect("batch create");
+    let ids = ids_of(&notes);
+    repo.patch_metadata(ids[1], metadata(json!({"tag": "x"})), &[], None)
+        .await
+        .expect("patch");
+
+    let all = MetadataFilter::default();
+    let (page, total) = repo
+        .list_notes(2, 1, &all, NoteOrder::Id, false)
+        .await
+        .expect("list");
+    assert_eq!((ids_of(&page), total), (ids[1..].to_vec(), 3));
+
+    let tagged = MetadataFilter {
+        contains: Some(json!({"tag": "x"})),
+        has_keys: None,
+    };
+    assert_eq!(repo.count_notes(&tagged, false).await.expect("count"), 1);
+    let with_key = MetadataFilter {
+        contains: None,
+        has_keys: Some(vec!["tag".into()]),
+    };
+    let (page, _) = repo
+        .list_notes(10, 0, &with_key, NoteOrder::Id, false)
+        .await
+        .expect("list");
+    assert_eq!(ids_of(&page), [ids[1]]);
+
+    let page = repo
+        .get_notes_page(ids[0], 10, &all, false)
+        .await
+        .expect("page");
+    assert_eq!(ids_of(&page), ids[1..]);
+
+    assert!(
+        repo.reorder_notes(&[ids[2], ids[0], ids[1]])
+            .await
+            .expect("reorder")
+    );
+    let (page, _) = repo
+        .list_notes(10, 0, &all, NoteOrder::Position, false)
+        .await
+        .expect("list");
+    assert_eq!(ids_of(&page), [ids[2], ids[0], ids[1]]);
+
+    // Nothing moves when one of the notes is missing
+    assert!(
+        !repo
+            .reorder_notes(&[ids[0], ids[2] + 1000])
+            .await
+            .expect("reorder")
+    );
+    let (page, _) = repo
+        .list_notes(10, 0, &all, NoteOrder::Position, false)
+        .await
+        .expect("list");
+    assert_eq!(ids_of(&page), [ids[2], ids[0], ids[1]]);
+}
+
+#[tokio::test]
+#[ignore = "needs Docker"]
+async fn duplicates_and_search() {
+    let db = TestDb::start().await;
+    let repo = &db.repo;
+    let remind_at = Utc::now() + Duration::hours(1);
+    let note = repo
+        .create_note("buy green apples".into(), None, Some(remind_at))
+        .await
+        .expect("create");
+    repo.create_note("sell old car".into(), None, None)
+        .await
+        .expect("create");
+
+    let copy = repo
+        .duplicate_note(note.id)
+        .await
+        .expect("duplicate")
+        .expect("note exists");
+    assert_eq!(copy.content, note.content);
+    assert!(copy.remind_at.is_none());
+    assert!(
+        repo.duplicate_note(note.id + 1000)
+            .await
+            .expect("duplicate")
+            .is_none()
+    );
+
+    let found = repo.search_notes("apples", 10).await.expect("search");
+    assert_eq!(found.len(), 2);
+    let found = repo.search_notes("car -apples", 10).await.expect("search");
+    assert_eq!(found.len(), 1);
+    assert!(
+        repo.search_notes("nothing", 10)
+            .await
+            .expect("search")
+            .is_empty()
+    );
+}
+
+#[tokio::test]
+#[ignore = "needs Docker"]
+async fn due_reminders_are_claimed_once_and_cleared_unless_rescheduled() {
+    let db = TestDb::start().await;
+    let repo = &db.repo;
+    let due = Utc::now() - Duration::minutes(1);
+    let lease_until = Utc::now() + Duration::minutes(5);
+    let first = repo
+        .create_note("first".into(), None, Some(due))
+        .await
+        .expect("create");
+    let second = repo
+        .create_note("second".into(), None, Some(due))
+        .await
+        .expect("create");
+    repo.create_note("later".into(), None, Some(Utc::now() + Duration::hours(1)))
+        .await
+        .expect("create");
+
+    let reminders = repo
+        .claim_due_reminders(10, lease_until)
+        .await
+        .expect("claim reminders");
+    let due_ids: Vec<i64> = reminders.iter().map(|(_, note)| note.id).collect();
+    assert_eq!(due_ids, [first.id, second.id]);
+    // Claimed reminders aren't handed to another instance
+    assert!(
+        repo.claim_due_reminders(10, lease_until)
+            .await
+            .expect("claim reminders")
+            .is_empty()
+    );
+
+    let read_at = reminders[0].1.remind_at.expect("has a reminder");
+    assert!(
+        repo.clear_reminder(first.id, read_at)
+            .await
+            .expect("clear reminder")
+    );
+    // Rescheduled after it was read, so it stays
+    assert!(
+        !repo
+            .clear_reminder(second.id, read_at - Duration::seconds(1))
+            .await
+            .expect("clear reminder")
+    );
+
+    // Clearing the reminder is an update, claiming and ending a lease aren't
+    let version = |id| async move {
+        repo.get_one_note(id)
+            .await
+            .expect("get")
+            .expect("exists")
+            .version
+    };
+    assert_eq!(version(first.id).await, first.version + 1);
+    assert_eq!(version(second.id).await, second.version);
+    let updates: Vec<(i64, Option<i64>)> = repo
+        .transaction(|tx| Box::pin(async move { tx.pending_outbox(10).await }))
+        .await
+        .expect("pending")
+        .iter()
+        .filter(|e| e.operation == "updated")
+        .map(|e| (e.note_id, e.version))
+        .collect();
+    assert_eq!(updates, [(first.id, Some(first.version + 1))]);
+
+    let due_ids: Vec<i64> = repo
+        .claim_due_reminders(10, lease_until)
+        .await
+        .expect("claim reminders")
+        .iter()
+        .map(|(_, note)| note.id)
+        .collect();
+    assert_eq!(due_ids, [second.id]);
+}
+
+#[tokio::test]
+#[ignore = "needs Docker"]
+async fn template_lifecycle() {
+    let db = TestDb::start().await;
+    let repo = &db.repo;
+
+    let template = repo
+        .create_template("daily", "Plan for {date}")
+        .await
+        .expect("create");
+    assert_eq!(repo.list_templates().await.expect("list").len(), 1);
+
+    let updated = repo
+        .update_template(template.id, "weekly", "Plan for {week}")
+        .await
+        .expect("update")
+        .expect("template exists");
+    assert_eq!(updated.name, "weekly");
+    assert_eq!(
+        repo.get_template(template.id)
+            .await
+            .expect("get")
+            .map(|t| t.content),
+        Some("Plan for {week}".into())
+    );
+
+    let missing = template.id + 1000;
+    assert!(repo.get_template(missing).await.expect("get").is_none());
+    assert!(
+        repo.update_template(missing, "x", "y")
+            .await
+            .expect("update")
+            .is_none()
+    );
+    assert!(!repo.delete_template(missing).await.expect("delete"));
+
+    assert!(repo.delete_template(template.id).await.expect("delete"));
+    assert!(repo.list_templates().await.expect("list").is_empty());
+}
+
+#[tokio::test]
+#[ignore = "needs Docker"]
+async fn share_links_resolve_until_expired() {
+    let db = TestDb::start().await;
+    let repo = &db.repo;
+    let note = repo
+        .create_note("shared".into(), None, None)
+        .await
+        .expect("create");
+
+    let link = repo
+        .create_share_link(note.id, b"live", Utc::now() + Duration::hours(1))
+        .await
+        .expect("create link")
+        .expect("note exists");
+    assert_eq!(link.note_id, note.id);
+    repo.create_share_link(note.id, b"stale", Utc::now() - Duration::minutes(1))
+        .await
+        .expect("create link");
+    assert!(
+        repo.create_share_link(note.id + 1000, b"missing", Utc::now() + Duration::hours(1))
+            .await
+            .expect("create link")
+            .is_none()
+    );
+
+    let shared = repo.get_shared_note(b"live").await.expect("get shared");
+    assert_eq!(shared.map(|n| n.id), Some(note.id));
+    assert!(
+        repo.get_shared_note(b"stale")
+            .await
+            .expect("get shared")
+            .is_none()
+    );
+    assert!(
+        repo.get_shared_note(b"unknown")
+            .await
+            .expect("get shared")
+            .is_none()
+    );
+
+    assert_eq!(repo.delete_expired_share_links().await.expect("prune"), 1);
+}
+
+#[tokio::test]
+#[ignore = "needs Docker"]
+async fn activity_is_recorded_published_and_pruned() {
+    let db = TestDb::start().await;
+    let repo = &db.repo;
+    let changes = repo.listen_changes().await.expect("listen");
+    let mut changes = std::pin::pin!(changes);
+
+    repo.record_activity(&[1, 2], "created", "a")
+        .await
+        .expect("record");
+    repo.record_activity(&[1], "deleted", "b")
+        .await
+        .expect("record");
+
+    let activity = repo.recent_activity(None, 10).await.expect("activity");
+    let recorded: Vec<(i64, &str)> = activity
+        .iter()
+        .map(|a| (a.note_id, a.operation.as_str()))
+        .collect();
+    assert_eq!(recorded.len(), 3);
+    assert_eq!(recorded[0], (1, "deleted"));
+    let since = activity[0].occurred_at;
+    assert!(
+        repo.recent_activity(Some(since), 10)
+            .await
+            .expect("activity")
+            .is_empty()
+    );
+
+    let published = tokio::time::timeout(std::time::Duration::from_secs(5), changes.next())
+        .await
+        .expect("no change notification")
+        .expect("change feed ended");
+    assert_eq!(published.operation, "created");
+    assert_eq!(published.tenant_id, "default");
+    assert_eq!(published.origin.as_deref(), Some("a"));
+
+    let pruned = repo
+        .delete_activity_before(Utc::now() + Duration::seconds(1))
+        .await
+        .expect("prune");
+    assert_eq!(pruned, 3);
+}
+
+#[tokio::test]
+#[ignore = "needs Docker"]
+async fn soap_audit_is_recorded_filtered_and_pruned() {
+    let db = TestDb::start().await;
+    let repo = &db.repo;
+    for (operation, fault_code) in [("CreateNote", None), ("DeleteNote", Some("soap:Client"))] {
+        repo.record_soap_audit(&NewSoapAuditEntry {
+            operations: vec![operation.into()],
+            caller: "127.0.0.1:1234".into(),
+            forwarded_for: None,
+            soap_version: "1.1".into(),
+            fault_code: fault_code.map(Into::into),
+            latency_ms: 3,
+            envelope: None,
+        })
+        .await
+        .expect("record");
+    }
+
+    assert_eq!(
+        repo.soap_audit(None, None, false, 10)
+            .await
+            .expect("audit")
+            .len(),
+        2
+    );
+    let faults = repo.soap_audit(None, None, true, 10).await.expect("audit");
+    assert_eq!(faults.len(), 1);
+    assert_eq!(faults[0].operations, ["DeleteNote"]);
+    let created = repo
+        .soap_audit(None, Some("CreateNote"), false, 10)
+        .await
+        .expect("audit");
+    assert_eq!(created.len(), 1);
+
+    let pruned = repo
+        .delete_soap_audit_before(Utc::now() + Duration::seconds(1))
+        .await
+        .expect("prune");
+    assert_eq!(pruned, 2);
+}
+
+#[tokio::test]
+#[ignore = "needs Docker"]
+async fn archived_notes_are_listed_on_request() {
+    let db = TestDb::start().await;
+    let repo = &db.repo;
+    let old = repo
+        .create_note("old".into(), None, None)
+        .await
+        .expect("create");
+    let reminded = repo
+        .create_note(
+            "reminded".into(),
+            None,
+            Some(Utc::now() + Duration::hours(1)),
+        )
+        .await
+        .expect("create");
+
+    let archived = repo
+        .archive_notes(Utc::now() + Duration::seconds(1), 10)
+        .await
+        .expect("archive");
+    assert_eq!(archived, [(Tenant::default(), old.id)]);
+
+    let all = MetadataFilter::default();
+    let (current, _) = repo
+        .list_notes(10, 0, &all, NoteOrder::Id, false)
+        .await
+        .expect("list");
+    assert_eq!(ids_of(&current), [reminded.id]);
+    let with_archived = repo.get_notes_page(0, 10, &all, true).await.expect("page");
+    assert_eq!(ids_of(&with_archived), [old.id, reminded.id]);
+    assert_eq!(repo.count_notes(&all, true).await.expect("count"), 2);
+}
+
+#[tokio::test]
+#[ignore = "needs Docker"]
+async fn retention_deletes_live_and_archived_notes() {
+    let db = TestDb::start().await;
+    let repo = &db.repo;
+    repo.create_note("archived".into(), None, None)
+        .await
+        .expect("create");
+    repo.archive_notes(Utc::now() + Duration::seconds(1), 10)
+        .await
+        .expect("archive");
+    let live = repo
+        .create_note("live".into(), None, None)
+        .await
+        .expect("create");
+
+    let before = Utc::now() + Duration::seconds(1);
+    assert_eq!(
+        repo.count_notes_modified_before(before)
+            .await
+            .expect("count"),
+        2
+    );
+    assert_eq!(
+        repo.count_notes_modified_before(Utc::now() - Duration::days(1))
+            .await
+            .expect("count"),
+        0
+    );
+
+    let deleted = repo
+        .delete_notes_modified_before(before, 10)
+        .await
+        .expect("delete");
+    assert_eq!(deleted, [(Tenant::default(), live.id)]);
+    assert_eq!(
+        repo.delete_archived_notes_modified_before(before, 10)
+            .await
+            .expect("delete archived"),
+        1
+    );
+    let all = MetadataFilter::default();
+    assert_eq!(repo.count_notes(&all, true).await.expect("count"), 0);
+}
+
+#[tokio::test]
+#[ignore = "needs Docker"]
+async fn last_delete_is_restored_from_tombstones() {
+    let db = TestDb::start().await;
+    let repo = &db.repo;
+    let first = repo
+        .create_note("first".into(), None, None)
+        .await
+        .expect("create");
+    let second = repo
+        .create_note("second".into(), None, None)
+        .await
+        .expect("create");
+    let third = repo
+        .create_note("third".into(), None, None)
+        .await
+        .expect("create");
+
+    repo.delete_note(first.id, None).await.expect("delete");
+    repo.batch_delete_notes(&[second.id, third.id])
+        .await
+        .expect("batch delete");
+
+    let since = Utc::now() - Duration::minutes(1);
+    let restored = repo.restore_last_deleted(since).await.expect("restore");
+    assert_eq!(ids_of(&restored), [second.id, third.id]);
+    assert_eq!(restored[0].content, "second");
+    assert_eq!(restored[0].version, second.version);
+
+    let restored = repo.restore_last_deleted(since).await.expect("restore");
+    assert_eq!(ids_of(&restored), [first.id]);
+    assert!(
+        repo.restore_last_deleted(since)
+            .await
+            .expect("restore")
+            .is_empty()
+    );
+
+    repo.delete_note(first.id, None).await.expect("delete");
+    assert!(
+        repo.restore_last_deleted(Utc::now() + Duration::seconds(1))
+            .await
+            .expect("restore")
+            .is_empty()
+    );
+    assert_eq!(
+        repo.delete_note_tombstones_before(Utc::now() + Duration::seconds(1))
+            .await
+            .expect("cleanup"),
+        1
+    );
+}
+
+#[tokio::test]
+#[ignore = "needs Docker"]
+async fn attachments_outlive_their_note_until_it_is_gone_for_good() {
+    let db = TestDb::start().await;
+    let repo = &db.repo;
+    let note = repo
+        .create_note("with a file".into(), None, None)
+        .await
+        .expect("create");
+    let draft = |key: &str| AttachmentDraft {
+        filename: "report.pdf".into(),
+        content_type: "application/pdf".into(),
+        size: 42,
+        storage_key: key.into(),
+    };
+
+    let attachment = repo
+        .create_attachment(note.id, &draft("first"))
+        .await
+        .expect("attach")
+        .expect("note exists");
+    assert_eq!(attachment.note_id, note.id);
+    assert!(
+        repo.create_attachment(note.id + 1, &draft("second"))
+            .await
+            .expect("attach")
+            .is_none()
+    );
+    assert_eq!(
+        repo.existing_attachment_keys(&["first".into(), "second".into()])
+            .await
+            .expect("keys"),
+        ["first"]
+    );
+
+    // A deleted note may still be restored, so its attachments are kept
+    repo.delete_note(note.id, None).await.expect("delete");
+    assert!(
+        repo.delete_orphaned_attachments(10)
+            .await
+            .expect("cleanup")
+            .is_empty()
+    );
+    repo.delete_note_tombstones_before(Utc::now() + Duration::seconds(1))
+        .await
+        .expect("forget");
+    assert_eq!(
+        repo.delete_orphaned_attachments(10).await.expect("cleanup"),
+        ["first"]
+    );
+    assert!(
+        repo.get_attachment(note.id, attachment.id)
+            .await
+            .expect("get")
+            .is_none()
+    );
+}
+
+#[tokio::test]
+#[ignore = "needs Docker"]
+async fn due_digests_are_claimed_once_and_resume_from_the_last_run() {
+    let db = TestDb::start().await;
+    let repo = &db.repo;
+    let digest = repo
+        .create_digest(&DigestDraft {
+            email: "me@example.com".into(),
+            schedule: "0 8 * * *".into(),
+            active: true,
+            next_run_at: Utc::now() - Duration::seconds(1),
+        })
+        .await
+        .expect("subscribe");
+    let note = repo
+        .create_note("changed".into(), None, None)
+        .await
+        .expect("create");
+
+    let lease = Utc::now() + Duration::minutes(10);
+    let due = repo.claim_due_digests(10, lease).await.expect("claim");
+    assert_eq!(due.len(), 1);
+    assert_eq!(due[0].since, digest.created_at);
+    assert!(
+        repo.claim_due_digests(10, lease)
+            .await
+            .expect("claim")
+            .is_empty()
+    );
+
+    let ran_at = Utc::now();
+    let (notes, total) = repo
+        .changed_notes(due[0].since, ran_at, 10)
+        .await
+        .expect("changed notes");
+    assert_eq!((ids_of(&notes), total), (vec![note.id], 1));
+
+    repo.complete_digest(digest.id, ran_at, None)
+        .await
+        .expect("complete");
+    let digest = repo
+        .get_digest(digest.id)
+        .await
+        .expect("get")
+        .expect("exists");
+    assert_eq!(
+        digest.last_run_at.map(|at| at.timestamp_micros()),
+        Some(ran_at.timestamp_micros())
+    );
+    assert!(
+        !digest.active,
+        "a schedule without more runs deactivates the digest"
+    );
+}
+
+#[tokio::test]
+#[ignore = "needs Docker"]
+async fn tenants_are_isolated() {
+    let db = TestDb::start().await;
+    let repo = &db.repo;
+    let acme = Tenant::from("acme");
+
+    let own = acme
+        .clone()
+        .scope(repo.create_note("acme".into(), None, None))
+        .await
+        .expect("create");
+    let other = repo
+        .create_note("default".into(), None, None)
+        .await
+        .expect("create");
+
+    assert!(repo.get_one_note(own.id).await.expect("get").is_none());
+    let delete = repo.delete_note(own.id, None).await.expect("delete");
+    assert!(matches!(delete, ConditionalWrite::NotFound));
+    let seen = acme
+        .clone()
+        .scope(repo.get_all_notes())
+        .await
+        .expect("get all");
+    assert_eq!(ids_of(&seen), [own.id]);
+    let reordered = acme
+        .clone()
+        .scope(repo.reorder_notes(&[other.id, own.id]))
+        .await
+        .expect("reorder");
+    assert!(!reordered);
+
+    let everyone = repo.get_all_tenants_page(0, 10).await.expect("page");
+    let tenants: Vec<(&str, i64)> = everyone
+        .iter()
+        .map(|(tenant, note)| (tenant.as_str(), note.id))
+        .collect();
+    assert_eq!(tenants, [("acme", own.id), ("default", other.id)]);
+}
+
+#[tokio::test]
+#[ignore = "needs Docker"]
+async fn transactions_commit_or_roll_back() {
+    let db = TestDb::start().await;
+    let repo = &db.repo;
+
+    let ids = repo
+        .transaction(|tx| Box::pin(async move { tx.copy_notes(&[draft("a"), draft("b")]).await }))
+        .await
+        .expect("copy");
+    assert_eq!(ids.len(), 2);
+    assert_eq!(ids_of(&repo.get_all_notes().await.expect("get all")), ids);
+
+    let failed = repo
+        .transaction(|tx| {
+            Box::pin(async move {
+                tx.copy_notes(&[draft("c")]).await?;
+                Err::<(), _>(RepositoryError::Irreversible(0))
+            })
+        })
+        .await;
+    assert!(matches!(failed, Err(RepositoryError::Irreversible(0))));
+    assert_eq!(repo.get_all_notes().await.expect("get all").len(), 2);
+}
+
+#[tokio::test]
+#[ignore = "needs Docker"]
+async fn committed_changes_are_queued_in_the_outbox() {
+    let db = TestDb::start().await;
+    let repo = &db.repo;
+    let pending = || repo.transaction(|tx| Box::pin(async move { tx.pending_outbox(10).await }));
+
+    let note = repo
+        .create_note("a".into(), None, None)
+        .await
+        .expect("create");
+    repo.update_note_if_version(note.id, "b".into(), None, None, None)
+        .await
+        .expect("update");
+    repo.reorder_notes(&[note.id]).await.expect("reorder");
+    let rolled_back = repo
+        .transaction(|tx| {
+            Box::pin(async move {
+                tx.copy_notes(&[draft("c")]).await?;
+                Err::<(), _>(RepositoryError::Irreversible(0))
+            })
+        })
+        .await;
+    assert!(rolled_back.is_err());
+    repo.delete_note(note.id, None).await.expect("delete");
+
+    let events = pending().await.expect("pending");
+    let queued: Vec<(i64, &str, Option<i64>)> = events
+        .iter()
+        .map(|e| (e.note_id, e.operation.as_str(), e.version))
+        .collect();
+    assert_eq!(
+        queued,
+        [
+            (note.id, "created", Some(1)),
+            (note.id, "updated", Some(2)),
+            (note.id, "deleted", None)
+        ]
+    );
+
+    let ids: Vec<i64> = events.iter().map(|e| e.id).collect();
+    repo.transaction(|tx| Box::pin(async move { tx.mark_outbox_sent(&ids).await }))
+        .await
+        .expect("mark sent");
+    assert!(pending().await.expect("pending").is_empty());
+    assert_eq!(
+        repo.delete_sent_outbox_before(Utc::now() + Duration::seconds(1))
+            .await
+            .expect("prune"),
+        3
+    );
+}
+
+#[tokio::test]
+#[ignore = "needs Docker"]
+async fn webhook_deliveries_follow_subscriptions_and_open_the_circuit() {
+    let db = TestDb::start().await;
+    let repo = &db.repo;
+    let subscribe = |events: &[&str]| WebhookDraft {
+        url: "http://localhost/hook".into(),
+        events: events.iter().map(ToString::to_string).collect(),
+        active: true,
+    };
+    let all = repo
+        .create_webhook(&subscribe(&[]), "secret")
+        .await
+        .expect("create");
+    let deletions = repo
+        .create_webhook(&subscribe(&["deleted"]), "secret")
+        .await
+        .expect("create");
+    let other_tenant = Tenant::from("other")
+        .scope(repo.create_webhook(&subscribe(&[]), "secret"))
+        .await
+        .expect("create");
+
+    let note = repo
+        .create_note("a".into(), None, None)
+        .await
+        .expect("create");
+    repo.delete_note(note.id, None).await.expect("delete");
+    let queued = repo
+        .transaction(|tx| {
+            Box::pin(async move {
+                let events = tx.pending_outbox(10).await?;
+                tx.enqueue_webhook_deliveries(&events).await
+            })
+        })
+        .await
+        .expect("enqueue");
+    assert_eq!(queued, 3);
+
+    let operations = |webhook_id| async move {
+        let deliveries = repo
+            .list_webhook_deliveries(webhook_id, 10)
+            .await
+            .expect("deliveries");
+        deliveries
+            .into_iter()
+            .map(|d| d.operation)
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(operations(all.id).await, ["deleted", "created"]);
+    assert_eq!(operations(deletions.id).await, ["deleted"]);
+    assert!(operations(other_tenant.id).await.is_empty());
+
+    // Claimed deliveries aren't due again until the lease ends
+    let claimed = repo
+        .claim_webhook_deliveries(10, Utc::now() + Duration::minutes(1))
+        .await
+        .expect("claim");
+    assert_eq!(claimed.len(), 3);
+    assert!(
+        repo.claim_webhook_deliveries(10, Utc::now())
+            .await
+            .expect("claim")
+            .is_empty()
+    );
+
+    // Two failures in a row open the circuit, which stops further attempts
+    let open_until = Utc::now() + Duration::minutes(5);
+    for delivery in claimed.iter().filter(|d| d.webhook_id == all.id) {
+        let failure = DeliveryFailure {
+            status_code: Some(500),
+            error: "boom".into(),
+            retry_at: Some(Utc::now() - Duration::seconds(1)),
+        };
+        repo.fail_webhook_delivery(delivery.id, &failure, 2, open_until)
+            .await
+            .expect("fail");
+    }
+    let webhook = repo
+        .get_webhook(all.id)
+        .await
+        .expect("get")
+        .expect("exists");
+    assert_eq!(webhook.consecutive_failures, 2);
+    assert!(webhook.circuit_open_until.is_some());
+    assert!(
+        repo.claim_webhook_deliveries(10, Utc::now())
+            .await
+            .expect("claim")
+            .is_empty()
+    );
+
+    // Delivered ones are done, updating the webhook closes its circuit
+    let delivery = claimed
+        .iter()
+        .find(|d| d.webhook_id == deletions.id)
+        .expect("claimed");
+    repo.complete_webhook_delivery(delivery.id, 200)
+        .await
+        .expect("complete");
+    repo.update_webhook(all.id, &subscribe(&[]))
+        .await
+        .expect("update");
+    assert_eq!(
+        repo.claim_webhook_deliveries(10, Utc::now())
+            .await
+            .expect("claim")
+            .len(),
+        2
+    );
+}
+
+#[tokio::test]
+#[ignore = "needs Docker"]
+async fn migrations_revert_and_reapply() {
+    let mut db = TestDb::start().await;
+    let (applied, pending) = db.repo.migration_status().await.expect("status");
+    assert!(pending.is_empty());
+    assert_eq!(db.repo.startup_migrations().len(), applied.len());
+
+    let newest = applied.iter().map(|m| m.version).max().expect("migrated");
+    let reverted = db.repo.revert_migrations(2).await.expect("revert");
+    let versions: Vec<i64> = reverted.iter().map(|m| m.version).collect();
+    assert_eq!(versions, [newest, newest - 1]);
+    let (_, pending) = db.repo.migration_status().await.expect("status");
+    assert_eq!(pending.len(), 2);
+
+    db.repo.migrate().await.expect("migrate");
+    let (_, pending) = db.repo.migration_status().await.expect("status");
+    assert!(pending.is_empty());
+    db.repo
+        .create_note("after reapplying".into(), None, None)
+        .await
+        .expect("create");
+}
+
+#[tokio::test]
+#[ignore = "needs Docker"]
+async fn plaintext_notes_get_encrypted() {
+    let db = TestDb::start().await;
+    let plain = db
+        .repo
+        .create_note("secret".into(), None, None)
+        .await
+        .expect("create");
+
+    let encrypted = db.encrypted_repo().await;
+    assert_eq!(
+        encrypted.encrypt_plaintext_notes().await.expect("encrypt"),
+        1
+    );
+    assert_eq!(
+        encrypted.encrypt_plaintext_notes().await.expect("encrypt"),
+        0
+    );
+
+    let note = encrypted
+        .get_one_note(plain.id)
+        .await
+        .expect("get")
+        .expect("note exists");
+    assert_eq!(note.content, "secret");
+    assert_eq!(note.updated_at, plain.updated_at);
+    assert_eq!(note.version, plain.version);
+    // Without the key only the stored ciphertext is seen
+    let stored = db
+        .repo
+        .get_one_note(plain.id)
+        .await
+        .expect("get")
+        .expect("note exists");
+    assert_ne!(stored.content, "secret");
+}
+
+#[tokio::test]
+#[ignore = "needs Docker"]
+async fn identical_notes_are_found_despite_encryption() {
+    let db = TestDb::start().await;
+    let repo = db.encrypted_repo().await;
+    let first = repo
+        .create_note("Hello  world".into(), None, None)
+        .await
+        .expect("create");
+    let second = repo
+        .create_note("hello\nWORLD".into(), None, None)
+        .await
+        .expect("create");
+    repo.create_note("Goodbye world".into(), None, None)
+        .await
+        .expect("create");
+
+    assert_eq!(
+        repo.find_identical_note("HELLO WORLD").await.expect("find"),
+        Some(first.id)
+    );
+    assert_eq!(repo.find_identical_note("hello").await.expect("find"), None);
+
+    let clusters = repo.duplicate_clusters(10).await.expect("clusters");
+    assert_eq!(clusters.len(), 1);
+    assert_eq!(clusters[0].note_ids, vec![first.id, second.id]);
+    // Nothing is left to hash, every write stores the hash
+    assert_eq!(repo.hash_unhashed_notes().await.expect("hash"), 0);
+}
+
+#[tokio::test]
+#[ignore = "needs Docker"]
+async fn users_are_provisioned_once_in_their_first_tenant() {
+    let db = TestDb::start().await;
+    let user = |email: &str| NewUser {
+        issuer: "https://idp.example.com/".into(),
+        subject: "user-1".into(),
+        email: Some(email.into()),
+        name: None,
+    };
+
+    let (first, created) = Tenant::from("acme")
+        .scope(db.repo.provision_user(&user("old@example.com")))
+        .await
+        .expect("provision");
+    assert!(created);
+    assert_eq!(first.tenant_id, "acme");
+
+    let (again, created) = Tenant::from("globex")
+        .scope(db.repo.provision_user(&user("new@example.com")))
+        .await
+        .expect("provision");
+    assert!(!created);
+    assert_eq!(again.id, first.id);
+    assert_eq!(again.tenant_id, "acme");
+    assert_eq!(again.email.as_deref(), Some("new@example.com"));
+    assert!(again.last_seen_at >= first.last_seen_at);
+
+    let users = db.repo.list_users(Some("acme"), 10).await.expect("list");
+    assert_eq!(users.len(), 1);
+    assert!(
+        db.repo
+            .list_users(Some("globex"), 10)
+            .await
+            .expect("list")
+            .is_empty()
+    );
+}
+
+#[tokio::test]
+#[ignore = "needs Docker"]
+async fn lost_connection_fails_fast_as_transient() {
+    let db = TestDb::start().await;
+    db.repo.ping().await.expect("ping");
+    let mut config = db.config.clone();
+
+    db.container.stop().await.expect("failed to stop Postgres");
+
+    let error = db.repo.ping().await.expect_err("database is down");
+    assert!(error.is_transient(), "{error:?}");
+    // Marked as degraded now, later calls don't wait for a connection
+    let Err(error) = db.repo.get_all_notes().await else {
+        panic!("database is down");
+    };
+    assert!(
+        matches!(error, RepositoryError::ConnectionLost(_)),
+        "{error:?}"
+    );
+
+    config.connect_timeout(std::time::Duration::from_secs(1));
+    let error = Repository::new(config, None, None)
+        .await
+        .err()
+        .expect("database is down");
+    assert!(error.is_transient(), "{error:?}");
+}
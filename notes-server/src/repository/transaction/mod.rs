@@ -0,0 +1,156 @@
+use deadpool_postgres::Object;
+use tokio_postgres::{binary_copy::BinaryCopyInWriter, types::Type};
+
+use crate::{
+    models::{NoteDraft, OutboxEvent},
+    tenant::Tenant,
+};
+
+use super::{Repository, RepositoryError, content_hash};
+
+/// Database transaction handed to the closure of `Repository::transaction`.
+/// Writes made through it are only visible to others once it is committed
+pub struct Transaction<'a> {
+    repo: &'a Repository,
+    transaction: deadpool_postgres::Transaction<'a>,
+}
+
+impl<'a> Transaction<'a> {
+    pub(super) async fn begin(
+        repo: &'a Repository,
+        client: &'a mut Object,
+    ) -> Result<Self, RepositoryError> {
+        Ok(Self {
+            repo,
+            transaction: client.transaction().await?,
+        })
+    }
+
+    pub(super) async fn commit(self) -> Result<(), RepositoryError> {
+        Ok(self.transaction.commit().await?)
+    }
+
+    /// Streams the notes to the database with the binary COPY protocol, which for
+    /// thousands of notes is much faster than inserting them. COPY can't return the
+    /// rows, so their IDs are taken from the sequence upfront and returned in order
+    pub async fn copy_notes(&self, notes: &[NoteDraft]) -> Result<Vec<i64>, RepositoryError> {
+        let count = i64::try_from(notes.len()).unwrap_or(i64::MAX);
+        let ids: Vec<i64> = self
+            .transaction
+            .query(
+                "SELECT nextval(pg_get_serial_sequence('notes', 'id')) \
+                 FROM generate_series(1, $1::bigint)",
+                &[&count],
+            )
+            .await?
+            .iter()
+            .map(|row| row.get(0))
+            .collect();
+
+        let sink = self
+            .transaction
+            .copy_in(
+                "COPY notes (id, content, expires_at, remind_at, tenant_id, content_hash) \
+                 FROM STDIN BINARY",
+            )
+            .await?;
+        let writer = BinaryCopyInWriter::new(
+            sink,
+            &[
+                Type::INT8,
+                Type::TEXT,
+                Type::TIMESTAMPTZ,
+                Type::TIMESTAMPTZ,
+                Type::TEXT,
+                Type::TEXT,
+            ],
+        );
+        let tenant = Tenant::current();
+        let mut writer = std::pin::pin!(writer);
+        for (id, note) in ids.iter().zip(notes) {
+            writer
+                .as_mut()
+                .write(&[
+                    id,
+                    &self.repo.seal(&note.content),
+                    &note.expires_at,
+                    &note.remind_at,
+                    &tenant.as_str(),
+                    &content_hash(&note.content),
+                ])
+                .await?;
+        }
+        writer.finish().await?;
+
+        Ok(ids)
+    }
+
+    /// Up to `limit` oldest changes not relayed yet, of any tenant. They stay locked
+    /// until the transaction ends, other relays skip them rather than wait
+    pub async fn pending_outbox(&self, limit: i64) -> Result<Vec<OutboxEvent>, RepositoryError> {
+        let rows = self
+            .transaction
+            .query(
+                "SELECT id, note_id, operation, tenant_id, version, occurred_at FROM note_outbox \
+                 WHERE sent_at IS NULL ORDER BY id LIMIT $1 FOR UPDATE SKIP LOCKED",
+                &[&limit],
+            )
+            .await?;
+
+        Ok(rows
+            .iter()
+            .map(|row| OutboxEvent {
+                id: row.get("id"),
+                note_id: row.get("note_id"),
+                operation: row.get("operation"),
+                tenant_id: row.get("tenant_id"),
+                version: row.get("version"),
+                occurred_at: row.get("occurred_at"),
+            })
+            .collect())
+    }
+
+    /// Marks the outbox entries as relayed
+    pub async fn mark_outbox_sent(&self, ids: &[i64]) -> Result<(), RepositoryError> {
+        self.transaction
+            .execute(
+                "UPDATE note_outbox SET sent_at = NOW() WHERE id = ANY($1)",
+                &[&ids],
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    /// Queues a delivery of each change to every active webhook of its tenant
+    /// subscribed to its operation, returning how many were queued
+    pub async fn enqueue_webhook_deliveries(
+        &self,
+        events: &[OutboxEvent],
+    ) -> Result<u64, RepositoryError> {
+        let mut ids = Vec::with_capacity(events.len());
+        let mut tenants = Vec::with_capacity(events.len());
+        let mut operations = Vec::with_capacity(events.len());
+        let mut payloads = Vec::with_capacity(events.len());
+        for event in events {
+            ids.push(event.id);
+            tenants.push(event.tenant_id.as_str());
+            operations.push(event.operation.as_str());
+            payloads.push(serde_json::to_value(event).unwrap_or_default());
+        }
+
+        Ok(self
+            .transaction
+            .execute(
+                "INSERT INTO webhook_deliveries (webhook_id, event_id, operation, payload) \
+                 SELECT w.id, e.id, e.operation, e.payload \
+                 FROM unnest($1::bigint[], $2::text[], $3::text[], $4::jsonb[]) \
+                     AS e(id, tenant_id, operation, payload) \
+                 JOIN webhooks w ON w.tenant_id = e.tenant_id AND w.active \
+                     AND (cardinality(w.events) = 0 OR e.operation = ANY(w.events)) \
+                 ORDER BY e.id, w.id",
+                &[&ids, &tenants, &operations, &payloads],
+            )
+            .await?)
+    }
+}
@@ -0,0 +1,185 @@
+use std::{collections::HashSet, sync::LazyLock};
+
+use ammonia::Builder;
+
+/// Largest note content accepted by default, after normalization
+const DEFAULT_MAX_BYTES: usize = 1024 * 1024;
+
+/// Why note content, or the expiration time it's written with, was rejected
+#[derive(Debug, Clone, Copy, thiserror::Error)]
+pub enum ContentError {
+    #[error("content is empty")]
+    Empty,
+
+    #[error("content is longer than {max_bytes} bytes")]
+    TooLarge { max_bytes: usize },
+
+    #[error("expiration time has already passed")]
+    ExpirationPassed,
+}
+
+/// Keeps no markup at all, only the text. Script and style elements are dropped
+/// along with their contents
+static STRICT: LazyLock<Builder<'static>> = LazyLock::new(|| {
+    let mut builder = Builder::empty();
+    builder.clean_content_tags(HashSet::from(["script", "style"]));
+    builder
+});
+
+/// Keeps formatting and links, drops scripts, styles, event handler attributes
+/// and `javascript:` URLs
+static LENIENT: LazyLock<Builder<'static>> = LazyLock::new(Builder::default);
+
+/// How HTML in note content is sanitized before it's stored. Content without a tag is
+/// plain text and is stored as is, e.g. `a < b && c`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Sanitization {
+    /// All tags are stripped
+    Strict,
+    /// Only safe tags and attributes are kept
+    Lenient,
+    /// Content is stored as given
+    #[default]
+    Off,
+}
+
+impl Sanitization {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "strict" => Some(Self::Strict),
+            "lenient" => Some(Self::Lenient),
+            "off" => Some(Self::Off),
+            _ => None,
+        }
+    }
+
+    fn apply(self, content: String) -> String {
+        if !has_tag(&content) {
+            return content;
+        }
+        match self {
+            Self::Strict => STRICT.clean(&content).to_string(),
+            Self::Lenient => LENIENT.clean(&content).to_string(),
+            Self::Off => content,
+        }
+    }
+}
+
+/// Whether the content has something that starts a tag, a comment or a doctype: `<`
+/// followed by a letter, `/` or `!`. Other `<` are plain text the sanitizer would escape
+fn has_tag(content: &str) -> bool {
+    content
+        .as_bytes()
+        .windows(2)
+        .any(|pair| pair[0] == b'<' && (pair[1].is_ascii_alphabetic() || b"/!".contains(&pair[1])))
+}
+
+/// What happens when a note is created with the same content as an existing one,
+/// ignoring case and whitespace
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DuplicatePolicy {
+    /// The note is created, existing notes are not looked up
+    #[default]
+    Allow,
+    /// The note is created, the response tells which note it duplicates
+    Hint,
+    /// The note is not created
+    Reject,
+}
+
+impl DuplicatePolicy {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "allow" => Some(Self::Allow),
+            "hint" => Some(Self::Hint),
+            "reject" => Some(Self::Reject),
+            _ => None,
+        }
+    }
+}
+
+/// How note content is normalized and checked before it's stored, the same for
+/// every protocol the note is written over
+#[derive(Debug, Clone, Copy)]
+pub struct ContentRules {
+    /// Longest content in bytes, as stored
+    pub max_bytes: usize,
+    /// How HTML in the content is sanitized
+    pub sanitization: Sanitization,
+    /// Whether notes identical to existing ones are created
+    pub duplicates: DuplicatePolicy,
+}
+
+impl Default for ContentRules {
+    fn default() -> Self {
+        Self {
+            max_bytes: DEFAULT_MAX_BYTES,
+            sanitization: Sanitization::default(),
+            duplicates: DuplicatePolicy::default(),
+        }
+    }
+}
+
+impl ContentRules {
+    /// Content with `\r\n` and `\r` line endings turned into `\n`, HTML sanitized and
+    /// surrounding whitespace trimmed. Empty (or whitespace-only) and too large content
+    /// is rejected
+    pub fn normalize(self, content: String) -> Result<String, ContentError> {
+        let content = if content.contains('\r') {
+            content.replace("\r\n", "\n").replace('\r', "\n")
+        } else {
+            content
+        };
+        let content = self.sanitization.apply(content);
+
+        let trimmed = content.trim();
+        if trimmed.is_empty() {
+            return Err(ContentError::Empty);
+        }
+        if trimmed.len() > self.max_bytes {
+            return Err(ContentError::TooLarge {
+                max_bytes: self.max_bytes,
+            });
+        }
+
+        if trimmed.len() == content.len() {
+            Ok(content)
+        } else {
+            Ok(trimmed.to_string())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ContentRules, Sanitization};
+
+    fn normalize(sanitization: Sanitization, content: &str) -> String {
+        let rules = ContentRules {
+            sanitization,
+            ..ContentRules::default()
+        };
+        rules.normalize(content.to_string()).unwrap()
+    }
+
+    #[test]
+    fn plain_text_is_stored_unchanged() {
+        for sanitization in [
+            Sanitization::Strict,
+            Sanitization::Lenient,
+            Sanitization::Off,
+        ] {
+            for content in ["a < b && c", "if x < 5 && y > 3", "1 <2 & 3> 0"] {
+                assert_eq!(normalize(sanitization, content), content);
+            }
+        }
+    }
+
+    #[test]
+    fn markup_is_sanitized() {
+        let content = "<b>bold</b><script>alert(1)</script>";
+        assert_eq!(normalize(Sanitization::Lenient, content), "<b>bold</b>");
+        assert_eq!(normalize(Sanitization::Strict, content), "bold");
+        assert_eq!(normalize(Sanitization::Off, content), content);
+    }
+}
@@ -0,0 +1,87 @@
+use chrono::{DateTime, Utc};
+use cron::Schedule;
+
+use std::{fmt::Write, str::FromStr};
+
+use super::format_time;
+use crate::{
+    dto::DigestRequest,
+    models::{DigestDraft, Note},
+};
+
+/// Why a digest subscription was rejected
+#[derive(Debug, Clone, Copy, thiserror::Error)]
+pub enum InvalidDigest {
+    #[error("email must be an address like name@example.com")]
+    Email,
+
+    #[error("schedule must be a cron expression that runs again, e.g. `0 8 * * Mon-Fri`")]
+    Schedule,
+}
+
+/// Cron expression with 5 fields (minute to day of week), 6 (seconds first) or 7
+/// (year last), or a shorthand like `@daily`. Times are in UTC
+pub(super) fn parse_schedule(schedule: &str) -> Result<Schedule, InvalidDigest> {
+    let schedule = schedule.trim();
+    let expression = if schedule.split_whitespace().count() == 5 {
+        format!("0 {schedule}")
+    } else {
+        schedule.to_string()
+    };
+    Schedule::from_str(&expression).map_err(|_| InvalidDigest::Schedule)
+}
+
+/// When the schedule runs next after `after`, `None` if it never runs again
+pub(super) fn next_run(schedule: &Schedule, after: DateTime<Utc>) -> Option<DateTime<Utc>> {
+    schedule.after(&after).next()
+}
+
+/// Subscription to store, due at the schedule's next run after `now`
+pub(super) fn draft(
+    request: &DigestRequest,
+    now: DateTime<Utc>,
+) -> Result<DigestDraft, InvalidDigest> {
+    let email = request.email.trim();
+    let valid_email = email.split_once('@').is_some_and(|(name, domain)| {
+        !name.is_empty() && !domain.is_empty() && !email.contains(char::is_whitespace)
+    });
+    if !valid_email {
+        return Err(InvalidDigest::Email);
+    }
+
+    let schedule = parse_schedule(&request.schedule)?;
+    let next_run_at = next_run(&schedule, now).ok_or(InvalidDigest::Schedule)?;
+
+    Ok(DigestDraft {
+        email: email.to_string(),
+        schedule: request.schedule.trim().to_string(),
+        active: request.active,
+        next_run_at,
+    })
+}
+
+/// Plain-text digest of the changed notes, in the order they changed. Notes left
+/// out for the size of the email are only counted
+pub(super) fn render(notes: &[Note], total: i64, since: DateTime<Utc>) -> String {
+    let mut body = format!("Notes changed since {}:\n", format_time(since));
+    for note in notes {
+        let change = if note.created_at > since {
+            "New"
+        } else {
+            "Updated"
+        };
+        let _ = write!(
+            body,
+            "\n{change} note #{} ({}):\n{}\n",
+            note.id,
+            format_time(note.updated_at),
+            note.content
+        );
+    }
+
+    let omitted = total - i64::try_from(notes.len()).unwrap_or(i64::MAX);
+    if omitted > 0 {
+        let _ = write!(body, "\n...and {omitted} more changed notes\n");
+    }
+    body
+}
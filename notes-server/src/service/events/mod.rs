@@ -0,0 +1,114 @@
+use chrono::{DateTime, Utc};
+use futures_util::{Stream, stream};
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast::{self, error::RecvError};
+use utoipa::ToSchema;
+
+use std::sync::Arc;
+
+use crate::tenant::Tenant;
+
+/// Number of events buffered per subscriber before the slowest ones start missing events
+const EVENTS_CAPACITY: usize = 256;
+
+/// Kind of change made to a note
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum NoteOperation {
+    Created,
+    Updated,
+    Deleted,
+}
+
+impl NoteOperation {
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Created => "created",
+            Self::Updated => "updated",
+            Self::Deleted => "deleted",
+        }
+    }
+
+    pub fn parse(operation: &str) -> Option<Self> {
+        match operation {
+            "created" => Some(Self::Created),
+            "updated" => Some(Self::Updated),
+            "deleted" => Some(Self::Deleted),
+            _ => None,
+        }
+    }
+}
+
+/// A single change of a note, published to change stream subscribers
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct NoteEvent {
+    pub id: i64,
+    pub operation: NoteOperation,
+    pub timestamp: DateTime<Utc>,
+    /// Only subscribers of the same tenant receive the event
+    #[serde(skip)]
+    pub tenant: Tenant,
+}
+
+/// Fan-out of note change events to any number of subscribers
+#[derive(Debug, Clone)]
+pub struct NoteEvents {
+    sender: broadcast::Sender<NoteEvent>,
+    /// Random ID of this instance, recorded along with its changes
+    origin: Arc<str>,
+}
+
+impl Default for NoteEvents {
+    fn default() -> Self {
+        Self {
+            sender: broadcast::channel(EVENTS_CAPACITY).0,
+            origin: format!("{:016x}", rand::random::<u64>()).into(),
+        }
+    }
+}
+
+impl NoteEvents {
+    /// Tells the changes made through this instance apart from the other instances' ones
+    pub fn origin(&self) -> &str {
+        &self.origin
+    }
+
+    pub fn publish(&self, event: NoteEvent) {
+        // Having no subscribers is not an error
+        let _ = self.sender.send(event);
+    }
+
+    /// Publishes a change of each of the current tenant's notes, made just now
+    pub fn publish_changes(&self, ids: &[i64], operation: NoteOperation) {
+        let tenant = Tenant::current();
+        let timestamp = Utc::now();
+        for &id in ids {
+            self.publish(NoteEvent {
+                id,
+                operation,
+                timestamp,
+                tenant: tenant.clone(),
+            });
+        }
+    }
+
+    /// Stream of the tenant's events published after the call. A subscriber that falls
+    /// behind skips the events it missed instead of ending the stream
+    pub fn subscribe(&self, tenant: Tenant) -> impl Stream<Item = NoteEvent> + Send + 'static {
+        let receiver = self.sender.subscribe();
+        stream::unfold((receiver, tenant), |(mut receiver, tenant)| async move {
+            loop {
+                match receiver.recv().await {
+                    Ok(event) if event.tenant == tenant => {
+                        return Some((event, (receiver, tenant)));
+                    }
+                    Ok(_) => {}
+                    Err(RecvError::Lagged(skipped)) => {
+                        tracing::warn!("Change stream subscriber missed {skipped} events");
+                    }
+                    Err(RecvError::Closed) => return None,
+                }
+            }
+        })
+    }
+}
@@ -0,0 +1,83 @@
+use serde::Deserialize;
+use utoipa::ToSchema;
+
+use std::fmt::Write;
+
+use crate::{dto::NoteResponse, models::Note};
+
+/// File format of a notes export
+#[derive(Debug, Clone, Copy, Default, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    #[default]
+    Json,
+    Csv,
+    Markdown,
+}
+
+impl ExportFormat {
+    pub const fn content_type(self) -> &'static str {
+        match self {
+            Self::Json => "application/json",
+            Self::Csv => "text/csv; charset=utf-8",
+            Self::Markdown => "text/markdown; charset=utf-8",
+        }
+    }
+
+    pub const fn file_extension(self) -> &'static str {
+        match self {
+            Self::Json => "json",
+            Self::Csv => "csv",
+            Self::Markdown => "md",
+        }
+    }
+
+    pub(super) fn header(self) -> String {
+        match self {
+            Self::Json => "[".to_string(),
+            Self::Csv => "id,content,created_at,updated_at\n".to_string(),
+            Self::Markdown => "# Notes\n\n".to_string(),
+        }
+    }
+
+    pub(super) fn footer(self) -> String {
+        match self {
+            Self::Json => "]".to_string(),
+            Self::Csv | Self::Markdown => String::new(),
+        }
+    }
+
+    /// Appends a single note to `out`, `first` tells whether it's the first note of the file
+    pub(super) fn write_note(self, out: &mut String, note: Note, first: bool) {
+        match self {
+            Self::Json => {
+                if !first {
+                    out.push(',');
+                }
+                // Serializing a plain struct of strings, numbers and timestamps can't fail
+                let json = serde_json::to_string(&NoteResponse::from(note)).unwrap_or_default();
+                out.push_str(&json);
+            }
+            Self::Csv => {
+                let _ = writeln!(
+                    out,
+                    "{},\"{}\",{},{}",
+                    note.id,
+                    note.content.replace('"', "\"\""),
+                    note.created_at.to_rfc3339(),
+                    note.updated_at.to_rfc3339()
+                );
+            }
+            Self::Markdown => {
+                let _ = write!(
+                    out,
+                    "## Note {}\n\n*Created: {}, updated: {}*\n\n{}\n\n",
+                    note.id,
+                    note.created_at.to_rfc3339(),
+                    note.updated_at.to_rfc3339(),
+                    note.content
+                );
+            }
+        }
+    }
+}
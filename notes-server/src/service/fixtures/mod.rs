@@ -0,0 +1,84 @@
+use chrono::{DateTime, TimeDelta, Utc};
+use rand::{Rng, rng};
+
+use crate::models::NewNote;
+
+/// Words synthetic note contents are made of
+const WORDS: [&str; 24] = [
+    "lorem",
+    "ipsum",
+    "dolor",
+    "sit",
+    "amet",
+    "consectetur",
+    "adipiscing",
+    "elit",
+    "sed",
+    "do",
+    "eiusmod",
+    "tempor",
+    "incididunt",
+    "ut",
+    "labore",
+    "et",
+    "dolore",
+    "magna",
+    "aliqua",
+    "enim",
+    "ad",
+    "minim",
+    "veniam",
+    "quis",
+];
+
+/// Shape of the generated notes
+#[derive(Debug, Clone, Copy)]
+pub struct FixtureSpec {
+    /// Creation times are spread uniformly over `from..=to`
+    pub from: DateTime<Utc>,
+    pub to: DateTime<Utc>,
+    /// Content length in bytes is picked uniformly from `min_size..=max_size`
+    pub min_size: usize,
+    pub max_size: usize,
+}
+
+impl FixtureSpec {
+    /// `count` random notes. Some of them are edited later than created, up to `to`
+    pub(super) fn generate(&self, count: usize) -> Vec<NewNote> {
+        let mut rng = rng();
+        let span = (self.to - self.from).num_seconds();
+
+        (0..count)
+            .map(|_| {
+                let created_at = self.from + TimeDelta::seconds(rng.random_range(0..=span));
+                let updated_at = if rng.random_bool(0.3) {
+                    let remaining = (self.to - created_at).num_seconds();
+                    created_at + TimeDelta::seconds(rng.random_range(0..=remaining))
+                } else {
+                    created_at
+                };
+
+                let size = rng.random_range(self.min_size..=self.max_size);
+
+                NewNote {
+                    content: random_content(&mut rng, size),
+                    created_at,
+                    updated_at,
+                }
+            })
+            .collect()
+    }
+}
+
+/// Space-separated words, truncated to exactly `size` bytes
+fn random_content(rng: &mut impl Rng, size: usize) -> String {
+    let mut content = String::with_capacity(size + 16);
+    while content.len() < size {
+        if !content.is_empty() {
+            content.push(' ');
+        }
+        content.push_str(WORDS[rng.random_range(0..WORDS.len())]);
+    }
+    content.truncate(size);
+    content
+}
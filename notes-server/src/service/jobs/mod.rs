@@ -0,0 +1,214 @@
+use async_trait::async_trait;
+use chrono::Utc;
+
+use std::sync::Arc;
+
+use super::{
+    ACTIVITY_RETENTION, NoteOperation, NoteService, OUTBOX_RETENTION, RetentionPolicy,
+    SOAP_AUDIT_RETENTION, UNDO_WINDOW, WEBHOOK_DELIVERY_RETENTION, by_tenant,
+};
+use crate::jobs::{Job, JobError};
+
+/// Removes expired notes and share links, and the activity, SOAP audit, outbox, webhook
+/// delivery and tombstone entries kept past their retention
+pub struct Cleanup(pub Arc<NoteService>);
+
+#[async_trait]
+impl Job for Cleanup {
+    fn name(&self) -> &'static str {
+        "cleanup"
+    }
+
+    async fn run(&self) -> Result<(), JobError> {
+        let service = &self.0;
+        let repo = &service.repo;
+
+        let expired = repo
+            .delete_expired_notes()
+            .await
+            .map_err(|e| format!("failed to remove expired notes: {e}"))?;
+        if !expired.is_empty() {
+            tracing::info!("Removed {} expired notes", expired.len());
+            for (tenant, ids) in by_tenant(expired) {
+                tenant
+                    .scope(service.record_changes(&ids, NoteOperation::Deleted))
+                    .await;
+            }
+        }
+
+        let count = repo
+            .delete_expired_share_links()
+            .await
+            .map_err(|e| format!("failed to remove expired share links: {e}"))?;
+        if count > 0 {
+            tracing::info!("Removed {count} expired share links");
+        }
+
+        let count = repo
+            .delete_activity_before(Utc::now() - ACTIVITY_RETENTION)
+            .await
+            .map_err(|e| format!("failed to remove old activity entries: {e}"))?;
+        if count > 0 {
+            tracing::info!("Removed {count} old activity entries");
+        }
+
+        let count = repo
+            .delete_soap_audit_before(Utc::now() - SOAP_AUDIT_RETENTION)
+            .await
+            .map_err(|e| format!("failed to remove old SOAP audit entries: {e}"))?;
+        if count > 0 {
+            tracing::info!("Removed {count} old SOAP audit entries");
+        }
+
+        let count = repo
+            .delete_sent_outbox_before(Utc::now() - OUTBOX_RETENTION)
+            .await
+            .map_err(|e| format!("failed to remove relayed outbox entries: {e}"))?;
+        if count > 0 {
+            tracing::info!("Removed {count} relayed outbox entries");
+        }
+
+        let count = repo
+            .delete_webhook_deliveries_before(Utc::now() - WEBHOOK_DELIVERY_RETENTION)
+            .await
+            .map_err(|e| format!("failed to remove old webhook deliveries: {e}"))?;
+        if count > 0 {
+            tracing::info!("Removed {count} old webhook deliveries");
+        }
+
+        let count = repo
+            .delete_note_tombstones_before(Utc::now() - UNDO_WINDOW)
+            .await
+            .map_err(|e| format!("failed to remove note tombstones: {e}"))?;
+        if count > 0 {
+            tracing::info!("Removed {count} tombstones past the undo window");
+        }
+        Ok(())
+    }
+}
+
+/// Applies the retention rules in order. Archived notes are only listed on request, no
+/// events are published for them, while deleted notes are recorded as deletions. In a
+/// dry run the notes are only counted
+pub struct Retention {
+    pub service: Arc<NoteService>,
+    pub policy: RetentionPolicy,
+}
+
+#[async_trait]
+impl Job for Retention {
+    fn name(&self) -> &'static str {
+        "retention"
+    }
+
+    async fn run(&self) -> Result<(), JobError> {
+        for &rule in &self.policy.rules {
+            if self.policy.dry_run {
+                let count = self
+                    .service
+                    .count_retained_notes(rule)
+                    .await
+                    .map_err(|e| format!("failed to check retention rule {rule}: {e}"))?;
+                tracing::info!(
+                    "Retention rule {rule} would {} {count} notes (dry run)",
+                    rule.action.as_str()
+                );
+            } else {
+                self.service
+                    .apply_retention_rule(rule)
+                    .await
+                    .map_err(|e| format!("failed to apply retention rule {rule}: {e}"))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Removes the attachments of notes gone for good along with their files, and stored
+/// files no attachment refers to, e.g. left by interrupted uploads
+pub struct AttachmentCleanup(pub Arc<NoteService>);
+
+#[async_trait]
+impl Job for AttachmentCleanup {
+    fn name(&self) -> &'static str {
+        "attachments"
+    }
+
+    async fn run(&self) -> Result<(), JobError> {
+        let Some(store) = &self.0.attachments else {
+            return Ok(());
+        };
+        self.0
+            .remove_orphaned_attachments(store)
+            .await
+            .map_err(|e| format!("failed to remove orphaned attachments: {e}").into())
+    }
+}
+
+/// Backs the notes up. The first snapshot is only taken once the schedule comes due,
+/// so restarts don't push the older snapshots out
+pub struct Backup(pub Arc<NoteService>);
+
+#[async_trait]
+impl Job for Backup {
+    fn name(&self) -> &'static str {
+        "backup"
+    }
+
+    fn runs_on_start(&self) -> bool {
+        false
+    }
+
+    async fn run(&self) -> Result<(), JobError> {
+        let snapshot = self
+            .0
+            .backup_notes()
+            .await
+            .map_err(|e| format!("failed to back up notes: {e}"))?;
+        tracing::info!(
+            "Backed up {} notes to {} ({} bytes)",
+            snapshot.notes,
+            snapshot.name,
+            snapshot.size
+        );
+        Ok(())
+    }
+}
+
+/// Emails notes whose reminder time has come to `to`
+pub struct Reminders {
+    pub service: Arc<NoteService>,
+    pub to: String,
+}
+
+#[async_trait]
+impl Job for Reminders {
+    fn name(&self) -> &'static str {
+        "reminders"
+    }
+
+    async fn run(&self) -> Result<(), JobError> {
+        self.service
+            .send_due_reminders(&self.to)
+            .await
+            .map_err(|e| format!("failed to load due reminders: {e}").into())
+    }
+}
+
+/// Sends the digests that are due. Instances claim the digests they send, so each
+/// is sent once
+pub struct Digests(pub Arc<NoteService>);
+
+#[async_trait]
+impl Job for Digests {
+    fn name(&self) -> &'static str {
+        "digests"
+    }
+
+    async fn run(&self) -> Result<(), JobError> {
+        self.0
+            .send_due_digests()
+            .await
+            .map_err(|e| format!("failed to send due digests: {e}").into())
+    }
+}
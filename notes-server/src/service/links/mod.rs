@@ -0,0 +1,18 @@
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
+use rand::{RngCore, rng};
+use ring::digest::{SHA256, digest};
+
+/// Random bytes per token, enough to make guessing hopeless
+const TOKEN_BYTES: usize = 32;
+
+/// New random URL-safe share link token
+pub(super) fn new_token() -> String {
+    let mut bytes = [0u8; TOKEN_BYTES];
+    rng().fill_bytes(&mut bytes);
+    URL_SAFE_NO_PAD.encode(bytes)
+}
+
+/// What is stored in place of the token
+pub(super) fn token_hash(token: &str) -> Vec<u8> {
+    digest(&SHA256, token.as_bytes()).as_ref().to_vec()
+}
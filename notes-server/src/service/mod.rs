@@ -1,83 +1,1413 @@
+mod content;
+mod digests;
+mod events;
+mod export;
+mod fixtures;
+pub mod jobs;
+mod links;
+mod retention;
+mod templates;
+mod webhooks;
+
+pub use content::{ContentError, ContentRules, DuplicatePolicy, Sanitization};
+pub use digests::InvalidDigest;
+pub use events::{NoteEvent, NoteOperation};
+pub use export::ExportFormat;
+pub use fixtures::FixtureSpec;
+pub use retention::{RetentionAction, RetentionPolicy, RetentionRule, parse_rules};
+pub use webhooks::InvalidWebhook;
+
+use events::NoteEvents;
+
+use chrono::{DateTime, Local, Utc};
+use futures_util::{Stream, StreamExt, TryStreamExt, future, stream};
+
 use crate::{
-    dto::{CreateNoteRequest, NoteResponse, UpdateNoteRequest},
-    models::Note,
-    repository::Repository,
+    attachments::{self, Attachments, ByteStream, StoreError},
+    backup::{BackupError, BackupStore, Snapshot},
+    cache::{LocalCache, NoteCache, PageKey},
+    dto::{
+        AttachmentResponse, CreateFromTemplateRequest, CreateNoteRequest, DigestRequest,
+        DigestResponse, DuplicateClusterResponse, MigrationStatusResponse, NoteResponse, NotesPage,
+        RetentionReport, RetentionRuleReport, SoapAuditResponse, TemplateRequest, TemplateResponse,
+        UpdateNoteRequest, UserResponse, WebhookDeliveryResponse, WebhookRequest, WebhookResponse,
+    },
+    email::{Email, EmailClient, EmailError},
+    models::{
+        Activity, AttachmentDraft, DueDigest, Metadata, MetadataFilter, NewSoapAuditEntry, Note,
+        NoteDraft, NoteOrder, ShareLink,
+    },
+    repository::{ConditionalWrite, Repository, RepositoryError},
+    tenant::Tenant,
+};
+
+use std::{
+    collections::{HashMap, HashSet},
+    io,
+    sync::Arc,
+    time::Duration,
 };
 
-use std::sync::Arc;
+/// Most notes rendered into one export chunk
+const EXPORT_CHUNK_SIZE: usize = 500;
+/// Number of notes fetched from the database at a time when backing them up
+const BACKUP_BATCH_SIZE: i64 = 500;
+/// Maximum number of reminders sent per scheduler tick
+const REMINDER_BATCH_SIZE: i64 = 100;
+/// How long a claimed reminder isn't sent by anyone else
+const REMINDER_LEASE: chrono::Duration = chrono::Duration::minutes(5);
+/// Number of generated notes inserted per statement
+const FIXTURE_BATCH_SIZE: usize = 1000;
+/// Maximum number of notes archived or deleted by a retention rule per statement
+const RETENTION_BATCH_SIZE: i64 = 1000;
+/// How long changes stay in the activity feed
+const ACTIVITY_RETENTION: chrono::Duration = chrono::Duration::days(30);
+/// How long SOAP requests stay in the audit trail
+const SOAP_AUDIT_RETENTION: chrono::Duration = chrono::Duration::days(90);
+/// How long relayed changes stay in the outbox
+const OUTBOX_RETENTION: chrono::Duration = chrono::Duration::days(7);
+/// How long completed webhook deliveries stay in the delivery log
+const WEBHOOK_DELIVERY_RETENTION: chrono::Duration = chrono::Duration::days(7);
+/// How long after a delete it can still be undone
+const UNDO_WINDOW: chrono::Duration = chrono::Duration::minutes(1);
+/// Maximum number of digests sent per scheduler tick
+const DIGEST_BATCH_SIZE: i64 = 50;
+/// Most changed notes listed in one digest, the rest are only counted
+const DIGEST_MAX_NOTES: i64 = 100;
+/// How long a claimed digest isn't sent by anyone else
+const DIGEST_LEASE: chrono::Duration = chrono::Duration::minutes(10);
+/// When a digest that couldn't be sent is tried again
+const DIGEST_RETRY_DELAY: chrono::Duration = chrono::Duration::minutes(5);
+/// Maximum number of orphaned attachments removed per statement
+const ATTACHMENT_CLEANUP_BATCH_SIZE: i64 = 500;
+/// Number of stored keys checked against the attachments per query
+const ATTACHMENT_KEY_BATCH_SIZE: usize = 500;
+/// Stored files younger than this may be uploads not recorded yet, they are never orphans
+const ATTACHMENT_UPLOAD_GRACE: chrono::Duration = chrono::Duration::hours(1);
+
+/// Removes the file from the attachment store, failing to is only logged: the file is
+/// left as an orphan for the cleanup task
+async fn discard_stored(attachments: &Attachments, key: &str) {
+    if let Err(e) = attachments.store.delete(key).await {
+        tracing::warn!("Failed to remove attachment file {key}: {e}");
+    }
+}
+
+fn event_from_activity(activity: &Activity) -> Option<NoteEvent> {
+    Some(NoteEvent {
+        id: activity.note_id,
+        operation: NoteOperation::parse(&activity.operation)?,
+        timestamp: activity.occurred_at,
+        tenant: Tenant::from(activity.tenant_id.as_str()),
+    })
+}
+
+/// Note IDs grouped by their tenants
+fn by_tenant(notes: Vec<(Tenant, i64)>) -> HashMap<Tenant, Vec<i64>> {
+    let mut grouped: HashMap<Tenant, Vec<i64>> = HashMap::new();
+    for (tenant, id) in notes {
+        grouped.entry(tenant).or_default().push(id);
+    }
+    grouped
+}
+
+/// Rejects expiration times that have already passed, the note would be gone at once
+fn check_expiration(expires_at: Option<DateTime<Utc>>) -> Result<(), ContentError> {
+    match expires_at {
+        Some(expires_at) if expires_at <= Utc::now() => Err(ContentError::ExpirationPassed),
+        _ => Ok(()),
+    }
+}
+
+/// New value of a time the update may set or remove, `None` keeps the current one
+fn time_change(time: Option<DateTime<Utc>>, clear: bool) -> Option<Option<DateTime<Utc>>> {
+    if clear { Some(None) } else { time.map(Some) }
+}
+
+/// Drafts of the requested notes, fails if any content is rejected by `rules`
+fn drafts(
+    requests: Vec<CreateNoteRequest>,
+    rules: ContentRules,
+) -> Result<Vec<NoteDraft>, ContentError> {
+    requests
+        .into_iter()
+        .map(|request| {
+            check_expiration(request.expires_at)?;
+            Ok(NoteDraft {
+                content: rules.normalize(request.content)?,
+                expires_at: request.expires_at,
+                remind_at: request.remind_at,
+            })
+        })
+        .collect()
+}
+
+/// Errors of the operations writing note content
+#[derive(Debug, thiserror::Error)]
+pub enum WriteError {
+    #[error("invalid content: {0}")]
+    InvalidContent(#[from] ContentError),
+
+    #[error("an identical note {0} already exists")]
+    Duplicate(i64),
+
+    #[error("failed to save notes: {0}")]
+    Database(#[from] RepositoryError),
+}
+
+/// Errors of the operations managing webhooks
+#[derive(Debug, thiserror::Error)]
+pub enum WebhookError {
+    #[error("invalid webhook: {0}")]
+    Invalid(#[from] InvalidWebhook),
+
+    #[error("failed to access webhooks: {0}")]
+    Database(#[from] RepositoryError),
+}
+
+/// Errors of the operations managing digest subscriptions
+#[derive(Debug, thiserror::Error)]
+pub enum DigestError {
+    #[error("invalid digest subscription: {0}")]
+    Invalid(#[from] InvalidDigest),
+
+    #[error("failed to access digest subscriptions: {0}")]
+    Database(#[from] RepositoryError),
+}
+
+/// Errors of the operations on note attachments
+#[derive(Debug, thiserror::Error)]
+pub enum AttachmentError {
+    #[error("attachments are not configured")]
+    Disabled,
+
+    #[error("note or attachment not found")]
+    NotFound,
+
+    #[error("attachment is larger than {max_bytes} bytes")]
+    TooLarge { max_bytes: u64 },
+
+    #[error("failed to access the attachment store: {0}")]
+    Storage(#[from] StoreError),
+
+    #[error("failed to access attachments: {0}")]
+    Database(#[from] RepositoryError),
+}
+
+/// Errors of the email sharing operations
+#[derive(Debug, thiserror::Error)]
+pub enum ShareError {
+    #[error("note not found")]
+    NotFound,
+
+    #[error("failed to load notes: {0}")]
+    Database(#[from] RepositoryError),
+
+    #[error("failed to send email: {0}")]
+    Email(#[from] EmailError),
+}
+
+/// Reasons the instance can't serve requests yet, see `NoteService::check_ready`
+#[derive(Debug, thiserror::Error)]
+pub enum NotReady {
+    #[error("database is unavailable: {0}")]
+    Database(#[from] RepositoryError),
+
+    #[error("{0} migrations are pending")]
+    PendingMigrations(usize),
+}
+
+fn format_time(time: DateTime<Utc>) -> String {
+    time.with_timezone(&Local)
+        .format("%Y-%m-%d %H:%M:%S")
+        .to_string()
+}
+
+/// Email body for a single note along with its timestamps
+fn note_email_body(note: &Note) -> String {
+    format!(
+        "Created: {}\nUpdated: {}\n\n{}",
+        format_time(note.created_at),
+        format_time(note.updated_at),
+        note.content
+    )
+}
 
 #[derive(Clone)]
 pub struct NoteService {
-    repo: Arc<tokio::sync::Mutex<Repository>>,
+    repo: Arc<Repository>,
+    email_client: Arc<dyn EmailClient>,
+    events: NoteEvents,
+    cache: Option<Arc<NoteCache>>,
+    local_cache: Option<Arc<LocalCache>>,
+    backups: Option<Arc<BackupStore>>,
+    attachments: Option<Attachments>,
+    content_rules: ContentRules,
 }
 
 impl NoteService {
-    pub const fn new(repo: Arc<tokio::sync::Mutex<Repository>>) -> Self {
-        Self { repo }
+    pub fn new(
+        repo: Arc<Repository>,
+        email_client: Arc<dyn EmailClient>,
+        cache: Option<Arc<NoteCache>>,
+        local_cache: Option<Arc<LocalCache>>,
+        backups: Option<Arc<BackupStore>>,
+        attachments: Option<Attachments>,
+        content_rules: ContentRules,
+    ) -> Self {
+        Self {
+            repo,
+            email_client,
+            events: NoteEvents::default(),
+            cache,
+            local_cache,
+            backups,
+            attachments,
+            content_rules,
+        }
+    }
+
+    /// Content as it would be stored, see `ContentRules::normalize`. Every write
+    /// normalizes its content, this is for checking requests up front
+    pub fn normalize_content(&self, content: String) -> Result<String, ContentError> {
+        self.content_rules.normalize(content)
+    }
+
+    /// ID of the note the new content duplicates, if duplicates are looked up.
+    /// Fails if they are rejected and there is one
+    async fn check_duplicate(&self, content: &str) -> Result<Option<i64>, WriteError> {
+        let policy = self.content_rules.duplicates;
+        if policy == DuplicatePolicy::Allow {
+            return Ok(None);
+        }
+
+        match self.repo.find_identical_note(content).await? {
+            Some(id) if policy == DuplicatePolicy::Reject => Err(WriteError::Duplicate(id)),
+            duplicate_of => Ok(duplicate_of),
+        }
+    }
+
+    /// Drops the changed notes, the list of all notes and the cached listing pages from
+    /// the caches, if any
+    async fn invalidate_cache(&self, ids: &[i64]) {
+        if let Some(local_cache) = &self.local_cache {
+            local_cache.invalidate(&Tenant::current(), ids);
+        }
+        if let Some(cache) = &self.cache {
+            cache.invalidate(ids).await;
+        }
+    }
+
+    /// Drops the changed notes from the cache, publishes the changes to the change
+    /// stream subscribers of this instance and records them in the activity feed,
+    /// which passes them on to the other instances' subscribers, see `run_change_feed`.
+    /// The notes are already changed at this point, so failing to record is only logged
+    async fn record_changes(&self, ids: &[i64], operation: NoteOperation) {
+        self.invalidate_cache(ids).await;
+        self.events.publish_changes(ids, operation);
+        let recorded = self
+            .repo
+            .record_activity(ids, operation.as_str(), self.events.origin())
+            .await;
+        if let Err(e) = recorded {
+            tracing::error!("Failed to record activity of {} notes: {e}", ids.len());
+        }
+    }
+
+    /// Up to `limit` most recent changes made after `since`, newest first
+    pub async fn recent_activity(
+        &self,
+        since: Option<DateTime<Utc>>,
+        limit: i64,
+    ) -> Result<Vec<NoteEvent>, RepositoryError> {
+        let activity = self.repo.recent_activity(since, limit).await?;
+
+        Ok(activity.iter().filter_map(event_from_activity).collect())
+    }
+
+    /// Appends a SOAP request to the audit trail. The request is already answered,
+    /// so failing to record is only logged
+    pub async fn record_soap_audit(&self, entry: NewSoapAuditEntry) {
+        if let Err(e) = self.repo.record_soap_audit(&entry).await {
+            tracing::error!("Failed to record SOAP audit entry: {e}");
+        }
+    }
+
+    /// Up to `limit` most recent SOAP requests made after `since`, newest first,
+    /// see `Repository::soap_audit`
+    pub async fn soap_audit(
+        &self,
+        since: Option<DateTime<Utc>>,
+        operation: Option<&str>,
+        faults_only: bool,
+        limit: i64,
+    ) -> Result<Vec<SoapAuditResponse>, RepositoryError> {
+        let entries = self
+            .repo
+            .soap_audit(since, operation, faults_only, limit)
+            .await?;
+
+        Ok(entries.into_iter().map(Into::into).collect())
+    }
+
+    pub async fn list_users(
+        &self,
+        tenant: Option<&str>,
+        limit: i64,
+    ) -> Result<Vec<UserResponse>, RepositoryError> {
+        let users = self.repo.list_users(tenant, limit).await?;
+        Ok(users.into_iter().map(Into::into).collect())
+    }
+
+    /// Whether the database answers queries and its schema is up to date. Fails fast
+    /// while the connection is being restored, see `Repository::run_reconnect`
+    pub async fn check_ready(&self) -> Result<(), NotReady> {
+        self.repo.ping().await?;
+
+        let (_, pending) = self.repo.migration_status().await?;
+        if !pending.is_empty() {
+            return Err(NotReady::PendingMigrations(pending.len()));
+        }
+        Ok(())
+    }
+
+    /// Changes made to the current tenant's notes from now on, see `NoteEvents::subscribe`
+    pub fn subscribe_events(&self) -> impl Stream<Item = NoteEvent> + Send + 'static {
+        self.events.subscribe(Tenant::current())
     }
 
     pub async fn create_note(
         &self,
         request: CreateNoteRequest,
-    ) -> Result<NoteResponse, tokio_postgres::Error> {
+    ) -> Result<NoteResponse, WriteError> {
+        let content = self.normalize_content(request.content)?;
+        check_expiration(request.expires_at)?;
+        let duplicate_of = self.check_duplicate(&content).await?;
+        let note = self
+            .repo
+            .create_note(content, request.expires_at, request.remind_at)
+            .await?;
+
+        self.record_changes(&[note.id], NoteOperation::Created)
+            .await;
+        Ok(NoteResponse {
+            duplicate_of,
+            ..note.into()
+        })
+    }
+
+    /// Creates all notes with a single statement, returning them in the order requested
+    pub async fn batch_create_notes(
+        &self,
+        requests: Vec<CreateNoteRequest>,
+    ) -> Result<Vec<NoteResponse>, WriteError> {
+        if requests.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let drafts = drafts(requests, self.content_rules)?;
+        let notes = self.repo.batch_create_notes(&drafts).await?;
+
+        let ids: Vec<i64> = notes.iter().map(|note| note.id).collect();
+        self.record_changes(&ids, NoteOperation::Created).await;
+        Ok(notes.into_iter().map(Into::into).collect())
+    }
+
+    /// Creates the notes of all batches in one transaction, returning how many were
+    /// created. If inserting fails, a note's content is rejected or `batches` yields
+    /// an error, nothing is created
+    pub async fn import_notes<S, E>(&self, batches: S) -> Result<u64, E>
+    where
+        S: Stream<Item = Result<Vec<CreateNoteRequest>, E>> + Send + 'static,
+        E: From<RepositoryError> + From<ContentError> + Send,
+    {
+        let rules = self.content_rules;
+        let ids = self
+            .repo
+            .transaction(|tx| {
+                Box::pin(async move {
+                    let mut ids = Vec::new();
+                    let mut batches = std::pin::pin!(batches);
+                    while let Some(batch) = batches.try_next().await? {
+                        ids.extend(tx.copy_notes(&drafts(batch, rules)?).await?);
+                    }
+                    Ok::<_, E>(ids)
+                })
+            })
+            .await?;
+
+        self.record_changes(&ids, NoteOperation::Created).await;
+        Ok(ids.len() as u64)
+    }
+
+    pub async fn duplicate_note(&self, id: i64) -> Result<Option<NoteResponse>, RepositoryError> {
+        let Some(note) = self.repo.duplicate_note(id).await? else {
+            return Ok(None);
+        };
+
+        self.record_changes(&[note.id], NoteOperation::Created)
+            .await;
+        Ok(Some(note.into()))
+    }
+
+    /// Up to `limit` groups of notes with the same content, ignoring case and
+    /// whitespace, largest first
+    pub async fn duplicate_clusters(
+        &self,
+        limit: i64,
+    ) -> Result<Vec<DuplicateClusterResponse>, RepositoryError> {
+        let clusters = self.repo.duplicate_clusters(limit).await?;
+        Ok(clusters.into_iter().map(Into::into).collect())
+    }
+
+    pub async fn create_template(
+        &self,
+        request: TemplateRequest,
+    ) -> Result<TemplateResponse, RepositoryError> {
         self.repo
-            .lock()
+            .create_template(&request.name, &request.content)
             .await
-            .create_note(request.content)
+            .map(TemplateResponse::from)
+    }
+
+    pub async fn list_templates(&self) -> Result<Vec<TemplateResponse>, RepositoryError> {
+        self.repo
+            .list_templates()
             .await
-            .map(|note| NoteResponse {
-                id: note.id,
-                content: note.content,
-            })
+            .map(|templates| templates.into_iter().map(TemplateResponse::from).collect())
     }
 
-    pub async fn update_note(
+    pub async fn get_template(&self, id: i64) -> Result<Option<TemplateResponse>, RepositoryError> {
+        self.repo
+            .get_template(id)
+            .await
+            .map(|template| template.map(TemplateResponse::from))
+    }
+
+    pub async fn update_template(
         &self,
         id: i64,
-        request: UpdateNoteRequest,
-    ) -> Result<Option<NoteResponse>, tokio_postgres::Error> {
+        request: TemplateRequest,
+    ) -> Result<Option<TemplateResponse>, RepositoryError> {
         self.repo
-            .lock()
+            .update_template(id, &request.name, &request.content)
             .await
-            .update_note(id, request.content)
+            .map(|template| template.map(TemplateResponse::from))
+    }
+
+    /// Returns whether the template existed
+    pub async fn delete_template(&self, id: i64) -> Result<bool, RepositoryError> {
+        self.repo.delete_template(id).await
+    }
+
+    /// Creates a note with the template's content, placeholders substituted.
+    /// `None` if there is no such template
+    pub async fn create_note_from_template(
+        &self,
+        template_id: i64,
+        request: CreateFromTemplateRequest,
+    ) -> Result<Option<NoteResponse>, WriteError> {
+        let Some(template) = self.repo.get_template(template_id).await? else {
+            return Ok(None);
+        };
+
+        let content = templates::render(&template.content, &request.values, Local::now());
+        let content = self.normalize_content(content)?;
+        check_expiration(request.expires_at)?;
+        let duplicate_of = self.check_duplicate(&content).await?;
+        let note = self
+            .repo
+            .create_note(content, request.expires_at, request.remind_at)
+            .await?;
+
+        self.record_changes(&[note.id], NoteOperation::Created)
+            .await;
+        Ok(Some(NoteResponse {
+            duplicate_of,
+            ..note.into()
+        }))
+    }
+
+    /// Subscribes the URL to the tenant's note changes. The response carries the
+    /// signing secret, it isn't returned again
+    pub async fn create_webhook(
+        &self,
+        request: WebhookRequest,
+    ) -> Result<WebhookResponse, WebhookError> {
+        let draft = webhooks::draft(&request)?;
+        let secret = webhooks::new_secret();
+        let webhook = self.repo.create_webhook(&draft, &secret).await?;
+
+        Ok(WebhookResponse {
+            secret: Some(secret),
+            ..webhook.into()
+        })
+    }
+
+    pub async fn list_webhooks(&self) -> Result<Vec<WebhookResponse>, RepositoryError> {
+        self.repo
+            .list_webhooks()
             .await
-            .map(|note| {
-                note.map(|note| NoteResponse {
-                    id: note.id,
-                    content: note.content,
-                })
-            })
+            .map(|webhooks| webhooks.into_iter().map(WebhookResponse::from).collect())
+    }
+
+    pub async fn get_webhook(&self, id: i64) -> Result<Option<WebhookResponse>, RepositoryError> {
+        self.repo
+            .get_webhook(id)
+            .await
+            .map(|webhook| webhook.map(WebhookResponse::from))
+    }
+
+    pub async fn update_webhook(
+        &self,
+        id: i64,
+        request: WebhookRequest,
+    ) -> Result<Option<WebhookResponse>, WebhookError> {
+        let draft = webhooks::draft(&request)?;
+        Ok(self
+            .repo
+            .update_webhook(id, &draft)
+            .await?
+            .map(WebhookResponse::from))
+    }
+
+    /// Returns whether the webhook existed
+    pub async fn delete_webhook(&self, id: i64) -> Result<bool, RepositoryError> {
+        self.repo.delete_webhook(id).await
+    }
+
+    /// Subscribes the address to digests of the tenant's changed notes, the first one
+    /// covers the notes changed from now on
+    pub async fn create_digest(
+        &self,
+        request: DigestRequest,
+    ) -> Result<DigestResponse, DigestError> {
+        let draft = digests::draft(&request, Utc::now())?;
+        Ok(self.repo.create_digest(&draft).await?.into())
+    }
+
+    pub async fn list_digests(&self) -> Result<Vec<DigestResponse>, RepositoryError> {
+        self.repo
+            .list_digests()
+            .await
+            .map(|digests| digests.into_iter().map(DigestResponse::from).collect())
+    }
+
+    pub async fn get_digest(&self, id: i64) -> Result<Option<DigestResponse>, RepositoryError> {
+        self.repo
+            .get_digest(id)
+            .await
+            .map(|digest| digest.map(DigestResponse::from))
+    }
+
+    /// The next digest is due at the schedule's next run from now
+    pub async fn update_digest(
+        &self,
+        id: i64,
+        request: DigestRequest,
+    ) -> Result<Option<DigestResponse>, DigestError> {
+        let draft = digests::draft(&request, Utc::now())?;
+        Ok(self
+            .repo
+            .update_digest(id, &draft)
+            .await?
+            .map(DigestResponse::from))
+    }
+
+    /// Returns whether the subscription existed
+    pub async fn delete_digest(&self, id: i64) -> Result<bool, RepositoryError> {
+        self.repo.delete_digest(id).await
     }
 
-    pub async fn delete_note(&self, id: i64) -> Result<bool, tokio_postgres::Error> {
-        self.repo.lock().await.delete_note(id).await
+    /// Up to `limit` latest deliveries of the webhook, `None` if there is no such webhook
+    pub async fn webhook_deliveries(
+        &self,
+        id: i64,
+        limit: i64,
+    ) -> Result<Option<Vec<WebhookDeliveryResponse>>, RepositoryError> {
+        if self.repo.get_webhook(id).await?.is_none() {
+            return Ok(None);
+        }
+
+        let deliveries = self.repo.list_webhook_deliveries(id, limit).await?;
+        Ok(Some(
+            deliveries
+                .into_iter()
+                .map(WebhookDeliveryResponse::from)
+                .collect(),
+        ))
+    }
+
+    /// Creates a public link to the note, returning the token along with the link.
+    /// `None` if there is no such note
+    pub async fn create_share_link(
+        &self,
+        note_id: i64,
+        expires_at: DateTime<Utc>,
+    ) -> Result<Option<(String, ShareLink)>, RepositoryError> {
+        let token = links::new_token();
+        let link = self
+            .repo
+            .create_share_link(note_id, &links::token_hash(&token), expires_at)
+            .await?;
+
+        Ok(link.map(|link| (token, link)))
+    }
+
+    pub async fn get_shared_note(
+        &self,
+        token: &str,
+    ) -> Result<Option<NoteResponse>, RepositoryError> {
+        self.repo
+            .get_shared_note(&links::token_hash(token))
+            .await
+            .map(|note| note.map(NoteResponse::from))
+    }
+
+    /// Updates the note only if its `version` matches one of `expected_versions`
+    /// (no check is made when `None`)
+    pub async fn update_note_if_match(
+        &self,
+        id: i64,
+        request: UpdateNoteRequest,
+        expected_versions: Option<&[i64]>,
+    ) -> Result<ConditionalWrite<NoteResponse>, WriteError> {
+        let content = self.normalize_content(request.content)?;
+        let expires_at = time_change(request.expires_at, request.clear_expires_at);
+        check_expiration(expires_at.flatten())?;
+        let outcome = self
+            .repo
+            .update_note_if_version(
+                id,
+                content,
+                expires_at,
+                time_change(request.remind_at, request.clear_remind_at),
+                expected_versions,
+            )
+            .await?;
+
+        Ok(match outcome {
+            ConditionalWrite::Applied(note) => {
+                self.record_changes(&[note.id], NoteOperation::Updated)
+                    .await;
+                ConditionalWrite::Applied(note.into())
+            }
+            ConditionalWrite::NotFound => ConditionalWrite::NotFound,
+            ConditionalWrite::PreconditionFailed => ConditionalWrite::PreconditionFailed,
+        })
     }
 
-    pub async fn get_one_note(
+    /// Merges `patch` into the note's metadata, `null` values remove the field.
+    /// Only applies if the note's `version` matches one of `expected_versions`
+    /// (no check is made when `None`)
+    pub async fn patch_metadata_if_match(
         &self,
         id: i64,
-    ) -> Result<Option<NoteResponse>, tokio_postgres::Error> {
-        self.repo.lock().await.get_one_note(id).await.map(|note| {
-            note.map(|note| NoteResponse {
-                id: note.id,
-                content: note.content,
+        patch: Metadata,
+        expected_versions: Option<&[i64]>,
+    ) -> Result<ConditionalWrite<NoteResponse>, RepositoryError> {
+        let (remove, set): (Vec<_>, Vec<_>) =
+            patch.into_iter().partition(|(_, value)| value.is_null());
+        let remove: Vec<String> = remove.into_iter().map(|(key, _)| key).collect();
+
+        let outcome = self
+            .repo
+            .patch_metadata(id, set.into_iter().collect(), &remove, expected_versions)
+            .await?;
+
+        Ok(match outcome {
+            ConditionalWrite::Applied(note) => {
+                self.record_changes(&[note.id], NoteOperation::Updated)
+                    .await;
+                ConditionalWrite::Applied(note.into())
+            }
+            ConditionalWrite::NotFound => ConditionalWrite::NotFound,
+            ConditionalWrite::PreconditionFailed => ConditionalWrite::PreconditionFailed,
+        })
+    }
+
+    /// Deletes the notes with a single statement, returning the IDs of the deleted ones
+    pub async fn batch_delete_notes(&self, ids: &[i64]) -> Result<Vec<i64>, RepositoryError> {
+        if ids.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let deleted = self.repo.batch_delete_notes(ids).await?;
+        if !deleted.is_empty() {
+            self.record_changes(&deleted, NoteOperation::Deleted).await;
+        }
+        Ok(deleted)
+    }
+
+    /// Deletes the note only if its `version` matches one of `expected_versions`
+    /// (no check is made when `None`)
+    pub async fn delete_note_if_match(
+        &self,
+        id: i64,
+        expected_versions: Option<&[i64]>,
+    ) -> Result<ConditionalWrite<()>, RepositoryError> {
+        let outcome = self.repo.delete_note(id, expected_versions).await?;
+
+        if matches!(outcome, ConditionalWrite::Applied(())) {
+            self.record_changes(&[id], NoteOperation::Deleted).await;
+        }
+        Ok(outcome)
+    }
+
+    /// Restores the notes removed by the tenant's most recent delete, if it was made
+    /// within the undo window. Empty when there is nothing to undo
+    pub async fn undo_last_delete(&self) -> Result<Vec<NoteResponse>, RepositoryError> {
+        let restored = self
+            .repo
+            .restore_last_deleted(Utc::now() - UNDO_WINDOW)
+            .await?;
+        if !restored.is_empty() {
+            let ids: Vec<i64> = restored.iter().map(|note| note.id).collect();
+            self.record_changes(&ids, NoteOperation::Created).await;
+        }
+        Ok(restored.into_iter().map(NoteResponse::from).collect())
+    }
+
+    pub async fn get_one_note(&self, id: i64) -> Result<Option<NoteResponse>, RepositoryError> {
+        if let Some(local_cache) = &self.local_cache
+            && let Some(note) = local_cache.note(id)
+        {
+            return Ok(Some(note));
+        }
+
+        let note = self.load_note(id).await?;
+        if let (Some(local_cache), Some(note)) = (&self.local_cache, &note) {
+            local_cache.put_note(note);
+        }
+        Ok(note)
+    }
+
+    /// The note from the Redis cache, if any, otherwise from the database
+    async fn load_note(&self, id: i64) -> Result<Option<NoteResponse>, RepositoryError> {
+        if let Some(cache) = &self.cache
+            && let Some(note) = cache.note(id).await
+        {
+            return Ok(Some(note));
+        }
+
+        let note = self.repo.get_one_note(id).await?.map(NoteResponse::from);
+        if let (Some(cache), Some(note)) = (&self.cache, &note) {
+            cache.put_note(note).await;
+        }
+        Ok(note)
+    }
+
+    /// The page from the local cache if it's a cacheable one, i.e. unfiltered and first,
+    /// otherwise (or on a miss) from `load`, cached for the next time
+    async fn first_page(
+        &self,
+        key: Option<PageKey>,
+        load: impl Future<Output = Result<NotesPage, RepositoryError>>,
+    ) -> Result<NotesPage, RepositoryError> {
+        let (Some(local_cache), Some(key)) = (&self.local_cache, key) else {
+            return load.await;
+        };
+        if let Some(page) = local_cache.page(key) {
+            return Ok(page);
+        }
+
+        let page = load.await?;
+        local_cache.put_page(key, &page);
+        Ok(page)
+    }
+
+    pub async fn get_all_notes(&self) -> Result<Vec<NoteResponse>, RepositoryError> {
+        if let Some(cache) = &self.cache
+            && let Some(notes) = cache.all_notes().await
+        {
+            return Ok(notes);
+        }
+
+        let notes: Vec<NoteResponse> = self
+            .repo
+            .get_all_notes()
+            .await?
+            .into_iter()
+            .map(NoteResponse::from)
+            .collect();
+        if let Some(cache) = &self.cache {
+            cache.put_all_notes(&notes).await;
+        }
+        Ok(notes)
+    }
+
+    pub async fn list_notes(
+        &self,
+        limit: i64,
+        offset: i64,
+        filter: &MetadataFilter,
+        order: NoteOrder,
+        include_archived: bool,
+    ) -> Result<NotesPage, RepositoryError> {
+        let key = (offset == 0 && filter.is_empty()).then_some(PageKey::Offset {
+            limit,
+            order,
+            include_archived,
+        });
+
+        self.first_page(key, async {
+            let (notes, total) = self
+                .repo
+                .list_notes(limit, offset, filter, order, include_archived)
+                .await?;
+
+            Ok(NotesPage {
+                items: notes.into_iter().map(NoteResponse::from).collect(),
+                total,
+                next_after: None,
+            })
+        })
+        .await
+    }
+
+    /// Up to `limit` notes matching the filter with ID greater than `after_id`, ordered by ID
+    pub async fn list_notes_after(
+        &self,
+        after_id: i64,
+        limit: i64,
+        filter: &MetadataFilter,
+        include_archived: bool,
+    ) -> Result<NotesPage, RepositoryError> {
+        let key = (after_id == 0 && filter.is_empty()).then_some(PageKey::After {
+            limit,
+            include_archived,
+        });
+
+        self.first_page(key, async {
+            // One extra note tells whether there is a next page
+            let mut notes = self
+                .repo
+                .get_notes_page(after_id, limit + 1, filter, include_archived)
+                .await?;
+            let page_size = usize::try_from(limit).unwrap_or(0);
+            let has_more = notes.len() > page_size;
+            notes.truncate(page_size);
+            let total = self.repo.count_notes(filter, include_archived).await?;
+
+            Ok(NotesPage {
+                next_after: notes.last().map(|note| note.id).filter(|_| has_more),
+                items: notes.into_iter().map(NoteResponse::from).collect(),
+                total,
             })
         })
+        .await
+    }
+
+    /// Up to `limit` notes matching `query`, best matches first
+    pub async fn search_notes(
+        &self,
+        query: &str,
+        limit: i64,
+    ) -> Result<Vec<NoteResponse>, RepositoryError> {
+        let notes = self.repo.search_notes(query, limit).await?;
+
+        Ok(notes.into_iter().map(NoteResponse::from).collect())
     }
 
-    pub async fn get_all_notes(&self) -> Result<Vec<NoteResponse>, tokio_postgres::Error> {
-        self.repo.lock().await.get_all_notes().await.map(|notes| {
+    /// Puts the notes in the order of `ids`, returns `false` if some of them don't exist
+    pub async fn reorder_notes(&self, ids: &[i64]) -> Result<bool, RepositoryError> {
+        let reordered = self.repo.reorder_notes(ids).await?;
+        if reordered {
+            self.invalidate_cache(ids).await;
+        }
+        Ok(reordered)
+    }
+
+    /// Inserts `count` synthetic notes, returning how many were created. The repository
+    /// is locked per batch so regular requests are served in between. No events are
+    /// published, subscribers would otherwise be flooded
+    pub async fn generate_notes(
+        &self,
+        count: usize,
+        spec: FixtureSpec,
+    ) -> Result<u64, RepositoryError> {
+        let mut created = 0;
+        let mut remaining = count;
+
+        while remaining > 0 {
+            let batch = spec.generate(remaining.min(FIXTURE_BATCH_SIZE));
+            remaining -= batch.len();
+            created += self.repo.create_notes(&batch).await?;
+        }
+
+        self.invalidate_cache(&[]).await;
+        Ok(created)
+    }
+
+    /// Schema state of the database as seen by this replica
+    pub async fn migration_status(&self) -> Result<MigrationStatusResponse, RepositoryError> {
+        let (applied, pending) = self.repo.migration_status().await?;
+
+        Ok(MigrationStatusResponse {
+            applied: applied.into_iter().map(Into::into).collect(),
+            pending: pending.into_iter().map(Into::into).collect(),
+            applied_at_startup: self
+                .repo
+                .startup_migrations()
+                .iter()
+                .cloned()
+                .map(Into::into)
+                .collect(),
+        })
+    }
+
+    /// Emails all notes, each prefixed with its creation time
+    pub async fn share_notes(&self, to: String) -> Result<(), ShareError> {
+        let notes = self.repo.get_all_notes().await?;
+
+        let body = if notes.is_empty() {
+            "No notes available.".to_string()
+        } else {
             notes
                 .into_iter()
-                .map(|note| NoteResponse {
-                    id: note.id,
-                    content: note.content,
-                })
-                .collect()
+                .map(|note| format!("{}: {}", format_time(note.created_at), note.content))
+                .collect::<Vec<_>>()
+                .join("\n")
+        };
+
+        self.email_client
+            .send(Email {
+                to,
+                subject: "Notes".to_string(),
+                body,
+            })
+            .await?;
+
+        Ok(())
+    }
+
+    /// Emails a single note along with its timestamps
+    pub async fn share_note(&self, id: i64, to: String) -> Result<(), ShareError> {
+        let note = self
+            .repo
+            .get_one_note(id)
+            .await?
+            .ok_or(ShareError::NotFound)?;
+
+        self.email_client
+            .send(Email {
+                to,
+                subject: format!("Note #{id}"),
+                body: note_email_body(&note),
+            })
+            .await?;
+
+        Ok(())
+    }
+
+    /// Number of notes of all tenants the rule currently applies to
+    async fn count_retained_notes(&self, rule: RetentionRule) -> Result<i64, RepositoryError> {
+        let before = Utc::now() - rule.age();
+        match rule.action {
+            RetentionAction::Archive => self.repo.count_archivable_notes(before).await,
+            RetentionAction::Delete => self.repo.count_notes_modified_before(before).await,
+        }
+    }
+
+    /// Batches keep the transactions short, so regular requests are served in between
+    async fn apply_retention_rule(&self, rule: RetentionRule) -> Result<(), RepositoryError> {
+        let before = Utc::now() - rule.age();
+        if rule.action == RetentionAction::Archive {
+            loop {
+                let archived = self
+                    .repo
+                    .archive_notes(before, RETENTION_BATCH_SIZE)
+                    .await?;
+                if archived.is_empty() {
+                    return Ok(());
+                }
+                tracing::info!("Archived {} notes by retention rule {rule}", archived.len());
+                for (tenant, ids) in by_tenant(archived) {
+                    tenant.scope(self.invalidate_cache(&ids)).await;
+                }
+            }
+        }
+
+        loop {
+            let deleted = self
+                .repo
+                .delete_notes_modified_before(before, RETENTION_BATCH_SIZE)
+                .await?;
+            if deleted.is_empty() {
+                break;
+            }
+            tracing::info!("Deleted {} notes by retention rule {rule}", deleted.len());
+            for (tenant, ids) in by_tenant(deleted) {
+                tenant
+                    .scope(self.record_changes(&ids, NoteOperation::Deleted))
+                    .await;
+            }
+        }
+        loop {
+            let deleted = self
+                .repo
+                .delete_archived_notes_modified_before(before, RETENTION_BATCH_SIZE)
+                .await?;
+            if deleted == 0 {
+                return Ok(());
+            }
+            tracing::info!("Deleted {deleted} archived notes by retention rule {rule}");
+        }
+    }
+
+    /// Number of notes of all tenants each rule currently applies to, nothing is changed
+    pub async fn retention_report(
+        &self,
+        policy: &RetentionPolicy,
+    ) -> Result<RetentionReport, RepositoryError> {
+        let mut rules = Vec::with_capacity(policy.rules.len());
+        for &rule in &policy.rules {
+            rules.push(RetentionRuleReport {
+                action: rule.action.as_str().to_string(),
+                after_days: rule.after_days,
+                notes: self.count_retained_notes(rule).await?,
+            });
+        }
+
+        Ok(RetentionReport {
+            dry_run: policy.dry_run,
+            rules,
+        })
+    }
+
+    /// Writes a snapshot of the notes of all tenants, archived ones included,
+    /// to the backup store
+    pub async fn backup_notes(&self) -> Result<Snapshot, BackupError> {
+        let backups = self.backups.as_ref().ok_or(BackupError::Disabled)?;
+        let repo = self.repo.clone();
+
+        // ID of the last note written
+        let notes = stream::try_unfold(0, move |after_id| {
+            let repo = repo.clone();
+            async move {
+                let notes = repo
+                    .get_all_tenants_page(after_id, BACKUP_BATCH_SIZE)
+                    .await?;
+
+                let Some(last_id) = notes.last().map(|(_, note)| note.id) else {
+                    return Ok(None);
+                };
+                let batch = notes
+                    .into_iter()
+                    .map(|(tenant, note)| Ok((tenant, NoteResponse::from(note))));
+                Ok::<_, RepositoryError>(Some((stream::iter(batch), last_id)))
+            }
         })
+        .try_flatten();
+
+        backups.write_snapshot(notes).await
+    }
+
+    pub const fn attachments_enabled(&self) -> bool {
+        self.attachments.is_some()
     }
 
-    pub async fn get_all_notes_with_timestamps(&self) -> Result<Vec<Note>, tokio_postgres::Error> {
-        self.repo.lock().await.get_all_notes().await
+    fn attachments(&self) -> Result<&Attachments, AttachmentError> {
+        self.attachments.as_ref().ok_or(AttachmentError::Disabled)
+    }
+
+    async fn ensure_note(&self, note_id: i64) -> Result<(), AttachmentError> {
+        match self.repo.get_one_note(note_id).await? {
+            Some(_) => Ok(()),
+            None => Err(AttachmentError::NotFound),
+        }
+    }
+
+    /// Streams `data` into the attachment store and attaches it to the note. The note
+    /// is checked first, so nothing is uploaded for a missing one
+    pub async fn upload_attachment(
+        &self,
+        note_id: i64,
+        filename: String,
+        content_type: String,
+        data: ByteStream,
+    ) -> Result<AttachmentResponse, AttachmentError> {
+        let store = self.attachments()?;
+        self.ensure_note(note_id).await?;
+
+        let key = attachments::new_key();
+        let max_bytes = store.max_bytes;
+        let size = store
+            .store
+            .put(&key, attachments::limit(data, max_bytes))
+            .await
+            .map_err(|e| match e {
+                StoreError::Io(e) if e.kind() == io::ErrorKind::FileTooLarge => {
+                    AttachmentError::TooLarge { max_bytes }
+                }
+                e => e.into(),
+            })?;
+
+        let draft = AttachmentDraft {
+            filename,
+            content_type,
+            size: i64::try_from(size).unwrap_or(i64::MAX),
+            storage_key: key,
+        };
+        let failure = match self.repo.create_attachment(note_id, &draft).await {
+            Ok(Some(attachment)) => return Ok(attachment.into()),
+            Ok(None) => AttachmentError::NotFound,
+            Err(e) => e.into(),
+        };
+        // The note is gone by now or the attachment wasn't recorded, the file is of no use
+        discard_stored(store, &draft.storage_key).await;
+        Err(failure)
+    }
+
+    pub async fn list_attachments(
+        &self,
+        note_id: i64,
+    ) -> Result<Vec<AttachmentResponse>, AttachmentError> {
+        self.attachments()?;
+        self.ensure_note(note_id).await?;
+        let attachments = self.repo.list_attachments(note_id).await?;
+        Ok(attachments
+            .into_iter()
+            .map(AttachmentResponse::from)
+            .collect())
+    }
+
+    /// The attachment along with its bytes, streamed from the attachment store
+    pub async fn download_attachment(
+        &self,
+        note_id: i64,
+        id: i64,
+    ) -> Result<(AttachmentResponse, ByteStream), AttachmentError> {
+        let store = self.attachments()?;
+        self.ensure_note(note_id).await?;
+        let attachment = self
+            .repo
+            .get_attachment(note_id, id)
+            .await?
+            .ok_or(AttachmentError::NotFound)?;
+
+        let Some(data) = store.store.get(&attachment.storage_key).await? else {
+            tracing::warn!(
+                "Attachment {id} is missing from the {} store",
+                store.store.name()
+            );
+            return Err(AttachmentError::NotFound);
+        };
+        Ok((attachment.into(), data))
+    }
+
+    pub async fn delete_attachment(&self, note_id: i64, id: i64) -> Result<(), AttachmentError> {
+        let store = self.attachments()?;
+        self.ensure_note(note_id).await?;
+        let key = self
+            .repo
+            .delete_attachment(note_id, id)
+            .await?
+            .ok_or(AttachmentError::NotFound)?;
+        discard_stored(store, &key).await;
+        Ok(())
+    }
+
+    async fn remove_orphaned_attachments(
+        &self,
+        store: &Attachments,
+    ) -> Result<(), AttachmentError> {
+        loop {
+            let keys = self
+                .repo
+                .delete_orphaned_attachments(ATTACHMENT_CLEANUP_BATCH_SIZE)
+                .await?;
+            if keys.is_empty() {
+                break;
+            }
+            tracing::info!("Removing {} attachments of deleted notes", keys.len());
+            for key in &keys {
+                discard_stored(store, key).await;
+            }
+        }
+
+        let before = Utc::now() - ATTACHMENT_UPLOAD_GRACE;
+        let stored: Vec<String> = store
+            .store
+            .list()
+            .await?
+            .into_iter()
+            .filter(|object| object.last_modified < before)
+            .map(|object| object.key)
+            .collect();
+        for keys in stored.chunks(ATTACHMENT_KEY_BATCH_SIZE) {
+            let existing: HashSet<String> = self
+                .repo
+                .existing_attachment_keys(keys)
+                .await?
+                .into_iter()
+                .collect();
+            for key in keys.iter().filter(|key| !existing.contains(*key)) {
+                tracing::info!("Removing orphaned attachment file {key}");
+                discard_stored(store, key).await;
+            }
+        }
+        Ok(())
+    }
+
+    pub const fn backups_enabled(&self) -> bool {
+        self.backups.is_some()
+    }
+
+    /// Publishes changes recorded by the other instances to change stream subscribers
+    /// and drops the changed notes from the local cache, this instance's own changes are
+    /// handled as they are made. When the database
+    /// connection is lost, listens again after `retry`, changes recorded in between
+    /// are not published. Runs until the process exits
+    pub async fn run_change_feed(self: Arc<Self>, retry: Duration) {
+        loop {
+            match self.repo.listen_changes().await {
+                Ok(changes) => {
+                    let mut changes = std::pin::pin!(changes);
+                    while let Some(activity) = changes.next().await {
+                        if activity.origin.as_deref() == Some(self.events.origin()) {
+                            continue;
+                        }
+                        if let Some(local_cache) = &self.local_cache {
+                            let tenant = Tenant::from(activity.tenant_id.as_str());
+                            local_cache.invalidate(&tenant, &[activity.note_id]);
+                        }
+                        if let Some(event) = event_from_activity(&activity) {
+                            self.events.publish(event);
+                        }
+                    }
+                    tracing::warn!("Change feed connection closed");
+                }
+                Err(e) => tracing::error!("Failed to listen for note changes: {e}"),
+            }
+            tokio::time::sleep(retry).await;
+        }
+    }
+
+    async fn send_due_reminders(&self, to: &str) -> Result<(), RepositoryError> {
+        let notes = self
+            .repo
+            .claim_due_reminders(REMINDER_BATCH_SIZE, Utc::now() + REMINDER_LEASE)
+            .await?;
+
+        for (tenant, note) in notes {
+            let Some(remind_at) = note.remind_at else {
+                continue;
+            };
+
+            let email = Email {
+                to: to.to_string(),
+                subject: format!("Reminder: note #{}", note.id),
+                body: note_email_body(&note),
+            };
+
+            // A failed reminder stays due and is retried once its lease ends
+            if let Err(e) = self.email_client.send(email).await {
+                tracing::error!("Failed to send reminder for note {}: {e}", note.id);
+                continue;
+            }
+
+            if self.repo.clear_reminder(note.id, remind_at).await? {
+                tenant
+                    .scope(self.record_changes(&[note.id], NoteOperation::Updated))
+                    .await;
+            }
+            tracing::info!("Sent reminder for note {}", note.id);
+        }
+
+        Ok(())
+    }
+
+    async fn send_due_digests(&self) -> Result<(), RepositoryError> {
+        let now = Utc::now();
+        let due = self
+            .repo
+            .claim_due_digests(DIGEST_BATCH_SIZE, now + DIGEST_LEASE)
+            .await?;
+
+        for digest in due {
+            let tenant = Tenant::from(digest.tenant_id.as_str());
+            tenant.scope(self.send_digest(digest, now)).await?;
+        }
+        Ok(())
+    }
+
+    /// Emails the notes changed since the previous digest up to `now`, nothing is
+    /// sent when there are none. A digest that fails is tried again with the same notes
+    async fn send_digest(
+        &self,
+        digest: DueDigest,
+        now: DateTime<Utc>,
+    ) -> Result<(), RepositoryError> {
+        let next_run_at = digests::parse_schedule(&digest.schedule)
+            .ok()
+            .and_then(|schedule| digests::next_run(&schedule, now));
+        let (notes, total) = self
+            .repo
+            .changed_notes(digest.since, now, DIGEST_MAX_NOTES)
+            .await?;
+
+        if total > 0 {
+            let email = Email {
+                to: digest.email,
+                subject: format!("Notes digest: {total} changed notes"),
+                body: digests::render(&notes, total, digest.since),
+            };
+            if let Err(e) = self.email_client.send(email).await {
+                tracing::error!("Failed to send digest {}: {e}", digest.id);
+                return self
+                    .repo
+                    .fail_digest(digest.id, &e.to_string(), now + DIGEST_RETRY_DELAY)
+                    .await;
+            }
+            tracing::info!("Sent digest {} with {total} changed notes", digest.id);
+        }
+
+        self.repo.complete_digest(digest.id, now, next_run_at).await
+    }
+
+    /// Streams all notes of the current tenant ordered by ID, archived ones too if
+    /// `include_archived`. Rows are decoded as the database sends them, so the whole
+    /// table is never held in memory, see `Repository::stream_notes`
+    pub fn stream_notes(
+        &self,
+        include_archived: bool,
+    ) -> impl Stream<Item = Result<NoteResponse, RepositoryError>> + Send + 'static {
+        let repo = self.repo.clone();
+        // The stream is polled after the request task returns
+        let tenant = Tenant::current();
+
+        stream::once(tenant.scope(repo.stream_notes(include_archived)))
+            .try_flatten()
+            .map_ok(NoteResponse::from)
+    }
+
+    /// Streams all notes of the current tenant rendered in the given format. Notes are
+    /// rendered as they arrive from the database, those already received are sent
+    /// together in one chunk
+    pub fn export_notes(
+        &self,
+        format: ExportFormat,
+    ) -> impl Stream<Item = Result<String, RepositoryError>> + Send + 'static {
+        let repo = self.repo.clone();
+        // The stream is polled after the request task returns
+        let tenant = Tenant::current();
+        let mut first = true;
+
+        let notes = stream::once(tenant.scope(repo.stream_notes(false)))
+            .try_flatten()
+            .ready_chunks(EXPORT_CHUNK_SIZE)
+            .map(move |notes| {
+                let mut chunk = String::new();
+                for note in notes {
+                    format.write_note(&mut chunk, note?, first);
+                    first = false;
+                }
+                Ok(chunk)
+            });
+
+        stream::once(future::ready(Ok(format.header())))
+            .chain(notes)
+            .chain(stream::once(future::ready(Ok(format.footer()))))
     }
 }
@@ -0,0 +1,75 @@
+use std::fmt;
+
+/// What a retention rule does with the notes it applies to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RetentionAction {
+    /// Move to the archive, notes that expire or have a pending reminder stay
+    Archive,
+    /// Remove for good, archived notes too
+    Delete,
+}
+
+impl RetentionAction {
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Archive => "archive",
+            Self::Delete => "delete",
+        }
+    }
+}
+
+/// Applies its action to notes not modified for `after_days` days
+#[derive(Debug, Clone, Copy)]
+pub struct RetentionRule {
+    pub action: RetentionAction,
+    pub after_days: i64,
+}
+
+impl RetentionRule {
+    pub const fn age(self) -> chrono::Duration {
+        chrono::Duration::days(self.after_days)
+    }
+}
+
+impl fmt::Display for RetentionRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.action.as_str(), self.after_days)
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("invalid retention rule '{0}', expected archive:<days> or delete:<days>")]
+pub struct InvalidRetentionRule(String);
+
+/// Rules from a comma-separated list of `archive:<days>` and `delete:<days>`,
+/// applied in the order given
+pub fn parse_rules(spec: &str) -> Result<Vec<RetentionRule>, InvalidRetentionRule> {
+    spec.split(',')
+        .map(str::trim)
+        .filter(|rule| !rule.is_empty())
+        .map(|rule| {
+            let invalid = || InvalidRetentionRule(rule.to_string());
+            let (action, days) = rule.split_once(':').ok_or_else(invalid)?;
+            let action = match action.trim() {
+                "archive" => RetentionAction::Archive,
+                "delete" => RetentionAction::Delete,
+                _ => return Err(invalid()),
+            };
+            let after_days = days
+                .trim()
+                .parse()
+                .ok()
+                .filter(|&days: &i64| days > 0)
+                .ok_or_else(invalid)?;
+            Ok(RetentionRule { action, after_days })
+        })
+        .collect()
+}
+
+/// Retention rules enforced by the background job. In a dry run the notes each rule
+/// applies to are only counted and logged
+#[derive(Debug, Clone, Default)]
+pub struct RetentionPolicy {
+    pub rules: Vec<RetentionRule>,
+    pub dry_run: bool,
+}
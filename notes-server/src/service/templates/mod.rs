@@ -0,0 +1,39 @@
+use chrono::{DateTime, Local};
+
+use std::collections::HashMap;
+
+/// Replaces `{{name}}` placeholders in `content`. Values given by the client come first,
+/// then the built-in `date`, `time` and `datetime` (server local time). Unknown
+/// placeholders are kept as they are
+pub(super) fn render(
+    content: &str,
+    values: &HashMap<String, String>,
+    now: DateTime<Local>,
+) -> String {
+    let builtin = |name: &str| match name {
+        "date" => Some(now.format("%Y-%m-%d").to_string()),
+        "time" => Some(now.format("%H:%M").to_string()),
+        "datetime" => Some(now.format("%Y-%m-%d %H:%M:%S").to_string()),
+        _ => None,
+    };
+
+    let mut rendered = String::with_capacity(content.len());
+    let mut rest = content;
+    while let Some(start) = rest.find("{{") {
+        let Some(len) = rest[start + 2..].find("}}") else {
+            break;
+        };
+        let placeholder = &rest[start..start + 2 + len + 2];
+        let name = placeholder[2..placeholder.len() - 2].trim();
+
+        rendered.push_str(&rest[..start]);
+        match values.get(name).cloned().or_else(|| builtin(name)) {
+            Some(value) => rendered.push_str(&value),
+            None => rendered.push_str(placeholder),
+        }
+        rest = &rest[start + placeholder.len()..];
+    }
+    rendered.push_str(rest);
+
+    rendered
+}
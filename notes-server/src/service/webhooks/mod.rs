@@ -0,0 +1,43 @@
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
+use rand::{RngCore, rng};
+
+use crate::{dto::WebhookRequest, models::WebhookDraft};
+
+/// Random bytes per signing secret
+const SECRET_BYTES: usize = 32;
+
+/// Why a webhook subscription was rejected
+#[derive(Debug, Clone, Copy, thiserror::Error)]
+pub enum InvalidWebhook {
+    #[error("URL must be an absolute http or https URL")]
+    Url,
+}
+
+/// New random key to sign a webhook's requests with
+pub(super) fn new_secret() -> String {
+    let mut bytes = [0u8; SECRET_BYTES];
+    rng().fill_bytes(&mut bytes);
+    URL_SAFE_NO_PAD.encode(bytes)
+}
+
+/// Subscription to store, the URL checked to be one requests can be sent to
+pub(super) fn draft(request: &WebhookRequest) -> Result<WebhookDraft, InvalidWebhook> {
+    let url = reqwest::Url::parse(request.url.trim()).map_err(|_| InvalidWebhook::Url)?;
+    if !matches!(url.scheme(), "http" | "https") || url.host().is_none() {
+        return Err(InvalidWebhook::Url);
+    }
+
+    let mut events: Vec<String> = request
+        .events
+        .iter()
+        .map(|operation| operation.as_str().to_string())
+        .collect();
+    events.sort();
+    events.dedup();
+
+    Ok(WebhookDraft {
+        url: url.into(),
+        events,
+        active: request.active,
+    })
+}
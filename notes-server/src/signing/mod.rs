@@ -0,0 +1,365 @@
+use axum::http::{HeaderMap, Method, Uri};
+use bytes::Bytes;
+use http_body::{Body, Frame, SizeHint};
+use moka::sync::Cache;
+use request_signing::{
+    CONTENT_SHA256_HEADER, ContentDigest, KEY_ID_HEADER, NONCE_HEADER, SIGNATURE_HEADER,
+    SigningKey, TIMESTAMP_HEADER,
+};
+use tower::BoxError;
+
+use std::{
+    collections::HashMap,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll, ready},
+    time::Duration,
+};
+
+use crate::tenant::Principal;
+
+/// Most nonces remembered at once
+const SEEN_NONCE_CAPACITY: u64 = 1_000_000;
+/// Longest nonce accepted, so remembering them takes bounded memory
+const MAX_NONCE_LENGTH: usize = 128;
+
+/// Why a request's signature wasn't accepted
+#[derive(Debug, thiserror::Error)]
+pub enum SigningError {
+    #[error("missing or invalid {0} header")]
+    Missing(&'static str),
+
+    #[error("request is signed with an unknown key")]
+    UnknownKey,
+
+    #[error("timestamp is more than {0}s off the server's clock")]
+    Expired(u64),
+
+    #[error("signature doesn't match the request")]
+    InvalidSignature,
+
+    #[error("nonce was already used")]
+    Replayed,
+
+    #[error("body doesn't match its signed hash")]
+    ContentMismatch,
+}
+
+/// Key a request was signed with, set as an extension once its signature was checked
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SignedBy(Arc<str>);
+
+impl SignedBy {
+    /// Service holding the key, for requests that don't carry an API key or token
+    pub fn principal(&self) -> Principal {
+        Principal::service(&self.0)
+    }
+}
+
+/// Checks the signatures of requests made with the shared keys of `request_signing`, for
+/// deployments that can't require client certificates. Nonces are remembered for twice
+/// the allowed clock skew, longer than their timestamps are accepted, so a request can't
+/// be replayed to the same instance
+pub struct RequestSigning {
+    keys: HashMap<String, SigningKey>,
+    max_skew: Duration,
+    seen: Cache<String, ()>,
+}
+
+impl RequestSigning {
+    /// Keys parsed from `id=secret` pairs separated by commas, several may be accepted
+    /// at once while rotating them
+    pub fn parse(spec: &str, max_skew: Duration) -> Result<Self, String> {
+        let mut keys = HashMap::new();
+        for pair in spec
+            .split(',')
+            .map(str::trim)
+            .filter(|pair| !pair.is_empty())
+        {
+            let (id, secret) = pair
+                .split_once('=')
+                .map(|(id, secret)| (id.trim(), secret.trim()))
+                .filter(|(id, secret)| !id.is_empty() && !secret.is_empty())
+                .ok_or_else(|| "expected 'id=secret' pairs".to_string())?;
+            let key = SigningKey::new(id, secret.as_bytes());
+            if keys.insert(id.to_string(), key).is_some() {
+                return Err(format!("signing key '{id}' is listed twice"));
+            }
+        }
+
+        if keys.is_empty() {
+            return Err("no signing keys given".into());
+        }
+        let seen = Cache::builder()
+            .max_capacity(SEEN_NONCE_CAPACITY)
+            .time_to_live(max_skew * 2)
+            .build();
+        Ok(Self {
+            keys,
+            max_skew,
+            seen,
+        })
+    }
+
+    /// Checks the signature of the request's headers, the key it was signed with and the
+    /// hash its body must have, see `ContentVerified`
+    pub fn verify(
+        &self,
+        method: &Method,
+        uri: &Uri,
+        headers: &HeaderMap,
+    ) -> Result<(SignedBy, String), SigningError> {
+        let header = |name: &'static str| {
+            headers
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .ok_or(SigningError::Missing(name))
+        };
+        let key = self
+            .keys
+            .get(header(KEY_ID_HEADER)?)
+            .ok_or(SigningError::UnknownKey)?;
+        let timestamp: u64 = header(TIMESTAMP_HEADER)?
+            .parse()
+            .map_err(|_| SigningError::Missing(TIMESTAMP_HEADER))?;
+        let nonce = header(NONCE_HEADER)?;
+        if nonce.is_empty() || nonce.len() > MAX_NONCE_LENGTH {
+            return Err(SigningError::Missing(NONCE_HEADER));
+        }
+        let content_sha256 = header(CONTENT_SHA256_HEADER)?;
+
+        if request_signing::unix_time().abs_diff(timestamp) > self.max_skew.as_secs() {
+            return Err(SigningError::Expired(self.max_skew.as_secs()));
+        }
+
+        let path_and_query = uri.path_and_query().map_or("/", |path| path.as_str());
+        let string_to_sign = request_signing::string_to_sign(
+            method.as_str(),
+            path_and_query,
+            timestamp,
+            nonce,
+            content_sha256,
+        );
+        if !key.verify(&string_to_sign, header(SIGNATURE_HEADER)?) {
+            return Err(SigningError::InvalidSignature);
+        }
+
+        // Only checked once the signature is, so forged requests can't use up nonces
+        let fresh = self
+            .seen
+            .entry(format!("{}:{nonce}", key.id()))
+            .or_insert(())
+            .is_fresh();
+        if !fresh {
+            return Err(SigningError::Replayed);
+        }
+
+        Ok((
+            SignedBy(key.id().into()),
+            content_sha256.to_ascii_lowercase(),
+        ))
+    }
+}
+
+/// Body that fails at its end unless its SHA-256 is the signed one. Bodies are streamed,
+/// so the handler may already have seen the data by then, but not the whole of it
+pub struct ContentVerified<B> {
+    inner: B,
+    expected: String,
+    digest: Option<ContentDigest>,
+}
+
+impl<B> ContentVerified<B> {
+    pub fn new(inner: B, expected: String) -> Self {
+        Self {
+            inner,
+            expected,
+            digest: Some(ContentDigest::default()),
+        }
+    }
+}
+
+impl<B> Body for ContentVerified<B>
+where
+    B: Body<Data = Bytes> + Unpin,
+    B::Error: Into<BoxError>,
+{
+    type Data = Bytes;
+    type Error = BoxError;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Bytes>, BoxError>>> {
+        let this = &mut *self;
+        match ready!(Pin::new(&mut this.inner).poll_frame(cx)) {
+            Some(Ok(frame)) => {
+                if let (Some(data), Some(digest)) = (frame.data_ref(), this.digest.as_mut()) {
+                    digest.update(data);
+                }
+                Poll::Ready(Some(Ok(frame)))
+            }
+            Some(Err(e)) => Poll::Ready(Some(Err(e.into()))),
+            None => {
+                let matches = this
+                    .digest
+                    .take()
+                    .is_none_or(|digest| digest.finish() == this.expected);
+                if matches {
+                    Poll::Ready(None)
+                } else {
+                    Poll::Ready(Some(Err(SigningError::ContentMismatch.into())))
+                }
+            }
+        }
+    }
+
+    // The end is only reported once the hash was checked
+    fn is_end_stream(&self) -> bool {
+        self.digest.is_none()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::{HeaderMap, HeaderValue, Method, Uri};
+    use bytes::Bytes;
+    use http_body_util::{BodyExt, Full};
+    use request_signing::{
+        CONTENT_SHA256_HEADER, KEY_ID_HEADER, NONCE_HEADER, SIGNATURE_HEADER, SigningKey,
+        TIMESTAMP_HEADER, content_sha256, string_to_sign, unix_time,
+    };
+
+    use std::time::Duration;
+
+    use super::{ContentVerified, RequestSigning, SigningError};
+
+    const PATH: &str = "/notes?limit=10";
+
+    fn signing() -> RequestSigning {
+        RequestSigning::parse("svc=secret", Duration::from_secs(300)).unwrap()
+    }
+
+    fn uri(path_and_query: &str) -> Uri {
+        path_and_query.parse().unwrap()
+    }
+
+    fn headers(pairs: impl IntoIterator<Item = (&'static str, String)>) -> HeaderMap {
+        pairs
+            .into_iter()
+            .map(|(name, value)| (name.parse().unwrap(), value.parse().unwrap()))
+            .collect()
+    }
+
+    /// Headers of a request signed at `timestamp` with the nonce `abc`
+    fn signed_at(timestamp: u64, body: &[u8]) -> HeaderMap {
+        let content_sha256 = content_sha256(body);
+        let signature = SigningKey::new("svc", b"secret").sign(&string_to_sign(
+            "POST",
+            PATH,
+            timestamp,
+            "abc",
+            &content_sha256,
+        ));
+        headers([
+            (KEY_ID_HEADER, "svc".to_string()),
+            (TIMESTAMP_HEADER, timestamp.to_string()),
+            (NONCE_HEADER, "abc".to_string()),
+            (CONTENT_SHA256_HEADER, content_sha256),
+            (SIGNATURE_HEADER, signature),
+        ])
+    }
+
+    #[test]
+    fn signed_request_is_accepted() {
+        let signed = headers(SigningKey::new("svc", b"secret").headers("POST", PATH, b"body"));
+        let (signed_by, hash) = signing()
+            .verify(&Method::POST, &uri(PATH), &signed)
+            .unwrap();
+        assert_eq!(signed_by.0.as_ref(), "svc");
+        assert_eq!(hash, content_sha256(b"body"));
+    }
+
+    #[test]
+    fn tampered_request_is_rejected() {
+        let signing = signing();
+        let signed = signed_at(unix_time(), b"body");
+        for path in ["/notes/1?limit=10", "/notes?limit=11"] {
+            assert!(matches!(
+                signing.verify(&Method::POST, &uri(path), &signed),
+                Err(SigningError::InvalidSignature)
+            ));
+        }
+        let mut tampered = signed.clone();
+        tampered.insert(
+            CONTENT_SHA256_HEADER,
+            HeaderValue::from_str(&content_sha256(b"other body")).unwrap(),
+        );
+        assert!(matches!(
+            signing.verify(&Method::POST, &uri(PATH), &tampered),
+            Err(SigningError::InvalidSignature)
+        ));
+        assert!(matches!(
+            signing.verify(&Method::PUT, &uri(PATH), &signed),
+            Err(SigningError::InvalidSignature)
+        ));
+    }
+
+    #[test]
+    fn timestamp_off_the_clock_is_rejected() {
+        let signing = signing();
+        for timestamp in [unix_time() - 301, unix_time() + 301] {
+            assert!(matches!(
+                signing.verify(&Method::POST, &uri(PATH), &signed_at(timestamp, b"body")),
+                Err(SigningError::Expired(300))
+            ));
+        }
+    }
+
+    #[test]
+    fn replayed_nonce_is_rejected() {
+        let signing = signing();
+        let signed = signed_at(unix_time(), b"body");
+        assert!(signing.verify(&Method::POST, &uri(PATH), &signed).is_ok());
+        assert!(matches!(
+            signing.verify(&Method::POST, &uri(PATH), &signed),
+            Err(SigningError::Replayed)
+        ));
+    }
+
+    #[test]
+    fn forged_signature_does_not_use_up_the_nonce() {
+        let signing = signing();
+        let signed = signed_at(unix_time(), b"body");
+        let mut forged = signed.clone();
+        forged.insert(
+            SIGNATURE_HEADER,
+            HeaderValue::from_static("sha256=0000000000000000000000000000000000000000"),
+        );
+        assert!(matches!(
+            signing.verify(&Method::POST, &uri(PATH), &forged),
+            Err(SigningError::InvalidSignature)
+        ));
+        assert!(signing.verify(&Method::POST, &uri(PATH), &signed).is_ok());
+    }
+
+    #[tokio::test]
+    async fn body_must_match_its_signed_hash() {
+        let body = ContentVerified::new(Full::new(Bytes::from("body")), content_sha256(b"body"));
+        assert_eq!(body.collect().await.unwrap().to_bytes(), "body");
+
+        let body = ContentVerified::new(
+            Full::new(Bytes::from("other body")),
+            content_sha256(b"body"),
+        );
+        let error = body.collect().await.unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<SigningError>(),
+            Some(SigningError::ContentMismatch)
+        ));
+    }
+}
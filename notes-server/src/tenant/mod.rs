@@ -0,0 +1,131 @@
+use axum::http::HeaderMap;
+use ring::digest::{SHA256, digest};
+
+use std::{collections::HashMap, fmt, fmt::Write, future::Future, sync::Arc};
+
+/// Header carrying the API key on HTTP requests and in gRPC metadata
+pub const API_KEY_HEADER: &str = "x-api-key";
+
+/// Tenant of every row created before multi-tenancy was enabled
+const DEFAULT_TENANT: &str = "default";
+
+tokio::task_local! {
+    static CURRENT: Tenant;
+}
+
+/// Customer whose notes, templates and activity are isolated from the other tenants'.
+/// The tenant of a request is set for its whole task by `scope`, the repository reads it
+/// with `current`, so handlers and the service don't pass it along
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Tenant(Arc<str>);
+
+impl Default for Tenant {
+    fn default() -> Self {
+        Self(DEFAULT_TENANT.into())
+    }
+}
+
+impl From<&str> for Tenant {
+    fn from(id: &str) -> Self {
+        Self(id.into())
+    }
+}
+
+impl fmt::Display for Tenant {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl Tenant {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// Tenant of the running task, the default one outside of `scope`, e.g. when
+    /// multi-tenancy is disabled
+    pub fn current() -> Self {
+        CURRENT.try_with(Self::clone).unwrap_or_default()
+    }
+
+    /// Runs `f` as this tenant. Streams polled after the request task returns, e.g.
+    /// response bodies, are outside the scope and must capture the tenant themselves
+    pub async fn scope<F: Future>(self, f: F) -> F::Output {
+        CURRENT.scope(self, f).await
+    }
+
+    /// Calls `f` as this tenant, for services that create their future synchronously
+    pub fn sync_scope<R>(self, f: impl FnOnce() -> R) -> R {
+        CURRENT.sync_scope(self, f)
+    }
+}
+
+/// Caller a request is authenticated as, quotas are counted per principal
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Principal(Arc<str>);
+
+impl Principal {
+    /// Caller with the API key, named by a fingerprint of it so the key isn't exposed
+    pub fn api_key(key: &str) -> Self {
+        let hash = digest(&SHA256, key.as_bytes());
+        let mut name = String::from("key:");
+        for byte in &hash.as_ref()[..6] {
+            let _ = write!(name, "{byte:02x}");
+        }
+        Self(name.into())
+    }
+
+    /// User signed in through the OIDC provider
+    pub fn user(subject: &str) -> Self {
+        Self(format!("user:{subject}").into())
+    }
+
+    /// Service identified by its client certificate
+    pub fn service(name: &str) -> Self {
+        Self(format!("service:{name}").into())
+    }
+}
+
+impl fmt::Display for Principal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// API keys and the tenants they belong to, parsed from `key=tenant` pairs
+/// separated by commas. A tenant may have several keys, e.g. while rotating them
+#[derive(Debug, Default)]
+pub struct TenantKeys(HashMap<String, Tenant>);
+
+impl TenantKeys {
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let mut keys = HashMap::new();
+        for pair in spec
+            .split(',')
+            .map(str::trim)
+            .filter(|pair| !pair.is_empty())
+        {
+            let (key, tenant) = pair
+                .split_once('=')
+                .map(|(key, tenant)| (key.trim(), tenant.trim()))
+                .filter(|(key, tenant)| !key.is_empty() && !tenant.is_empty())
+                .ok_or_else(|| format!("expected 'key=tenant', got '{pair}'"))?;
+            if keys.insert(key.to_string(), Tenant::from(tenant)).is_some() {
+                return Err(format!("API key of tenant '{tenant}' is listed twice"));
+            }
+        }
+
+        if keys.is_empty() {
+            return Err("no API keys given".into());
+        }
+        Ok(Self(keys))
+    }
+
+    /// Tenant of the API key the request carries and the caller it identifies,
+    /// `None` if it has none or an unknown one
+    pub fn resolve(&self, headers: &HeaderMap) -> Option<(Tenant, Principal)> {
+        let key = headers.get(API_KEY_HEADER)?.to_str().ok()?;
+        let tenant = self.0.get(key)?.clone();
+        Some((tenant, Principal::api_key(key)))
+    }
+}
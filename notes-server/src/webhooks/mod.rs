@@ -0,0 +1,175 @@
+use chrono::Utc;
+use futures_util::future;
+use ring::hmac;
+
+use std::{fmt::Write, sync::Arc, time::Duration};
+
+use crate::{
+    models::{DeliveryFailure, DueDelivery},
+    repository::{Repository, RepositoryError},
+};
+
+/// Most deliveries sent at a time
+const DELIVERY_BATCH_SIZE: i64 = 100;
+/// Attempts made before a delivery fails for good
+const MAX_ATTEMPTS: i32 = 8;
+/// Delay before the first retry, doubled after every failed one
+const RETRY_MIN_BACKOFF: Duration = Duration::from_secs(10);
+/// Longest delay between retries
+const RETRY_MAX_BACKOFF: Duration = Duration::from_hours(1);
+/// Failed requests in a row after which nothing is sent to a webhook for a while
+const CIRCUIT_FAILURE_THRESHOLD: i32 = 5;
+/// How long nothing is sent to a webhook once its circuit opens
+const CIRCUIT_OPEN_FOR: Duration = Duration::from_mins(5);
+/// Longest wait for a webhook to respond
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+/// Claimed deliveries not recorded by then, e.g. as the instance crashed, are sent again
+const CLAIM_LEASE: Duration = Duration::from_mins(1);
+
+/// Signature of a webhook request, `sha256=` followed by the hex HMAC-SHA256 of
+/// `<timestamp>.<body>` keyed with the webhook's secret. The timestamp is signed so
+/// receivers can reject replayed requests
+pub fn signature(secret: &str, timestamp: i64, body: &[u8]) -> String {
+    let key = hmac::Key::new(hmac::HMAC_SHA256, secret.as_bytes());
+    let mut context = hmac::Context::with_key(&key);
+    context.update(timestamp.to_string().as_bytes());
+    context.update(b".");
+    context.update(body);
+
+    let mut signature = String::from("sha256=");
+    for byte in context.sign().as_ref() {
+        let _ = write!(signature, "{byte:02x}");
+    }
+    signature
+}
+
+/// Sends the queued deliveries to the webhooks. A delivery is retried with backoff until
+/// the webhook responds with a success status or it runs out of attempts. A webhook that
+/// keeps failing gets nothing for a while (its circuit opens), so a dead endpoint doesn't
+/// use up the attempts of every change. Several instances can send at the same time,
+/// each claims other deliveries
+pub struct WebhookDispatcher {
+    repo: Arc<Repository>,
+    client: reqwest::Client,
+}
+
+impl WebhookDispatcher {
+    pub fn new(repo: Arc<Repository>) -> Self {
+        // Redirects aren't followed, the subscribed URL is the one that gets the changes
+        let client = reqwest::Client::builder()
+            .timeout(REQUEST_TIMEOUT)
+            .redirect(reqwest::redirect::Policy::none())
+            .build()
+            .unwrap_or_else(|_| reqwest::Client::new());
+
+        Self { repo, client }
+    }
+
+    /// Sends the deliveries due now, returning how many there were
+    async fn send_batch(&self) -> Result<usize, RepositoryError> {
+        let lease_until = Utc::now() + CLAIM_LEASE;
+        let deliveries = self
+            .repo
+            .claim_webhook_deliveries(DELIVERY_BATCH_SIZE, lease_until)
+            .await?;
+
+        future::join_all(deliveries.iter().map(|delivery| self.deliver(delivery))).await;
+        Ok(deliveries.len())
+    }
+
+    /// Sends the delivery and records the outcome
+    async fn deliver(&self, delivery: &DueDelivery) {
+        let recorded = match self.send(delivery).await {
+            Ok(status_code) => {
+                self.repo
+                    .complete_webhook_delivery(delivery.id, status_code)
+                    .await
+            }
+            Err((status_code, error)) => {
+                tracing::warn!(
+                    "Webhook {} delivery {} failed: {error}",
+                    delivery.webhook_id,
+                    delivery.id
+                );
+                let attempts = delivery.attempts + 1;
+                let failure = DeliveryFailure {
+                    status_code,
+                    error,
+                    retry_at: (attempts < MAX_ATTEMPTS)
+                        .then(|| Utc::now() + retry_backoff(attempts)),
+                };
+                self.repo
+                    .fail_webhook_delivery(
+                        delivery.id,
+                        &failure,
+                        CIRCUIT_FAILURE_THRESHOLD,
+                        Utc::now() + CIRCUIT_OPEN_FOR,
+                    )
+                    .await
+            }
+        };
+
+        if let Err(e) = recorded {
+            tracing::error!("Failed to record webhook delivery {}: {e}", delivery.id);
+        }
+    }
+
+    /// Status of the successful response, or of the failed one (if any) with the error
+    async fn send(&self, delivery: &DueDelivery) -> Result<i32, (Option<i32>, String)> {
+        let body = serde_json::to_vec(&delivery.payload).map_err(|e| (None, e.to_string()))?;
+        let timestamp = Utc::now().timestamp();
+
+        let response = self
+            .client
+            .post(&delivery.url)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .header("X-Notes-Delivery", delivery.id)
+            .header("X-Notes-Event", &delivery.operation)
+            .header("X-Notes-Timestamp", timestamp)
+            .header(
+                "X-Notes-Signature",
+                signature(&delivery.secret, timestamp, &body),
+            )
+            .body(body)
+            .send()
+            .await
+            .map_err(|e| (None, e.to_string()))?;
+
+        let status = response.status();
+        let status_code = i32::from(status.as_u16());
+        if status.is_success() {
+            Ok(status_code)
+        } else {
+            Err((
+                Some(status_code),
+                format!("webhook responded with {status}"),
+            ))
+        }
+    }
+
+    /// Sends all due deliveries every `interval`, runs until the process exits
+    pub async fn run(self, interval: Duration) {
+        let mut interval = tokio::time::interval(interval);
+        loop {
+            interval.tick().await;
+            loop {
+                match self.send_batch().await {
+                    Ok(0) => break,
+                    Ok(count) => tracing::debug!("Sent {count} webhook deliveries"),
+                    Err(e) => {
+                        tracing::error!("Failed to send webhook deliveries: {e}");
+                        break;
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Delay before retrying a delivery that failed `attempts` times
+fn retry_backoff(attempts: i32) -> Duration {
+    let exponent = u32::try_from(attempts - 1).unwrap_or(0).min(16);
+    RETRY_MIN_BACKOFF
+        .saturating_mul(1 << exponent)
+        .min(RETRY_MAX_BACKOFF)
+}
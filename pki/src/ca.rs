@@ -0,0 +1,84 @@
+use rcgen::{
+    BasicConstraints, Certificate, CertificateParams, DistinguishedName, DnType,
+    ExtendedKeyUsagePurpose, IsCa, KeyPair, KeyUsagePurpose,
+};
+use time::{Duration, OffsetDateTime};
+
+use std::{error::Error, fs, path::Path};
+
+const CA_CERT_FILE: &str = "ca.pem";
+const CA_KEY_FILE: &str = "ca-key.pem";
+const CA_VALIDITY_DAYS: i64 = 3650;
+const SERVICE_VALIDITY_DAYS: i64 = 365;
+
+/// Internal certificate authority used to sign the services' certificates
+pub struct CertificateAuthority {
+    cert: Certificate,
+    key: KeyPair,
+}
+
+impl CertificateAuthority {
+    /// Loads the CA from `dir`, creating a new one if it doesn't exist yet
+    pub fn load_or_create(dir: &Path) -> Result<Self, Box<dyn Error>> {
+        let cert_path = dir.join(CA_CERT_FILE);
+        let key_path = dir.join(CA_KEY_FILE);
+
+        if cert_path.exists() && key_path.exists() {
+            let key = KeyPair::from_pem(&fs::read_to_string(&key_path)?)?;
+            let params = CertificateParams::from_ca_cert_pem(&fs::read_to_string(&cert_path)?)?;
+            // Re-signing yields the same subject and key identifier, which is all
+            // that's needed to issue certificates under the existing CA
+            let cert = params.self_signed(&key)?;
+            return Ok(Self { cert, key });
+        }
+
+        let mut params = CertificateParams::default();
+        params.distinguished_name = distinguished_name("notes-server internal CA");
+        params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+        params.key_usages = vec![KeyUsagePurpose::KeyCertSign, KeyUsagePurpose::CrlSign];
+        params.not_before = OffsetDateTime::now_utc();
+        params.not_after = params.not_before + Duration::days(CA_VALIDITY_DAYS);
+
+        let key = KeyPair::generate()?;
+        let cert = params.self_signed(&key)?;
+
+        fs::create_dir_all(dir)?;
+        fs::write(&cert_path, cert.pem())?;
+        fs::write(&key_path, key.serialize_pem())?;
+
+        Ok(Self { cert, key })
+    }
+
+    /// Issues a certificate for `service`, usable both as a server certificate
+    /// (for the service's DNS name and localhost) and as a client certificate.
+    /// Writes `<service>.pem` and `<service>-key.pem` into `dir`
+    pub fn issue(&self, dir: &Path, service: &str) -> Result<(), Box<dyn Error>> {
+        let mut params =
+            CertificateParams::new(vec![service.to_string(), "localhost".to_string()])?;
+        params.distinguished_name = distinguished_name(service);
+        params.key_usages = vec![
+            KeyUsagePurpose::DigitalSignature,
+            KeyUsagePurpose::KeyEncipherment,
+        ];
+        params.extended_key_usages = vec![
+            ExtendedKeyUsagePurpose::ServerAuth,
+            ExtendedKeyUsagePurpose::ClientAuth,
+        ];
+        params.not_before = OffsetDateTime::now_utc();
+        params.not_after = params.not_before + Duration::days(SERVICE_VALIDITY_DAYS);
+
+        let key = KeyPair::generate()?;
+        let cert = params.signed_by(&key, &self.cert, &self.key)?;
+
+        fs::write(dir.join(format!("{service}.pem")), cert.pem())?;
+        fs::write(dir.join(format!("{service}-key.pem")), key.serialize_pem())?;
+
+        Ok(())
+    }
+}
+
+fn distinguished_name(common_name: &str) -> DistinguishedName {
+    let mut name = DistinguishedName::new();
+    name.push(DnType::CommonName, common_name);
+    name
+}
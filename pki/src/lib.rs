@@ -0,0 +1,4 @@
+pub mod ca;
+pub mod tls;
+
+pub use tls::{ClientTls, server_config, server_config_from_env, server_config_from_pem};
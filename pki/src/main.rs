@@ -0,0 +1,37 @@
+use pki::ca::CertificateAuthority;
+
+use std::{env, path::Path, process::ExitCode};
+
+const USAGE: &str = "Usage: pki <out-dir> <service-name>...\n\n\
+    Creates an internal CA in <out-dir> (or reuses the existing one) and issues\n\
+    a certificate for every service name, valid for that DNS name and localhost";
+
+fn main() -> ExitCode {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let Some((out_dir, services)) = args.split_first() else {
+        eprintln!("{USAGE}");
+        return ExitCode::FAILURE;
+    };
+    let out_dir = Path::new(out_dir);
+
+    let ca = match CertificateAuthority::load_or_create(out_dir) {
+        Ok(ca) => ca,
+        Err(e) => {
+            eprintln!(
+                "Failed to load or create the CA in {}: {e}",
+                out_dir.display()
+            );
+            return ExitCode::FAILURE;
+        }
+    };
+
+    for service in services {
+        if let Err(e) = ca.issue(out_dir, service) {
+            eprintln!("Failed to issue a certificate for {service}: {e}");
+            return ExitCode::FAILURE;
+        }
+        println!("Issued {}/{service}.pem", out_dir.display());
+    }
+
+    ExitCode::SUCCESS
+}
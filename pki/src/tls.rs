@@ -0,0 +1,158 @@
+use reqwest::{Certificate, ClientBuilder, Identity};
+use rustls::{
+    ConfigBuilder, RootCertStore, ServerConfig,
+    crypto::CryptoProvider,
+    pki_types::{CertificateDer, PrivateKeyDer, pem::PemObject},
+    server::{WantsServerCert, WebPkiClientVerifier},
+};
+
+use std::{env, error::Error, fs, sync::Arc};
+
+/// TLS settings for outgoing requests to other services, read from the environment:
+/// - `TLS_CA_PATH`: CA used to verify the servers (system roots when unset)
+/// - `TLS_CLIENT_CERT_PATH` and `TLS_CLIENT_KEY_PATH`: certificate presented
+///   to servers that require mutual TLS
+#[derive(Debug, Clone, Default)]
+pub struct ClientTls {
+    ca: Option<Certificate>,
+    identity: Option<Identity>,
+}
+
+impl ClientTls {
+    pub fn from_env() -> Result<Self, Box<dyn Error>> {
+        let ca = match env::var("TLS_CA_PATH") {
+            Ok(path) => Some(
+                fs::read(&path)
+                    .map_err(|e| format!("Failed to read CA certificate '{path}': {e}"))?,
+            ),
+            Err(_) => None,
+        };
+
+        let identity = match (
+            env::var("TLS_CLIENT_CERT_PATH"),
+            env::var("TLS_CLIENT_KEY_PATH"),
+        ) {
+            (Ok(cert_path), Ok(key_path)) => {
+                let cert = fs::read(&cert_path)
+                    .map_err(|e| format!("Failed to read client certificate '{cert_path}': {e}"))?;
+                let key = fs::read(&key_path)
+                    .map_err(|e| format!("Failed to read client key '{key_path}': {e}"))?;
+                Some((cert, key))
+            }
+            (Err(_), Err(_)) => None,
+            _ => {
+                return Err(
+                    "TLS_CLIENT_CERT_PATH and TLS_CLIENT_KEY_PATH must be set together".into(),
+                );
+            }
+        };
+
+        Self::from_pem(
+            ca.as_deref(),
+            identity.as_ref().map(|(cert, key)| (&cert[..], &key[..])),
+        )
+    }
+
+    /// Settings from PEM: the CA and the client certificate with its key, for when
+    /// they don't come from files
+    pub fn from_pem(
+        ca: Option<&[u8]>,
+        identity: Option<(&[u8], &[u8])>,
+    ) -> Result<Self, Box<dyn Error>> {
+        Ok(Self {
+            ca: ca.map(Certificate::from_pem).transpose()?,
+            identity: identity
+                .map(|(cert, key)| Identity::from_pkcs8_pem(cert, key))
+                .transpose()?,
+        })
+    }
+
+    /// Whether requests are sent with a client certificate
+    pub const fn is_mutual(&self) -> bool {
+        self.identity.is_some()
+    }
+
+    /// Applies the trusted CA and the client certificate to a client builder
+    pub fn apply(&self, mut builder: ClientBuilder) -> ClientBuilder {
+        if let Some(ca) = &self.ca {
+            builder = builder.add_root_certificate(ca.clone());
+        }
+        if let Some(identity) = &self.identity {
+            builder = builder.identity(identity.clone());
+        }
+        builder
+    }
+}
+
+/// Server TLS config that only accepts clients presenting a certificate
+/// signed by the CA at `client_ca_path`
+pub fn server_config(
+    cert_path: &str,
+    key_path: &str,
+    client_ca_path: &str,
+) -> Result<ServerConfig, Box<dyn Error>> {
+    let read = |path: &str| fs::read(path).map_err(|e| format!("Failed to read '{path}': {e}"));
+    server_config_from_pem(&read(cert_path)?, &read(key_path)?, &read(client_ca_path)?)
+}
+
+/// Server TLS config of the listeners, requiring client certificates signed by the CA at
+/// `TLS_CLIENT_CA_PATH` if it is set (mutual TLS)
+pub fn server_config_from_env(
+    cert_path: &str,
+    key_path: &str,
+) -> Result<ServerConfig, Box<dyn Error>> {
+    if let Ok(client_ca_path) = env::var("TLS_CLIENT_CA_PATH") {
+        tracing::info!(
+            "Requiring client certificates signed by {} (mutual TLS)",
+            client_ca_path
+        );
+        return server_config(cert_path, key_path, &client_ca_path);
+    }
+
+    let read = |path: &str| fs::read(path).map_err(|e| format!("Failed to read '{path}': {e}"));
+    let builder = ServerConfig::builder_with_provider(provider())
+        .with_safe_default_protocol_versions()?
+        .with_no_client_auth();
+    with_identity(builder, &read(cert_path)?, &read(key_path)?)
+}
+
+/// Same as `server_config`, with the certificate, key and CA given as PEM
+pub fn server_config_from_pem(
+    cert: &[u8],
+    key: &[u8],
+    client_ca: &[u8],
+) -> Result<ServerConfig, Box<dyn Error>> {
+    let provider = provider();
+
+    let mut roots = RootCertStore::empty();
+    for cert in CertificateDer::pem_slice_iter(client_ca) {
+        roots.add(cert?)?;
+    }
+    let verifier =
+        WebPkiClientVerifier::builder_with_provider(Arc::new(roots), provider.clone()).build()?;
+
+    let builder = ServerConfig::builder_with_provider(provider)
+        .with_safe_default_protocol_versions()?
+        .with_client_cert_verifier(verifier);
+    with_identity(builder, cert, key)
+}
+
+fn provider() -> Arc<CryptoProvider> {
+    Arc::new(rustls::crypto::aws_lc_rs::default_provider())
+}
+
+/// Finishes the config with the server's certificate and key given as PEM
+fn with_identity(
+    builder: ConfigBuilder<ServerConfig, WantsServerCert>,
+    cert: &[u8],
+    key: &[u8],
+) -> Result<ServerConfig, Box<dyn Error>> {
+    let certs = CertificateDer::pem_slice_iter(cert).collect::<Result<Vec<_>, _>>()?;
+    let key = PrivateKeyDer::from_pem_slice(key)?;
+
+    let mut config = builder.with_single_cert(certs, key)?;
+    // Same protocols as axum-server offers, gRPC needs h2
+    config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+
+    Ok(config)
+}
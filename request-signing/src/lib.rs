@@ -0,0 +1,260 @@
+use ring::{
+    digest,
+    hmac::{self, HMAC_SHA256},
+    rand::{SecureRandom, SystemRandom},
+};
+
+use std::{
+    fmt::{self, Write},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// ID of the key the request is signed with
+pub const KEY_ID_HEADER: &str = "x-notes-key-id";
+/// Unix time the request was signed at, in seconds
+pub const TIMESTAMP_HEADER: &str = "x-notes-timestamp";
+/// Random value that is never used twice with the same key
+pub const NONCE_HEADER: &str = "x-notes-nonce";
+/// Hex SHA-256 of the request body
+pub const CONTENT_SHA256_HEADER: &str = "x-notes-content-sha256";
+/// `sha256=<hex HMAC-SHA256 of the string to sign>`
+pub const SIGNATURE_HEADER: &str = "x-notes-signature";
+
+/// SHA-256 of a body read in chunks, e.g. while it's streamed
+pub struct ContentDigest(digest::Context);
+
+impl Default for ContentDigest {
+    fn default() -> Self {
+        Self(digest::Context::new(&digest::SHA256))
+    }
+}
+
+impl ContentDigest {
+    pub fn update(&mut self, data: &[u8]) {
+        self.0.update(data);
+    }
+
+    /// Hex digest, as in `CONTENT_SHA256_HEADER`
+    pub fn finish(self) -> String {
+        hex(self.0.finish().as_ref())
+    }
+}
+
+/// Hex SHA-256 of the whole body
+pub fn content_sha256(body: &[u8]) -> String {
+    let mut digest = ContentDigest::default();
+    digest.update(body);
+    digest.finish()
+}
+
+/// What the signature is computed over: the method, path and query as sent, timestamp,
+/// nonce and content hash, each on its own line
+pub fn string_to_sign(
+    method: &str,
+    path_and_query: &str,
+    timestamp: u64,
+    nonce: &str,
+    content_sha256: &str,
+) -> String {
+    format!("{method}\n{path_and_query}\n{timestamp}\n{nonce}\n{content_sha256}")
+}
+
+/// Seconds since the Unix epoch
+pub fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs())
+}
+
+/// Key shared by a caller and the server. The caller signs its requests with an
+/// HMAC-SHA256 over `string_to_sign`, sent in the `x-notes-*` headers, the server checks it
+/// and rejects timestamps off its clock and nonces seen before. Its `Debug` doesn't
+/// show the secret
+#[derive(Clone)]
+pub struct SigningKey {
+    id: String,
+    key: hmac::Key,
+}
+
+impl fmt::Debug for SigningKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SigningKey").field("id", &self.id).finish()
+    }
+}
+
+impl SigningKey {
+    pub fn new(id: &str, secret: &[u8]) -> Self {
+        Self {
+            id: id.to_string(),
+            key: hmac::Key::new(HMAC_SHA256, secret),
+        }
+    }
+
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// Signature header value of the string to sign
+    pub fn sign(&self, string_to_sign: &str) -> String {
+        let tag = hmac::sign(&self.key, string_to_sign.as_bytes());
+        format!("sha256={}", hex(tag.as_ref()))
+    }
+
+    /// Whether the signature header value is this key's signature of the string, compared
+    /// in constant time
+    pub fn verify(&self, string_to_sign: &str, signature: &str) -> bool {
+        signature
+            .strip_prefix("sha256=")
+            .and_then(unhex)
+            .is_some_and(|tag| hmac::verify(&self.key, string_to_sign.as_bytes(), &tag).is_ok())
+    }
+
+    /// Headers that sign the request, to send along with it. `path_and_query` must be
+    /// the one of the request as the server receives it, e.g. `/notes?limit=10`
+    pub fn headers(
+        &self,
+        method: &str,
+        path_and_query: &str,
+        body: &[u8],
+    ) -> [(&'static str, String); 5] {
+        let timestamp = unix_time();
+        let nonce = nonce();
+        let content_sha256 = content_sha256(body);
+        let signature = self.sign(&string_to_sign(
+            method,
+            path_and_query,
+            timestamp,
+            &nonce,
+            &content_sha256,
+        ));
+
+        [
+            (KEY_ID_HEADER, self.id.clone()),
+            (TIMESTAMP_HEADER, timestamp.to_string()),
+            (NONCE_HEADER, nonce),
+            (CONTENT_SHA256_HEADER, content_sha256),
+            (SIGNATURE_HEADER, signature),
+        ]
+    }
+}
+
+/// 16 random bytes in hex
+fn nonce() -> String {
+    let mut bytes = [0u8; 16];
+    SystemRandom::new()
+        .fill(&mut bytes)
+        .expect("system random number generator failed");
+    hex(&bytes)
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .fold(String::with_capacity(bytes.len() * 2), |mut out, byte| {
+            let _ = write!(out, "{byte:02x}");
+            out
+        })
+}
+
+fn unhex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{SigningKey, content_sha256, string_to_sign, unhex};
+
+    fn signed(key: &SigningKey) -> String {
+        key.sign(&string_to_sign(
+            "POST",
+            "/notes?limit=10",
+            1_700_000_000,
+            "abc",
+            &content_sha256(b"body"),
+        ))
+    }
+
+    #[test]
+    fn string_to_sign_has_each_part_on_its_own_line() {
+        assert_eq!(
+            string_to_sign("GET", "/notes?limit=10", 42, "abc", "ff"),
+            "GET\n/notes?limit=10\n42\nabc\nff"
+        );
+    }
+
+    #[test]
+    fn unhex_reverses_hex() {
+        assert_eq!(unhex("00ff7f"), Some(vec![0x00, 0xff, 0x7f]));
+        assert_eq!(unhex(""), Some(vec![]));
+        assert_eq!(unhex("abc"), None);
+        assert_eq!(unhex("zz"), None);
+        assert_eq!(unhex("éa"), None);
+    }
+
+    #[test]
+    fn signature_of_the_same_string_is_accepted() {
+        let key = SigningKey::new("svc", b"secret");
+        let signature = signed(&key);
+        assert!(key.verify(
+            &string_to_sign(
+                "POST",
+                "/notes?limit=10",
+                1_700_000_000,
+                "abc",
+                &content_sha256(b"body"),
+            ),
+            &signature,
+        ));
+    }
+
+    #[test]
+    fn signature_of_a_changed_request_is_rejected() {
+        let key = SigningKey::new("svc", b"secret");
+        let signature = signed(&key);
+        let tampered = [
+            string_to_sign(
+                "POST",
+                "/notes/1?limit=10",
+                1_700_000_000,
+                "abc",
+                &content_sha256(b"body"),
+            ),
+            string_to_sign(
+                "POST",
+                "/notes?limit=11",
+                1_700_000_000,
+                "abc",
+                &content_sha256(b"body"),
+            ),
+            string_to_sign(
+                "POST",
+                "/notes?limit=10",
+                1_700_000_000,
+                "abc",
+                &content_sha256(b"other body"),
+            ),
+        ];
+        for string_to_sign in tampered {
+            assert!(!key.verify(&string_to_sign, &signature));
+        }
+    }
+
+    #[test]
+    fn malformed_or_foreign_signature_is_rejected() {
+        let key = SigningKey::new("svc", b"secret");
+        let string_to_sign = string_to_sign("GET", "/", 0, "abc", "ff");
+        let signature = key.sign(&string_to_sign);
+
+        let other = SigningKey::new("svc", b"other secret");
+        assert!(!other.verify(&string_to_sign, &signature));
+        assert!(!key.verify(&string_to_sign, signature.trim_start_matches("sha256=")));
+        assert!(!key.verify(&string_to_sign, &signature[..signature.len() - 1]));
+        assert!(!key.verify(&string_to_sign, "sha256=zz"));
+    }
+}
@@ -0,0 +1,113 @@
+pub mod vault;
+
+pub use vault::{Vault, VaultError};
+
+use std::{env, fmt, fs, io, sync::Arc};
+
+/// Value of a secret. Its `Debug` doesn't show it, so it doesn't end up in logs
+#[derive(Clone, PartialEq, Eq)]
+pub struct Secret(String);
+
+impl Secret {
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+
+    pub fn into_inner(self) -> String {
+        self.0
+    }
+}
+
+impl fmt::Debug for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Secret(***)")
+    }
+}
+
+/// Why a secret couldn't be read
+#[derive(Debug, thiserror::Error)]
+pub enum SecretError {
+    #[error("failed to read {name} from '{path}': {source}")]
+    File {
+        name: String,
+        path: String,
+        source: io::Error,
+    },
+
+    #[error("invalid {name}_VAULT '{reference}', expected <path>#<field>")]
+    InvalidReference { name: String, reference: String },
+
+    #[error("{0}_VAULT is set, but VAULT_ADDR isn't")]
+    NoVault(String),
+
+    #[error("failed to read {name} from Vault: {source}")]
+    Vault { name: String, source: VaultError },
+}
+
+/// Suffixes of the variables naming a file with the secret. `_PATH` is what the TLS
+/// keys have always been configured with
+const FILE_SUFFIXES: [&str; 2] = ["_FILE", "_PATH"];
+
+/// Reads secrets from where the deployment keeps them. A secret `NAME` is read from
+/// the file at `NAME_FILE` (or `NAME_PATH`), e.g. a Docker or Kubernetes secret, from
+/// Vault with `NAME_VAULT=<path>#<field>`, or else from the `NAME` variable itself
+#[derive(Debug, Clone, Default)]
+pub struct Secrets {
+    vault: Option<Arc<Vault>>,
+}
+
+impl Secrets {
+    /// Secrets of the environment, Vault is used when `VAULT_ADDR` is set (see
+    /// `Vault::from_env`)
+    pub async fn from_env() -> Result<Self, SecretError> {
+        let vault = Vault::from_env(&Self::default()).await?;
+        Ok(Self {
+            vault: vault.map(Arc::new),
+        })
+    }
+
+    /// Vault the secrets are read from, its leases need renewing (see `Vault::run_renewal`)
+    pub const fn vault(&self) -> Option<&Arc<Vault>> {
+        self.vault.as_ref()
+    }
+
+    /// The secret, `None` when none of its variables is set
+    pub async fn get(&self, name: &str) -> Result<Option<Secret>, SecretError> {
+        for suffix in FILE_SUFFIXES {
+            if let Ok(path) = env::var(format!("{name}{suffix}")) {
+                let value = fs::read_to_string(&path).map_err(|source| SecretError::File {
+                    name: name.to_string(),
+                    path,
+                    source,
+                })?;
+                // Secret files usually end with a newline that isn't part of the secret
+                return Ok(Some(Secret(
+                    value.trim_end_matches(['\r', '\n']).to_string(),
+                )));
+            }
+        }
+
+        if let Ok(reference) = env::var(format!("{name}_VAULT")) {
+            let Some((path, field)) = reference.split_once('#') else {
+                return Err(SecretError::InvalidReference {
+                    name: name.to_string(),
+                    reference,
+                });
+            };
+            let vault = self
+                .vault
+                .as_ref()
+                .ok_or_else(|| SecretError::NoVault(name.to_string()))?;
+            let value = vault
+                .read(path, field)
+                .await
+                .map_err(|source| SecretError::Vault {
+                    name: name.to_string(),
+                    source,
+                })?;
+            return Ok(Some(Secret(value)));
+        }
+
+        Ok(env::var(name).ok().map(Secret))
+    }
+}
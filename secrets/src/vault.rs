@@ -0,0 +1,348 @@
+use reqwest::Certificate;
+use serde::Deserialize;
+use serde_json::{Map, Value};
+
+use std::{
+    collections::HashMap,
+    env, fmt, fs,
+    sync::{Arc, Mutex, PoisonError},
+    time::{Duration, Instant},
+};
+
+use tokio::sync::Notify;
+
+use crate::{Secret, SecretError, Secrets};
+
+/// Longest wait for Vault to respond
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+/// Wait before trying again to renew a lease that failed to renew
+const RETRY_INTERVAL: Duration = Duration::from_secs(30);
+/// ID of the lease of the token itself
+const TOKEN_LEASE: &str = "token";
+
+/// Why Vault couldn't be read
+#[derive(Debug, thiserror::Error)]
+pub enum VaultError {
+    #[error("request failed: {0}")]
+    Request(#[from] reqwest::Error),
+
+    #[error("secret at '{path}' has no field '{field}'")]
+    NoField { path: String, field: String },
+
+    #[error("field '{field}' of the secret at '{path}' is not a string")]
+    NotString { path: String, field: String },
+}
+
+#[derive(Deserialize)]
+struct SecretResponse {
+    #[serde(default)]
+    lease_id: String,
+    #[serde(default)]
+    lease_duration: u64,
+    #[serde(default)]
+    renewable: bool,
+    data: Map<String, Value>,
+}
+
+#[derive(Deserialize)]
+struct TokenLookup {
+    data: TokenData,
+}
+
+#[derive(Deserialize)]
+struct TokenData {
+    ttl: u64,
+    renewable: bool,
+}
+
+#[derive(Deserialize)]
+struct LeaseRenewal {
+    lease_duration: u64,
+    renewable: bool,
+}
+
+#[derive(Deserialize)]
+struct TokenRenewal {
+    auth: LeaseRenewal,
+}
+
+/// Lease of a secret that was read, or of the token
+struct Lease {
+    id: String,
+    /// Path of the secret, for logs
+    path: String,
+    renew_at: Instant,
+    expires_at: Instant,
+}
+
+impl Lease {
+    /// Leases are renewed once two thirds of them passed
+    fn new(id: &str, path: &str, duration: Duration) -> Self {
+        let now = Instant::now();
+        Self {
+            id: id.to_string(),
+            path: path.to_string(),
+            renew_at: now + duration * 2 / 3,
+            expires_at: now + duration,
+        }
+    }
+}
+
+/// HashiCorp Vault secrets are read from, over its HTTP API. Secrets are read once per
+/// path, so the fields of dynamic secrets (e.g. the user and password of `database/creds`)
+/// come from the same lease. KV version 2 secrets are unwrapped. The leases of the secrets
+/// and the token are renewed by `run_renewal` for as long as Vault allows, the secrets
+/// have to be read again, i.e. the service restarted, once they expire
+pub struct Vault {
+    addr: String,
+    token: Secret,
+    namespace: Option<String>,
+    client: reqwest::Client,
+    /// Data of the secrets read, by path
+    read: Mutex<HashMap<String, Map<String, Value>>>,
+    leases: Mutex<Vec<Lease>>,
+    /// Wakes `run_renewal` when a lease is tracked
+    tracked: Notify,
+}
+
+impl fmt::Debug for Vault {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Vault").field("addr", &self.addr).finish()
+    }
+}
+
+impl Vault {
+    /// Vault at `VAULT_ADDR` with the token `VAULT_TOKEN`, itself a secret that can be
+    /// read from a file. `VAULT_NAMESPACE` selects the namespace and `VAULT_CACERT` is the
+    /// CA Vault's certificate is verified with. `None` when `VAULT_ADDR` isn't set
+    pub async fn from_env(secrets: &Secrets) -> Result<Option<Self>, SecretError> {
+        let Ok(addr) = env::var("VAULT_ADDR") else {
+            return Ok(None);
+        };
+        let vault_error = |source: VaultError| SecretError::Vault {
+            name: "VAULT_TOKEN".into(),
+            source,
+        };
+
+        let token = secrets
+            .get("VAULT_TOKEN")
+            .await?
+            .unwrap_or_else(|| Secret(String::new()));
+        let mut client = reqwest::Client::builder().timeout(REQUEST_TIMEOUT);
+        if let Ok(path) = env::var("VAULT_CACERT") {
+            let pem = fs::read(&path).map_err(|source| SecretError::File {
+                name: "VAULT_CACERT".into(),
+                path,
+                source,
+            })?;
+            let ca = Certificate::from_pem(&pem).map_err(|e| vault_error(e.into()))?;
+            client = client.add_root_certificate(ca);
+        }
+
+        let vault = Self {
+            addr: addr.trim_end_matches('/').to_string(),
+            token,
+            namespace: env::var("VAULT_NAMESPACE").ok(),
+            client: client.build().map_err(|e| vault_error(e.into()))?,
+            read: Mutex::new(HashMap::new()),
+            leases: Mutex::new(Vec::new()),
+            tracked: Notify::new(),
+        };
+
+        // Root and other tokens without a TTL never expire, the rest need renewing
+        let lookup: TokenLookup = vault
+            .request(reqwest::Method::GET, "auth/token/lookup-self")
+            .send()
+            .await
+            .and_then(reqwest::Response::error_for_status)
+            .map_err(|e| vault_error(e.into()))?
+            .json()
+            .await
+            .map_err(|e| vault_error(e.into()))?;
+        tracing::info!("Reading secrets from Vault at {}", vault.addr);
+        if lookup.data.ttl > 0 {
+            vault.track(TOKEN_LEASE, "token", lookup.data.ttl, lookup.data.renewable);
+        }
+
+        Ok(Some(vault))
+    }
+
+    fn request(&self, method: reqwest::Method, path: &str) -> reqwest::RequestBuilder {
+        let mut request = self
+            .client
+            .request(method, format!("{}/v1/{path}", self.addr))
+            .header("X-Vault-Token", self.token.expose());
+        if let Some(namespace) = &self.namespace {
+            request = request.header("X-Vault-Namespace", namespace);
+        }
+        request
+    }
+
+    /// Field of the secret at the path
+    pub async fn read(&self, path: &str, field: &str) -> Result<String, VaultError> {
+        let cached = self
+            .read
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(path)
+            .cloned();
+        let data = match cached {
+            Some(data) => data,
+            None => {
+                let data = self.fetch(path).await?;
+                self.read
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .insert(path.to_string(), data.clone());
+                data
+            }
+        };
+
+        match data.get(field) {
+            Some(Value::String(value)) => Ok(value.clone()),
+            Some(_) => Err(VaultError::NotString {
+                path: path.to_string(),
+                field: field.to_string(),
+            }),
+            None => Err(VaultError::NoField {
+                path: path.to_string(),
+                field: field.to_string(),
+            }),
+        }
+    }
+
+    async fn fetch(&self, path: &str) -> Result<Map<String, Value>, VaultError> {
+        let response: SecretResponse = self
+            .request(reqwest::Method::GET, path)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        if !response.lease_id.is_empty() && response.lease_duration > 0 {
+            self.track(
+                &response.lease_id,
+                path,
+                response.lease_duration,
+                response.renewable,
+            );
+        }
+
+        let mut data = response.data;
+        // KV version 2 nests the secret under `data`, next to its `metadata`
+        if data.contains_key("metadata")
+            && let Some(Value::Object(inner)) = data.remove("data")
+        {
+            data = inner;
+        }
+        Ok(data)
+    }
+
+    fn track(&self, id: &str, path: &str, duration: u64, renewable: bool) {
+        if !renewable {
+            tracing::warn!("Vault lease of {path} can't be renewed, it expires in {duration}s");
+            return;
+        }
+        self.leases
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push(Lease::new(id, path, Duration::from_secs(duration)));
+        self.tracked.notify_one();
+    }
+
+    /// Renews the leases once two thirds of them passed, for as long as the service runs.
+    /// Leases of secrets read later are picked up as they're tracked. Failed renewals are
+    /// retried while the lease lasts
+    pub async fn run_renewal(self: Arc<Self>) {
+        loop {
+            let next = self
+                .leases
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .iter()
+                .map(|lease| lease.renew_at)
+                .min();
+            // A newly tracked lease may be due sooner, the next one is looked up again
+            match next {
+                Some(next) => tokio::select! {
+                    () = tokio::time::sleep_until(next.into()) => {}
+                    () = self.tracked.notified() => continue,
+                },
+                None => {
+                    self.tracked.notified().await;
+                    continue;
+                }
+            }
+
+            let due: Vec<(String, String)> = self
+                .leases
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .iter()
+                .filter(|lease| lease.renew_at <= Instant::now())
+                .map(|lease| (lease.id.clone(), lease.path.clone()))
+                .collect();
+            for (id, path) in due {
+                let renewal = self.renew(&id).await;
+                self.renewed(&id, &path, renewal);
+            }
+        }
+    }
+
+    async fn renew(&self, id: &str) -> Result<LeaseRenewal, VaultError> {
+        if id == TOKEN_LEASE {
+            let renewal: TokenRenewal = self
+                .request(reqwest::Method::POST, "auth/token/renew-self")
+                .send()
+                .await?
+                .error_for_status()?
+                .json()
+                .await?;
+            return Ok(renewal.auth);
+        }
+
+        Ok(self
+            .request(reqwest::Method::PUT, "sys/leases/renew")
+            .json(&serde_json::json!({ "lease_id": id }))
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?)
+    }
+
+    fn renewed(&self, id: &str, path: &str, renewal: Result<LeaseRenewal, VaultError>) {
+        let mut leases = self.leases.lock().unwrap_or_else(PoisonError::into_inner);
+        let Some(index) = leases.iter().position(|lease| lease.id == id) else {
+            return;
+        };
+
+        match renewal {
+            Ok(renewal) if renewal.renewable && renewal.lease_duration > 0 => {
+                tracing::debug!(
+                    "Renewed Vault lease of {path} for {}s",
+                    renewal.lease_duration
+                );
+                leases[index] = Lease::new(id, path, Duration::from_secs(renewal.lease_duration));
+            }
+            Ok(renewal) => {
+                tracing::warn!(
+                    "Vault lease of {path} can't be renewed anymore, it expires in {}s",
+                    renewal.lease_duration
+                );
+                leases.remove(index);
+            }
+            Err(e) => {
+                let lease = &mut leases[index];
+                if lease.expires_at <= Instant::now() {
+                    tracing::error!("Vault lease of {path} expired: {e}");
+                    leases.remove(index);
+                } else {
+                    tracing::warn!("Failed to renew Vault lease of {path}, retrying: {e}");
+                    lease.renew_at = (Instant::now() + RETRY_INTERVAL).min(lease.expires_at);
+                }
+            }
+        }
+        drop(leases);
+    }
+}
@@ -14,6 +14,10 @@ pub struct Upstream {
     pub base_url: String,
     pub rest_port: u16,
     pub grpc_port: u16,
+    /// Whether the upstream is called over TLS, presenting the client certificate
+    /// of `TLS_CLIENT_CERT_PATH` when it requires one
+    #[serde(default)]
+    pub tls: bool,
 }
 
 fn load_from_env() -> Result<Config, Box<dyn std::error::Error>> {
@@ -30,6 +34,7 @@ fn load_from_env() -> Result<Config, Box<dyn std::error::Error>> {
             .map_err(|_| "UPSTREAM_GRPC_PORT environment variable is required")?
             .parse::<u16>()
             .map_err(|e| format!("Failed to parse UPSTREAM_GRPC_PORT: {}", e))?,
+        tls: env::var("UPSTREAM_TLS").is_ok_and(|v| v == "true"),
     };
 
     let rest_port = env::var("REST_PORT")
@@ -1,11 +1,53 @@
 use crate::proxy::Proxy;
 use axum::{
+    Json,
     extract::{Request, State},
+    http::StatusCode,
     response::{IntoResponse, Response},
 };
 use axum_macros::debug_handler;
+use serde::Serialize;
 use std::sync::Arc;
 
+/// Readiness of the upstream, per protocol
+#[derive(Debug, Serialize)]
+struct Readiness {
+    ready: bool,
+    rest: bool,
+    grpc: bool,
+}
+
+fn readiness_status(ready: bool) -> StatusCode {
+    if ready {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    }
+}
+
+/// Ready only when both REST and gRPC ports of the upstream are
+#[debug_handler]
+pub async fn health_handler(State(side_car): State<Arc<Proxy>>) -> Response {
+    let (rest, grpc) = tokio::join!(side_car.rest_ready(), side_car.grpc_ready());
+    let ready = rest && grpc;
+
+    (
+        readiness_status(ready),
+        Json(Readiness { ready, rest, grpc }),
+    )
+        .into_response()
+}
+
+#[debug_handler]
+pub async fn rest_health_handler(State(side_car): State<Arc<Proxy>>) -> StatusCode {
+    readiness_status(side_car.rest_ready().await)
+}
+
+#[debug_handler]
+pub async fn grpc_health_handler(State(side_car): State<Arc<Proxy>>) -> StatusCode {
+    readiness_status(side_car.grpc_ready().await)
+}
+
 #[debug_handler]
 pub async fn proxy_handler(State(side_car): State<Arc<Proxy>>, request: Request) -> Response {
     tracing::info!("Forwarding request to inner service");
@@ -1,9 +1,10 @@
 mod config;
 mod handlers;
+mod openapi;
 mod proxy;
 
 use axum::Router;
-use axum::routing::any;
+use axum::routing::{any, get};
 use axum_server::tls_rustls::RustlsConfig;
 use proxy::Proxy;
 use std::fs;
@@ -23,6 +24,9 @@ async fn main() {
     let proxy = Arc::new(Proxy::new(cfg.upstream));
 
     let router = Router::new()
+        .route("/health", get(handlers::health_handler))
+        .route("/health/rest", get(handlers::rest_health_handler))
+        .route("/health/grpc", get(handlers::grpc_health_handler))
         .route("/{*path}", any(handlers::proxy_handler))
         .with_state(proxy.clone())
         .layer(TraceLayer::new_for_http());
@@ -55,9 +59,9 @@ async fn main() {
         cert_path,
         key_path
     );
-    let tls_config = RustlsConfig::from_pem_file(&cert_path, &key_path)
-        .await
-        .expect("Failed to load TLS certificates");
+    let tls_config =
+        pki::server_config_from_env(&cert_path, &key_path).expect("Failed to load TLS config");
+    let tls_config = RustlsConfig::from_config(Arc::new(tls_config));
 
     tracing::info!("HTTPS side-car listening on {}", rest_addr);
     tracing::info!("HTTPS gRPC side-car listening on {}", grpc_addr);
@@ -0,0 +1,89 @@
+use axum::body::Bytes;
+use axum::http::HeaderMap;
+
+/// Path of the OpenAPI spec served by the upstream
+const OPENAPI_PATH: &str = "/api-doc/openapi.json";
+/// Prefix of the Swagger UI static files served by the upstream
+const SWAGGER_UI_PREFIX: &str = "/swagger-ui";
+
+/// Whether the response for `path` is part of the upstream's API docs and needs rewriting
+pub fn is_docs_path(path: &str) -> bool {
+    path == OPENAPI_PATH || path.starts_with(SWAGGER_UI_PREFIX)
+}
+
+/// Public base URL of the docs as seen by the client, built from the forwarding headers
+/// (falls back to the `Host` header, the side-car itself only speaks HTTPS)
+fn public_base_url(headers: &HeaderMap) -> Option<String> {
+    let header = |name: &str| headers.get(name).and_then(|v| v.to_str().ok());
+
+    let proto = header("x-forwarded-proto").unwrap_or("https");
+    let host = header("x-forwarded-host").or_else(|| header("host"))?;
+    let prefix = forwarded_prefix(headers);
+
+    Some(format!("{}://{}{}", proto, host, prefix))
+}
+
+/// Path prefix under which the client reaches the side-car, without a trailing slash
+fn forwarded_prefix(headers: &HeaderMap) -> String {
+    headers
+        .get("x-forwarded-prefix")
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or("")
+        .trim_end_matches('/')
+        .to_string()
+}
+
+/// Rewrites the upstream docs so they are usable through the proxy chain:
+/// the spec gets a `servers` entry with the public URL, and Swagger UI files
+/// get the spec location prefixed with the public path prefix
+pub fn rewrite(path: &str, request_headers: &HeaderMap, body: Bytes) -> Bytes {
+    if path == OPENAPI_PATH {
+        return rewrite_spec(request_headers, body);
+    }
+
+    let prefix = forwarded_prefix(request_headers);
+    if prefix.is_empty() {
+        return body;
+    }
+
+    match String::from_utf8(body.to_vec()) {
+        Ok(text) => Bytes::from(text.replace(
+            &format!("\"{}\"", OPENAPI_PATH),
+            &format!("\"{}{}\"", prefix, OPENAPI_PATH),
+        )),
+        // Images and other binary assets don't reference the spec
+        Err(_) => body,
+    }
+}
+
+fn rewrite_spec(request_headers: &HeaderMap, body: Bytes) -> Bytes {
+    let Some(base_url) = public_base_url(request_headers) else {
+        return body;
+    };
+
+    let mut spec: serde_json::Value = match serde_json::from_slice(&body) {
+        Ok(spec) => spec,
+        Err(e) => {
+            tracing::warn!(
+                "Upstream OpenAPI spec is not valid JSON, passing as is: {}",
+                e
+            );
+            return body;
+        }
+    };
+
+    if let Some(obj) = spec.as_object_mut() {
+        obj.insert(
+            "servers".to_string(),
+            serde_json::json!([{ "url": base_url }]),
+        );
+    }
+
+    match serde_json::to_vec(&spec) {
+        Ok(rewritten) => {
+            tracing::debug!("Rewrote OpenAPI spec servers to {}", base_url);
+            Bytes::from(rewritten)
+        }
+        Err(_) => body,
+    }
+}
@@ -1,24 +1,49 @@
 use crate::config::Upstream;
+use crate::openapi;
 use axum::extract::Request;
-use axum::http::StatusCode;
+use axum::http::{StatusCode, header};
 use axum::response::Response;
+use request_signing::SigningKey;
+use std::env;
 use std::time::Duration;
+use tokio::net::TcpStream;
+
+/// Timeout of a single readiness probe of the upstream
+const HEALTH_CHECK_TIMEOUT: Duration = Duration::from_secs(2);
 
 #[derive(Clone)]
 pub struct Proxy {
     upstream: Upstream,
     client: reqwest::Client,
     grpc_client: reqwest::Client,
+    signing_key: Option<SigningKey>,
+}
+
+/// Key the forwarded requests are signed with when the upstream requires signed requests,
+/// from `UPSTREAM_SIGNING_KEY_ID` and `UPSTREAM_SIGNING_KEY`
+fn signing_key_from_env() -> Option<SigningKey> {
+    let id = env::var("UPSTREAM_SIGNING_KEY_ID").ok()?;
+    let secret = env::var("UPSTREAM_SIGNING_KEY").expect("UPSTREAM_SIGNING_KEY is required");
+    tracing::info!("Signing upstream requests with key {}", id);
+    Some(SigningKey::new(&id, secret.as_bytes()))
 }
 
 impl Proxy {
     pub fn new(upstream: Upstream) -> Self {
-        let client = reqwest::Client::builder()
+        let tls = if upstream.tls {
+            pki::ClientTls::from_env().expect("Failed to load upstream TLS settings")
+        } else {
+            pki::ClientTls::default()
+        };
+
+        let client = tls
+            .apply(reqwest::Client::builder())
             .timeout(Duration::from_secs(30))
             .build()
             .expect("Failed to create HTTP client");
 
-        let grpc_client = reqwest::Client::builder()
+        let grpc_client = tls
+            .apply(reqwest::Client::builder())
             .http2_prior_knowledge()
             .timeout(Duration::from_secs(30))
             .build()
@@ -28,23 +53,83 @@ impl Proxy {
             upstream,
             client,
             grpc_client,
+            signing_key: signing_key_from_env(),
         }
     }
 
+    /// Adds the signature headers when a signing key is configured
+    fn sign(
+        &self,
+        request: reqwest::RequestBuilder,
+        method: &str,
+        path_and_query: &str,
+        body: &[u8],
+    ) -> reqwest::RequestBuilder {
+        let Some(key) = &self.signing_key else {
+            return request;
+        };
+        key.headers(method, path_and_query, body)
+            .into_iter()
+            .fold(request, |request, (name, value)| {
+                request.header(name, value)
+            })
+    }
+
+    fn scheme(&self) -> &'static str {
+        if self.upstream.tls { "https" } else { "http" }
+    }
+
     fn get_rest_url(&self) -> String {
         format!(
-            "http://{}:{}",
-            self.upstream.base_url, self.upstream.rest_port
+            "{}://{}:{}",
+            self.scheme(),
+            self.upstream.base_url,
+            self.upstream.rest_port
         )
     }
 
     fn get_grpc_url(&self) -> String {
         format!(
-            "http://{}:{}",
-            self.upstream.base_url, self.upstream.grpc_port
+            "{}://{}:{}",
+            self.scheme(),
+            self.upstream.base_url,
+            self.upstream.grpc_port
         )
     }
 
+    /// Whether the upstream's readiness probe (`GET /readyz`) succeeds
+    pub async fn rest_ready(&self) -> bool {
+        let health_url = format!("{}/readyz", self.get_rest_url());
+        match self
+            .sign(self.client.get(&health_url), "GET", "/readyz", b"")
+            .timeout(HEALTH_CHECK_TIMEOUT)
+            .send()
+            .await
+        {
+            Ok(response) => response.status().is_success(),
+            Err(e) => {
+                tracing::debug!("REST health check of {} failed: {}", health_url, e);
+                false
+            }
+        }
+    }
+
+    /// Whether the upstream's gRPC port accepts connections
+    pub async fn grpc_ready(&self) -> bool {
+        let addr = (self.upstream.base_url.as_str(), self.upstream.grpc_port);
+        match tokio::time::timeout(HEALTH_CHECK_TIMEOUT, TcpStream::connect(addr)).await {
+            Ok(Ok(_)) => true,
+            Ok(Err(e)) => {
+                tracing::debug!("gRPC health check of {} failed: {}", self.get_grpc_url(), e);
+                false
+            }
+            Err(_) => {
+                tracing::debug!("gRPC health check of {} timed out", self.get_grpc_url());
+                false
+            }
+        }
+    }
+
     pub async fn forward_request(&self, request: Request) -> Result<Response, StatusCode> {
         let (parts, body) = request.into_parts();
         let body_bytes = axum::body::to_bytes(body, usize::MAX)
@@ -52,6 +137,7 @@ impl Proxy {
             .map_err(|_| StatusCode::BAD_REQUEST)?;
 
         let method = parts.method;
+        let path = parts.uri.path();
         let path_and_query = parts.uri.path_and_query().map(|s| s.as_str()).unwrap_or("");
         let headers = parts.headers;
 
@@ -59,13 +145,22 @@ impl Proxy {
 
         tracing::debug!("Proxying {} request to {}", method, upstream_url);
 
-        let mut upstream_request = self.client.request(method, &upstream_url);
+        let mut upstream_request = self.sign(
+            self.client.request(method.clone(), &upstream_url),
+            method.as_str(),
+            path_and_query,
+            &body_bytes,
+        );
+
+        // The docs are rewritten below, so they're asked for uncompressed
+        let rewrites_docs = openapi::is_docs_path(path);
 
         // Copy headers (excluding Host header which should be for upstream)
         for (name, value) in headers.iter() {
-            if name != "host" {
-                upstream_request = upstream_request.header(name, value);
+            if name == "host" || (rewrites_docs && name == header::ACCEPT_ENCODING) {
+                continue;
             }
+            upstream_request = upstream_request.header(name, value);
         }
 
         let response = upstream_request
@@ -89,6 +184,12 @@ impl Proxy {
             response_body.len()
         );
 
+        let response_body = if status.is_success() && rewrites_docs {
+            openapi::rewrite(path, &headers, response_body)
+        } else {
+            response_body
+        };
+
         let mut axum_response = Response::builder()
             .status(status)
             .body(axum::body::Body::from(response_body))
@@ -127,7 +228,12 @@ impl Proxy {
 
         tracing::debug!("Proxying gRPC {} request to {}", method, upstream_url);
 
-        let mut upstream_request = self.grpc_client.request(method, &upstream_url);
+        let mut upstream_request = self.sign(
+            self.grpc_client.request(method.clone(), &upstream_url),
+            method.as_str(),
+            path_and_query,
+            &body_bytes,
+        );
 
         // Copy headers (excluding Host header which should be for upstream)
         for (name, value) in headers.iter() {
@@ -176,3 +282,59 @@ impl Proxy {
         Ok(axum_response)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::Proxy;
+    use crate::config::Upstream;
+    use axum::Router;
+    use axum::body::Body;
+    use axum::extract::Request;
+    use axum::http::header;
+    use axum::routing::get;
+    use tower_http::compression::CompressionLayer;
+
+    /// Upstream serving a spec big enough to be compressed when the client accepts it
+    async fn upstream() -> u16 {
+        let spec = serde_json::json!({
+            "openapi": "3.1.0",
+            "info": { "title": "notes", "version": "1" },
+            "paths": {},
+            "components": { "description": "x".repeat(4096) },
+        });
+        let router = Router::new()
+            .route(
+                "/api-doc/openapi.json",
+                get(move || async move { axum::Json(spec) }),
+            )
+            .layer(CompressionLayer::new());
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move { axum::serve(listener, router).await });
+        port
+    }
+
+    #[tokio::test]
+    async fn spec_is_rewritten_for_clients_accepting_gzip() {
+        let proxy = Proxy::new(Upstream {
+            base_url: "127.0.0.1".into(),
+            rest_port: upstream().await,
+            grpc_port: 0,
+            tls: false,
+        });
+        let request = Request::get("/api-doc/openapi.json")
+            .header(header::HOST, "notes.example.com")
+            .header(header::ACCEPT_ENCODING, "gzip, deflate, br")
+            .body(Body::empty())
+            .unwrap();
+
+        let response = proxy.forward_request(request).await.unwrap();
+        assert!(response.headers().get(header::CONTENT_ENCODING).is_none());
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let spec: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(spec["servers"][0]["url"], "https://notes.example.com");
+    }
+}
@@ -0,0 +1,111 @@
+use serde::de::DeserializeOwned;
+use soap_envelope::SoapVersion;
+
+use std::fmt;
+
+use crate::model::FaultXml;
+
+/// Namespace of the notes server operation elements
+pub const NAMESPACE: &str = "https://notes-server/soap/v1";
+
+#[derive(Debug)]
+pub enum CallError {
+    Http(reqwest::Error),
+    /// The server answered with a SOAP fault
+    Fault {
+        code: String,
+        reason: String,
+    },
+    /// The response is not an envelope with a response element
+    InvalidResponse(String),
+}
+
+impl fmt::Display for CallError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Http(e) => write!(f, "request failed: {e}"),
+            Self::Fault { code, reason } => write!(f, "fault {code}: {reason}"),
+            Self::InvalidResponse(reason) => write!(f, "invalid response: {reason}"),
+        }
+    }
+}
+
+impl std::error::Error for CallError {}
+
+impl From<reqwest::Error> for CallError {
+    fn from(e: reqwest::Error) -> Self {
+        Self::Http(e)
+    }
+}
+
+pub struct SoapClient {
+    http: reqwest::Client,
+    url: String,
+    version: SoapVersion,
+}
+
+impl SoapClient {
+    pub fn new(url: String, version: SoapVersion) -> Self {
+        Self {
+            http: reqwest::Client::new(),
+            url,
+            version,
+        }
+    }
+
+    /// Envelope calling `operation`, with `children` as the markup inside its element
+    pub fn request_envelope(&self, operation: &str, children: &str) -> String {
+        soap_envelope::envelope(
+            self.version,
+            &format!(r#"<m:{operation} xmlns:m="{NAMESPACE}">{children}</m:{operation}>"#),
+        )
+    }
+
+    /// Calls `operation` and deserializes the response element. The operation is
+    /// also named by the action, in the way of the envelope version
+    pub async fn call<T: DeserializeOwned>(
+        &self,
+        operation: &str,
+        children: &str,
+    ) -> Result<T, CallError> {
+        let action = format!("{NAMESPACE}/{operation}");
+        let request = self.http.post(&self.url);
+        let request = match self.version {
+            SoapVersion::Soap11 => request
+                .header("Content-Type", self.version.content_type())
+                .header("SOAPAction", format!("\"{action}\"")),
+            SoapVersion::Soap12 => request.header(
+                "Content-Type",
+                format!("{}; action=\"{action}\"", self.version.content_type()),
+            ),
+        };
+
+        let body = request
+            .body(self.request_envelope(operation, children))
+            .send()
+            .await?
+            .text()
+            .await?;
+
+        parse_response(&body)
+    }
+}
+
+/// Response element of the envelope, or the fault it carries
+fn parse_response<T: DeserializeOwned>(envelope: &str) -> Result<T, CallError> {
+    let elements = soap_envelope::body_elements(envelope)
+        .ok_or_else(|| CallError::InvalidResponse(format!("not a SOAP envelope: {envelope}")))?;
+    let (name, element) = elements
+        .first()
+        .ok_or_else(|| CallError::InvalidResponse("empty Body".to_string()))?;
+
+    if name == "Fault" {
+        let fault: FaultXml = quick_xml::de::from_str(element)
+            .map_err(|e| CallError::InvalidResponse(format!("malformed fault: {e}")))?;
+        let (code, reason) = fault.into_parts();
+        return Err(CallError::Fault { code, reason });
+    }
+
+    quick_xml::de::from_str(element)
+        .map_err(|e| CallError::InvalidResponse(format!("malformed {name}: {e}")))
+}
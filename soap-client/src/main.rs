@@ -0,0 +1,88 @@
+mod client;
+mod model;
+
+use client::{CallError, SoapClient};
+use model::{Empty, NoteElement, NoteList, NotesPage};
+use quick_xml::escape::escape;
+use soap_envelope::SoapVersion;
+
+use std::env;
+
+/// Address used when `SOAP_SERVER_ADDR` is not set, the balancer's REST/SOAP port
+const DEFAULT_ADDR: &str = "http://127.0.0.1:4000/soap";
+
+fn version() -> Result<SoapVersion, Box<dyn std::error::Error>> {
+    match env::var("SOAP_VERSION").as_deref() {
+        Err(_) | Ok("1.2") => Ok(SoapVersion::Soap12),
+        Ok("1.1") => Ok(SoapVersion::Soap11),
+        Ok(other) => Err(format!("Unknown SOAP_VERSION '{other}', expected 1.1 or 1.2").into()),
+    }
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let addr = env::var("SOAP_SERVER_ADDR").unwrap_or_else(|_| DEFAULT_ADDR.to_string());
+    let version = version()?;
+    let client = SoapClient::new(addr.clone(), version);
+    println!("Calling SOAP endpoint at address {addr} ({version:?})\n");
+
+    // Create note
+    println!("1. Creating a note...");
+    let children = format!("<m:Content>{}</m:Content>", escape("Test string SOAP"));
+    println!(
+        "Request: {}",
+        client.request_envelope("CreateNote", &children)
+    );
+    let created: NoteElement = client.call("CreateNote", &children).await?;
+    println!(
+        "Created note: {}\n",
+        serde_json::to_string_pretty(&created)?
+    );
+    let note_id = created.note.id;
+
+    // Get one note
+    println!("2. Getting note by ID...");
+    let children = format!("<m:Id>{note_id}</m:Id>");
+    let note: NoteElement = client.call("GetNote", &children).await?;
+    println!("Note: {}\n", serde_json::to_string_pretty(&note)?);
+
+    // Update note
+    println!("3. Updating the note...");
+    let children = format!(
+        "<m:Id>{note_id}</m:Id><m:Content>{}</m:Content>",
+        escape("Test string SOAP 2")
+    );
+    let updated: NoteElement = client.call("UpdateNote", &children).await?;
+    println!(
+        "Updated note: {}\n",
+        serde_json::to_string_pretty(&updated)?
+    );
+
+    // Get a page of notes
+    println!("4. Getting the first page of notes...");
+    let page: NotesPage = client
+        .call("GetNotesPage", "<m:PageSize>2</m:PageSize>")
+        .await?;
+    println!("Page: {}\n", serde_json::to_string_pretty(&page)?);
+
+    // Get all notes
+    println!("5. Getting all notes...");
+    let all_notes: NoteList = client.call("GetAllNotes", "").await?;
+    println!("Notes: {}\n", serde_json::to_string_pretty(&all_notes)?);
+
+    // Delete note
+    println!("6. Deleting the note...");
+    let children = format!("<m:Id>{note_id}</m:Id>");
+    let _: Empty = client.call("DeleteNote", &children).await?;
+    println!("Deleted note {note_id}\n");
+
+    // Faults
+    println!("7. Getting the deleted note...");
+    match client.call::<NoteElement>("GetNote", &children).await {
+        Err(fault @ CallError::Fault { .. }) => println!("Expected {fault}\n"),
+        Err(e) => return Err(e.into()),
+        Ok(note) => return Err(format!("Deleted note {} was returned", note.note.id).into()),
+    }
+
+    Ok(())
+}
@@ -0,0 +1,91 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Note {
+    #[serde(rename = "Id")]
+    pub id: i64,
+    #[serde(rename = "Content")]
+    pub content: String,
+    #[serde(rename = "CreatedAt")]
+    pub created_at: String,
+    #[serde(rename = "UpdatedAt")]
+    pub updated_at: String,
+    #[serde(rename = "ExpiresAt", default, skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<String>,
+    #[serde(rename = "RemindAt", default, skip_serializing_if = "Option::is_none")]
+    pub remind_at: Option<String>,
+    /// JSON object as a string
+    #[serde(rename = "Metadata", default, skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<String>,
+    #[serde(rename = "Position")]
+    pub position: i64,
+}
+
+/// `CreateNoteResponse`, `GetOneNoteResponse` and `UpdateNoteResponse`
+#[derive(Debug, Serialize, Deserialize)]
+pub struct NoteElement {
+    #[serde(rename = "Note")]
+    pub note: Note,
+}
+
+/// `GetAllNotesResponse`
+#[derive(Debug, Serialize, Deserialize)]
+pub struct NoteList {
+    #[serde(rename = "Note", default)]
+    pub notes: Vec<Note>,
+}
+
+/// `GetNotesPageResponse`
+#[derive(Debug, Serialize, Deserialize)]
+pub struct NotesPage {
+    #[serde(rename = "Note", default)]
+    pub notes: Vec<Note>,
+    #[serde(rename = "Total")]
+    pub total: i64,
+    #[serde(rename = "NextPageToken", default)]
+    pub next_page_token: Option<String>,
+}
+
+/// `DeleteNoteResponse`
+#[derive(Debug, Deserialize)]
+pub struct Empty {}
+
+#[derive(Debug, Deserialize)]
+struct Value {
+    #[serde(rename = "Value")]
+    value: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct Text {
+    #[serde(rename = "Text")]
+    text: String,
+}
+
+/// SOAP 1.1 (`faultcode`, `faultstring`) or SOAP 1.2 (`Code`, `Reason`) fault
+#[derive(Debug, Deserialize)]
+pub struct FaultXml {
+    #[serde(rename = "faultcode", default)]
+    fault_code: Option<String>,
+    #[serde(rename = "faultstring", default)]
+    fault_string: Option<String>,
+    #[serde(rename = "Code", default)]
+    code: Option<Value>,
+    #[serde(rename = "Reason", default)]
+    reason: Option<Text>,
+}
+
+impl FaultXml {
+    /// Fault code and reason, whichever the version
+    pub fn into_parts(self) -> (String, String) {
+        let code = self
+            .fault_code
+            .or(self.code.map(|code| code.value))
+            .unwrap_or_default();
+        let reason = self
+            .fault_string
+            .or(self.reason.map(|reason| reason.text))
+            .unwrap_or_default();
+        (code, reason)
+    }
+}
@@ -0,0 +1,75 @@
+use quick_xml::{
+    events::Event,
+    name::ResolveResult,
+    reader::{NsReader, Reader},
+};
+
+use crate::version::SoapVersion;
+
+/// Envelope of the given version around the `Body` content
+pub fn envelope(version: SoapVersion, body: &str) -> String {
+    format!(
+        r#"<soap:Envelope xmlns:soap="{namespace}"><soap:Body>{body}</soap:Body></soap:Envelope>"#,
+        namespace = version.namespace(),
+    )
+}
+
+/// Namespace and local name of the document's root element
+pub fn root_element(document: &str) -> Option<(Option<String>, String)> {
+    let mut reader = NsReader::from_str(document);
+    loop {
+        match reader.read_resolved_event().ok()? {
+            (namespace, Event::Start(element) | Event::Empty(element)) => {
+                let namespace = match namespace {
+                    ResolveResult::Bound(namespace) => {
+                        Some(String::from_utf8_lossy(namespace.as_ref()).into_owned())
+                    }
+                    _ => None,
+                };
+                let name = String::from_utf8_lossy(element.local_name().as_ref()).into_owned();
+                return Some((namespace, name));
+            }
+            (_, Event::Eof) => return None,
+            _ => {}
+        }
+    }
+}
+
+/// Local names and markup of the elements in the envelope `Body`, in document order.
+/// `None` if the document is malformed or has no `Body`
+pub fn body_elements(envelope: &str) -> Option<Vec<(String, &str)>> {
+    let mut reader = Reader::from_str(envelope);
+    let mut elements = Vec::new();
+    // Envelope, Body
+    let mut depth = 0;
+
+    loop {
+        let start = usize::try_from(reader.buffer_position()).ok()?;
+        match reader.read_event().ok()? {
+            Event::Start(element) if depth == 2 => {
+                reader.read_to_end(element.name()).ok()?;
+                let end = usize::try_from(reader.buffer_position()).ok()?;
+                let name = String::from_utf8_lossy(element.local_name().as_ref()).into_owned();
+                elements.push((name, &envelope[start..end]));
+            }
+            Event::Empty(element) if depth == 2 => {
+                let end = usize::try_from(reader.buffer_position()).ok()?;
+                let name = String::from_utf8_lossy(element.local_name().as_ref()).into_owned();
+                elements.push((name, &envelope[start..end]));
+            }
+            Event::Start(element) if depth == 0 || element.local_name().as_ref() == b"Body" => {
+                depth += 1;
+            }
+            // Header blocks are not processed
+            Event::Start(element) => {
+                reader.read_to_end(element.name()).ok()?;
+            }
+            Event::Empty(element) if depth == 1 && element.local_name().as_ref() == b"Body" => {
+                return Some(elements);
+            }
+            Event::End(_) if depth == 2 => return Some(elements),
+            Event::Eof => return None,
+            _ => {}
+        }
+    }
+}
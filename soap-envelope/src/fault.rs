@@ -0,0 +1,132 @@
+use crate::version::SoapVersion;
+
+/// Common SOAP fault codes, named after their SOAP 1.1 versions
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FaultCode {
+    /// The message was incorrectly formed or contained incorrect information
+    Client,
+    /// The message could not be processed for reasons not directly attributable to the client
+    Server,
+    /// An immediate child element of the Header was not understood
+    MustUnderstand,
+    /// The SOAP Envelope namespace is not supported
+    VersionMismatch,
+}
+
+impl FaultCode {
+    /// Name of the code in faults of the given version (SOAP 1.2 renamed two of them)
+    pub const fn name(self, version: SoapVersion) -> &'static str {
+        match (self, version) {
+            (Self::Client, SoapVersion::Soap11) => "Client",
+            (Self::Client, SoapVersion::Soap12) => "Sender",
+            (Self::Server, SoapVersion::Soap11) => "Server",
+            (Self::Server, SoapVersion::Soap12) => "Receiver",
+            (Self::MustUnderstand, _) => "MustUnderstand",
+            (Self::VersionMismatch, _) => "VersionMismatch",
+        }
+    }
+}
+
+/// A SOAP fault, serialized in the shape of the envelope version it answers
+#[derive(Debug, Clone)]
+pub struct Fault<'a> {
+    pub code: FaultCode,
+    /// Human-readable explanation, escaped on serialization
+    pub reason: &'a str,
+    /// Language of `reason`, only SOAP 1.2 faults carry it
+    pub language: &'a str,
+    /// Markup placed in the fault `detail` as is
+    pub detail: Option<&'a str>,
+}
+
+impl<'a> Fault<'a> {
+    pub const fn new(code: FaultCode, reason: &'a str) -> Self {
+        Self {
+            code,
+            reason,
+            language: "en",
+            detail: None,
+        }
+    }
+
+    #[must_use]
+    pub const fn language(self, language: &'a str) -> Self {
+        Self { language, ..self }
+    }
+
+    #[must_use]
+    pub const fn detail(self, detail: &'a str) -> Self {
+        Self {
+            detail: Some(detail),
+            ..self
+        }
+    }
+
+    /// Complete fault envelope in the given version
+    pub fn to_envelope(&self, version: SoapVersion) -> String {
+        let reason = quick_xml::escape::escape(self.reason);
+        let fault_code = self.code.name(version);
+
+        match version {
+            SoapVersion::Soap11 => {
+                let detail = self
+                    .detail
+                    .map(|detail| format!("\n      <detail>{detail}</detail>"))
+                    .unwrap_or_default();
+
+                format!(
+                    r#"<?xml version="1.0" encoding="UTF-8"?>
+<soap:Envelope xmlns:soap="{namespace}">
+  <soap:Body>
+    <soap:Fault>
+      <faultcode>soap:{fault_code}</faultcode>
+      <faultstring>{reason}</faultstring>{detail}
+    </soap:Fault>
+  </soap:Body>
+</soap:Envelope>"#,
+                    namespace = version.namespace(),
+                )
+            }
+            SoapVersion::Soap12 => {
+                let detail = self
+                    .detail
+                    .map(|detail| format!("\n      <soap:Detail>{detail}</soap:Detail>"))
+                    .unwrap_or_default();
+                // Tells the client which envelope versions to use instead
+                let upgrade = if self.code == FaultCode::VersionMismatch {
+                    format!(
+                        r#"
+  <soap:Header>
+    <soap:Upgrade>
+      <soap:SupportedEnvelope qname="v12:Envelope" xmlns:v12="{v12}"/>
+      <soap:SupportedEnvelope qname="v11:Envelope" xmlns:v11="{v11}"/>
+    </soap:Upgrade>
+  </soap:Header>"#,
+                        v12 = SoapVersion::Soap12.namespace(),
+                        v11 = SoapVersion::Soap11.namespace(),
+                    )
+                } else {
+                    String::new()
+                };
+
+                format!(
+                    r#"<?xml version="1.0" encoding="UTF-8"?>
+<soap:Envelope xmlns:soap="{namespace}">{upgrade}
+  <soap:Body>
+    <soap:Fault>
+      <soap:Code>
+        <soap:Value>soap:{fault_code}</soap:Value>
+      </soap:Code>
+      <soap:Reason>
+        <soap:Text xml:lang="{language}">{reason}</soap:Text>
+      </soap:Reason>{detail}
+    </soap:Fault>
+  </soap:Body>
+</soap:Envelope>"#,
+                    namespace = version.namespace(),
+                    language = quick_xml::escape::escape(self.language),
+                )
+            }
+        }
+    }
+}
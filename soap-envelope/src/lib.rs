@@ -0,0 +1,7 @@
+pub mod envelope;
+pub mod fault;
+pub mod version;
+
+pub use envelope::{body_elements, envelope, root_element};
+pub use fault::{Fault, FaultCode};
+pub use version::SoapVersion;
@@ -0,0 +1,47 @@
+pub const SOAP_11_NAMESPACE: &str = "http://schemas.xmlsoap.org/soap/envelope/";
+pub const SOAP_12_NAMESPACE: &str = "http://www.w3.org/2003/05/soap-envelope";
+
+/// Version of a SOAP envelope, responses and faults use the same one as the request
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SoapVersion {
+    /// `text/xml` envelopes
+    Soap11,
+    /// `application/soap+xml` envelopes
+    Soap12,
+}
+
+impl SoapVersion {
+    pub const fn namespace(self) -> &'static str {
+        match self {
+            Self::Soap11 => SOAP_11_NAMESPACE,
+            Self::Soap12 => SOAP_12_NAMESPACE,
+        }
+    }
+
+    pub const fn content_type(self) -> &'static str {
+        match self {
+            Self::Soap11 => "text/xml; charset=utf-8",
+            Self::Soap12 => "application/soap+xml; charset=utf-8",
+        }
+    }
+
+    /// Version of the envelope with this namespace
+    pub fn from_namespace(namespace: &str) -> Option<Self> {
+        match namespace {
+            SOAP_11_NAMESPACE => Some(Self::Soap11),
+            SOAP_12_NAMESPACE => Some(Self::Soap12),
+            _ => None,
+        }
+    }
+
+    /// Version implied by a `Content-Type` value, if it names a SOAP media type
+    pub fn from_content_type(content_type: &str) -> Option<Self> {
+        let mime = content_type.split(';').next()?.trim().to_lowercase();
+
+        match mime.as_str() {
+            "text/xml" => Some(Self::Soap11),
+            "application/soap+xml" => Some(Self::Soap12),
+            _ => None,
+        }
+    }
+}